@@ -0,0 +1,223 @@
+//! `calc debug`, an interactive debugger for programs in the calculator language
+//!
+//! Blocks are referred to by their number within their function, in the order [`crate::lower`] created them, so block 0 is always the entry.
+
+use std::io::{self, BufRead, Write};
+
+use calc_interpreter::debugger::{Breakpoint, Stop};
+use calc_interpreter::{Debugger, Interpreter, Limits};
+use calc_ir::program::implementations::{BasicProgram, BlockID};
+use calc_ir::Number;
+
+use crate::lower::Lowered;
+
+const HELP: &str = "\
+commands:
+  step, s                  run a single instruction
+  next, n                  run a single instruction, running calls until they return
+  out, o                   run until the current function returns
+  continue, c              run until a breakpoint or the end of the program
+  break, b <fn> [block]    pause when <fn> is called, or when its block number [block] is entered
+  delete, d <fn> [block]   remove a breakpoint
+  breakpoints              list every breakpoint
+  registers, r [frame]     print the registers of a frame, 0 is the innermost and the default
+  stack, bt                print the call stack
+  help, h                  print this message
+  quit, q                  stop debugging";
+
+type ProgramDebugger<'p> = Debugger<'p, BasicProgram>;
+
+/// Debug `function` of `lowered`, reading commands from `input` until it ends or the user quits
+///
+/// The program runs within `limits` for the whole session, so a command that would never stop reports an error instead
+///
+/// # Errors
+/// If reading from `input` or writing to `output` fails
+pub fn debug(
+    lowered: &Lowered,
+    function: &str,
+    arguments: &[Number],
+    limits: Limits,
+    mut input: impl BufRead,
+    output: &mut impl Write,
+) -> io::Result<()> {
+    let interpreter = Interpreter::new(&lowered.program).with_limits(limits);
+    let mut debugger = match interpreter.debug(&function.to_string(), arguments) {
+        Ok(debugger) => debugger,
+        Err(error) => return writeln!(output, "error: {error}"),
+    };
+    let mut session = Session { lowered, output };
+    writeln!(session.output, "type `help` for a list of commands")?;
+    session.print_location(&debugger)?;
+
+    let mut line = String::new();
+    loop {
+        write!(session.output, "(calc) ")?;
+        session.output.flush()?;
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return writeln!(session.output);
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((command, arguments)) = words.split_first() else {
+            continue;
+        };
+        match *command {
+            "step" | "s" => session.report(&mut debugger, Debugger::step)?,
+            "next" | "n" => session.report(&mut debugger, Debugger::step_over)?,
+            "out" | "o" => session.report(&mut debugger, Debugger::step_out)?,
+            "continue" | "c" => session.report(&mut debugger, Debugger::resume)?,
+            "break" | "b" => match session.breakpoint(arguments) {
+                Ok(breakpoint) => debugger.add_breakpoint(breakpoint),
+                Err(message) => writeln!(session.output, "{message}")?,
+            },
+            "delete" | "d" => match session.breakpoint(arguments) {
+                Ok(breakpoint) => {
+                    if !debugger.remove_breakpoint(&breakpoint) {
+                        writeln!(session.output, "no such breakpoint")?;
+                    }
+                }
+                Err(message) => writeln!(session.output, "{message}")?,
+            },
+            "breakpoints" => {
+                for breakpoint in debugger.breakpoints() {
+                    let breakpoint = session.describe_breakpoint(breakpoint);
+                    writeln!(session.output, "{breakpoint}")?;
+                }
+            }
+            "registers" | "r" => session.print_registers(&debugger, arguments)?,
+            "stack" | "bt" => session.print_stack(&debugger)?,
+            "help" | "h" => writeln!(session.output, "{HELP}")?,
+            "quit" | "q" => return Ok(()),
+            _ => writeln!(
+                session.output,
+                "unknown command {command}, type `help` for a list of commands"
+            )?,
+        }
+    }
+}
+
+struct Session<'l, W: Write> {
+    lowered: &'l Lowered,
+    output: &'l mut W,
+}
+
+impl<W: Write> Session<'_, W> {
+    /// `block` of `function` as it's shown to the user
+    fn describe_block(&self, function: &str, block: BlockID) -> String {
+        self.lowered
+            .block_number(function, block)
+            .map_or(format!("{block:?}"), |number| format!("block {number}"))
+    }
+
+    fn describe_breakpoint(&self, breakpoint: &Breakpoint<BlockID, String>) -> String {
+        match breakpoint {
+            Breakpoint::Function(function) => function.clone(),
+            Breakpoint::Block(function, block) => {
+                format!("{function} {}", self.describe_block(function, *block))
+            }
+        }
+    }
+
+    /// Parse the arguments of `break` and `delete`
+    fn breakpoint(&self, arguments: &[&str]) -> Result<Breakpoint<BlockID, String>, String> {
+        let (function, block) = match arguments {
+            [function] => (*function, None),
+            [function, block] => (*function, Some(*block)),
+            _ => return Err("expected a function and an optional block number".to_string()),
+        };
+        let blocks = self
+            .lowered
+            .blocks
+            .get(function)
+            .ok_or_else(|| format!("there's no function called {function}"))?;
+        let Some(block) = block else {
+            return Ok(Breakpoint::Function(function.to_string()));
+        };
+        let block = block
+            .parse::<usize>()
+            .ok()
+            .and_then(|number| blocks.get(number))
+            .ok_or_else(|| format!("{function} has no block {block}"))?;
+        Ok(Breakpoint::Block(function.to_string(), *block))
+    }
+
+    /// Run `action`, and tell the user where it stopped
+    fn report<'p>(
+        &mut self,
+        debugger: &mut ProgramDebugger<'p>,
+        action: impl FnOnce(
+            &mut ProgramDebugger<'p>,
+        )
+            -> Result<Stop<BlockID, String, Number>, calc_interpreter::Error<String>>,
+    ) -> io::Result<()> {
+        match action(debugger) {
+            Ok(Stop::Stepped) => self.print_location(debugger),
+            Ok(Stop::Breakpoint(breakpoint)) => {
+                let breakpoint = self.describe_breakpoint(&breakpoint);
+                writeln!(self.output, "breakpoint {breakpoint}")?;
+                self.print_location(debugger)
+            }
+            Ok(Stop::Finished(result)) => writeln!(self.output, "finished with {result}"),
+            Err(error) => writeln!(self.output, "error: {error}"),
+        }
+    }
+
+    /// Print the instruction that will run next
+    fn print_location(&mut self, debugger: &ProgramDebugger<'_>) -> io::Result<()> {
+        let Some(frame) = debugger.call_stack().last() else {
+            return Ok(());
+        };
+        let block = self.describe_block(frame.function(), *frame.block());
+        write!(
+            self.output,
+            "{} {block} [{}]",
+            frame.function(),
+            frame.instruction()
+        )?;
+        match debugger.current_instruction() {
+            Some(instruction) => writeln!(self.output, ": {instruction:?}"),
+            None => writeln!(self.output),
+        }
+    }
+
+    fn print_stack(&mut self, debugger: &ProgramDebugger<'_>) -> io::Result<()> {
+        for (depth, frame) in debugger.call_stack().iter().rev().enumerate() {
+            let block = self.describe_block(frame.function(), *frame.block());
+            writeln!(
+                self.output,
+                "#{depth} {} {block} [{}]",
+                frame.function(),
+                frame.instruction()
+            )?;
+        }
+        Ok(())
+    }
+
+    fn print_registers(
+        &mut self,
+        debugger: &ProgramDebugger<'_>,
+        arguments: &[&str],
+    ) -> io::Result<()> {
+        let depth = match arguments {
+            [] => Some(0),
+            [depth] => depth.parse::<usize>().ok(),
+            _ => None,
+        };
+        let stack = debugger.call_stack();
+        let Some(frame) = depth
+            .filter(|depth| *depth < stack.len())
+            .map(|depth| &stack[stack.len() - 1 - depth])
+        else {
+            return writeln!(self.output, "no such frame, see `stack`");
+        };
+
+        for (register, value) in frame.registers().iter().enumerate() {
+            if let Some(value) = value {
+                writeln!(self.output, "r{register} = {value}")?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,283 @@
+//! Lowering parsed functions to IR with [`calc_ir::builder`]
+
+use std::collections::HashMap;
+
+use calc_ir::builder::instructions::{Arithmetic, BitWise, BlockJump};
+use calc_ir::builder::{self, Block};
+use calc_ir::program::implementations::{BasicProgram, BlockID};
+use calc_ir::Register;
+
+use crate::parser::{BinaryOperator, Condition, Expression, Function, Position};
+
+/// A lowered program, along with the blocks of each function so that they can be referred to by number
+pub struct Lowered {
+    pub program: BasicProgram,
+    /// the blocks of each function in the order they were created, the first block is the function's entry
+    pub blocks: HashMap<String, Vec<BlockID>>,
+}
+
+impl Lowered {
+    /// The number of `block` within `function`
+    pub fn block_number(&self, function: &str, block: BlockID) -> Option<usize> {
+        self.blocks
+            .get(function)?
+            .iter()
+            .position(|id| *id == block)
+    }
+}
+
+/// Why a program couldn't be lowered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LowerError {
+    DuplicateFunction {
+        name: String,
+        position: Position,
+    },
+    DuplicateParameter {
+        function: String,
+        name: String,
+    },
+    UnknownVariable {
+        function: String,
+        name: String,
+    },
+    UnknownFunction {
+        function: String,
+        name: String,
+    },
+    ArgumentMismatch {
+        function: String,
+        callee: String,
+        expected: usize,
+        provided: usize,
+    },
+}
+
+impl std::fmt::Display for LowerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LowerError::DuplicateFunction { name, position } => {
+                write!(f, "{position}: {name} is already defined")
+            }
+            LowerError::DuplicateParameter { function, name } => {
+                write!(f, "in {function}: the parameter {name} is declared twice")
+            }
+            LowerError::UnknownVariable { function, name } => {
+                write!(f, "in {function}: {name} isn't a parameter")
+            }
+            LowerError::UnknownFunction { function, name } => {
+                write!(f, "in {function}: call to unknown function {name}")
+            }
+            LowerError::ArgumentMismatch {
+                function,
+                callee,
+                expected,
+                provided,
+            } => write!(
+                f,
+                "in {function}: {callee} takes {expected} arguments, but was called with {provided}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LowerError {}
+
+/// Lower every function in `functions` into a single program
+///
+/// # Errors
+/// If a function refers to something that doesn't exist, see [`LowerError`]
+pub fn lower(functions: &[Function]) -> Result<Lowered, LowerError> {
+    let mut arities = HashMap::new();
+    for function in functions {
+        if arities
+            .insert(function.name.as_str(), function.parameters.len())
+            .is_some()
+        {
+            return Err(LowerError::DuplicateFunction {
+                name: function.name.clone(),
+                position: function.position,
+            });
+        }
+    }
+
+    let mut builder = builder::Program::new();
+    let mut blocks = HashMap::new();
+    for function in functions {
+        let mut context = Context {
+            function: &function.name,
+            arities: &arities,
+            variables: HashMap::new(),
+            blocks: Vec::new(),
+        };
+
+        let mut function_builder = builder.make_fn(function.name.clone());
+        let entry_id = function_builder.reserve_block();
+        context.blocks.push(entry_id);
+        let mut entry = function_builder.build_reserved_block(entry_id);
+
+        let arguments = entry.add_load_args(function.parameters.len());
+        for (name, register) in function.parameters.iter().zip(arguments) {
+            if context.variables.insert(name.as_str(), register).is_some() {
+                return Err(LowerError::DuplicateParameter {
+                    function: function.name.clone(),
+                    name: name.clone(),
+                });
+            }
+        }
+
+        let (mut block, result) = context.expression(entry, &function.body)?;
+        block.add_ret(result);
+        let (_, function_builder) = block.finalize();
+        function_builder.finalize(entry_id);
+
+        blocks.insert(function.name.clone(), context.blocks);
+    }
+
+    Ok(Lowered {
+        program: builder.finalize(),
+        blocks,
+    })
+}
+
+/// What's in scope while lowering a function
+struct Context<'f> {
+    function: &'f str,
+    arities: &'f HashMap<&'f str, usize>,
+    variables: HashMap<&'f str, Register>,
+    /// every block of the function, in the order they were reserved
+    blocks: Vec<BlockID>,
+}
+
+impl<'f> Context<'f> {
+    fn reserve_block(&mut self, block: &mut Block<'_, '_>) -> BlockID {
+        let id = block.reserve_block();
+        self.blocks.push(id);
+        id
+    }
+
+    /// Lower `expression` into `block`, returning the block that lowering ended in and the register holding the result.
+    ///
+    /// The returned block is a different one from `block` if the expression needed control flow
+    fn expression<'b>(
+        &mut self,
+        mut block: Block<'b, 'b>,
+        expression: &Expression,
+    ) -> Result<(Block<'b, 'b>, Register), LowerError> {
+        let result = match expression {
+            Expression::Number(number) => block.add_immediate(*number),
+            Expression::Variable(name) => {
+                *self
+                    .variables
+                    .get(name.as_str())
+                    .ok_or_else(|| LowerError::UnknownVariable {
+                        function: self.function.to_string(),
+                        name: name.clone(),
+                    })?
+            }
+            Expression::Call(name, arguments) => {
+                let expected = *self.arities.get(name.as_str()).ok_or_else(|| {
+                    LowerError::UnknownFunction {
+                        function: self.function.to_string(),
+                        name: name.clone(),
+                    }
+                })?;
+                if expected != arguments.len() {
+                    return Err(LowerError::ArgumentMismatch {
+                        function: self.function.to_string(),
+                        callee: name.clone(),
+                        expected,
+                        provided: arguments.len(),
+                    });
+                }
+
+                let mut registers = Vec::new();
+                for argument in arguments {
+                    let register;
+                    (block, register) = self.expression(block, argument)?;
+                    registers.push(register);
+                }
+                block.add_fn_call(name.clone(), registers)
+            }
+            Expression::Negate(operand) => {
+                let register;
+                (block, register) = self.expression(block, operand)?;
+                let zero = block.add_immediate(0);
+                block.add_arithmetic(Arithmetic::Subtract, zero, register)
+            }
+            Expression::Binary(operator, lhs_expression, rhs_expression) => {
+                let (lhs, rhs);
+                (block, lhs) = self.expression(block, lhs_expression)?;
+                (block, rhs) = self.expression(block, rhs_expression)?;
+                match operator {
+                    BinaryOperator::Add => block.add_arithmetic(Arithmetic::Add, lhs, rhs),
+                    BinaryOperator::Subtract => {
+                        block.add_arithmetic(Arithmetic::Subtract, lhs, rhs)
+                    }
+                    BinaryOperator::Multiply => {
+                        block.add_arithmetic(Arithmetic::Multiply, lhs, rhs)
+                    }
+                    BinaryOperator::Divide => block.add_arithmetic(Arithmetic::Divide, lhs, rhs),
+                    BinaryOperator::Modulo => block.add_arithmetic(Arithmetic::Mod, lhs, rhs),
+                    BinaryOperator::Or => block.add_bitwise(BitWise::Or, lhs, rhs),
+                    // the IR calls exclusive or "not or"
+                    BinaryOperator::Xor => block.add_bitwise(BitWise::NotOr, lhs, rhs),
+                    BinaryOperator::And => block.add_bitwise(BitWise::And, lhs, rhs),
+                    BinaryOperator::ShiftLeft => block.add_bitwise(BitWise::ShiftLeft, lhs, rhs),
+                    BinaryOperator::ShiftRight => block.add_bitwise(BitWise::ShiftRight, lhs, rhs),
+                }
+            }
+            Expression::If {
+                condition,
+                then,
+                otherwise,
+            } => return self.if_expression(block, condition, then, otherwise),
+        };
+        Ok((block, result))
+    }
+
+    /// Lower an if expression into a block for each branch, which both jump to a block that takes the result as a parameter
+    fn if_expression<'b>(
+        &mut self,
+        mut block: Block<'b, 'b>,
+        condition: &Condition,
+        then: &Expression,
+        otherwise: &Expression,
+    ) -> Result<(Block<'b, 'b>, Register), LowerError> {
+        let jump = match condition {
+            Condition::Equal(lhs, rhs) | Condition::NotEqual(lhs, rhs) => {
+                let (lhs_register, rhs_register);
+                (block, lhs_register) = self.expression(block, lhs)?;
+                (block, rhs_register) = self.expression(block, rhs)?;
+                if matches!(condition, Condition::Equal(..)) {
+                    BlockJump::Equal(lhs_register, rhs_register)
+                } else {
+                    BlockJump::NotEqual(lhs_register, rhs_register)
+                }
+            }
+            Condition::NonZero(check) => {
+                let check_register;
+                (block, check_register) = self.expression(block, check)?;
+                BlockJump::NoneZero(check_register)
+            }
+        };
+
+        let then_id = self.reserve_block(&mut block);
+        let otherwise_id = self.reserve_block(&mut block);
+        let join_id = self.reserve_block(&mut block);
+        block.add_cond_jump(jump, then_id);
+        block.add_cond_jump(BlockJump::Unconditional, otherwise_id);
+        let (_, mut function) = block.finalize();
+
+        for (id, branch) in [(then_id, then), (otherwise_id, otherwise)] {
+            let (mut branch_block, result) =
+                self.expression(function.build_reserved_block(id), branch)?;
+            branch_block.add_cond_jump_with_args(BlockJump::Unconditional, join_id, vec![result]);
+            (_, function) = branch_block.finalize();
+        }
+
+        let mut join = function.build_reserved_block(join_id);
+        let result = join.add_block_params(1)[0];
+        Ok((join, result))
+    }
+}
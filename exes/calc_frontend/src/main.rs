@@ -1,3 +1,135 @@
-fn main() {
-    println!("Hello, world!");
+mod debug;
+mod lower;
+mod parser;
+
+#[cfg(test)]
+mod test;
+
+use std::process::ExitCode;
+
+use calc_interpreter::Limits;
+use calc_ir::program::implementations::BasicProgram;
+use calc_ir::{Number, Program as _};
+
+const USAGE: &str = "\
+usage:
+  calc debug <file> [function [arguments...]]    debug a function, main by default
+  calc compile <file> <output>                   write the program to a .zir file
+  calc ir <file> [--dot | --calls]               print the IR of a program, or draw its control flow or calls as DOT
+  calc run <file.zir> [function [arguments...]]  run a function of a compiled program, main by default";
+
+/// The budget of a debugging session, so that continuing into a function that never returns stops instead of hanging
+const DEBUG_LIMITS: Limits = Limits {
+    fuel: Some(100_000_000),
+    max_call_depth: Some(100_000),
+    deadline: None,
+};
+
+fn main() -> ExitCode {
+    let arguments: Vec<String> = std::env::args().skip(1).collect();
+    let arguments: Vec<&str> = arguments.iter().map(String::as_str).collect();
+
+    let result = match arguments.as_slice() {
+        ["debug", file, rest @ ..] => debug(file, rest),
+        ["compile", file, output] => compile(file, output),
+        ["run", file, rest @ ..] => run(file, rest),
+        ["ir", file] => ir(file, None),
+        ["ir", file, view @ ("--dot" | "--calls")] => ir(file, Some(view)),
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Read, parse and lower the program in `file`
+fn load(file: &str) -> Result<lower::Lowered, String> {
+    let source =
+        std::fs::read_to_string(file).map_err(|error| format!("couldn't read {file}: {error}"))?;
+    let functions = parser::parse(&source).map_err(|error| format!("{file}:{error}"))?;
+    lower::lower(&functions).map_err(|error| format!("{file}: {error}"))
+}
+
+/// Split `arguments` into the function to run, main by default, and the numbers to run it with
+fn call<'a>(arguments: &[&'a str]) -> Result<(&'a str, Vec<Number>), String> {
+    let (function, arguments) = arguments.split_first().unwrap_or((&"main", &[]));
+    let arguments = arguments
+        .iter()
+        .map(|argument| {
+            argument
+                .parse::<Number>()
+                .map_err(|_| format!("{argument} isn't a number"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((function, arguments))
+}
+
+fn debug(file: &str, arguments: &[&str]) -> Result<(), String> {
+    let lowered = load(file)?;
+    let (function, arguments) = call(arguments)?;
+
+    debug::debug(
+        &lowered,
+        function,
+        &arguments,
+        DEBUG_LIMITS,
+        std::io::stdin().lock(),
+        &mut std::io::stdout(),
+    )
+    .map_err(|error| error.to_string())
+}
+
+fn compile(file: &str, output: &str) -> Result<(), String> {
+    let lowered = load(file)?;
+    std::fs::write(output, lowered.program.to_zir())
+        .map_err(|error| format!("couldn't write {output}: {error}"))
+}
+
+fn run(file: &str, arguments: &[&str]) -> Result<(), String> {
+    let bytes = std::fs::read(file).map_err(|error| format!("couldn't read {file}: {error}"))?;
+    let program = BasicProgram::from_zir(&bytes).map_err(|error| format!("{file}: {error}"))?;
+    let (function, arguments) = call(arguments)?;
+    let result = calc_interpreter::interpret_function(&function.to_string(), &program, &arguments)
+        .map_err(|error| error.to_string())?;
+    println!("{result}");
+    Ok(())
+}
+
+fn ir(file: &str, view: Option<&str>) -> Result<(), String> {
+    let program = load(file)?.program;
+    match view {
+        Some("--dot") => print!("{}", calc_optimizer::dot::program_control_flow(&program)),
+        Some(_) => print!("{}", calc_optimizer::dot::program_call_graph(&program)),
+        None => {
+            let mut functions: Vec<String> = program
+                .get_all_functions()
+                .into_iter()
+                .map(|(function, _)| function.clone())
+                .collect();
+            functions.sort();
+            let graph = calc_optimizer::Graph::from_program(&program, functions.clone());
+            for function in functions
+                .iter()
+                .filter_map(|function| graph.function(function).map(|graph| (function, graph)))
+            {
+                let (name, function) = function;
+                println!("{name}:");
+                for (index, block) in function.blocks.iter().enumerate() {
+                    println!("  block {index}:");
+                    for instruction in block {
+                        println!("    {instruction:?}");
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
 }
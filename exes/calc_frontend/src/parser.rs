@@ -0,0 +1,462 @@
+//! Parsing the calculator language
+//!
+//! A program is a list of functions, each of which is a single expression:
+//! ```text
+//! # comments run to the end of the line
+//! fn fact(n) = if n == 0 then 1 else n * fact(n - 1)
+//! fn main() = fact(10)
+//! ```
+//! The full grammar, and what a program means, is written down in `grammar.md` at the root of this crate.
+//! In short, expressions are integer literals, parameters, calls, `-` negation, parentheses, `if <condition> then <expr> else <expr>`,
+//! and the binary operators, from lowest to highest precedence: `|`, `^`, `&`, `<<` `>>`, `+` `-`, `*` `/` `%`.
+
+use calc_ir::Number;
+
+/// A function definition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub name: String,
+    pub parameters: Vec<String>,
+    pub body: Expression,
+    /// where the definition starts
+    pub position: Position,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expression {
+    Number(Number),
+    Variable(String),
+    Call(String, Vec<Expression>),
+    Negate(Box<Expression>),
+    Binary(BinaryOperator, Box<Expression>, Box<Expression>),
+    If {
+        condition: Box<Condition>,
+        then: Box<Expression>,
+        otherwise: Box<Expression>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    Equal(Expression, Expression),
+    NotEqual(Expression, Expression),
+    NonZero(Expression),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Or,
+    Xor,
+    And,
+    ShiftLeft,
+    ShiftRight,
+}
+
+/// How deeply expressions can be nested in each other, through parentheses, negation, calls, `if`s or chains of binary operators,
+/// where every operator nests the chain before it one deeper.
+///
+/// Parsing and lowering are both recursive, so this keeps a deeply nested program from overflowing the stack
+pub const MAX_NESTING: usize = 256;
+
+/// A line and column in the source, both starting at 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Why the source couldn't be parsed, and where
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub position: Position,
+    pub message: String,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.position, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(Number),
+    Identifier(String),
+    Fn,
+    If,
+    Then,
+    Else,
+    OpenParen,
+    CloseParen,
+    Comma,
+    Assign,
+    Equal,
+    NotEqual,
+    Operator(BinaryOperator),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(number) => write!(f, "{number}"),
+            Token::Identifier(name) => write!(f, "{name}"),
+            Token::Fn => write!(f, "fn"),
+            Token::If => write!(f, "if"),
+            Token::Then => write!(f, "then"),
+            Token::Else => write!(f, "else"),
+            Token::OpenParen => write!(f, "("),
+            Token::CloseParen => write!(f, ")"),
+            Token::Comma => write!(f, ","),
+            Token::Assign => write!(f, "="),
+            Token::Equal => write!(f, "=="),
+            Token::NotEqual => write!(f, "!="),
+            Token::Operator(operator) => write!(f, "{}", operator.symbol()),
+        }
+    }
+}
+
+impl BinaryOperator {
+    fn symbol(self) -> &'static str {
+        match self {
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Modulo => "%",
+            BinaryOperator::Or => "|",
+            BinaryOperator::Xor => "^",
+            BinaryOperator::And => "&",
+            BinaryOperator::ShiftLeft => "<<",
+            BinaryOperator::ShiftRight => ">>",
+        }
+    }
+
+    /// How tightly the operator binds, higher binds tighter
+    fn precedence(self) -> u8 {
+        match self {
+            BinaryOperator::Or => 1,
+            BinaryOperator::Xor => 2,
+            BinaryOperator::And => 3,
+            BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => 4,
+            BinaryOperator::Add | BinaryOperator::Subtract => 5,
+            BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Modulo => 6,
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, Position)>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut characters = source.chars().peekable();
+    let mut position = Position { line: 1, column: 1 };
+
+    while let Some(&character) = characters.peek() {
+        let start = position;
+        let mut next = || {
+            let character = characters.next();
+            if character == Some('\n') {
+                position.line += 1;
+                position.column = 1;
+            } else {
+                position.column += 1;
+            }
+            character
+        };
+
+        let token = match character {
+            '\n' | ' ' | '\t' | '\r' => {
+                next();
+                continue;
+            }
+            '#' => {
+                while !matches!(next(), Some('\n') | None) {}
+                continue;
+            }
+            '0'..='9' | 'a'..='z' | 'A'..='Z' | '_' => {
+                let mut word = String::new();
+                while let Some(&character) = characters.peek() {
+                    if !(character.is_ascii_alphanumeric() || character == '_') {
+                        break;
+                    }
+                    word.push(character);
+                    characters.next();
+                    position.column += 1;
+                }
+                if word.starts_with(|c: char| c.is_ascii_digit()) {
+                    let number = word.parse().map_err(|_| ParseError {
+                        position: start,
+                        message: format!("invalid number {word}"),
+                    })?;
+                    Token::Number(number)
+                } else {
+                    match word.as_str() {
+                        "fn" => Token::Fn,
+                        "if" => Token::If,
+                        "then" => Token::Then,
+                        "else" => Token::Else,
+                        _ => Token::Identifier(word),
+                    }
+                }
+            }
+            _ => {
+                next();
+                let mut followed_by = |expected| {
+                    let matched = characters.peek() == Some(&expected);
+                    if matched {
+                        characters.next();
+                        position.column += 1;
+                    }
+                    matched
+                };
+                match character {
+                    '(' => Token::OpenParen,
+                    ')' => Token::CloseParen,
+                    ',' => Token::Comma,
+                    '=' if followed_by('=') => Token::Equal,
+                    '=' => Token::Assign,
+                    '!' if followed_by('=') => Token::NotEqual,
+                    '<' if followed_by('<') => Token::Operator(BinaryOperator::ShiftLeft),
+                    '>' if followed_by('>') => Token::Operator(BinaryOperator::ShiftRight),
+                    '+' => Token::Operator(BinaryOperator::Add),
+                    '-' => Token::Operator(BinaryOperator::Subtract),
+                    '*' => Token::Operator(BinaryOperator::Multiply),
+                    '/' => Token::Operator(BinaryOperator::Divide),
+                    '%' => Token::Operator(BinaryOperator::Modulo),
+                    '|' => Token::Operator(BinaryOperator::Or),
+                    '^' => Token::Operator(BinaryOperator::Xor),
+                    '&' => Token::Operator(BinaryOperator::And),
+                    _ => {
+                        return Err(ParseError {
+                            position: start,
+                            message: format!("unexpected character {character:?}"),
+                        })
+                    }
+                }
+            }
+        };
+        tokens.push((token, start));
+    }
+
+    Ok(tokens)
+}
+
+/// Parse a whole program
+///
+/// # Errors
+/// If `source` isn't a valid program, see the module documentation for what a program looks like
+pub fn parse(source: &str) -> Result<Vec<Function>, ParseError> {
+    let tokens = tokenize(source)?;
+    let end = tokens
+        .last()
+        .map_or(Position { line: 1, column: 1 }, |(_, position)| *position);
+    let mut parser = Parser {
+        tokens,
+        next: 0,
+        end,
+        depth: 0,
+    };
+
+    let mut functions = Vec::new();
+    while parser.peek().is_some() {
+        functions.push(parser.function()?);
+    }
+    Ok(functions)
+}
+
+struct Parser {
+    tokens: Vec<(Token, Position)>,
+    /// the index of the next token
+    next: usize,
+    /// the position reported for errors at the end of the source
+    end: Position,
+    /// how many expressions the one being parsed is nested in
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(token, _)| token)
+    }
+
+    fn position(&self) -> Position {
+        self.tokens
+            .get(self.next)
+            .map_or(self.end, |(_, position)| *position)
+    }
+
+    fn error<T>(&self, expected: &str) -> Result<T, ParseError> {
+        let found = self
+            .peek()
+            .map_or("the end of the file".to_string(), |token| {
+                format!("`{token}`")
+            });
+        Err(ParseError {
+            position: self.position(),
+            message: format!("expected {expected}, found {found}"),
+        })
+    }
+
+    /// Consume the next token if it's `token`
+    fn eat(&mut self, token: &Token) -> bool {
+        let matched = self.peek() == Some(token);
+        if matched {
+            self.next += 1;
+        }
+        matched
+    }
+
+    fn expect(&mut self, token: &Token) -> Result<(), ParseError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            self.error(&format!("`{token}`"))
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, ParseError> {
+        if let Some(Token::Identifier(name)) = self.peek() {
+            let name = name.clone();
+            self.next += 1;
+            Ok(name)
+        } else {
+            self.error("a name")
+        }
+    }
+
+    fn function(&mut self) -> Result<Function, ParseError> {
+        let position = self.position();
+        self.expect(&Token::Fn)?;
+        let name = self.identifier()?;
+        self.expect(&Token::OpenParen)?;
+        let mut parameters = Vec::new();
+        if !self.eat(&Token::CloseParen) {
+            loop {
+                parameters.push(self.identifier()?);
+                if self.eat(&Token::CloseParen) {
+                    break;
+                }
+                self.expect(&Token::Comma)?;
+            }
+        }
+        self.expect(&Token::Assign)?;
+        let body = self.expression()?;
+        Ok(Function {
+            name,
+            parameters,
+            body,
+            position,
+        })
+    }
+
+    fn expression(&mut self) -> Result<Expression, ParseError> {
+        self.binary(0)
+    }
+
+    /// Parse a chain of binary operators that bind tighter than `precedence`
+    fn binary(&mut self, precedence: u8) -> Result<Expression, ParseError> {
+        let depth = self.depth;
+        let mut lhs = self.unary()?;
+        while let Some(Token::Operator(operator)) = self.peek() {
+            let operator = *operator;
+            if operator.precedence() <= precedence {
+                break;
+            }
+            // the chain so far becomes the left hand side of this operator
+            self.nest()?;
+            self.next += 1;
+            let rhs = self.binary(operator.precedence())?;
+            lhs = Expression::Binary(operator, Box::new(lhs), Box::new(rhs));
+        }
+        self.depth = depth;
+        Ok(lhs)
+    }
+
+    /// Go one expression deeper, which is where the nesting is limited to [`MAX_NESTING`]
+    fn nest(&mut self) -> Result<(), ParseError> {
+        if self.depth == MAX_NESTING {
+            return Err(ParseError {
+                position: self.position(),
+                message: format!("expressions can't be nested more than {MAX_NESTING} deep"),
+            });
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Every nested expression other than a chain of binary operators is parsed through here
+    fn unary(&mut self) -> Result<Expression, ParseError> {
+        self.nest()?;
+        let expression = if self.eat(&Token::Operator(BinaryOperator::Subtract)) {
+            Expression::Negate(Box::new(self.unary()?))
+        } else {
+            self.atom()?
+        };
+        self.depth -= 1;
+        Ok(expression)
+    }
+
+    fn atom(&mut self) -> Result<Expression, ParseError> {
+        match self.peek().cloned() {
+            Some(Token::Number(number)) => {
+                self.next += 1;
+                Ok(Expression::Number(number))
+            }
+            Some(Token::Identifier(name)) => {
+                self.next += 1;
+                if !self.eat(&Token::OpenParen) {
+                    return Ok(Expression::Variable(name));
+                }
+                let mut arguments = Vec::new();
+                if !self.eat(&Token::CloseParen) {
+                    loop {
+                        arguments.push(self.expression()?);
+                        if self.eat(&Token::CloseParen) {
+                            break;
+                        }
+                        self.expect(&Token::Comma)?;
+                    }
+                }
+                Ok(Expression::Call(name, arguments))
+            }
+            Some(Token::OpenParen) => {
+                self.next += 1;
+                let expression = self.expression()?;
+                self.expect(&Token::CloseParen)?;
+                Ok(expression)
+            }
+            Some(Token::If) => {
+                self.next += 1;
+                let lhs = self.expression()?;
+                let condition = if self.eat(&Token::Equal) {
+                    Condition::Equal(lhs, self.expression()?)
+                } else if self.eat(&Token::NotEqual) {
+                    Condition::NotEqual(lhs, self.expression()?)
+                } else {
+                    Condition::NonZero(lhs)
+                };
+                self.expect(&Token::Then)?;
+                let then = self.expression()?;
+                self.expect(&Token::Else)?;
+                let otherwise = self.expression()?;
+                Ok(Expression::If {
+                    condition: Box::new(condition),
+                    then: Box::new(then),
+                    otherwise: Box::new(otherwise),
+                })
+            }
+            _ => self.error("an expression"),
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use calc_interpreter::Limits;
+
+use crate::{lower, parser};
+
+const FACTORIAL: &str = "
+# the classic
+fn fact(n) = if n == 0 then 1 else n * fact(n - 1)
+fn main() = fact(5)
+";
+
+fn run(source: &str, function: &str, arguments: &[isize]) -> isize {
+    let lowered = lower::lower(&parser::parse(source).unwrap()).unwrap();
+    calc_interpreter::interpret_function(&function.to_string(), &lowered.program, arguments)
+        .unwrap()
+}
+
+#[test]
+fn operators_and_precedence() {
+    assert_eq!(run("fn main() = 1 + 2 * 3 - 4 / 2", "main", &[]), 5);
+    assert_eq!(run("fn main() = (1 + 2) * 3 % 4", "main", &[]), 1);
+    assert_eq!(run("fn main() = 1 << 4 | 3 & 6 ^ 1", "main", &[]), 19);
+    assert_eq!(run("fn main() = -3 - -4", "main", &[]), 1);
+    assert_eq!(
+        run("fn f(a, b) = a - b fn main() = f(10, 3)", "main", &[]),
+        7
+    );
+}
+
+#[test]
+fn conditions() {
+    let source = "
+        fn equal(a, b) = if a == b then 1 else 0
+        fn different(a, b) = if a != b then 1 else 0
+        fn truthy(a) = if a then 1 else 0
+        fn sign(a) = if a then if a >> 63 then -1 else 1 else 0
+    ";
+    assert_eq!(run(source, "equal", &[2, 2]), 1);
+    assert_eq!(run(source, "equal", &[2, 3]), 0);
+    assert_eq!(run(source, "different", &[2, 3]), 1);
+    assert_eq!(run(source, "truthy", &[0]), 0);
+    assert_eq!(run(source, "truthy", &[7]), 1);
+    assert_eq!(run(source, "sign", &[-7]), -1);
+    assert_eq!(run(source, "sign", &[0]), 0);
+    assert_eq!(run(source, "sign", &[7]), 1);
+    assert_eq!(run(FACTORIAL, "main", &[]), 120);
+}
+
+#[test]
+fn errors() {
+    let error = parser::parse("fn main() = 1 +\nfn").unwrap_err();
+    assert_eq!(error.to_string(), "2:1: expected an expression, found `fn`");
+    let error = parser::parse("fn main() = 1 $ 2").unwrap_err();
+    assert_eq!(error.to_string(), "1:15: unexpected character '$'");
+
+    // nesting is limited so that parsing and lowering can't overflow the stack
+    let nested = |depth| format!("fn main() = {}1{}", "(".repeat(depth), ")".repeat(depth));
+    assert_eq!(run(&nested(parser::MAX_NESTING - 1), "main", &[]), 1);
+    let error = parser::parse(&nested(parser::MAX_NESTING)).unwrap_err();
+    assert_eq!(
+        error.message,
+        format!(
+            "expressions can't be nested more than {} deep",
+            parser::MAX_NESTING
+        )
+    );
+    let error = parser::parse(&format!("fn main() = {}1", "-".repeat(100_000))).unwrap_err();
+    assert_eq!(error.position.column, 13 + parser::MAX_NESTING);
+    // a long chain of operators nests each one in the next just as deeply, even without parentheses
+    let chained = |terms: usize| format!("fn main() = 1{}", "+1".repeat(terms - 1));
+    assert_eq!(run(&chained(128), "main", &[]), 128);
+    let error = parser::parse(&chained(200_000)).unwrap_err();
+    assert_eq!(
+        error.message,
+        format!(
+            "expressions can't be nested more than {} deep",
+            parser::MAX_NESTING
+        )
+    );
+
+    let lower = |source| lower::lower(&parser::parse(source).unwrap()).err().unwrap();
+    assert_eq!(
+        lower("fn main() = x"),
+        lower::LowerError::UnknownVariable {
+            function: "main".to_string(),
+            name: "x".to_string()
+        }
+    );
+    assert_eq!(
+        lower("fn f(a) = a fn main() = f(1, 2)"),
+        lower::LowerError::ArgumentMismatch {
+            function: "main".to_string(),
+            callee: "f".to_string(),
+            expected: 1,
+            provided: 2
+        }
+    );
+    assert!(matches!(
+        lower("fn main() = g()"),
+        lower::LowerError::UnknownFunction { .. }
+    ));
+    assert!(matches!(
+        lower("fn main() = 1 fn main() = 2"),
+        lower::LowerError::DuplicateFunction { .. }
+    ));
+}
+
+/// drive a whole debugging session through the same code that reads from stdin
+#[test]
+fn debug_session() {
+    let lowered = lower::lower(&parser::parse(FACTORIAL).unwrap()).unwrap();
+    let commands =
+        "b fact\nb fact 1\nbreakpoints\nc\nbt\nr\nd fact\nc\nbt\nr 2\nd fact 1\no\ns\nn\no\nc\nq\n";
+    let mut output = Vec::new();
+    crate::debug::debug(
+        &lowered,
+        "main",
+        &[],
+        Limits::default(),
+        commands.as_bytes(),
+        &mut output,
+    )
+    .unwrap();
+    let output = String::from_utf8(output).unwrap();
+
+    let expected = "\
+type `help` for a list of commands
+main block 0 [0]: LoadArgs([])
+(calc) (calc) (calc) fact
+fact block 1
+(calc) breakpoint fact
+fact block 0 [0]: LoadArgs([Register(0)])
+(calc) #0 fact block 0 [0]
+#1 main block 0 [3]
+(calc) (calc) (calc) breakpoint fact block 1
+fact block 1 [0]: LoadImmediate(1, Register(2))
+(calc) #0 fact block 1 [0]
+#1 fact block 2 [3]
+#2 fact block 2 [3]
+#3 fact block 2 [3]
+#4 fact block 2 [3]
+#5 fact block 2 [3]
+#6 main block 0 [3]
+(calc) r0 = 2
+r1 = 0
+r3 = 1
+r4 = 1
+(calc) (calc) fact block 2 [3]: Multiply { lhs: Register(0), rhs: Register(5), out: Register(6) }
+(calc) fact block 2 [4]: Jump { to: BlockID(3), arguments: [Register(6)] }
+(calc) fact block 3 [0]: LoadBlockArgs([Register(7)])
+(calc) fact block 2 [3]: Multiply { lhs: Register(0), rhs: Register(5), out: Register(6) }
+(calc) finished with 120
+(calc) ";
+    assert_eq!(output, expected);
+}
+
+/// the session runs within its limits, so continuing into a function that never returns reports an error instead of hanging
+#[test]
+fn debug_limits() {
+    let lowered = lower::lower(
+        &parser::parse("fn forever(n) = forever(n + 1) fn main() = forever(0)").unwrap(),
+    )
+    .unwrap();
+    let mut output = Vec::new();
+    crate::debug::debug(
+        &lowered,
+        "main",
+        &[],
+        Limits::default().with_fuel(1000),
+        "c\nq\n".as_bytes(),
+        &mut output,
+    )
+    .unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert_eq!(
+        output,
+        "type `help` for a list of commands\n\
+         main block 0 [0]: LoadArgs([])\n\
+         (calc) error: exceeded the Fuel limit in \"forever\" after 1000 instructions, at a call depth of 251\n\
+         (calc) "
+    );
+}
@@ -0,0 +1,442 @@
+declare void @llvm.trap() noreturn nounwind
+
+define internal i64 @calc_divide(i64 %lhs, i64 %rhs) {
+  %zero = icmp eq i64 %rhs, 0
+  br i1 %zero, label %trap, label %nonzero
+trap:
+  call void @llvm.trap()
+  unreachable
+nonzero:
+  ; the minimum divided by -1 overflows, which sdiv leaves undefined instead of wrapping back to the minimum
+  %minus_one = icmp eq i64 %rhs, -1
+  br i1 %minus_one, label %negate, label %divide
+negate:
+  %negated = sub i64 0, %lhs
+  ret i64 %negated
+divide:
+  %quotient = sdiv i64 %lhs, %rhs
+  ret i64 %quotient
+}
+
+define internal i64 @calc_modulo(i64 %lhs, i64 %rhs) {
+  %zero = icmp eq i64 %rhs, 0
+  br i1 %zero, label %trap, label %nonzero
+trap:
+  call void @llvm.trap()
+  unreachable
+nonzero:
+  %minus_one = icmp eq i64 %rhs, -1
+  br i1 %minus_one, label %none, label %modulo
+none:
+  ret i64 0
+modulo:
+  %remainder = srem i64 %lhs, %rhs
+  ret i64 %remainder
+}
+
+declare i64 @host_add(i64, i64, i64, i64, i64, i64, i64)
+
+define i64 @call_host() {
+start:
+  %r0 = alloca i64
+  store i64 0, i64* %r0
+  %r1 = alloca i64
+  store i64 0, i64* %r1
+  %r2 = alloca i64
+  store i64 0, i64* %r2
+  %r3 = alloca i64
+  store i64 0, i64* %r3
+  %r4 = alloca i64
+  store i64 0, i64* %r4
+  %r5 = alloca i64
+  store i64 0, i64* %r5
+  %r6 = alloca i64
+  store i64 0, i64* %r6
+  %r7 = alloca i64
+  store i64 0, i64* %r7
+  br label %block0
+block0:
+  store i64 1, i64* %r0
+  store i64 2, i64* %r1
+  store i64 3, i64* %r2
+  store i64 4, i64* %r3
+  store i64 5, i64* %r4
+  store i64 6, i64* %r5
+  store i64 7, i64* %r6
+  %v1 = load i64, i64* %r0
+  %v2 = load i64, i64* %r1
+  %v3 = load i64, i64* %r2
+  %v4 = load i64, i64* %r3
+  %v5 = load i64, i64* %r4
+  %v6 = load i64, i64* %r5
+  %v7 = load i64, i64* %r6
+  %v8 = call i64 @host_add(i64 %v1, i64 %v2, i64 %v3, i64 %v4, i64 %v5, i64 %v6, i64 %v7)
+  store i64 %v8, i64* %r7
+  %v9 = load i64, i64* %r7
+  ret i64 %v9
+}
+
+define i64 @call_weighted() {
+start:
+  %r0 = alloca i64
+  store i64 0, i64* %r0
+  %r1 = alloca i64
+  store i64 0, i64* %r1
+  %r2 = alloca i64
+  store i64 0, i64* %r2
+  %r3 = alloca i64
+  store i64 0, i64* %r3
+  %r4 = alloca i64
+  store i64 0, i64* %r4
+  %r5 = alloca i64
+  store i64 0, i64* %r5
+  %r6 = alloca i64
+  store i64 0, i64* %r6
+  %r7 = alloca i64
+  store i64 0, i64* %r7
+  %r8 = alloca i64
+  store i64 0, i64* %r8
+  br label %block0
+block0:
+  store i64 1, i64* %r0
+  store i64 2, i64* %r1
+  store i64 3, i64* %r2
+  store i64 4, i64* %r3
+  store i64 5, i64* %r4
+  store i64 6, i64* %r5
+  store i64 7, i64* %r6
+  store i64 8, i64* %r7
+  %v1 = load i64, i64* %r0
+  %v2 = load i64, i64* %r1
+  %v3 = load i64, i64* %r2
+  %v4 = load i64, i64* %r3
+  %v5 = load i64, i64* %r4
+  %v6 = load i64, i64* %r5
+  %v7 = load i64, i64* %r6
+  %v8 = load i64, i64* %r7
+  %v9 = call i64 @weighted(i64 %v1, i64 %v2, i64 %v3, i64 %v4, i64 %v5, i64 %v6, i64 %v7, i64 %v8)
+  store i64 %v9, i64* %r8
+  %v10 = load i64, i64* %r8
+  ret i64 %v10
+}
+
+define i64 @divide(i64 %a0, i64 %a1) {
+start:
+  %r0 = alloca i64
+  store i64 0, i64* %r0
+  %r1 = alloca i64
+  store i64 0, i64* %r1
+  %r2 = alloca i64
+  store i64 0, i64* %r2
+  br label %block0
+block0:
+  store i64 %a0, i64* %r0
+  store i64 %a1, i64* %r1
+  %v1 = load i64, i64* %r0
+  %v2 = load i64, i64* %r1
+  %v3 = call i64 @calc_divide(i64 %v1, i64 %v2)
+  store i64 %v3, i64* %r2
+  %v4 = load i64, i64* %r2
+  ret i64 %v4
+}
+
+define i64 @fibonacci(i64 %a0) {
+start:
+  %r0 = alloca i64
+  store i64 0, i64* %r0
+  %r1 = alloca i64
+  store i64 0, i64* %r1
+  %r2 = alloca i64
+  store i64 0, i64* %r2
+  %r3 = alloca i64
+  store i64 0, i64* %r3
+  %r4 = alloca i64
+  store i64 0, i64* %r4
+  %r5 = alloca i64
+  store i64 0, i64* %r5
+  %r6 = alloca i64
+  store i64 0, i64* %r6
+  %r7 = alloca i64
+  store i64 0, i64* %r7
+  %r8 = alloca i64
+  store i64 0, i64* %r8
+  br label %block0
+block0:
+  store i64 %a0, i64* %r0
+  store i64 1, i64* %r1
+  store i64 2, i64* %r2
+  %v1 = load i64, i64* %r0
+  %v2 = icmp eq i64 %v1, 0
+  br i1 %v2, label %split1, label %split2
+split1:
+  %v3 = load i64, i64* %r0
+  store i64 %v3, i64* %r8
+  br label %block1
+split2:
+  %v4 = load i64, i64* %r0
+  %v5 = load i64, i64* %r1
+  %v6 = icmp eq i64 %v4, %v5
+  br i1 %v6, label %split3, label %split4
+split3:
+  %v7 = load i64, i64* %r0
+  store i64 %v7, i64* %r8
+  br label %block1
+split4:
+  %v8 = load i64, i64* %r0
+  %v9 = load i64, i64* %r1
+  %v10 = sub i64 %v8, %v9
+  store i64 %v10, i64* %r3
+  %v11 = load i64, i64* %r0
+  %v12 = load i64, i64* %r2
+  %v13 = sub i64 %v11, %v12
+  store i64 %v13, i64* %r4
+  %v14 = load i64, i64* %r3
+  %v15 = call i64 @fibonacci(i64 %v14)
+  store i64 %v15, i64* %r5
+  %v16 = load i64, i64* %r4
+  %v17 = call i64 @fibonacci(i64 %v16)
+  store i64 %v17, i64* %r6
+  %v18 = load i64, i64* %r5
+  %v19 = load i64, i64* %r6
+  %v20 = add i64 %v18, %v19
+  store i64 %v20, i64* %r7
+  %v21 = load i64, i64* %r7
+  ret i64 %v21
+block1:
+  %v22 = load i64, i64* %r8
+  ret i64 %v22
+}
+
+define i64 @shift_right(i64 %a0, i64 %a1) {
+start:
+  %r0 = alloca i64
+  store i64 0, i64* %r0
+  %r1 = alloca i64
+  store i64 0, i64* %r1
+  %r2 = alloca i64
+  store i64 0, i64* %r2
+  br label %block0
+block0:
+  store i64 %a0, i64* %r0
+  store i64 %a1, i64* %r1
+  %v1 = load i64, i64* %r0
+  %v2 = load i64, i64* %r1
+  %v3 = and i64 %v2, 63
+  %v4 = ashr i64 %v1, %v3
+  store i64 %v4, i64* %r2
+  %v5 = load i64, i64* %r2
+  ret i64 %v5
+}
+
+define i64 @swap(i64 %a0, i64 %a1, i64 %a2) {
+start:
+  %r0 = alloca i64
+  store i64 0, i64* %r0
+  %r1 = alloca i64
+  store i64 0, i64* %r1
+  %r2 = alloca i64
+  store i64 0, i64* %r2
+  %r3 = alloca i64
+  store i64 0, i64* %r3
+  %r4 = alloca i64
+  store i64 0, i64* %r4
+  %r5 = alloca i64
+  store i64 0, i64* %r5
+  %r6 = alloca i64
+  store i64 0, i64* %r6
+  %r7 = alloca i64
+  store i64 0, i64* %r7
+  %r8 = alloca i64
+  store i64 0, i64* %r8
+  br label %block0
+block0:
+  store i64 %a0, i64* %r0
+  store i64 %a1, i64* %r1
+  store i64 %a2, i64* %r2
+  store i64 1, i64* %r3
+  %v1 = load i64, i64* %r0
+  %v2 = load i64, i64* %r1
+  %v3 = load i64, i64* %r2
+  store i64 %v1, i64* %r4
+  store i64 %v2, i64* %r5
+  store i64 %v3, i64* %r6
+  br label %block1
+block1:
+  %v4 = load i64, i64* %r6
+  %v5 = icmp eq i64 %v4, 0
+  br i1 %v5, label %split1, label %split2
+split1:
+  %v6 = load i64, i64* %r4
+  store i64 %v6, i64* %r8
+  br label %block2
+split2:
+  %v7 = load i64, i64* %r6
+  %v8 = load i64, i64* %r3
+  %v9 = sub i64 %v7, %v8
+  store i64 %v9, i64* %r7
+  %v10 = load i64, i64* %r5
+  %v11 = load i64, i64* %r4
+  %v12 = load i64, i64* %r7
+  store i64 %v10, i64* %r4
+  store i64 %v11, i64* %r5
+  store i64 %v12, i64* %r6
+  br label %block1
+block2:
+  %v13 = load i64, i64* %r8
+  ret i64 %v13
+}
+
+define i64 @weighted(i64 %a0, i64 %a1, i64 %a2, i64 %a3, i64 %a4, i64 %a5, i64 %a6, i64 %a7) {
+start:
+  %r0 = alloca i64
+  store i64 0, i64* %r0
+  %r1 = alloca i64
+  store i64 0, i64* %r1
+  %r2 = alloca i64
+  store i64 0, i64* %r2
+  %r3 = alloca i64
+  store i64 0, i64* %r3
+  %r4 = alloca i64
+  store i64 0, i64* %r4
+  %r5 = alloca i64
+  store i64 0, i64* %r5
+  %r6 = alloca i64
+  store i64 0, i64* %r6
+  %r7 = alloca i64
+  store i64 0, i64* %r7
+  %r8 = alloca i64
+  store i64 0, i64* %r8
+  %r9 = alloca i64
+  store i64 0, i64* %r9
+  %r10 = alloca i64
+  store i64 0, i64* %r10
+  %r11 = alloca i64
+  store i64 0, i64* %r11
+  %r12 = alloca i64
+  store i64 0, i64* %r12
+  %r13 = alloca i64
+  store i64 0, i64* %r13
+  %r14 = alloca i64
+  store i64 0, i64* %r14
+  %r15 = alloca i64
+  store i64 0, i64* %r15
+  %r16 = alloca i64
+  store i64 0, i64* %r16
+  %r17 = alloca i64
+  store i64 0, i64* %r17
+  %r18 = alloca i64
+  store i64 0, i64* %r18
+  %r19 = alloca i64
+  store i64 0, i64* %r19
+  %r20 = alloca i64
+  store i64 0, i64* %r20
+  %r21 = alloca i64
+  store i64 0, i64* %r21
+  %r22 = alloca i64
+  store i64 0, i64* %r22
+  %r23 = alloca i64
+  store i64 0, i64* %r23
+  %r24 = alloca i64
+  store i64 0, i64* %r24
+  %r25 = alloca i64
+  store i64 0, i64* %r25
+  %r26 = alloca i64
+  store i64 0, i64* %r26
+  %r27 = alloca i64
+  store i64 0, i64* %r27
+  %r28 = alloca i64
+  store i64 0, i64* %r28
+  %r29 = alloca i64
+  store i64 0, i64* %r29
+  %r30 = alloca i64
+  store i64 0, i64* %r30
+  %r31 = alloca i64
+  store i64 0, i64* %r31
+  %r32 = alloca i64
+  store i64 0, i64* %r32
+  br label %block0
+block0:
+  store i64 %a0, i64* %r0
+  store i64 %a1, i64* %r1
+  store i64 %a2, i64* %r2
+  store i64 %a3, i64* %r3
+  store i64 %a4, i64* %r4
+  store i64 %a5, i64* %r5
+  store i64 %a6, i64* %r6
+  store i64 %a7, i64* %r7
+  store i64 0, i64* %r8
+  store i64 1, i64* %r9
+  %v1 = load i64, i64* %r0
+  %v2 = load i64, i64* %r9
+  %v3 = mul i64 %v1, %v2
+  store i64 %v3, i64* %r10
+  %v4 = load i64, i64* %r8
+  %v5 = load i64, i64* %r10
+  %v6 = add i64 %v4, %v5
+  store i64 %v6, i64* %r11
+  store i64 2, i64* %r12
+  %v7 = load i64, i64* %r1
+  %v8 = load i64, i64* %r12
+  %v9 = mul i64 %v7, %v8
+  store i64 %v9, i64* %r13
+  %v10 = load i64, i64* %r11
+  %v11 = load i64, i64* %r13
+  %v12 = add i64 %v10, %v11
+  store i64 %v12, i64* %r14
+  store i64 3, i64* %r15
+  %v13 = load i64, i64* %r2
+  %v14 = load i64, i64* %r15
+  %v15 = mul i64 %v13, %v14
+  store i64 %v15, i64* %r16
+  %v16 = load i64, i64* %r14
+  %v17 = load i64, i64* %r16
+  %v18 = add i64 %v16, %v17
+  store i64 %v18, i64* %r17
+  store i64 4, i64* %r18
+  %v19 = load i64, i64* %r3
+  %v20 = load i64, i64* %r18
+  %v21 = mul i64 %v19, %v20
+  store i64 %v21, i64* %r19
+  %v22 = load i64, i64* %r17
+  %v23 = load i64, i64* %r19
+  %v24 = add i64 %v22, %v23
+  store i64 %v24, i64* %r20
+  store i64 5, i64* %r21
+  %v25 = load i64, i64* %r4
+  %v26 = load i64, i64* %r21
+  %v27 = mul i64 %v25, %v26
+  store i64 %v27, i64* %r22
+  %v28 = load i64, i64* %r20
+  %v29 = load i64, i64* %r22
+  %v30 = add i64 %v28, %v29
+  store i64 %v30, i64* %r23
+  store i64 6, i64* %r24
+  %v31 = load i64, i64* %r5
+  %v32 = load i64, i64* %r24
+  %v33 = mul i64 %v31, %v32
+  store i64 %v33, i64* %r25
+  %v34 = load i64, i64* %r23
+  %v35 = load i64, i64* %r25
+  %v36 = add i64 %v34, %v35
+  store i64 %v36, i64* %r26
+  store i64 7, i64* %r27
+  %v37 = load i64, i64* %r6
+  %v38 = load i64, i64* %r27
+  %v39 = mul i64 %v37, %v38
+  store i64 %v39, i64* %r28
+  %v40 = load i64, i64* %r26
+  %v41 = load i64, i64* %r28
+  %v42 = add i64 %v40, %v41
+  store i64 %v42, i64* %r29
+  store i64 8, i64* %r30
+  %v43 = load i64, i64* %r7
+  %v44 = load i64, i64* %r30
+  %v45 = mul i64 %v43, %v44
+  store i64 %v45, i64* %r31
+  %v46 = load i64, i64* %r29
+  %v47 = load i64, i64* %r31
+  %v48 = add i64 %v46, %v47
+  store i64 %v48, i64* %r32
+  %v49 = load i64, i64* %r32
+  ret i64 %v49
+}
@@ -0,0 +1,360 @@
+//! Lowering to a self-contained C file
+//!
+//! Every function becomes a C function `intptr_t name(intptr_t, ...)` taking as many arguments as its arity,
+//! every block a label that jumps `goto`, and every register a local variable.
+//! Arithmetic goes through helper functions that give overflow the same wrapping behaviour as the interpreter, rather than leaving it undefined,
+//! and traps call `abort`.
+//!
+//! The output only needs a C99 compiler, and `intptr_t` must be as wide as [`calc_ir::Number`] is for the program.
+//! Every name that the output declares on its own starts with `calc_`, so functions whose symbols start with it may collide with them.
+//! A function whose symbol is a C keyword, `main`, or a name that the included headers declare, is called `calc_fn_` followed by its symbol instead,
+//! which goes for functions that the program calls without defining as well.
+
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Display, Write};
+use std::hash::Hash;
+
+use calc_ir::arithmetic::Operation;
+use calc_ir::{Instruction, Number, Program, Register};
+use calc_optimizer::{FunctionGraph, Graph};
+
+use crate::{
+    block_params, functions, jump_moves, outside_functions, program_graph, symbol, used_registers,
+    CodegenError, Function,
+};
+
+/// The helpers that every operation goes through, which are written for any width of `intptr_t`
+const PRELUDE: &str = "\
+#include <limits.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define CALC_SHIFT_MASK ((uintptr_t)(sizeof(intptr_t) * CHAR_BIT - 1))
+
+static intptr_t calc_add(intptr_t lhs, intptr_t rhs) { return (intptr_t)((uintptr_t)lhs + (uintptr_t)rhs); }
+static intptr_t calc_subtract(intptr_t lhs, intptr_t rhs) { return (intptr_t)((uintptr_t)lhs - (uintptr_t)rhs); }
+static intptr_t calc_multiply(intptr_t lhs, intptr_t rhs) { return (intptr_t)((uintptr_t)lhs * (uintptr_t)rhs); }
+static intptr_t calc_divide(intptr_t lhs, intptr_t rhs) {
+    if (rhs == 0) abort();
+    /* INTPTR_MIN / -1 overflows, and wraps back to INTPTR_MIN */
+    if (rhs == -1) return calc_subtract(0, lhs);
+    return lhs / rhs;
+}
+static intptr_t calc_modulo(intptr_t lhs, intptr_t rhs) {
+    if (rhs == 0) abort();
+    if (rhs == -1) return 0;
+    return lhs % rhs;
+}
+static intptr_t calc_bit_or(intptr_t lhs, intptr_t rhs) { return lhs | rhs; }
+static intptr_t calc_bit_not_or(intptr_t lhs, intptr_t rhs) { return lhs ^ rhs; }
+static intptr_t calc_bit_and(intptr_t lhs, intptr_t rhs) { return lhs & rhs; }
+static intptr_t calc_shift_left(intptr_t lhs, intptr_t rhs) {
+    return (intptr_t)((uintptr_t)lhs << ((uintptr_t)rhs & CALC_SHIFT_MASK));
+}
+/* shifting a negative number right is implementation defined, so it's done on its complement */
+static intptr_t calc_shift_right(intptr_t lhs, intptr_t rhs) {
+    uintptr_t amount = (uintptr_t)rhs & CALC_SHIFT_MASK;
+    return lhs < 0 ? ~(intptr_t)(~(uintptr_t)lhs >> amount) : (intptr_t)((uintptr_t)lhs >> amount);
+}
+";
+
+/// The C keywords, along with `main` and every function, type and macro declared by the headers that [`PRELUDE`] includes
+/// that isn't caught by [`c_symbol`]'s rules, which a function can't be named in C.
+///
+/// Keywords that start with an underscore are left out, since a symbol only starts with one when it's followed by a digit
+const RESERVED: &[&str] = &[
+    // keywords
+    "auto", "break", "case", "char", "const", "continue", "default", "do", "double", "else", "enum",
+    "extern", "float", "for", "goto", "if", "inline", "int", "long", "register", "restrict",
+    "return", "short", "signed", "sizeof", "static", "struct", "switch", "typedef", "union",
+    "unsigned", "void", "volatile", "while",
+    // the entry point of the program that the output is linked into, and stdlib.h
+    "main", "abort", "abs", "atexit", "atof", "atoi", "atol", "atoll", "bsearch", "calloc", "div",
+    "exit", "free", "getenv", "labs", "ldiv", "llabs", "lldiv", "malloc", "mblen", "mbstowcs",
+    "mbtowc", "qsort", "rand", "realloc", "srand", "strtod", "strtof", "strtol", "strtold",
+    "strtoll", "strtoul", "strtoull", "system", "wcstombs", "wctomb",
+];
+
+/// The name that the function with `symbol` has in C, see the module documentation.
+///
+/// Besides [`RESERVED`], symbols ending in `_t` are types in the headers, or reserved for them, and symbols without a lowercase letter may be macros
+fn c_symbol(symbol: &str) -> String {
+    if RESERVED.contains(&symbol)
+        || symbol.ends_with("_t")
+        || !symbol.contains(|c: char| c.is_ascii_lowercase())
+    {
+        format!("calc_fn_{symbol}")
+    } else {
+        symbol.to_string()
+    }
+}
+
+/// The helper in [`PRELUDE`] that performs `operation`
+fn helper(operation: Operation) -> &'static str {
+    match operation {
+        Operation::Add => "calc_add",
+        Operation::Subtract => "calc_subtract",
+        Operation::Multiply => "calc_multiply",
+        Operation::Divide => "calc_divide",
+        Operation::Modulo => "calc_modulo",
+        Operation::BitOr => "calc_bit_or",
+        Operation::BitNotOr => "calc_bit_not_or",
+        Operation::BitAnd => "calc_bit_and",
+        Operation::ShiftL => "calc_shift_left",
+        Operation::ShiftR => "calc_shift_right",
+    }
+}
+
+/// A parameter list of `count` unnamed `intptr_t`s
+fn parameter_types(count: usize) -> String {
+    if count == 0 {
+        "void".to_string()
+    } else {
+        vec!["intptr_t"; count].join(", ")
+    }
+}
+
+/// `value` as a C expression
+fn literal(value: Number) -> String {
+    if value == Number::MIN {
+        // the literal for MIN would be the negation of a number that's too large
+        "INTPTR_MIN".to_string()
+    } else {
+        format!("(intptr_t){value}LL")
+    }
+}
+
+fn register(register: Register) -> String {
+    format!("calc_r{}", register.0)
+}
+
+/// Lower every function in `program` to a C file, which only includes the standard library
+///
+/// # Errors
+/// If the program can't be lowered, see [`CodegenError`]
+pub fn emit<
+    BlockPointerT: Eq + Debug + Clone,
+    FunctionPointerT: Eq + Debug + Clone + Hash + Display,
+    ProgramT: Program<BlockPointer = BlockPointerT, FunctionPointer = FunctionPointerT, Number = Number>,
+>(
+    program: &ProgramT,
+) -> Result<String, CodegenError<FunctionPointerT>> {
+    let graph = program_graph(program);
+    let functions = functions(&graph)?;
+
+    let mut source = String::from(PRELUDE);
+    source.push('\n');
+    let outside = outside_functions(&graph, &functions);
+    for (symbol, arity) in &outside {
+        writeln!(
+            source,
+            "extern intptr_t {}({});",
+            c_symbol(symbol),
+            parameter_types(*arity)
+        )
+        .expect("writing to a String can't fail");
+    }
+    for function in &functions {
+        writeln!(
+            source,
+            "intptr_t {}({});",
+            c_symbol(&function.symbol),
+            parameter_types(function.arity)
+        )
+        .expect("writing to a String can't fail");
+    }
+
+    for function in &functions {
+        source.push('\n');
+        FunctionEmitter {
+            out: &mut source,
+            graph: &graph,
+            function,
+            outside: &outside,
+        }
+        .emit();
+    }
+    Ok(source)
+}
+
+/// Emits a single function
+struct FunctionEmitter<'a, 'g, FunctionPointerT: Eq + Debug + Clone + Hash> {
+    out: &'a mut String,
+    graph: &'g Graph<FunctionPointerT>,
+    function: &'a Function<'g, FunctionPointerT>,
+    /// the arity that every function the program doesn't define is declared with
+    outside: &'a BTreeMap<String, usize>,
+}
+
+impl<FunctionPointerT: Eq + Debug + Clone + Hash + Display>
+    FunctionEmitter<'_, '_, FunctionPointerT>
+{
+    fn line(&mut self, line: impl Display) {
+        writeln!(self.out, "    {line}").expect("writing to a String can't fail");
+    }
+
+    fn emit(mut self) {
+        let parameters: Vec<_> = (0..self.function.arity)
+            .map(|argument| format!("intptr_t calc_a{argument}"))
+            .collect();
+        let parameters = if parameters.is_empty() {
+            "void".to_string()
+        } else {
+            parameters.join(", ")
+        };
+        writeln!(
+            self.out,
+            "intptr_t {}({parameters}) {{",
+            c_symbol(&self.function.symbol)
+        )
+        .expect("writing to a String can't fail");
+
+        let registers = used_registers(self.function.graph);
+        // starting every register at 0 keeps reading one before it's written from being undefined
+        for register in registers {
+            let register = self::register(register);
+            self.line(format!("intptr_t {register} = 0;"));
+        }
+        for argument in 0..self.function.arity {
+            self.line(format!("(void)calc_a{argument};"));
+        }
+        // the function was called without the arguments its entry block loads
+        if block_params(
+            self.function.graph,
+            FunctionGraph::<FunctionPointerT>::ENTRY,
+        )
+        .is_some_and(|params| !params.is_empty())
+        {
+            self.line("abort();");
+        }
+
+        // only blocks that are jumped to get a label, an unused one would be warned about
+        let targets: Vec<_> = (0..self.function.graph.blocks.len())
+            .filter(|block| !self.function.graph.predecessors(*block).is_empty())
+            .collect();
+        for (block, instructions) in self.function.graph.blocks.iter().enumerate() {
+            if targets.contains(&block) {
+                writeln!(self.out, "calc_block{block}:;").expect("writing to a String can't fail");
+            }
+            for instruction in instructions {
+                self.instruction(instruction);
+            }
+            if !instructions.last().is_some_and(Instruction::is_terminator) {
+                self.line("abort();");
+            }
+        }
+        self.out.push_str("}\n");
+    }
+
+    fn instruction(&mut self, instruction: &Instruction<usize, FunctionPointerT>) {
+        if let Some((operation, lhs, rhs, out)) = instruction.operation() {
+            let (lhs, rhs, out) = (register(lhs), register(rhs), register(out));
+            self.line(format!("{out} = {}({lhs}, {rhs});", helper(operation)));
+            return;
+        }
+
+        match instruction {
+            Instruction::LoadImmediate(value, out) => {
+                self.line(format!("{} = {};", register(*out), literal(*value)));
+            }
+            Instruction::Call {
+                function_id,
+                arguments,
+                out,
+            } => {
+                let symbol = symbol(function_id);
+                let arity = match self.graph.function(function_id) {
+                    Some(callee) => crate::arity(callee),
+                    None => self.outside[&symbol],
+                };
+                if arguments.len() != arity {
+                    self.line("abort();");
+                    return;
+                }
+                let arguments: Vec<_> = arguments
+                    .iter()
+                    .map(|argument| register(*argument))
+                    .collect();
+                self.line(format!(
+                    "{} = {}({});",
+                    register(*out),
+                    c_symbol(&symbol),
+                    arguments.join(", ")
+                ));
+            }
+            Instruction::Ret(value) => self.line(format!("return {};", register(*value))),
+            Instruction::LoadArgs(registers) => {
+                if registers.len() != self.function.arity {
+                    self.line("abort();");
+                    return;
+                }
+                for (argument, register) in registers.iter().enumerate() {
+                    self.line(format!("{} = calc_a{argument};", self::register(*register)));
+                }
+            }
+            Instruction::LoadBlockArgs(_) => {}
+            Instruction::Jump { to, arguments } => {
+                let jump = self.jump(*to, arguments);
+                self.line(jump);
+            }
+            Instruction::JEqual {
+                lhs,
+                rhs,
+                to,
+                arguments,
+            } => self.conditional_jump(
+                &format!("{} == {}", register(*lhs), register(*rhs)),
+                *to,
+                arguments,
+            ),
+            Instruction::JNotEqual {
+                lhs,
+                rhs,
+                to,
+                arguments,
+            } => self.conditional_jump(
+                &format!("{} != {}", register(*lhs), register(*rhs)),
+                *to,
+                arguments,
+            ),
+            Instruction::JNonZero {
+                check,
+                to,
+                arguments,
+            } => self.conditional_jump(&format!("{} != 0", register(*check)), *to, arguments),
+            Instruction::JZero {
+                check,
+                to,
+                arguments,
+            } => self.conditional_jump(&format!("{} == 0", register(*check)), *to, arguments),
+            Instruction::Invalid => self.line("abort();"),
+            _ => unreachable!("arithmetic instructions are handled above"),
+        }
+    }
+
+    /// The statement that jumps to `to`, moving `arguments` into the registers it loads them into
+    fn jump(&self, to: usize, arguments: &[Register]) -> String {
+        let Some(moves) = jump_moves(self.function.graph, to, arguments) else {
+            return "abort();".to_string();
+        };
+        if moves.is_empty() {
+            return format!("goto calc_block{to};");
+        }
+        let mut jump = String::from("{ ");
+        for (index, (argument, _)) in moves.iter().enumerate() {
+            write!(jump, "intptr_t calc_t{index} = {}; ", register(*argument))
+                .expect("writing to a String can't fail");
+        }
+        for (index, (_, param)) in moves.iter().enumerate() {
+            write!(jump, "{} = calc_t{index}; ", register(*param))
+                .expect("writing to a String can't fail");
+        }
+        write!(jump, "goto calc_block{to}; }}").expect("writing to a String can't fail");
+        jump
+    }
+
+    fn conditional_jump(&mut self, condition: &str, to: usize, arguments: &[Register]) {
+        let jump = self.jump(to, arguments);
+        self.line(format!("if ({condition}) {jump}"));
+    }
+}
@@ -0,0 +1,258 @@
+//! Writing relocatable ELF64 object files for x86-64, without an external assembler
+//!
+//! [`emit`] encodes the same instructions that [`crate::x86_64::emit`] writes out as assembly, so an object file behaves exactly like the
+//! assembly would once assembled.
+//!
+//! Every function of the program gets a global symbol in `.text`, and every call, including those between functions of the program,
+//! is left to the linker as an `R_X86_64_PLT32` relocation against the symbol of the callee. Functions that the program doesn't define
+//! are undefined symbols, so the object can be linked with `cc` just like one built from the assembly.
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+
+use calc_ir::{Number, Program};
+
+use crate::x86_64::instruction::Encoder;
+use crate::x86_64::{lower, Label, LoweredFunction};
+use crate::CodegenError;
+
+/// Lower every function in `program` to a relocatable ELF64 object file for x86-64 following the System V ABI
+///
+/// # Errors
+/// If the program can't be lowered, see [`CodegenError`]
+pub fn emit<
+    BlockPointerT: Eq + Debug + Clone,
+    FunctionPointerT: Eq + Debug + Clone + Hash + Display,
+    ProgramT: Program<BlockPointer = BlockPointerT, FunctionPointer = FunctionPointerT, Number = Number>,
+>(
+    program: &ProgramT,
+) -> Result<Vec<u8>, CodegenError<FunctionPointerT>> {
+    let mut encoder = Encoder::default();
+    let mut symbols = Vec::new();
+    for LoweredFunction {
+        symbol,
+        instructions,
+    } in lower(program)?
+    {
+        let offset = encoder.code.len();
+        for instruction in instructions {
+            encoder.encode(instruction);
+        }
+        symbols.push(Symbol {
+            name: symbol,
+            offset,
+            size: encoder.code.len() - offset,
+        });
+    }
+    let relocations = encoder.link(|label| match label {
+        Label::Function(symbol) | Label::Outside(symbol) => Some(symbol.clone()),
+        _ => None,
+    });
+    Ok(Object::new(encoder.code, &symbols, &relocations).write())
+}
+
+/// `R_X86_64_PLT32`, a 32-bit displacement to a function, through the PLT if it's in another shared object
+const R_X86_64_PLT32: u32 = 4;
+
+/// The global symbol of a function in `.text`
+struct Symbol {
+    name: String,
+    offset: usize,
+    size: usize,
+}
+
+/// The numbers of the sections in the object file
+mod section {
+    pub(super) const TEXT: u16 = 1;
+    pub(super) const RELA_TEXT: u16 = 2;
+    pub(super) const SYMTAB: u16 = 3;
+    pub(super) const STRTAB: u16 = 4;
+    pub(super) const NOTE_GNU_STACK: u16 = 5;
+    pub(super) const SHSTRTAB: u16 = 6;
+    pub(super) const COUNT: u16 = 7;
+}
+
+/// The contents of every section, ready to be laid out
+struct Object {
+    text: Vec<u8>,
+    rela_text: Vec<u8>,
+    symtab: Vec<u8>,
+    strtab: Vec<u8>,
+    shstrtab: Vec<u8>,
+    /// where the name of each section starts in `.shstrtab`
+    section_names: [u32; section::COUNT as usize],
+}
+
+/// Append `name` to a string table, and return where it starts
+fn push_string(table: &mut Vec<u8>, name: &str) -> u32 {
+    let start = u32::try_from(table.len()).expect("string tables are small");
+    table.extend(name.as_bytes());
+    table.push(0);
+    start
+}
+
+impl Object {
+    /// The object for `text`, which defines `symbols`, and has calls to the symbols of `relocations`, by where their 32-bit displacements are
+    fn new(text: Vec<u8>, symbols: &[Symbol], relocations: &[(usize, String)]) -> Self {
+        let mut strtab = vec![0];
+        // the null symbol, every other symbol is global so none of them have to come before the rest as locals would
+        let mut symtab = vec![0; 24];
+        let mut indices = HashMap::new();
+        let mut symbol = |name: &str, section: u16, kind: u8, offset: usize, size: usize| {
+            let index = u32::try_from(symtab.len() / 24).expect("symbol tables are small");
+            indices.insert(name.to_string(), index);
+            symtab.extend(push_string(&mut strtab, name).to_le_bytes());
+            // STB_GLOBAL in the high nibble
+            symtab.push((1 << 4) | kind);
+            // STV_DEFAULT
+            symtab.push(0);
+            symtab.extend(section.to_le_bytes());
+            symtab.extend((offset as u64).to_le_bytes());
+            symtab.extend((size as u64).to_le_bytes());
+        };
+        for defined in symbols {
+            // STT_FUNC
+            symbol(
+                &defined.name,
+                section::TEXT,
+                2,
+                defined.offset,
+                defined.size,
+            );
+        }
+        let mut undefined: Vec<_> = relocations
+            .iter()
+            .map(|(_, name)| name)
+            .filter(|name| !symbols.iter().any(|symbol| symbol.name == **name))
+            .collect();
+        undefined.sort();
+        undefined.dedup();
+        for name in undefined {
+            // STT_NOTYPE in SHN_UNDEF
+            symbol(name, 0, 0, 0, 0);
+        }
+
+        let mut rela_text = Vec::new();
+        for (offset, name) in relocations {
+            rela_text.extend((*offset as u64).to_le_bytes());
+            rela_text.extend(
+                ((u64::from(indices[name]) << 32) | u64::from(R_X86_64_PLT32)).to_le_bytes(),
+            );
+            // the displacement is from the end of the field
+            rela_text.extend((-4_i64).to_le_bytes());
+        }
+
+        let mut shstrtab = vec![0];
+        let mut section_names = [0; section::COUNT as usize];
+        for (section, name) in [
+            (section::TEXT, ".text"),
+            (section::RELA_TEXT, ".rela.text"),
+            (section::SYMTAB, ".symtab"),
+            (section::STRTAB, ".strtab"),
+            (section::NOTE_GNU_STACK, ".note.GNU-stack"),
+            (section::SHSTRTAB, ".shstrtab"),
+        ] {
+            section_names[usize::from(section)] = push_string(&mut shstrtab, name);
+        }
+
+        Self {
+            text,
+            rela_text,
+            symtab,
+            strtab,
+            shstrtab,
+            section_names,
+        }
+    }
+
+    fn write(self) -> Vec<u8> {
+        const HEADER_SIZE: usize = 64;
+        const SECTION_HEADER_SIZE: u16 = 64;
+
+        // the contents of every section, each aligned to 8 bytes, followed by the section headers
+        let mut file = vec![0; HEADER_SIZE];
+        let mut offsets = [0; section::COUNT as usize];
+        for (section, contents) in [
+            (section::TEXT, &self.text),
+            (section::RELA_TEXT, &self.rela_text),
+            (section::SYMTAB, &self.symtab),
+            (section::STRTAB, &self.strtab),
+            (section::SHSTRTAB, &self.shstrtab),
+        ] {
+            file.resize(file.len().next_multiple_of(8), 0);
+            offsets[usize::from(section)] = file.len();
+            file.extend(contents);
+        }
+        offsets[usize::from(section::NOTE_GNU_STACK)] = file.len();
+        file.resize(file.len().next_multiple_of(8), 0);
+        let section_headers = file.len();
+
+        // type, flags, size, link, info, alignment and entry size of every section
+        let headers: [(u32, u64, usize, u16, u32, u64, u64); section::COUNT as usize] = [
+            (0, 0, 0, 0, 0, 0, 0),
+            // SHT_PROGBITS, SHF_ALLOC | SHF_EXECINSTR
+            (1, 0x6, self.text.len(), 0, 0, 16, 0),
+            // SHT_RELA, SHF_INFO_LINK
+            (
+                4,
+                0x40,
+                self.rela_text.len(),
+                section::SYMTAB,
+                u32::from(section::TEXT),
+                8,
+                24,
+            ),
+            // SHT_SYMTAB, with the index of the first global symbol as its info, which is right after the null symbol
+            (2, 0, self.symtab.len(), section::STRTAB, 1, 8, 24),
+            // SHT_STRTAB
+            (3, 0, self.strtab.len(), 0, 0, 1, 0),
+            // SHT_PROGBITS without SHF_EXECINSTR, marking the stack as non-executable
+            (1, 0, 0, 0, 0, 1, 0),
+            (3, 0, self.shstrtab.len(), 0, 0, 1, 0),
+        ];
+        for (section, (kind, flags, size, link, info, alignment, entry_size)) in
+            headers.into_iter().enumerate()
+        {
+            file.extend(self.section_names[section].to_le_bytes());
+            file.extend(kind.to_le_bytes());
+            file.extend(flags.to_le_bytes());
+            // the address, which is only known once the object is linked
+            file.extend(0_u64.to_le_bytes());
+            let offset = if section == 0 { 0 } else { offsets[section] };
+            file.extend((offset as u64).to_le_bytes());
+            file.extend((size as u64).to_le_bytes());
+            file.extend(u32::from(link).to_le_bytes());
+            file.extend(info.to_le_bytes());
+            file.extend(alignment.to_le_bytes());
+            file.extend(entry_size.to_le_bytes());
+        }
+
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        // ELFCLASS64, ELFDATA2LSB, EV_CURRENT and ELFOSABI_NONE, padded to 16 bytes
+        header.extend(b"\x7fELF\x02\x01\x01\x00");
+        header.extend([0; 8]);
+        // ET_REL
+        header.extend(1_u16.to_le_bytes());
+        // EM_X86_64
+        header.extend(62_u16.to_le_bytes());
+        header.extend(1_u32.to_le_bytes());
+        // relocatable files have no entry point or program headers
+        header.extend(0_u64.to_le_bytes());
+        header.extend(0_u64.to_le_bytes());
+        header.extend((section_headers as u64).to_le_bytes());
+        header.extend(0_u32.to_le_bytes());
+        header.extend(
+            u16::try_from(HEADER_SIZE)
+                .expect("the header is 64 bytes")
+                .to_le_bytes(),
+        );
+        header.extend(0_u16.to_le_bytes());
+        header.extend(0_u16.to_le_bytes());
+        header.extend(SECTION_HEADER_SIZE.to_le_bytes());
+        header.extend(section::COUNT.to_le_bytes());
+        header.extend(section::SHSTRTAB.to_le_bytes());
+        file[..HEADER_SIZE].copy_from_slice(&header);
+        file
+    }
+}
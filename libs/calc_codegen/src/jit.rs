@@ -0,0 +1,616 @@
+//! Compiling programs to machine code in memory, and running it in the same process, on Linux x86-64
+//!
+//! [`Jit::compile`] lowers every function of a program to machine code, and [`Jit::function`] returns a [`JitFunction`] that calls one.
+//! Anything that the compiled code can't finish is run by [`calc_interpreter`] instead: functions that can't be compiled at all,
+//! and any call that runs into something the compiled code doesn't handle, such as a division by zero, a call to a function
+//! that the program doesn't define, or recursion deep enough to threaten the native stack.
+//! Programs have no side effects, so when compiled code bails out the interpreter runs the call again from the start,
+//! and returns exactly what it would have if it had run it to begin with, including its errors.
+//!
+//! The compiled code doesn't follow the System V ABI, it's only ever called through a trampoline at the start of the code:
+//! every IR register has a stack slot below `%rbp`, arguments are pushed in order by the caller, which pops them again after the call,
+//! and `%r15` holds the [`Context`] of the call for the whole time it runs.
+
+use std::collections::HashMap;
+use std::ffi::{c_int, c_void};
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use calc_interpreter::{Error, Interpreter, Natives};
+use calc_ir::arithmetic::Operation;
+use calc_ir::{Instruction, Number, Program, Register};
+use calc_optimizer::FunctionGraph;
+
+use crate::x86_64::instruction::{Alu, Condition, Encoder, Gpr, Instruction as Asm, Rm};
+use crate::{arity, block_params, jump_moves, misplaced_block_args, program_graph, registers};
+
+/// How much of the bottom of the native stack compiled code leaves alone, above the guard pages, it bails out rather than going any deeper
+const STACK_RESERVE: u64 = 64 * 1024;
+
+/// The state of a single call into compiled code, which `%r15` points to
+#[repr(C)]
+struct Context {
+    /// `%rbp` of the trampoline, which bailing out returns through
+    frame: u64,
+    /// set when the code bailed out
+    bailed: u64,
+    /// the lowest address `%rsp` may reach, see [`stack_limit`]
+    stack_limit: u64,
+}
+
+/// The trampoline, `extern "C" fn(*mut Context, function: *const u8, arguments: *const Number, count: usize) -> Number`,
+/// which is always at the start of the code
+#[rustfmt::skip]
+const TRAMPOLINE: &[u8] = &[
+    0x55,                         // push %rbp
+    0x48, 0x89, 0xe5,             // mov %rsp, %rbp
+    0x41, 0x57,                   // push %r15
+    0x53,                         // push %rbx
+    0x49, 0x89, 0xff,             // mov %rdi, %r15
+    0x49, 0x89, 0x2f,             // mov %rbp, (%r15)
+    0x48, 0x85, 0xc9,             // test %rcx, %rcx
+    0x74, 0x0b,                   // je call
+    0xff, 0x32,                   // loop: push (%rdx)
+    0x48, 0x83, 0xc2, 0x08,       // add $8, %rdx
+    0x48, 0xff, 0xc9,             // dec %rcx
+    0x75, 0xf5,                   // jne loop
+    0xff, 0xd6,                   // call: call *%rsi
+    0x48, 0x8d, 0x65, 0xf0,       // lea -16(%rbp), %rsp
+    0x5b,                         // pop %rbx
+    0x41, 0x5f,                   // pop %r15
+    0x5d,                         // pop %rbp
+    0xc3,                         // ret
+];
+
+/// Bailing out, which compiled code jumps to from anywhere, returns from the trampoline with [`Context::bailed`] set
+#[rustfmt::skip]
+const BAIL: &[u8] = &[
+    0x49, 0xc7, 0x47, 0x08, 0x01, 0x00, 0x00, 0x00, // movq $1, 8(%r15)
+    0x49, 0x8b, 0x2f,                               // mov (%r15), %rbp
+    0x48, 0x8d, 0x65, 0xf0,                         // lea -16(%rbp), %rsp
+    0x5b,                                           // pop %rbx
+    0x41, 0x5f,                                     // pop %r15
+    0x5d,                                           // pop %rbp
+    0xc3,                                           // ret
+];
+
+/// Something that a jump or call can go to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Target {
+    /// a block of the function at an index
+    Block {
+        function: usize,
+        block: usize,
+    },
+    /// the epilogue of the function at an index
+    Return(usize),
+    /// a label that's only used once, for jumps within the code of an instruction
+    Local {
+        function: usize,
+        label: usize,
+    },
+    Function(usize),
+    Bail,
+}
+
+/// A slot in the frame, at an offset from `%rbp`
+fn stack(displacement: i32) -> Rm {
+    Rm::Memory {
+        base: Gpr::Rbp,
+        displacement,
+    }
+}
+
+/// Emits a single function
+struct FunctionEmitter<'a, FunctionPointerT: Eq + Debug + Clone + Hash> {
+    encoder: &'a mut Encoder<Target>,
+    function: &'a FunctionGraph<FunctionPointerT>,
+    /// the index of the function, which keeps its labels apart from those of other functions
+    index: usize,
+    arity: usize,
+    /// the index of every function that's compiled, along with its arity
+    compiled: &'a HashMap<FunctionPointerT, (usize, usize)>,
+    next_label: usize,
+    slots: HashMap<Register, i32>,
+}
+
+impl<FunctionPointerT: Eq + Debug + Clone + Hash> FunctionEmitter<'_, FunctionPointerT> {
+    fn slot(&self, register: Register) -> Rm {
+        stack(self.slots[&register])
+    }
+
+    fn add(&mut self, instruction: Asm<Target>) {
+        self.encoder.encode(instruction);
+    }
+
+    /// A label that's only used once, for jumps within the code of an instruction
+    fn fresh_label(&mut self) -> Target {
+        self.next_label += 1;
+        Target::Local {
+            function: self.index,
+            label: self.next_label,
+        }
+    }
+
+    fn bail(&mut self) {
+        self.add(Asm::Jump(Target::Bail));
+    }
+
+    /// Emit the function, returning where it starts
+    fn emit(mut self) -> usize {
+        let start = self.encoder.code.len();
+        for instruction in self.function.blocks.iter().flatten() {
+            for register in registers(instruction) {
+                let next = -8 * (i32::try_from(self.slots.len()).expect("frames are small") + 1);
+                self.slots.entry(register).or_insert(next);
+            }
+        }
+        let frame_size =
+            i32::try_from((8 * self.slots.len()).next_multiple_of(16)).expect("frames are small");
+
+        self.add(Asm::Label(Target::Function(self.index)));
+        self.add(Asm::Push(Rm::Register(Gpr::Rbp)));
+        self.add(Asm::Store(Gpr::Rsp, Rm::Register(Gpr::Rbp)));
+        self.add(Asm::AluImmediate(
+            Alu::Subtract,
+            frame_size,
+            Rm::Register(Gpr::Rsp),
+        ));
+        // Context::stack_limit
+        let stack_limit = Rm::Memory {
+            base: Gpr::R15,
+            displacement: 16,
+        };
+        self.add(Asm::Alu(Alu::Compare, stack_limit, Gpr::Rsp));
+        self.add(Asm::JumpIf(Condition::Below, Target::Bail));
+        if block_params(self.function, FunctionGraph::<FunctionPointerT>::ENTRY)
+            .is_some_and(|params| !params.is_empty())
+        {
+            self.bail();
+        }
+
+        for (block, instructions) in self.function.blocks.iter().enumerate() {
+            self.add(Asm::Label(Target::Block {
+                function: self.index,
+                block,
+            }));
+            for instruction in instructions {
+                self.instruction(instruction);
+            }
+            if !instructions.last().is_some_and(Instruction::is_terminator) {
+                self.bail();
+            }
+        }
+
+        self.add(Asm::Label(Target::Return(self.index)));
+        self.add(Asm::Load(Rm::Register(Gpr::Rbp), Gpr::Rsp));
+        self.add(Asm::Pop(Rm::Register(Gpr::Rbp)));
+        self.add(Asm::Return);
+        start
+    }
+
+    fn instruction(&mut self, instruction: &Instruction<usize, FunctionPointerT>) {
+        if let Some((operation, lhs, rhs, out)) = instruction.operation() {
+            self.operation(operation, lhs, rhs, out);
+            return;
+        }
+
+        match instruction {
+            Instruction::LoadImmediate(value, out) => {
+                if let Ok(value) = i32::try_from(*value) {
+                    self.add(Asm::MoveImmediate(value, Rm::Register(Gpr::Rax)));
+                } else {
+                    let value = i64::try_from(*value).expect("numbers are at most 64 bits");
+                    self.add(Asm::MoveAbsolute(value, Gpr::Rax));
+                }
+                self.add(Asm::Store(Gpr::Rax, self.slot(*out)));
+            }
+            Instruction::Call {
+                function_id,
+                arguments,
+                out,
+            } => match self.compiled.get(function_id) {
+                Some((index, arity)) if *arity == arguments.len() => {
+                    for argument in arguments {
+                        self.add(Asm::Push(self.slot(*argument)));
+                    }
+                    self.add(Asm::Call(Target::Function(*index)));
+                    let popped = i32::try_from(8 * arguments.len()).expect("frames are small");
+                    self.add(Asm::AluImmediate(Alu::Add, popped, Rm::Register(Gpr::Rsp)));
+                    self.add(Asm::Store(Gpr::Rax, self.slot(*out)));
+                }
+                _ => self.bail(),
+            },
+            Instruction::Ret(value) => {
+                self.add(Asm::Load(self.slot(*value), Gpr::Rax));
+                self.add(Asm::Jump(Target::Return(self.index)));
+            }
+            Instruction::LoadArgs(registers) => {
+                if registers.len() != self.arity {
+                    self.bail();
+                    return;
+                }
+                for (argument, register) in registers.iter().enumerate() {
+                    // the last argument was pushed last, right above the return address and %rbp
+                    let from = 16
+                        + 8 * i32::try_from(self.arity - 1 - argument).expect("frames are small");
+                    self.add(Asm::Load(stack(from), Gpr::Rax));
+                    self.add(Asm::Store(Gpr::Rax, self.slot(*register)));
+                }
+            }
+            Instruction::LoadBlockArgs(_) => {}
+            Instruction::Jump { to, arguments } => self.jump(*to, arguments),
+            Instruction::JEqual {
+                lhs,
+                rhs,
+                to,
+                arguments,
+            } => self.conditional_jump(*lhs, Some(*rhs), Condition::NotEqual, *to, arguments),
+            Instruction::JNotEqual {
+                lhs,
+                rhs,
+                to,
+                arguments,
+            } => self.conditional_jump(*lhs, Some(*rhs), Condition::Equal, *to, arguments),
+            Instruction::JNonZero {
+                check,
+                to,
+                arguments,
+            } => self.conditional_jump(*check, None, Condition::Equal, *to, arguments),
+            Instruction::JZero {
+                check,
+                to,
+                arguments,
+            } => self.conditional_jump(*check, None, Condition::NotEqual, *to, arguments),
+            Instruction::Invalid => self.bail(),
+            _ => unreachable!("arithmetic instructions are handled above"),
+        }
+    }
+
+    fn operation(&mut self, operation: Operation, lhs: Register, rhs: Register, out: Register) {
+        let (rax, rcx) = (Rm::Register(Gpr::Rax), Rm::Register(Gpr::Rcx));
+        self.add(Asm::Load(self.slot(lhs), Gpr::Rax));
+        self.add(Asm::Load(self.slot(rhs), Gpr::Rcx));
+        match operation {
+            Operation::Add => self.add(Asm::Alu(Alu::Add, rcx, Gpr::Rax)),
+            Operation::Subtract => self.add(Asm::Alu(Alu::Subtract, rcx, Gpr::Rax)),
+            Operation::Multiply => self.add(Asm::Multiply(rcx, Gpr::Rax)),
+            Operation::BitOr => self.add(Asm::Alu(Alu::Or, rcx, Gpr::Rax)),
+            Operation::BitNotOr => self.add(Asm::Alu(Alu::Xor, rcx, Gpr::Rax)),
+            Operation::BitAnd => self.add(Asm::Alu(Alu::And, rcx, Gpr::Rax)),
+            // shifts only use the low 6 bits of %cl, which is the same as wrapping the amount
+            Operation::ShiftL => self.add(Asm::ShiftLeft(rax)),
+            Operation::ShiftR => self.add(Asm::ShiftRight(rax)),
+            Operation::Divide | Operation::Modulo => {
+                let (divide, done) = (self.fresh_label(), self.fresh_label());
+                self.add(Asm::Test(Gpr::Rcx, rcx));
+                self.add(Asm::JumpIf(Condition::Equal, Target::Bail));
+                // idiv faults on MIN / -1, which wraps to MIN, and whose remainder is 0
+                self.add(Asm::AluImmediate(Alu::Compare, -1, rcx));
+                self.add(Asm::JumpIf(Condition::NotEqual, divide.clone()));
+                if operation == Operation::Divide {
+                    self.add(Asm::Negate(rax));
+                } else {
+                    self.add(Asm::Xor32(Gpr::Rax, Gpr::Rax));
+                }
+                self.add(Asm::Jump(done.clone()));
+                self.add(Asm::Label(divide));
+                self.add(Asm::SignExtend);
+                self.add(Asm::Divide(rcx));
+                if operation == Operation::Modulo {
+                    self.add(Asm::Load(Rm::Register(Gpr::Rdx), Gpr::Rax));
+                }
+                self.add(Asm::Label(done));
+            }
+        }
+        self.add(Asm::Store(Gpr::Rax, self.slot(out)));
+    }
+
+    /// Jump to `to`, moving `arguments` into the registers it loads them into
+    fn jump(&mut self, to: usize, arguments: &[Register]) {
+        let Some(moves) = jump_moves(self.function, to, arguments) else {
+            self.bail();
+            return;
+        };
+        // going through the stack reads every argument before any parameter is written
+        for (argument, _) in &moves {
+            self.add(Asm::Push(self.slot(*argument)));
+        }
+        for (_, param) in moves.iter().rev() {
+            self.add(Asm::Pop(self.slot(*param)));
+        }
+        self.add(Asm::Jump(Target::Block {
+            function: self.index,
+            block: to,
+        }));
+    }
+
+    /// Compare `lhs` against `rhs`, or against zero if there isn't one, and jump to `to` unless `skip` holds
+    fn conditional_jump(
+        &mut self,
+        lhs: Register,
+        rhs: Option<Register>,
+        skip: Condition,
+        to: usize,
+        arguments: &[Register],
+    ) {
+        self.add(Asm::Load(self.slot(lhs), Gpr::Rax));
+        match rhs {
+            Some(rhs) => {
+                self.add(Asm::Load(self.slot(rhs), Gpr::Rcx));
+                self.add(Asm::Alu(Alu::Compare, Rm::Register(Gpr::Rcx), Gpr::Rax));
+            }
+            None => self.add(Asm::Test(Gpr::Rax, Rm::Register(Gpr::Rax))),
+        }
+        let skipped = self.fresh_label();
+        self.add(Asm::JumpIf(skip, skipped.clone()));
+        self.jump(to, arguments);
+        self.add(Asm::Label(skipped));
+    }
+}
+
+const PROT_READ: c_int = 1;
+const PROT_WRITE: c_int = 2;
+const PROT_EXEC: c_int = 4;
+const MAP_PRIVATE: c_int = 2;
+const MAP_ANONYMOUS: c_int = 0x20;
+
+extern "C" {
+    fn mmap(
+        address: *mut c_void,
+        length: usize,
+        protection: c_int,
+        flags: c_int,
+        fd: c_int,
+        offset: i64,
+    ) -> *mut c_void;
+    fn mprotect(address: *mut c_void, length: usize, protection: c_int) -> c_int;
+    fn munmap(address: *mut c_void, length: usize) -> c_int;
+    fn pthread_self() -> u64;
+    fn pthread_getattr_np(thread: u64, attributes: *mut PthreadAttributes) -> c_int;
+    fn pthread_attr_getstack(
+        attributes: *const PthreadAttributes,
+        address: *mut *mut c_void,
+        size: *mut usize,
+    ) -> c_int;
+    fn pthread_attr_getguardsize(attributes: *const PthreadAttributes, size: *mut usize) -> c_int;
+    fn pthread_attr_destroy(attributes: *mut PthreadAttributes) -> c_int;
+}
+
+/// `pthread_attr_t`, which is only ever handed to pthreads
+#[repr(C, align(8))]
+struct PthreadAttributes([u8; 56]);
+
+thread_local! {
+    /// the stack limit of every thread is only looked up once, since finding the bounds of the main thread's stack reads `/proc`
+    static STACK_LIMIT: Option<u64> = stack_limit();
+}
+
+/// The lowest address that compiled code running on this thread may move `%rsp` to, which is [`STACK_RESERVE`] above the guard pages at
+/// the bottom of the thread's stack, or None if the bounds of the stack can't be found
+fn stack_limit() -> Option<u64> {
+    let mut attributes = PthreadAttributes([0; 56]);
+    let mut address = std::ptr::null_mut();
+    let (mut size, mut guard) = (0, 0);
+    // SAFETY: the attributes are initialized by `pthread_getattr_np` before they're read, and destroyed once they have been
+    unsafe {
+        if pthread_getattr_np(pthread_self(), &raw mut attributes) != 0 {
+            return None;
+        }
+        let found = pthread_attr_getstack(&raw const attributes, &raw mut address, &raw mut size)
+            == 0
+            && pthread_attr_getguardsize(&raw const attributes, &raw mut guard) == 0;
+        pthread_attr_destroy(&raw mut attributes);
+        found.then(|| address as u64 + guard as u64 + STACK_RESERVE)
+    }
+}
+
+/// Pages of memory that hold machine code, which can be executed but not written to
+struct ExecutableMemory {
+    pointer: *mut c_void,
+    length: usize,
+}
+
+impl ExecutableMemory {
+    fn new(code: &[u8]) -> std::io::Result<Self> {
+        let length = code.len().next_multiple_of(4096);
+        // SAFETY: mapping fresh anonymous memory doesn't touch any memory that already exists
+        let pointer = unsafe {
+            mmap(
+                std::ptr::null_mut(),
+                length,
+                PROT_READ | PROT_WRITE,
+                MAP_PRIVATE | MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        // MAP_FAILED
+        if pointer as isize == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let memory = Self { pointer, length };
+        // SAFETY: the mapping is at least as long as the code, and nothing else refers to it yet
+        unsafe {
+            std::ptr::copy_nonoverlapping(code.as_ptr(), pointer.cast(), code.len());
+            if mprotect(pointer, length, PROT_READ | PROT_EXEC) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(memory)
+    }
+}
+
+impl Drop for ExecutableMemory {
+    fn drop(&mut self) {
+        // SAFETY: the memory was mapped by `new`, and nothing can call into it once it's dropped
+        unsafe {
+            munmap(self.pointer, self.length);
+        }
+    }
+}
+
+/// A program compiled to machine code, see the module documentation
+pub struct Jit<'p, ProgramT: Program> {
+    program: &'p ProgramT,
+    natives: Option<&'p Natives<ProgramT::FunctionPointer, Number>>,
+    memory: ExecutableMemory,
+    /// where every compiled function starts in `memory`, along with its arity
+    functions: HashMap<ProgramT::FunctionPointer, (usize, usize)>,
+}
+
+impl<
+        'p,
+        BlockPointerT: Eq + Debug + Clone,
+        FunctionPointerT: Eq + Debug + Clone + Hash,
+        ProgramT: Program<BlockPointer = BlockPointerT, FunctionPointer = FunctionPointerT, Number = Number>,
+    > Jit<'p, ProgramT>
+{
+    /// Compile every function in `program` that can be compiled
+    ///
+    /// # Errors
+    /// If the memory for the code couldn't be mapped
+    pub fn compile(program: &'p ProgramT) -> std::io::Result<Self> {
+        let graph = program_graph(program);
+
+        let supported: Vec<_> = graph
+            .functions()
+            .filter(|(_, function)| misplaced_block_args(function).is_none())
+            .collect();
+        let indices: HashMap<_, _> = supported
+            .iter()
+            .enumerate()
+            .map(|(index, (pointer, function))| ((*pointer).clone(), (index, arity(function))))
+            .collect();
+
+        let mut encoder = Encoder::default();
+        encoder.bytes(TRAMPOLINE);
+        encoder.encode(Asm::Label(Target::Bail));
+        encoder.bytes(BAIL);
+        let starts: Vec<_> = supported
+            .iter()
+            .map(|(pointer, function)| {
+                let (index, arity) = indices[*pointer];
+                FunctionEmitter {
+                    encoder: &mut encoder,
+                    function,
+                    index,
+                    arity,
+                    compiled: &indices,
+                    next_label: 0,
+                    slots: HashMap::new(),
+                }
+                .emit()
+            })
+            .collect();
+        // every label is in the code, so there's nothing external
+        encoder.link(|_| None::<()>);
+
+        let functions = indices
+            .into_iter()
+            .map(|(pointer, (index, arity))| (pointer, (starts[index], arity)))
+            .collect();
+        Ok(Self {
+            program,
+            natives: None,
+            memory: ExecutableMemory::new(&encoder.code)?,
+            functions,
+        })
+    }
+
+    /// Call the functions in `natives` whenever the program calls a function that it doesn't define itself,
+    /// these calls are always run by the interpreter
+    #[must_use]
+    pub fn with_natives(self, natives: &'p Natives<FunctionPointerT, Number>) -> Self {
+        Self {
+            natives: Some(natives),
+            ..self
+        }
+    }
+
+    /// Whether `function` was compiled, calls to functions that weren't are run by the interpreter
+    pub fn is_compiled(&self, function: &FunctionPointerT) -> bool {
+        self.functions.contains_key(function)
+    }
+
+    /// A handle for calling `function`
+    pub fn function(&self, function: &FunctionPointerT) -> JitFunction<'_, 'p, ProgramT> {
+        JitFunction {
+            jit: self,
+            function: function.clone(),
+            compiled: self.functions.get(function).copied(),
+        }
+    }
+
+    fn interpret(
+        &self,
+        function: &FunctionPointerT,
+        arguments: &[Number],
+    ) -> Result<Number, Error<FunctionPointerT>> {
+        let interpreter = Interpreter::new(self.program);
+        match self.natives {
+            Some(natives) => interpreter
+                .with_natives(natives)
+                .interpret(function, arguments),
+            None => interpreter.interpret(function, arguments),
+        }
+    }
+}
+
+/// A function of a [`Jit`], which runs its machine code if there is any, and the interpreter otherwise
+pub struct JitFunction<'j, 'p, ProgramT: Program> {
+    jit: &'j Jit<'p, ProgramT>,
+    function: ProgramT::FunctionPointer,
+    /// where the function starts, along with its arity
+    compiled: Option<(usize, usize)>,
+}
+
+impl<
+        BlockPointerT: Eq + Debug + Clone,
+        FunctionPointerT: Eq + Debug + Clone + Hash,
+        ProgramT: Program<BlockPointer = BlockPointerT, FunctionPointer = FunctionPointerT, Number = Number>,
+    > JitFunction<'_, '_, ProgramT>
+{
+    /// Whether calling this function runs machine code, rather than the interpreter
+    pub fn is_compiled(&self) -> bool {
+        self.compiled.is_some()
+    }
+
+    /// Call the function with `arguments`, returning the same as [`calc_interpreter::interpret_function`] would
+    ///
+    /// This can be called from anywhere on any thread, compiled code hands over to the interpreter before it gets within
+    /// 64 KiB of the bottom of the thread's stack
+    ///
+    /// # Errors
+    /// In the same cases as [`calc_interpreter::Interpreter::interpret`]
+    pub fn call(&self, arguments: &[Number]) -> Result<Number, Error<FunctionPointerT>> {
+        let Some((start, arity)) = self.compiled.filter(|(_, arity)| *arity == arguments.len())
+        else {
+            return self.jit.interpret(&self.function, arguments);
+        };
+        // compiled code only checks how deep it is against the real bounds of the stack, so without them it can't run
+        let Some(stack_limit) = STACK_LIMIT.with(|limit| *limit) else {
+            return self.jit.interpret(&self.function, arguments);
+        };
+
+        let mut context = Context {
+            frame: 0,
+            bailed: 0,
+            stack_limit,
+        };
+        // SAFETY: the trampoline is at the start of the code and has this signature, and the function it's given was compiled
+        // to take `arity` arguments, which `arguments` holds
+        let result = unsafe {
+            let trampoline: extern "C" fn(*mut Context, *const u8, *const Number, usize) -> Number =
+                std::mem::transmute(self.jit.memory.pointer);
+            let function = self.jit.memory.pointer.cast::<u8>().add(start);
+            trampoline(&raw mut context, function, arguments.as_ptr(), arity)
+        };
+
+        if context.bailed == 0 {
+            Ok(result)
+        } else {
+            self.jit.interpret(&self.function, arguments)
+        }
+    }
+}
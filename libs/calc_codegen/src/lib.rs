@@ -0,0 +1,255 @@
+#![warn(clippy::pedantic, clippy::all, clippy::perf)]
+
+//! Lowering programs built by [`calc_ir`] to code that runs without the interpreter
+//!
+//! Every backend works on the [`Graph`] of a program, and lowers each of its functions to a function of its own, named with [`symbol`].
+//! Calls to functions that the program doesn't define become calls to outside functions with the same name,
+//! so they can be provided by whatever the code is linked with.
+//!
+//! Backends compute with [`calc_ir::Number`]s in [`calc_ir::ArithmeticMode::Wrapping`]. Division or modulo by zero, as well as anything
+//! that the interpreter fails on regardless of the values involved, such as calling a function with the wrong amount of arguments, traps.
+//! Reading a register before anything was written to it gives an unspecified value.
+//! The [`jit`] runs in the same process as the interpreter, so it hands such calls over to it instead of trapping.
+//!
+//! The parameters of a block are written by every jump into it, so [`calc_ir::Instruction::LoadBlockArgs`] doesn't lower to anything.
+
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+
+use calc_ir::{Instruction, Number, Program, Register};
+use calc_optimizer::{FunctionGraph, Graph};
+
+pub mod c;
+pub mod elf;
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+pub mod jit;
+pub mod llvm;
+pub mod regalloc;
+pub mod wasm;
+pub mod x86_64;
+
+#[cfg(test)]
+mod test;
+
+/// The ways that lowering a program can fail
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodegenError<FunctionPointerT> {
+    /// two functions have the same [`symbol`]
+    SymbolCollision(FunctionPointerT, FunctionPointerT),
+    /// a [`calc_ir::Instruction::LoadBlockArgs`] that isn't the first instruction of its block,
+    /// the arguments of a block are passed on the jump into it, so there's nothing to load later on
+    MisplacedBlockArgs {
+        function: FunctionPointerT,
+        block: usize,
+    },
+}
+
+impl<FunctionPointerT: Debug> Display for CodegenError<FunctionPointerT> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CodegenError::SymbolCollision(first, second) => {
+                write!(f, "{first:?} and {second:?} have the same symbol")
+            }
+            CodegenError::MisplacedBlockArgs { function, block } => write!(
+                f,
+                "block {block} of {function:?} loads block arguments after its first instruction"
+            ),
+        }
+    }
+}
+
+impl<FunctionPointerT: Debug> std::error::Error for CodegenError<FunctionPointerT> {}
+
+/// The name of the function that `function` is lowered to, every character that can't be part of a C identifier is replaced with an `_`
+pub fn symbol(function: &impl Display) -> String {
+    let mut symbol: String = function
+        .to_string()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if !symbol.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        symbol.insert(0, '_');
+    }
+    symbol
+}
+
+/// A function of the program that's being lowered
+pub(crate) struct Function<'g, FunctionPointerT: Eq + Debug + Clone + Hash> {
+    pub(crate) pointer: &'g FunctionPointerT,
+    pub(crate) symbol: String,
+    pub(crate) graph: &'g FunctionGraph<FunctionPointerT>,
+    /// see [`arity`]
+    pub(crate) arity: usize,
+}
+
+/// The graph of every function in `program`, along with every function that they call
+pub(crate) fn program_graph<
+    BlockPointerT: Eq + Debug + Clone,
+    FunctionPointerT: Eq + Debug + Clone + Hash,
+    ProgramT: Program<BlockPointer = BlockPointerT, FunctionPointer = FunctionPointerT, Number = Number>,
+>(
+    program: &ProgramT,
+) -> Graph<FunctionPointerT> {
+    let entry_pointers = program
+        .get_all_functions()
+        .into_iter()
+        .map(|(function, _)| function.clone())
+        .collect();
+    Graph::from_program(program, entry_pointers)
+}
+
+/// Every function in `graph`, ordered by symbol so that the output doesn't depend on the order of a `HashMap`
+pub(crate) fn functions<FunctionPointerT: Eq + Debug + Clone + Hash + Display>(
+    graph: &Graph<FunctionPointerT>,
+) -> Result<Vec<Function<'_, FunctionPointerT>>, CodegenError<FunctionPointerT>> {
+    let mut functions: Vec<_> = graph
+        .functions()
+        .map(|(pointer, function)| Function {
+            pointer,
+            symbol: symbol(pointer),
+            graph: function,
+            arity: arity(function),
+        })
+        .collect();
+    functions.sort_by(|lhs, rhs| lhs.symbol.cmp(&rhs.symbol));
+
+    for pair in functions.windows(2) {
+        if pair[0].symbol == pair[1].symbol {
+            return Err(CodegenError::SymbolCollision(
+                pair[0].pointer.clone(),
+                pair[1].pointer.clone(),
+            ));
+        }
+    }
+    for function in &functions {
+        if let Some(block) = misplaced_block_args(function.graph) {
+            return Err(CodegenError::MisplacedBlockArgs {
+                function: function.pointer.clone(),
+                block,
+            });
+        }
+    }
+    Ok(functions)
+}
+
+/// The symbol of every function that `functions` call but that `graph` doesn't define, along with the amount of arguments it's first called with,
+/// which is what it's declared with
+pub(crate) fn outside_functions<FunctionPointerT: Eq + Debug + Clone + Hash + Display>(
+    graph: &Graph<FunctionPointerT>,
+    functions: &[Function<'_, FunctionPointerT>],
+) -> BTreeMap<String, usize> {
+    let mut outside = BTreeMap::new();
+    for function in functions {
+        for instruction in function.graph.blocks.iter().flatten() {
+            if let Instruction::Call {
+                function_id,
+                arguments,
+                ..
+            } = instruction
+            {
+                if graph.function(function_id).is_none() {
+                    outside
+                        .entry(symbol(function_id))
+                        .or_insert(arguments.len());
+                }
+            }
+        }
+    }
+    outside
+}
+
+/// The amount of arguments `function` takes, the arity the program reports, or the amount that it loads if it doesn't
+pub(crate) fn arity<FunctionPointerT: Eq + Debug + Clone + Hash>(
+    function: &FunctionGraph<FunctionPointerT>,
+) -> usize {
+    function.arity.unwrap_or_else(|| {
+        function
+            .blocks
+            .iter()
+            .flatten()
+            .find_map(|instruction| match instruction {
+                Instruction::LoadArgs(registers) => Some(registers.len()),
+                _ => None,
+            })
+            .unwrap_or(0)
+    })
+}
+
+/// The first block of `function` with a [`calc_ir::Instruction::LoadBlockArgs`] after its first instruction, see [`CodegenError::MisplacedBlockArgs`]
+pub(crate) fn misplaced_block_args<FunctionPointerT: Eq + Debug + Clone + Hash>(
+    function: &FunctionGraph<FunctionPointerT>,
+) -> Option<usize> {
+    function.blocks.iter().position(|instructions| {
+        instructions
+            .iter()
+            .skip(1)
+            .any(|instruction| matches!(instruction, Instruction::LoadBlockArgs(_)))
+    })
+}
+
+/// Every register that `instruction` reads or writes
+pub(crate) fn registers<F: Eq + Clone>(instruction: &Instruction<usize, F>) -> Vec<Register> {
+    let mut registers = instruction.reads();
+    registers.extend(instruction.writes());
+    registers
+}
+
+/// Every register that `function` reads or writes, in order and without duplicates
+pub(crate) fn used_registers<FunctionPointerT: Eq + Debug + Clone + Hash>(
+    function: &FunctionGraph<FunctionPointerT>,
+) -> Vec<Register> {
+    let mut used: Vec<_> = function
+        .blocks
+        .iter()
+        .flatten()
+        .flat_map(registers)
+        .collect();
+    used.sort();
+    used.dedup();
+    used
+}
+
+/// The registers that `block` loads its arguments into, or None if it doesn't take any
+pub(crate) fn block_params<FunctionPointerT: Eq + Debug + Clone + Hash>(
+    function: &FunctionGraph<FunctionPointerT>,
+    block: usize,
+) -> Option<&[Register]> {
+    match function.blocks[block].first() {
+        Some(Instruction::LoadBlockArgs(registers)) => Some(registers),
+        _ => None,
+    }
+}
+
+/// Whether jumping to `block` with `provided` arguments fails in the interpreter
+pub(crate) fn jump_fails<FunctionPointerT: Eq + Debug + Clone + Hash>(
+    function: &FunctionGraph<FunctionPointerT>,
+    block: usize,
+    provided: usize,
+) -> bool {
+    match block_params(function, block) {
+        Some(params) => params.len() != provided,
+        None => provided != 0,
+    }
+}
+
+/// Every argument of a jump to `block` paired with the parameter it's moved into, or None if the jump fails, see [`jump_fails`]
+///
+/// The arguments and parameters may overlap, so every argument has to be read before any parameter is written
+pub(crate) fn jump_moves<FunctionPointerT: Eq + Debug + Clone + Hash>(
+    function: &FunctionGraph<FunctionPointerT>,
+    block: usize,
+    arguments: &[Register],
+) -> Option<Vec<(Register, Register)>> {
+    if jump_fails(function, block, arguments.len()) {
+        return None;
+    }
+    let params = block_params(function, block).unwrap_or_default();
+    Some(
+        arguments
+            .iter()
+            .copied()
+            .zip(params.iter().copied())
+            .collect(),
+    )
+}
@@ -0,0 +1,388 @@
+//! Lowering to textual LLVM IR
+//!
+//! Every function becomes an LLVM function `i64 @name(i64, ...)`, and every block an LLVM basic block.
+//! Registers can be written any number of times, so each one is a stack slot that's loaded from and stored to around every instruction,
+//! which `opt -passes=mem2reg`, or any of the usual pipelines, turns into SSA values.
+//! Jumps that don't end their block split it in two, since LLVM only allows branches at the end of a basic block.
+//!
+//! Numbers are always 64 bits wide in the output, and wrap rather than being poison on overflow. Traps call `llvm.trap`.
+//! Pointers are written in the typed syntax, which every version of LLVM since 3.7 reads.
+
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Display, Write};
+use std::hash::Hash;
+
+use calc_ir::arithmetic::Operation;
+use calc_ir::{Instruction, Number, Program, Register};
+use calc_optimizer::{FunctionGraph, Graph};
+
+use crate::{
+    block_params, functions, jump_moves, outside_functions, program_graph, symbol, used_registers,
+    CodegenError, Function,
+};
+
+/// The helpers for operations that LLVM has no instruction with the same behaviour for
+const PRELUDE: &str = "\
+declare void @llvm.trap() noreturn nounwind
+
+define internal i64 @calc_divide(i64 %lhs, i64 %rhs) {
+  %zero = icmp eq i64 %rhs, 0
+  br i1 %zero, label %trap, label %nonzero
+trap:
+  call void @llvm.trap()
+  unreachable
+nonzero:
+  ; the minimum divided by -1 overflows, which sdiv leaves undefined instead of wrapping back to the minimum
+  %minus_one = icmp eq i64 %rhs, -1
+  br i1 %minus_one, label %negate, label %divide
+negate:
+  %negated = sub i64 0, %lhs
+  ret i64 %negated
+divide:
+  %quotient = sdiv i64 %lhs, %rhs
+  ret i64 %quotient
+}
+
+define internal i64 @calc_modulo(i64 %lhs, i64 %rhs) {
+  %zero = icmp eq i64 %rhs, 0
+  br i1 %zero, label %trap, label %nonzero
+trap:
+  call void @llvm.trap()
+  unreachable
+nonzero:
+  %minus_one = icmp eq i64 %rhs, -1
+  br i1 %minus_one, label %none, label %modulo
+none:
+  ret i64 0
+modulo:
+  %remainder = srem i64 %lhs, %rhs
+  ret i64 %remainder
+}
+";
+
+/// The LLVM instruction that performs `operation`, or the helper in [`PRELUDE`] that's called for it
+fn operation_instruction(operation: Operation) -> &'static str {
+    match operation {
+        Operation::Add => "add",
+        Operation::Subtract => "sub",
+        Operation::Multiply => "mul",
+        Operation::Divide => "@calc_divide",
+        Operation::Modulo => "@calc_modulo",
+        Operation::BitOr => "or",
+        Operation::BitNotOr => "xor",
+        Operation::BitAnd => "and",
+        Operation::ShiftL => "shl",
+        Operation::ShiftR => "ashr",
+    }
+}
+
+/// A parameter list of `count` unnamed `i64`s
+fn parameter_types(count: usize) -> String {
+    vec!["i64"; count].join(", ")
+}
+
+fn register(register: Register) -> String {
+    format!("%r{}", register.0)
+}
+
+/// Lower every function in `program` to an LLVM module
+///
+/// # Errors
+/// If the program can't be lowered, see [`CodegenError`]
+pub fn emit<
+    BlockPointerT: Eq + Debug + Clone,
+    FunctionPointerT: Eq + Debug + Clone + Hash + Display,
+    ProgramT: Program<BlockPointer = BlockPointerT, FunctionPointer = FunctionPointerT, Number = Number>,
+>(
+    program: &ProgramT,
+) -> Result<String, CodegenError<FunctionPointerT>> {
+    let graph = program_graph(program);
+    let functions = functions(&graph)?;
+
+    let mut module = String::from(PRELUDE);
+    let outside = outside_functions(&graph, &functions);
+    if !outside.is_empty() {
+        module.push('\n');
+    }
+    for (symbol, arity) in &outside {
+        writeln!(module, "declare i64 @{symbol}({})", parameter_types(*arity))
+            .expect("writing to a String can't fail");
+    }
+
+    for function in &functions {
+        module.push('\n');
+        FunctionEmitter {
+            out: &mut module,
+            graph: &graph,
+            function,
+            outside: &outside,
+            values: 0,
+            labels: 0,
+            split: false,
+        }
+        .emit();
+    }
+    Ok(module)
+}
+
+/// Emits a single function
+struct FunctionEmitter<'a, 'g, FunctionPointerT: Eq + Debug + Clone + Hash> {
+    out: &'a mut String,
+    graph: &'g Graph<FunctionPointerT>,
+    function: &'a Function<'g, FunctionPointerT>,
+    /// the arity that every function the program doesn't define is declared with
+    outside: &'a BTreeMap<String, usize>,
+    /// how many SSA values have been named so far
+    values: usize,
+    /// how many basic blocks that don't belong to a block of the function have been named so far
+    labels: usize,
+    /// whether a terminator was written in the middle of a block, so a new basic block has to start before anything else goes in it
+    split: bool,
+}
+
+impl<FunctionPointerT: Eq + Debug + Clone + Hash + Display>
+    FunctionEmitter<'_, '_, FunctionPointerT>
+{
+    fn line(&mut self, line: impl Display) {
+        if self.split {
+            self.split = false;
+            let label = self.fresh_label();
+            writeln!(self.out, "{label}:").expect("writing to a String can't fail");
+        }
+        writeln!(self.out, "  {line}").expect("writing to a String can't fail");
+    }
+
+    /// Write `terminator`, everything after it goes in a new basic block
+    fn terminate(&mut self, terminator: impl Display) {
+        self.line(terminator);
+        self.split = true;
+    }
+
+    fn label(&mut self, label: impl Display) {
+        self.split = false;
+        writeln!(self.out, "{label}:").expect("writing to a String can't fail");
+    }
+
+    fn fresh_value(&mut self) -> String {
+        self.values += 1;
+        format!("%v{}", self.values)
+    }
+
+    fn fresh_label(&mut self) -> String {
+        self.labels += 1;
+        format!("split{}", self.labels)
+    }
+
+    fn trap(&mut self) {
+        self.line("call void @llvm.trap()");
+        self.terminate("unreachable");
+    }
+
+    /// Load `register` into a new SSA value
+    fn load(&mut self, register: Register) -> String {
+        let value = self.fresh_value();
+        self.line(format!(
+            "{value} = load i64, i64* {}",
+            self::register(register)
+        ));
+        value
+    }
+
+    fn store(&mut self, value: impl Display, register: Register) {
+        self.line(format!(
+            "store i64 {value}, i64* {}",
+            self::register(register)
+        ));
+    }
+
+    fn emit(mut self) {
+        let parameters: Vec<_> = (0..self.function.arity)
+            .map(|argument| format!("i64 %a{argument}"))
+            .collect();
+        writeln!(
+            self.out,
+            "define i64 @{}({}) {{",
+            self.function.symbol,
+            parameters.join(", ")
+        )
+        .expect("writing to a String can't fail");
+
+        // the entry block of an LLVM function can't be jumped to, so the slots are made in one of its own
+        self.label("start");
+        let registers = used_registers(self.function.graph);
+        for register in registers {
+            // starting every register at 0 keeps reading one before it's written from being undefined
+            self.line(format!("{} = alloca i64", self::register(register)));
+            self.store(0, register);
+        }
+        // the function was called without the arguments its entry block loads
+        if block_params(
+            self.function.graph,
+            FunctionGraph::<FunctionPointerT>::ENTRY,
+        )
+        .is_some_and(|params| !params.is_empty())
+        {
+            self.trap();
+        } else {
+            self.terminate(format!(
+                "br label %block{}",
+                FunctionGraph::<FunctionPointerT>::ENTRY
+            ));
+        }
+
+        let graph = self.function.graph;
+        for (block, instructions) in graph.blocks.iter().enumerate() {
+            self.label(format!("block{block}"));
+            for instruction in instructions {
+                self.instruction(instruction);
+            }
+            if !instructions.last().is_some_and(Instruction::is_terminator) {
+                self.trap();
+            }
+        }
+        self.out.push_str("}\n");
+    }
+
+    fn operation(&mut self, operation: Operation, lhs: Register, rhs: Register, out: Register) {
+        let lhs = self.load(lhs);
+        let mut rhs = self.load(rhs);
+        if matches!(operation, Operation::ShiftL | Operation::ShiftR) {
+            // LLVM makes shifting by the width or more poison, while wrapping shifts only use the low bits of the amount
+            let masked = self.fresh_value();
+            self.line(format!("{masked} = and i64 {rhs}, 63"));
+            rhs = masked;
+        }
+        let result = self.fresh_value();
+        let instruction = operation_instruction(operation);
+        if instruction.starts_with('@') {
+            self.line(format!(
+                "{result} = call i64 {instruction}(i64 {lhs}, i64 {rhs})"
+            ));
+        } else {
+            self.line(format!("{result} = {instruction} i64 {lhs}, {rhs}"));
+        }
+        self.store(result, out);
+    }
+
+    fn instruction(&mut self, instruction: &Instruction<usize, FunctionPointerT>) {
+        if let Some((operation, lhs, rhs, out)) = instruction.operation() {
+            self.operation(operation, lhs, rhs, out);
+            return;
+        }
+
+        match instruction {
+            Instruction::LoadImmediate(value, out) => self.store(value, *out),
+            Instruction::Call {
+                function_id,
+                arguments,
+                out,
+            } => {
+                let symbol = symbol(function_id);
+                let arity = match self.graph.function(function_id) {
+                    Some(callee) => crate::arity(callee),
+                    None => self.outside[&symbol],
+                };
+                if arguments.len() != arity {
+                    self.trap();
+                    return;
+                }
+                let arguments: Vec<_> = arguments
+                    .iter()
+                    .map(|argument| format!("i64 {}", self.load(*argument)))
+                    .collect();
+                let result = self.fresh_value();
+                self.line(format!(
+                    "{result} = call i64 @{symbol}({})",
+                    arguments.join(", ")
+                ));
+                self.store(result, *out);
+            }
+            Instruction::Ret(value) => {
+                let value = self.load(*value);
+                self.terminate(format!("ret i64 {value}"));
+            }
+            Instruction::LoadArgs(registers) => {
+                if registers.len() != self.function.arity {
+                    self.trap();
+                    return;
+                }
+                for (argument, register) in registers.iter().enumerate() {
+                    self.store(format!("%a{argument}"), *register);
+                }
+            }
+            Instruction::LoadBlockArgs(_) => {}
+            Instruction::Jump { to, arguments } => self.jump(*to, arguments),
+            Instruction::JEqual {
+                lhs,
+                rhs,
+                to,
+                arguments,
+            }
+            | Instruction::JNotEqual {
+                lhs,
+                rhs,
+                to,
+                arguments,
+            } => {
+                let lhs = self.load(*lhs);
+                let rhs = self.load(*rhs);
+                let condition = if matches!(instruction, Instruction::JEqual { .. }) {
+                    "eq"
+                } else {
+                    "ne"
+                };
+                let check = self.fresh_value();
+                self.line(format!("{check} = icmp {condition} i64 {lhs}, {rhs}"));
+                self.conditional_jump(&check, *to, arguments);
+            }
+            Instruction::JNonZero {
+                check,
+                to,
+                arguments,
+            }
+            | Instruction::JZero {
+                check,
+                to,
+                arguments,
+            } => {
+                let value = self.load(*check);
+                let condition = if matches!(instruction, Instruction::JZero { .. }) {
+                    "eq"
+                } else {
+                    "ne"
+                };
+                let check = self.fresh_value();
+                self.line(format!("{check} = icmp {condition} i64 {value}, 0"));
+                self.conditional_jump(&check, *to, arguments);
+            }
+            Instruction::Invalid => self.trap(),
+            _ => unreachable!("arithmetic instructions are handled above"),
+        }
+    }
+
+    /// Move `arguments` into the registers that `to` loads them into, and branch to it
+    fn jump(&mut self, to: usize, arguments: &[Register]) {
+        let Some(moves) = jump_moves(self.function.graph, to, arguments) else {
+            self.trap();
+            return;
+        };
+        let values: Vec<_> = moves
+            .iter()
+            .map(|(argument, _)| self.load(*argument))
+            .collect();
+        for (value, (_, param)) in values.into_iter().zip(moves) {
+            self.store(value, param);
+        }
+        self.terminate(format!("br label %block{to}"));
+    }
+
+    /// Jump if `check` is true, otherwise carry on in a new basic block
+    fn conditional_jump(&mut self, check: &str, to: usize, arguments: &[Register]) {
+        let taken = self.fresh_label();
+        let not_taken = self.fresh_label();
+        self.line(format!("br i1 {check}, label %{taken}, label %{not_taken}"));
+        self.label(taken);
+        self.jump(to, arguments);
+        self.label(not_taken);
+    }
+}
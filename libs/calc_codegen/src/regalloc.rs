@@ -0,0 +1,461 @@
+//! Linear scan register allocation, which maps the unbounded registers of a function onto a fixed amount of physical ones
+//!
+//! The instructions of a function are numbered in the order of its blocks, giving every instruction a position where it reads its registers
+//! and a position right after it where it writes them. The [`Liveness`] of the [`FunctionGraph`] then gives each register a [`LiveInterval`]
+//! from the first to the last position it's live at, and the intervals are allocated in the order they start, following
+//! "Linear Scan Register Allocation" by Poletto and Sarkar: once every physical register is taken, whichever interval ends last is spilled
+//! to a stack slot. Stack slots are reused the same way once the interval in them ends, so a function never needs more of them than it has
+//! intervals live at the same time.
+//!
+//! [`AllocatedProgram`] applies the allocation to every function of a [`Graph`], numbering physical registers from 0 and stack slots right
+//! after them, so it can be run by the interpreter. [`verify`] does exactly that to check that an allocation didn't change what a program does.
+//! Reading a register before anything was written to it doesn't give an error in the allocated program if another register shares its location,
+//! so programs that do can't be verified.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+
+use calc_interpreter::Error;
+use calc_ir::{FunctionAttributes, Instruction, Number, Program, Register};
+use calc_optimizer::analysis::Liveness;
+use calc_optimizer::{FunctionGraph, Graph};
+
+/// Where a register of a function lives once it's been allocated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Location {
+    /// one of the physical registers, numbered from 0
+    Register(usize),
+    /// a stack slot, numbered from 0
+    Stack(usize),
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Location::Register(register) => write!(f, "%{register}"),
+            Location::Stack(slot) => write!(f, "[{slot}]"),
+        }
+    }
+}
+
+/// The positions that a register is live between, both inclusive.
+///
+/// Instruction `n` in the order of the blocks of its function reads at position `2n` and writes at position `2n + 1`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LiveInterval {
+    pub register: Register,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The [`LiveInterval`] of every register used in `function`, ordered by where they start, then by register
+#[must_use]
+pub fn live_intervals<FunctionPointerT: Eq + Debug + Clone + Hash>(
+    function: &FunctionGraph<FunctionPointerT>,
+) -> Vec<LiveInterval> {
+    let firsts: Vec<_> = function
+        .blocks
+        .iter()
+        .scan(0, |position, block| {
+            let first = *position;
+            *position += block.len();
+            Some(first)
+        })
+        .collect();
+
+    let liveness = Liveness::of(function);
+    let mut intervals: HashMap<Register, (usize, usize)> = HashMap::new();
+    let mut extend = |register: Register, position: usize| {
+        let interval = intervals.entry(register).or_insert((position, position));
+        interval.0 = interval.0.min(position);
+        interval.1 = interval.1.max(position);
+    };
+    for (block, instructions) in function.blocks.iter().enumerate() {
+        let before = liveness.before_each(function, block);
+        for (offset, instruction) in instructions.iter().enumerate() {
+            let read = 2 * (firsts[block] + offset);
+            // whatever is live after the instruction has to survive it writing its registers
+            let mut after = before.get(offset + 1).cloned().unwrap_or_default();
+            if let Some((to, _)) = instruction.jump_target() {
+                after.extend(liveness.live_in(*to).iter().copied());
+            }
+            for register in after.into_iter().chain(instruction.writes()) {
+                extend(register, read + 1);
+            }
+            for register in &before[offset] {
+                extend(*register, read);
+            }
+        }
+    }
+
+    let mut intervals: Vec<_> = intervals
+        .into_iter()
+        .map(|(register, (start, end))| LiveInterval {
+            register,
+            start,
+            end,
+        })
+        .collect();
+    intervals.sort_by_key(|interval| (interval.start, interval.register));
+    intervals
+}
+
+/// Where every register of a function lives
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Allocation {
+    locations: HashMap<Register, Location>,
+    registers: usize,
+    stack_slots: usize,
+}
+
+impl Allocation {
+    /// Where `register` lives, or None if the function doesn't use it
+    #[must_use]
+    pub fn location(&self, register: Register) -> Option<Location> {
+        self.locations.get(&register).copied()
+    }
+
+    /// How many physical registers the allocation could use
+    #[must_use]
+    pub fn registers(&self) -> usize {
+        self.registers
+    }
+
+    /// How many stack slots the allocation needed on top of the physical registers
+    #[must_use]
+    pub fn stack_slots(&self) -> usize {
+        self.stack_slots
+    }
+
+    /// The register that `register` is renamed to in an [`AllocatedProgram`], physical registers followed by stack slots
+    #[must_use]
+    pub fn rename(&self, register: Register) -> Register {
+        match self.location(register) {
+            Some(Location::Register(physical)) => Register(physical),
+            Some(Location::Stack(slot)) => Register(self.registers + slot),
+            None => register,
+        }
+    }
+}
+
+/// The intervals currently in a location, and the locations that are free
+struct Pool {
+    /// the end of each interval in a location, along with the register and the location
+    active: Vec<(usize, Register, usize)>,
+    free: BTreeSet<usize>,
+    /// how many locations have been handed out so far
+    used: usize,
+}
+
+impl Pool {
+    /// A pool that starts with `size` free locations
+    fn new(size: usize) -> Self {
+        Self {
+            active: Vec::new(),
+            free: (0..size).collect(),
+            used: 0,
+        }
+    }
+
+    /// Free the location of every interval that ends before `position`
+    fn expire(&mut self, position: usize) {
+        let free = &mut self.free;
+        self.active.retain(|(end, _, location)| {
+            let expired = *end < position;
+            if expired {
+                free.insert(*location);
+            }
+            !expired
+        });
+    }
+
+    /// Take a free location, or None if there are none left
+    fn take(&mut self) -> Option<usize> {
+        let location = self.free.pop_first()?;
+        self.used = self.used.max(location + 1);
+        Some(location)
+    }
+
+    /// Take a free location, or a new one if there are none left
+    fn take_or_grow(&mut self) -> usize {
+        self.take().unwrap_or_else(|| {
+            self.used += 1;
+            self.used - 1
+        })
+    }
+}
+
+/// Allocate the registers of `function` to `registers` physical registers, spilling to stack slots when they run out
+#[must_use]
+pub fn allocate<FunctionPointerT: Eq + Debug + Clone + Hash>(
+    function: &FunctionGraph<FunctionPointerT>,
+    registers: usize,
+) -> Allocation {
+    let mut locations = HashMap::new();
+    let mut physical = Pool::new(registers);
+    let mut stack = Pool::new(0);
+
+    for interval in live_intervals(function) {
+        physical.expire(interval.start);
+        stack.expire(interval.start);
+
+        if let Some(register) = physical.take() {
+            physical
+                .active
+                .push((interval.end, interval.register, register));
+            locations.insert(interval.register, Location::Register(register));
+            continue;
+        }
+        // the interval that's live for the longest gives up its register, which may be the new one itself
+        let longest = physical
+            .active
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, (end, register, _))| (*end, *register))
+            .map(|(index, _)| index);
+        let spilled = match longest {
+            Some(index) if physical.active[index].0 > interval.end => {
+                let (end, spilled, register) = physical.active.swap_remove(index);
+                physical
+                    .active
+                    .push((interval.end, interval.register, register));
+                locations.insert(interval.register, Location::Register(register));
+                (end, spilled)
+            }
+            _ => (interval.end, interval.register),
+        };
+        let slot = stack.take_or_grow();
+        stack.active.push((spilled.0, spilled.1, slot));
+        locations.insert(spilled.1, Location::Stack(slot));
+    }
+
+    Allocation {
+        locations,
+        registers,
+        stack_slots: stack.used,
+    }
+}
+
+/// A function of an [`AllocatedProgram`]
+struct AllocatedFunction<FunctionPointerT: Eq + Clone> {
+    pointer: FunctionPointerT,
+    entry: (usize, usize),
+    blocks: Vec<Vec<Instruction<(usize, usize), FunctionPointerT>>>,
+    allocation: Allocation,
+    arity: Option<usize>,
+    attributes: FunctionAttributes,
+}
+
+/// Every function of a [`Graph`] with its registers allocated, where each register is renamed to its location with [`Allocation::rename`].
+///
+/// Blocks are pointed to by the index of their function along with their index in it, and its [`Display`] lists every instruction
+/// along with where each of the registers it uses lives
+pub struct AllocatedProgram<FunctionPointerT: Eq + Clone> {
+    functions: Vec<AllocatedFunction<FunctionPointerT>>,
+    /// the instructions of every function before renaming, which is what gets displayed
+    original: Vec<Vec<Vec<Instruction<usize, FunctionPointerT>>>>,
+}
+
+impl<FunctionPointerT: Eq + Debug + Clone + Hash + Ord> AllocatedProgram<FunctionPointerT> {
+    /// Allocate every function in `graph` to `registers` physical registers
+    #[must_use]
+    pub fn new(graph: &Graph<FunctionPointerT>, registers: usize) -> Self {
+        let mut functions: Vec<_> = graph.functions().collect();
+        functions.sort_by(|lhs, rhs| lhs.0.cmp(rhs.0));
+
+        let original = functions
+            .iter()
+            .map(|(_, function)| function.blocks.clone())
+            .collect();
+        let functions = functions
+            .into_iter()
+            .enumerate()
+            .map(|(index, (pointer, function))| {
+                let allocation = allocate(function, registers);
+                let blocks = function
+                    .blocks
+                    .iter()
+                    .map(|block| {
+                        block
+                            .iter()
+                            .cloned()
+                            .map(|instruction| {
+                                instruction
+                                    .map_registers(|register| allocation.rename(register))
+                                    .map_blocks(|block| (index, block))
+                            })
+                            .collect()
+                    })
+                    .collect();
+                AllocatedFunction {
+                    pointer: pointer.clone(),
+                    entry: (index, FunctionGraph::<FunctionPointerT>::ENTRY),
+                    blocks,
+                    allocation,
+                    arity: function.arity,
+                    attributes: function.attributes,
+                }
+            })
+            .collect();
+        Self {
+            functions,
+            original,
+        }
+    }
+
+    /// The allocation of `function`, if it's part of the program
+    #[must_use]
+    pub fn allocation(&self, function: &FunctionPointerT) -> Option<&Allocation> {
+        self.function(function).map(|function| &function.allocation)
+    }
+
+    fn function(
+        &self,
+        function: &FunctionPointerT,
+    ) -> Option<&AllocatedFunction<FunctionPointerT>> {
+        self.functions
+            .iter()
+            .find(|allocated| allocated.pointer == *function)
+    }
+}
+
+impl<FunctionPointerT: Eq + Debug + Clone + Hash + Ord> Program
+    for AllocatedProgram<FunctionPointerT>
+{
+    type BlockPointer = (usize, usize);
+
+    type FunctionPointer = FunctionPointerT;
+
+    type Number = Number;
+
+    fn get_function_entry(
+        &self,
+        function_id: &Self::FunctionPointer,
+    ) -> Option<Self::BlockPointer> {
+        self.function(function_id).map(|function| function.entry)
+    }
+
+    fn get_ir(
+        &self,
+        block_id: &Self::BlockPointer,
+    ) -> &[Instruction<Self::BlockPointer, Self::FunctionPointer, Self::Number>] {
+        &self.functions[block_id.0].blocks[block_id.1]
+    }
+
+    fn get_all_functions(&self) -> Vec<(&Self::FunctionPointer, &Self::BlockPointer)> {
+        self.functions
+            .iter()
+            .map(|function| (&function.pointer, &function.entry))
+            .collect()
+    }
+
+    fn get_register_count(&self, function_id: &Self::FunctionPointer) -> Option<usize> {
+        self.function(function_id)
+            .map(|function| function.allocation.registers() + function.allocation.stack_slots())
+    }
+
+    fn get_arity(&self, function_id: &Self::FunctionPointer) -> Option<usize> {
+        self.function(function_id)
+            .and_then(|function| function.arity)
+    }
+
+    fn get_attributes(&self, function_id: &Self::FunctionPointer) -> FunctionAttributes {
+        self.function(function_id)
+            .map(|function| function.attributes)
+            .unwrap_or_default()
+    }
+}
+
+impl<FunctionPointerT: Eq + Clone + Debug> Display for AllocatedProgram<FunctionPointerT> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (function, blocks) in self.functions.iter().zip(&self.original) {
+            writeln!(
+                f,
+                "{:?}: {} registers, {} stack slots",
+                function.pointer,
+                function.allocation.registers(),
+                function.allocation.stack_slots()
+            )?;
+            for (index, block) in blocks.iter().enumerate() {
+                writeln!(f, "  block {index}:")?;
+                for instruction in block {
+                    let mut used = instruction.reads();
+                    used.extend(instruction.writes());
+                    used.sort();
+                    used.dedup();
+                    let locations: Vec<_> = used
+                        .into_iter()
+                        .filter_map(|register| {
+                            let location = function.allocation.location(register)?;
+                            Some(format!("{} -> {location}", register.0))
+                        })
+                        .collect();
+                    if locations.is_empty() {
+                        writeln!(f, "    {instruction:?}")?;
+                    } else {
+                        writeln!(f, "    {instruction:?} ; {}", locations.join(", "))?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The allocated program gave a different result than the program it was allocated from, see [`verify`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch<FunctionPointerT> {
+    pub function: FunctionPointerT,
+    pub arguments: Vec<Number>,
+    pub expected: Result<Number, Error<FunctionPointerT>>,
+    pub allocated: Result<Number, Error<FunctionPointerT>>,
+}
+
+impl<FunctionPointerT: Debug> Display for Mismatch<FunctionPointerT> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let describe = |result: &Result<Number, Error<FunctionPointerT>>| match result {
+            Ok(value) => value.to_string(),
+            Err(error) => error.to_string(),
+        };
+        write!(
+            f,
+            "calling {:?} with {:?} gave {}, but {} after allocating registers",
+            self.function,
+            self.arguments,
+            describe(&self.expected),
+            describe(&self.allocated)
+        )
+    }
+}
+
+impl<FunctionPointerT: Debug> std::error::Error for Mismatch<FunctionPointerT> {}
+
+/// Interpret `function` with `arguments` in both `program` and `allocated`, which must have been allocated from it,
+/// and check that they give the same result
+///
+/// # Errors
+/// If the results differ, see [`Mismatch`]
+pub fn verify<
+    BlockPointerT: Eq + Debug + Clone,
+    FunctionPointerT: Eq + Debug + Clone + Hash + Ord,
+    ProgramT: Program<BlockPointer = BlockPointerT, FunctionPointer = FunctionPointerT, Number = Number>,
+>(
+    program: &ProgramT,
+    allocated: &AllocatedProgram<FunctionPointerT>,
+    function: &FunctionPointerT,
+    arguments: &[Number],
+) -> Result<(), Mismatch<FunctionPointerT>> {
+    let expected = calc_interpreter::interpret_function(function, program, arguments);
+    let result = calc_interpreter::interpret_function(function, allocated, arguments);
+    if expected == result {
+        Ok(())
+    } else {
+        Err(Mismatch {
+            function: function.clone(),
+            arguments: arguments.to_vec(),
+            expected,
+            allocated: result,
+        })
+    }
+}
@@ -0,0 +1,903 @@
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use calc_ir::builder::{
+    instructions::{Arithmetic, BitWise, BlockJump},
+    Program,
+};
+use calc_ir::program::implementations::BasicProgram;
+use calc_ir::{Number, Program as _};
+
+/// a directory of its own for the files of the test `name`
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("calc_codegen_{}_{name}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// compile `sources`, which are named by their extension, along with a C `main` that runs `body`, and run the result.
+///
+/// `body` prints the results of calls with `print(...)`, which are returned in order, or None if the program crashed
+fn compile_and_run(
+    name: &str,
+    sources: &[(&str, &[u8])],
+    declarations: &str,
+    body: &str,
+) -> Option<Vec<Number>> {
+    let dir = scratch_dir(name);
+    let harness = dir.join("main.c");
+    std::fs::write(
+        &harness,
+        format!(
+            "#include <stdint.h>\n#include <stdio.h>\n{declarations}\n\
+             static void print(intptr_t value) {{ printf(\"%ld\\n\", (long)value); }}\n\
+             int main(void) {{\n{body}\nreturn 0;\n}}\n"
+        ),
+    )
+    .unwrap();
+
+    let mut cc = Command::new("cc");
+    cc.arg("-o").arg(dir.join("main")).arg(&harness);
+    for (index, (extension, source)) in sources.iter().enumerate() {
+        let path = dir.join(format!("program{index}.{extension}"));
+        std::fs::write(&path, source).unwrap();
+        cc.arg(path);
+    }
+    let compiled = cc.output().unwrap();
+    assert!(
+        compiled.status.success(),
+        "{}",
+        String::from_utf8_lossy(&compiled.stderr)
+    );
+
+    let run = Command::new(dir.join("main")).output().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    run.status.success().then(|| {
+        String::from_utf8(run.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| line.parse().unwrap())
+            .collect()
+    })
+}
+
+/// a function that applies `operation` to its two arguments
+fn build_arithmetic(builder: &mut Program, name: &str, operation: Arithmetic) {
+    let mut function = builder.make_fn(name.to_string());
+    let mut entry_block = function.build_block();
+    let args = entry_block.add_load_args(2);
+    let result = entry_block.add_arithmetic(operation, args[0], args[1]);
+    entry_block.add_ret(result);
+    let (entry_block_id, function) = entry_block.finalize();
+    function.finalize(entry_block_id);
+}
+
+/// a function that applies `operation` to its two arguments
+fn build_bitwise(builder: &mut Program, name: &str, operation: BitWise) {
+    let mut function = builder.make_fn(name.to_string());
+    let mut entry_block = function.build_block();
+    let args = entry_block.add_load_args(2);
+    let result = entry_block.add_bitwise(operation, args[0], args[1]);
+    entry_block.add_ret(result);
+    let (entry_block_id, function) = entry_block.finalize();
+    function.finalize(entry_block_id);
+}
+
+/// the naive recursive fibonacci, which jumps to a block with an argument to return early
+fn build_fibonacci(builder: &mut Program) {
+    let mut function = builder.make_fn("fibonacci".to_string());
+    let base_block_id = function.reserve_block();
+
+    let mut entry_block = function.build_block();
+    let n = entry_block.add_load_args(1)[0];
+    let one = entry_block.add_immediate(1);
+    let two = entry_block.add_immediate(2);
+    entry_block.add_cond_jump_with_args(BlockJump::Zero(n), base_block_id, vec![n]);
+    entry_block.add_cond_jump_with_args(BlockJump::Equal(n, one), base_block_id, vec![n]);
+    let n_minus_one = entry_block.add_arithmetic(Arithmetic::Subtract, n, one);
+    let n_minus_two = entry_block.add_arithmetic(Arithmetic::Subtract, n, two);
+    let lhs = entry_block.add_fn_call("fibonacci".to_string(), vec![n_minus_one]);
+    let rhs = entry_block.add_fn_call("fibonacci".to_string(), vec![n_minus_two]);
+    let result = entry_block.add_arithmetic(Arithmetic::Add, lhs, rhs);
+    entry_block.add_ret(result);
+    let (entry_block_id, function) = entry_block.finalize();
+
+    let mut base_block = function.build_reserved_block(base_block_id);
+    let result = base_block.add_block_params(1)[0];
+    base_block.add_ret(result);
+    let (_, function) = base_block.finalize();
+    function.finalize(entry_block_id);
+}
+
+/// swaps its two arguments as many times as its third argument says, and returns the first one
+fn build_swap(builder: &mut Program) {
+    let mut function = builder.make_fn("swap".to_string());
+    let loop_block_id = function.reserve_block();
+    let exit_block_id = function.reserve_block();
+
+    let mut entry_block = function.build_block();
+    let args = entry_block.add_load_args(3);
+    let one = entry_block.add_immediate(1);
+    entry_block.add_cond_jump_with_args(BlockJump::Unconditional, loop_block_id, args);
+    let (entry_block_id, function) = entry_block.finalize();
+
+    let mut loop_block = function.build_reserved_block(loop_block_id);
+    let params = loop_block.add_block_params(3);
+    loop_block.add_cond_jump_with_args(BlockJump::Zero(params[2]), exit_block_id, vec![params[0]]);
+    let remaining = loop_block.add_arithmetic(Arithmetic::Subtract, params[2], one);
+    // the arguments are the parameters themselves, the other way around
+    loop_block.add_cond_jump_with_args(
+        BlockJump::Unconditional,
+        loop_block_id,
+        vec![params[1], params[0], remaining],
+    );
+    let (_, function) = loop_block.finalize();
+
+    let mut exit_block = function.build_reserved_block(exit_block_id);
+    let result = exit_block.add_block_params(1)[0];
+    exit_block.add_ret(result);
+    let (_, function) = exit_block.finalize();
+    function.finalize(entry_block_id);
+}
+
+/// `weighted` takes 8 arguments, so some of them are passed on the stack, and returns the sum of each multiplied by its position,
+/// `call_weighted` calls it with 1 through 8, and `call_host` calls the outside function `host_add` with 7 arguments
+fn build_many_arguments(builder: &mut Program) {
+    let mut function = builder.make_fn("weighted".to_string());
+    let mut entry_block = function.build_block();
+    let args = entry_block.add_load_args(8);
+    let mut total = entry_block.add_immediate(0);
+    for (position, argument) in args.into_iter().enumerate() {
+        let weight = entry_block.add_immediate(Number::try_from(position + 1).unwrap());
+        let weighted = entry_block.add_arithmetic(Arithmetic::Multiply, argument, weight);
+        total = entry_block.add_arithmetic(Arithmetic::Add, total, weighted);
+    }
+    entry_block.add_ret(total);
+    let (entry_block_id, function) = entry_block.finalize();
+    let builder = function.finalize(entry_block_id);
+
+    for (name, callee, count) in [
+        ("call_weighted", "weighted", 8),
+        ("call_host", "host_add", 7),
+    ] {
+        let mut function = builder.make_fn(name.to_string());
+        let mut entry_block = function.build_block();
+        let args = (1..=count)
+            .map(|value| entry_block.add_immediate(value))
+            .collect();
+        let result = entry_block.add_fn_call(callee.to_string(), args);
+        entry_block.add_ret(result);
+        let (entry_block_id, function) = entry_block.finalize();
+        function.finalize(entry_block_id);
+    }
+}
+
+/// two blocks that loop by jumping to each other, where either can be jumped to first, so the loop has two ways in.
+/// The first one adds 1 to the total and the second doubles it, each counting down the first argument, and the second argument picks which one starts
+fn build_ping_pong(builder: &mut Program) {
+    let mut function = builder.make_fn("ping_pong".to_string());
+    let add_block_id = function.reserve_block();
+    let double_block_id = function.reserve_block();
+    let exit_block_id = function.reserve_block();
+
+    let mut entry_block = function.build_block();
+    let args = entry_block.add_load_args(2);
+    let total = entry_block.add_immediate(0);
+    entry_block.add_cond_jump_with_args(
+        BlockJump::Zero(args[1]),
+        add_block_id,
+        vec![args[0], total],
+    );
+    entry_block.add_cond_jump_with_args(
+        BlockJump::Unconditional,
+        double_block_id,
+        vec![args[0], total],
+    );
+    let (entry_block_id, mut function) = entry_block.finalize();
+
+    for (block_id, next_block_id, operation) in [
+        (add_block_id, double_block_id, Arithmetic::Add),
+        (double_block_id, add_block_id, Arithmetic::Multiply),
+    ] {
+        let mut block = function.build_reserved_block(block_id);
+        let params = block.add_block_params(2);
+        block.add_cond_jump_with_args(BlockJump::Zero(params[0]), exit_block_id, vec![params[1]]);
+        let one = block.add_immediate(1);
+        let two = block.add_immediate(2);
+        let remaining = block.add_arithmetic(Arithmetic::Subtract, params[0], one);
+        let operand = if operation == Arithmetic::Add {
+            one
+        } else {
+            two
+        };
+        let total = block.add_arithmetic(operation, params[1], operand);
+        block.add_cond_jump_with_args(
+            BlockJump::Unconditional,
+            next_block_id,
+            vec![remaining, total],
+        );
+        function = block.finalize().1;
+    }
+
+    let mut exit_block = function.build_reserved_block(exit_block_id);
+    let result = exit_block.add_block_params(1)[0];
+    exit_block.add_ret(result);
+    let (_, function) = exit_block.finalize();
+    function.finalize(entry_block_id);
+}
+
+/// a program with a bit of everything, along with calls to make to it, which are a function and its arguments
+fn test_program() -> (BasicProgram, Vec<(&'static str, Vec<Number>)>) {
+    let mut builder = Program::new();
+    let arithmetic = [
+        ("add", Arithmetic::Add),
+        ("subtract", Arithmetic::Subtract),
+        ("multiply", Arithmetic::Multiply),
+        ("divide", Arithmetic::Divide),
+        ("modulo", Arithmetic::Mod),
+    ];
+    for (name, operation) in arithmetic {
+        build_arithmetic(&mut builder, name, operation);
+    }
+    let bitwise = [
+        ("or", BitWise::Or),
+        ("not_or", BitWise::NotOr),
+        ("and", BitWise::And),
+        ("shift_left", BitWise::ShiftLeft),
+        ("shift_right", BitWise::ShiftRight),
+    ];
+    for (name, operation) in bitwise {
+        build_bitwise(&mut builder, name, operation);
+    }
+    build_fibonacci(&mut builder);
+    build_swap(&mut builder);
+    build_many_arguments(&mut builder);
+    build_ping_pong(&mut builder);
+
+    let mut calls = Vec::new();
+    for (name, _) in arithmetic {
+        calls.push((name, vec![-17, 5]));
+        calls.push((name, vec![Number::MIN, -1]));
+        calls.push((name, vec![Number::MAX, 3]));
+    }
+    for (name, _) in bitwise {
+        calls.push((name, vec![-12345, 6]));
+        calls.push((name, vec![0x0ff0, 67]));
+    }
+    calls.push(("fibonacci", vec![20]));
+    calls.push(("swap", vec![1, 2, 7]));
+    calls.push(("swap", vec![1, 2, 8]));
+    calls.push(("weighted", vec![1, 1, 1, 1, 1, 1, 1, -1]));
+    calls.push(("call_weighted", vec![]));
+    calls.push(("call_host", vec![]));
+    calls.push(("ping_pong", vec![9, 0]));
+    calls.push(("ping_pong", vec![9, 1]));
+    (builder.finalize(), calls)
+}
+
+/// the C version of the outside function that the test program calls, and the same function for the interpreter
+const HOST_ADD: &str = "#include <stdint.h>\nintptr_t host_add(intptr_t a, intptr_t b, intptr_t c, intptr_t d, intptr_t e, intptr_t f, intptr_t g) \
+                        { return a + b + c + d + e + f + g * 100; }";
+
+fn host_natives() -> calc_interpreter::Natives<String> {
+    calc_interpreter::Natives::new().with_function(
+        "host_add".to_string(),
+        7,
+        |arguments: &[Number]| {
+            Ok::<_, String>(arguments[..6].iter().sum::<Number>() + arguments[6] * 100)
+        },
+    )
+}
+
+/// run `calls` through the interpreter, along with the C code that makes the same calls and prints their results
+fn expected_results(
+    program: &BasicProgram,
+    calls: &[(&str, Vec<Number>)],
+) -> (Vec<Number>, String, String) {
+    let natives = host_natives();
+    let interpreter = calc_interpreter::Interpreter::new(program).with_natives(&natives);
+
+    let mut expected = Vec::new();
+    let mut declarations = String::new();
+    let mut body = String::new();
+    for (function, arguments) in calls {
+        expected.push(
+            interpreter
+                .interpret(&(*function).to_string(), arguments)
+                .unwrap(),
+        );
+        let parameters = vec!["intptr_t"; arguments.len()].join(", ");
+        let declaration = format!("intptr_t {function}({parameters});\n");
+        if !declarations.contains(&declaration) {
+            declarations.push_str(&declaration);
+        }
+        let arguments: Vec<String> = arguments
+            .iter()
+            .map(|argument| format!("(intptr_t){argument}ll"))
+            .collect();
+        writeln!(body, "print({function}({}));", arguments.join(", ")).unwrap();
+    }
+    // INT64_MIN can't be written as a literal
+    (
+        expected,
+        declarations,
+        body.replace("-9223372036854775808ll", "(-9223372036854775807ll - 1)"),
+    )
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn x86_64_matches_interpreter() {
+    let (program, calls) = test_program();
+    let (expected, declarations, body) = expected_results(&program, &calls);
+    let assembly = crate::x86_64::emit(&program).unwrap();
+
+    let results = compile_and_run(
+        "x86_64",
+        &[("s", assembly.as_bytes()), ("c", HOST_ADD.as_bytes())],
+        &declarations,
+        &body,
+    );
+    assert_eq!(results, Some(expected));
+}
+
+/// division by zero is an error in the interpreter, so native code traps instead of returning anything
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn x86_64_division_by_zero_traps() {
+    let mut builder = Program::new();
+    build_arithmetic(&mut builder, "divide", Arithmetic::Divide);
+    let program = builder.finalize();
+    let assembly = crate::x86_64::emit(&program).unwrap();
+
+    let results = compile_and_run(
+        "x86_64_trap",
+        &[("s", assembly.as_bytes())],
+        "intptr_t divide(intptr_t, intptr_t);",
+        "print(divide(1, 0));",
+    );
+    assert_eq!(results, None);
+}
+
+/// a program whose `subtract` doesn't report its arity, and is called with one argument fewer than it loads
+fn build_short_call() -> BasicProgram {
+    use calc_ir::zir::{FunctionEntry, Layout, Module};
+    use calc_ir::{FunctionAttributes, Instruction, Register};
+
+    let function = |name: &str, entry, arity| FunctionEntry {
+        name: name.to_string(),
+        entry,
+        arity,
+        register_count: None,
+        attributes: FunctionAttributes::default(),
+    };
+    BasicProgram::from_module(Module {
+        layout: Layout::Blocks,
+        functions: vec![
+            function("call_short", 0, Some(1)),
+            function("subtract", 1, None),
+        ],
+        blocks: vec![
+            vec![
+                Instruction::LoadArgs(vec![Register(0)]),
+                Instruction::Call {
+                    function_id: "subtract".to_string(),
+                    arguments: vec![Register(0)],
+                    out: Register(1),
+                },
+                Instruction::Ret(Register(1)),
+            ],
+            vec![
+                Instruction::LoadArgs(vec![Register(0), Register(1)]),
+                Instruction::Subtract {
+                    lhs: Register(0),
+                    rhs: Register(1),
+                    out: Register(2),
+                },
+                Instruction::Ret(Register(2)),
+            ],
+        ],
+    })
+    .unwrap()
+}
+
+/// calls are checked against the amount of arguments a function loads when the program doesn't know its arity, just like in the interpreter
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn x86_64_short_call_traps() {
+    let program = build_short_call();
+    assert!(
+        calc_interpreter::interpret_function(&"call_short".to_string(), &program, &[1]).is_err()
+    );
+    let assembly = crate::x86_64::emit(&program).unwrap();
+    let object = crate::elf::emit(&program).unwrap();
+
+    for (name, source) in [
+        ("x86_64_short", ("s", assembly.as_bytes())),
+        ("elf_short", ("o", &object[..])),
+    ] {
+        let results = compile_and_run(
+            name,
+            &[source],
+            "intptr_t call_short(intptr_t);",
+            "print(call_short(1));",
+        );
+        assert_eq!(results, None, "{name}");
+    }
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn elf_matches_interpreter() {
+    let (program, calls) = test_program();
+    let (expected, declarations, body) = expected_results(&program, &calls);
+    let object = crate::elf::emit(&program).unwrap();
+
+    let results = compile_and_run(
+        "elf",
+        &[("o", &object), ("c", HOST_ADD.as_bytes())],
+        &declarations,
+        &body,
+    );
+    assert_eq!(results, Some(expected));
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn elf_division_by_zero_traps() {
+    let mut builder = Program::new();
+    build_arithmetic(&mut builder, "divide", Arithmetic::Divide);
+    let program = builder.finalize();
+    let object = crate::elf::emit(&program).unwrap();
+
+    let results = compile_and_run(
+        "elf_trap",
+        &[("o", &object)],
+        "intptr_t divide(intptr_t, intptr_t);",
+        "print(divide(1, 0));",
+    );
+    assert_eq!(results, None);
+}
+
+#[test]
+fn x86_64_encoding() {
+    use crate::x86_64::instruction::{Alu, Condition, Encoder, Gpr, Instruction, Rm};
+
+    let r15 = Rm::Memory {
+        base: Gpr::R15,
+        displacement: 16,
+    };
+    let r12 = Rm::Memory {
+        base: Gpr::R12,
+        displacement: -1024,
+    };
+    let instructions = [
+        (Instruction::Label("start"), "start:", &[][..]),
+        (
+            Instruction::Alu(Alu::Compare, r15, Gpr::Rsp),
+            "cmpq 16(%r15), %rsp",
+            &[0x49, 0x3b, 0x67, 0x10],
+        ),
+        (
+            Instruction::Store(Gpr::Rax, r12),
+            "movq %rax, -1024(%r12)",
+            &[0x49, 0x89, 0x84, 0x24, 0x00, 0xfc, 0xff, 0xff],
+        ),
+        (
+            Instruction::AluImmediate(Alu::Compare, -1, Rm::Register(Gpr::Rcx)),
+            "cmpq $-1, %rcx",
+            &[0x48, 0x83, 0xf9, 0xff],
+        ),
+        (
+            Instruction::Xor32(Gpr::Rax, Gpr::Rax),
+            "xorl %eax, %eax",
+            &[0x31, 0xc0],
+        ),
+        (
+            Instruction::Push(Rm::Register(Gpr::R13)),
+            "pushq %r13",
+            &[0x41, 0x55],
+        ),
+        (
+            Instruction::JumpIf(Condition::Below, "start"),
+            "jb start",
+            &[0x0f, 0x82, 0xe6, 0xff, 0xff, 0xff],
+        ),
+    ];
+
+    let mut encoder = Encoder::default();
+    let mut expected = Vec::new();
+    for (instruction, assembly, code) in instructions {
+        assert_eq!(instruction.to_string(), assembly);
+        encoder.encode(instruction);
+        expected.extend(code);
+    }
+    assert!(encoder.link(|_| None::<()>).is_empty());
+    assert_eq!(encoder.code, expected);
+}
+
+#[test]
+fn symbols() {
+    assert_eq!(crate::symbol(&"main"), "main");
+    assert_eq!(crate::symbol(&"my-function"), "my_function");
+    assert_eq!(crate::symbol(&"2x"), "_2x");
+
+    let mut builder = Program::new();
+    build_arithmetic(&mut builder, "a-b", Arithmetic::Add);
+    build_arithmetic(&mut builder, "a_b", Arithmetic::Add);
+    let program = builder.finalize();
+    assert!(matches!(
+        crate::x86_64::emit(&program),
+        Err(crate::CodegenError::SymbolCollision(..))
+    ));
+}
+
+/// counts down from its argument recursively, which is deep enough to run out of the stack that compiled code is allowed
+fn build_countdown(builder: &mut Program) {
+    let mut function = builder.make_fn("countdown".to_string());
+    let base_block_id = function.reserve_block();
+
+    let mut entry_block = function.build_block();
+    let n = entry_block.add_load_args(1)[0];
+    let one = entry_block.add_immediate(1);
+    entry_block.add_cond_jump_with_args(BlockJump::Zero(n), base_block_id, vec![n]);
+    let n_minus_one = entry_block.add_arithmetic(Arithmetic::Subtract, n, one);
+    let rest = entry_block.add_fn_call("countdown".to_string(), vec![n_minus_one]);
+    let result = entry_block.add_arithmetic(Arithmetic::Add, rest, one);
+    entry_block.add_ret(result);
+    let (entry_block_id, function) = entry_block.finalize();
+
+    let mut base_block = function.build_reserved_block(base_block_id);
+    let result = base_block.add_block_params(1)[0];
+    base_block.add_ret(result);
+    let (_, function) = base_block.finalize();
+    function.finalize(entry_block_id);
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn jit_matches_interpreter() {
+    let (program, calls) = test_program();
+    let natives = host_natives();
+    let interpreter = calc_interpreter::Interpreter::new(&program).with_natives(&natives);
+    let jit = crate::jit::Jit::compile(&program)
+        .unwrap()
+        .with_natives(&natives);
+
+    for (function, arguments) in calls {
+        let function = function.to_string();
+        assert!(jit.is_compiled(&function));
+        assert_eq!(
+            jit.function(&function).call(&arguments),
+            interpreter.interpret(&function, &arguments),
+            "{function}{arguments:?}"
+        );
+    }
+}
+
+/// whatever compiled code can't finish is handed over to the interpreter, which gets the same result or error as it would have on its own
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn jit_falls_back() {
+    use calc_interpreter::Error;
+    use calc_ir::arithmetic::ArithmeticError;
+
+    let mut builder = Program::new();
+    build_arithmetic(&mut builder, "divide", Arithmetic::Divide);
+    build_countdown(&mut builder);
+    build_many_arguments(&mut builder);
+    let program = builder.finalize();
+    let jit = crate::jit::Jit::compile(&program).unwrap();
+
+    let call = |function: &str, arguments: &[Number]| {
+        let function = function.to_string();
+        let result = jit.function(&function).call(arguments);
+        assert_eq!(
+            result,
+            calc_interpreter::interpret_function(&function, &program, arguments)
+        );
+        result
+    };
+    assert_eq!(call("divide", &[7, 2]), Ok(3));
+    assert_eq!(
+        call("divide", &[7, 0]),
+        Err(Error::Arithmetic(ArithmeticError::DivisionByZero))
+    );
+    assert_eq!(
+        call("divide", &[7]),
+        Err(Error::ArgumentMismatch {
+            expected: 2,
+            provided: 1
+        })
+    );
+    assert_eq!(call("countdown", &[1000]), Ok(1000));
+    assert_eq!(call("countdown", &[100_000]), Ok(100_000));
+    assert_eq!(
+        call("call_host", &[]),
+        Err(Error::UnknownFunction("host_add".to_string()))
+    );
+    assert_eq!(
+        call("missing", &[]),
+        Err(Error::UnknownFunction("missing".to_string()))
+    );
+    assert!(!jit.function(&"missing".to_string()).is_compiled());
+}
+
+/// compiled code stays within the bounds of the stack of the thread it runs on, however little of it is left
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn jit_small_stack() {
+    let mut builder = Program::new();
+    build_countdown(&mut builder);
+    let program = builder.finalize();
+
+    let result = std::thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            let jit = crate::jit::Jit::compile(&program).unwrap();
+            jit.function(&"countdown".to_string()).call(&[100_000])
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+    assert_eq!(result, Ok(100_000));
+}
+
+#[test]
+fn c_matches_interpreter() {
+    let (program, calls) = test_program();
+    let (expected, declarations, body) = expected_results(&program, &calls);
+    let source = crate::c::emit(&program).unwrap();
+
+    let results = compile_and_run(
+        "c",
+        &[("c", source.as_bytes()), ("c", HOST_ADD.as_bytes())],
+        &declarations,
+        &body,
+    );
+    assert_eq!(results, Some(expected));
+}
+
+/// functions named after C keywords or what the C library declares are renamed, so the output still compiles
+#[test]
+fn c_reserved_names() {
+    let mut builder = Program::new();
+    for (name, operation) in [
+        ("int", Arithmetic::Add),
+        ("return", Arithmetic::Subtract),
+        ("abort", Arithmetic::Multiply),
+        ("main", Arithmetic::Add),
+        ("size_t", Arithmetic::Subtract),
+        ("CHAR_BIT", Arithmetic::Multiply),
+    ] {
+        build_arithmetic(&mut builder, name, operation);
+    }
+    // calls one of them, and `exit`, which the program doesn't define
+    let mut function = builder.make_fn("caller".to_string());
+    let mut entry_block = function.build_block();
+    let args = entry_block.add_load_args(2);
+    let sum = entry_block.add_fn_call("int".to_string(), args.clone());
+    let result = entry_block.add_fn_call("exit".to_string(), vec![sum]);
+    entry_block.add_ret(result);
+    let (entry_block_id, function) = entry_block.finalize();
+    function.finalize(entry_block_id);
+    let program = builder.finalize();
+    let source = crate::c::emit(&program).unwrap();
+
+    let results = compile_and_run(
+        "c_reserved",
+        &[("c", source.as_bytes())],
+        "intptr_t calc_fn_int(intptr_t, intptr_t);\n\
+         intptr_t calc_fn_return(intptr_t, intptr_t);\n\
+         intptr_t calc_fn_abort(intptr_t, intptr_t);\n\
+         intptr_t calc_fn_main(intptr_t, intptr_t);\n\
+         intptr_t calc_fn_size_t(intptr_t, intptr_t);\n\
+         intptr_t calc_fn_CHAR_BIT(intptr_t, intptr_t);\n\
+         intptr_t caller(intptr_t, intptr_t);\n\
+         intptr_t calc_fn_exit(intptr_t value) { return value * 10; }",
+        "print(calc_fn_int(2, 3)); print(calc_fn_return(2, 3)); print(calc_fn_abort(2, 3));\n\
+         print(calc_fn_main(2, 3)); print(calc_fn_size_t(2, 3)); print(calc_fn_CHAR_BIT(2, 3));\n\
+         print(caller(2, 3));",
+    );
+    assert_eq!(results, Some(vec![5, -1, 6, 5, -1, 6, 50]));
+}
+
+#[test]
+fn c_division_by_zero_traps() {
+    let mut builder = Program::new();
+    build_arithmetic(&mut builder, "divide", Arithmetic::Divide);
+    let program = builder.finalize();
+    let source = crate::c::emit(&program).unwrap();
+
+    let results = compile_and_run(
+        "c_trap",
+        &[("c", source.as_bytes())],
+        "intptr_t divide(intptr_t, intptr_t);",
+        "print(divide(1, 0));",
+    );
+    assert_eq!(results, None);
+}
+
+/// assemble `module`, which validates it, instantiate it with `host_add` as its only import, and make `calls` to it.
+///
+/// Returns the result of every call, or None if it trapped
+fn run_wasm(module: &str, calls: &[(&str, Vec<Number>)]) -> Vec<Option<Number>> {
+    use wasmi::{Engine, Linker, Module, Store, Val};
+
+    let engine = Engine::default();
+    let module = Module::new(&engine, &wat::parse_str(module).unwrap()).unwrap();
+    let mut store = Store::new(&engine, ());
+    let mut linker = Linker::new(&engine);
+    linker
+        .func_wrap(
+            "env",
+            "host_add",
+            |_: wasmi::Caller<'_, ()>, a: i64, b: i64, c: i64, d: i64, e: i64, f: i64, g: i64| {
+                a + b + c + d + e + f + g * 100
+            },
+        )
+        .unwrap();
+    let instance = linker
+        .instantiate(&mut store, &module)
+        .unwrap()
+        .start(&mut store)
+        .unwrap();
+
+    calls
+        .iter()
+        .map(|(function, arguments)| {
+            let function = instance.get_func(&store, function).unwrap();
+            let arguments: Vec<_> = arguments
+                .iter()
+                .map(|argument| Val::I64(i64::try_from(*argument).unwrap()))
+                .collect();
+            let mut result = [Val::I64(0)];
+            function.call(&mut store, &arguments, &mut result).ok()?;
+            result[0]
+                .i64()
+                .map(|result| Number::try_from(result).unwrap())
+        })
+        .collect()
+}
+
+#[test]
+fn wasm_matches_interpreter() {
+    let (program, calls) = test_program();
+    let (expected, _, _) = expected_results(&program, &calls);
+    let module = crate::wasm::emit(&program).unwrap();
+
+    // only ping_pong has a loop with more than one way in
+    assert_eq!(module.matches("br_table").count(), 1);
+    let results = run_wasm(&module, &calls);
+    assert_eq!(results, expected.into_iter().map(Some).collect::<Vec<_>>());
+}
+
+#[test]
+fn wasm_division_by_zero_traps() {
+    let mut builder = Program::new();
+    build_arithmetic(&mut builder, "divide", Arithmetic::Divide);
+    build_arithmetic(&mut builder, "modulo", Arithmetic::Mod);
+    build_many_arguments(&mut builder);
+    let program = builder.finalize();
+    let module = crate::wasm::emit(&program).unwrap();
+
+    let results = run_wasm(
+        &module,
+        &[
+            ("divide", vec![1, 0]),
+            ("modulo", vec![1, 0]),
+            ("divide", vec![Number::MIN, -1]),
+        ],
+    );
+    assert_eq!(results, [None, None, Some(Number::MIN)]);
+}
+
+/// compare `output` with the file `name` in `golden/`, which is overwritten with `output` instead if `UPDATE_GOLDEN` is set
+fn check_golden(name: &str, output: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("golden")
+        .join(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, output).unwrap();
+    }
+    let golden = std::fs::read_to_string(&path).unwrap();
+    assert!(
+        output == golden,
+        "the output differs from {}, rerun with UPDATE_GOLDEN=1 if that's intended",
+        path.display()
+    );
+}
+
+#[test]
+fn llvm_golden() {
+    let mut builder = Program::new();
+    build_arithmetic(&mut builder, "divide", Arithmetic::Divide);
+    build_bitwise(&mut builder, "shift_right", BitWise::ShiftRight);
+    build_fibonacci(&mut builder);
+    build_swap(&mut builder);
+    build_many_arguments(&mut builder);
+    let program = builder.finalize();
+
+    check_golden("llvm.ll", &crate::llvm::emit(&program).unwrap());
+}
+
+#[test]
+fn live_intervals() {
+    use crate::regalloc::{allocate, live_intervals, LiveInterval, Location};
+    use calc_ir::Register;
+
+    let mut builder = Program::new();
+    build_arithmetic(&mut builder, "add", Arithmetic::Add);
+    let program = builder.finalize();
+    let graph = calc_optimizer::Graph::from_program(&program, vec!["add".to_string()]);
+    let add = graph.function(&"add".to_string()).unwrap();
+
+    let interval = |register, start, end| LiveInterval {
+        register: Register(register),
+        start,
+        end,
+    };
+    assert_eq!(
+        live_intervals(add),
+        [interval(0, 1, 2), interval(1, 1, 2), interval(2, 3, 4)]
+    );
+
+    // the result is written after both arguments are read for the last time, so it can take the place of one of them
+    let allocation = allocate(add, 2);
+    assert_eq!(
+        allocation.location(Register(0)),
+        Some(Location::Register(0))
+    );
+    assert_eq!(
+        allocation.location(Register(1)),
+        Some(Location::Register(1))
+    );
+    assert_eq!(
+        allocation.location(Register(2)),
+        Some(Location::Register(0))
+    );
+    assert_eq!(allocation.stack_slots(), 0);
+
+    let allocation = allocate(add, 1);
+    assert_eq!(
+        allocation.location(Register(0)),
+        Some(Location::Register(0))
+    );
+    assert_eq!(allocation.location(Register(1)), Some(Location::Stack(0)));
+    assert_eq!(
+        allocation.location(Register(2)),
+        Some(Location::Register(0))
+    );
+    assert_eq!(allocation.stack_slots(), 1);
+}
+
+#[test]
+fn regalloc_matches_interpreter() {
+    use crate::regalloc::{verify, AllocatedProgram};
+
+    let (program, calls) = test_program();
+    let functions = program
+        .get_all_functions()
+        .into_iter()
+        .map(|(function, _)| function.clone())
+        .collect();
+    let graph = calc_optimizer::Graph::from_program(&program, functions);
+
+    for registers in [0, 1, 2, 3, 8, 64] {
+        let allocated = AllocatedProgram::new(&graph, registers);
+        for (function, arguments) in &calls {
+            let function = (*function).to_string();
+            if let Err(mismatch) = verify(&program, &allocated, &function, arguments) {
+                panic!("with {registers} registers: {mismatch}\n{allocated}");
+            }
+        }
+
+        let weighted = allocated.allocation(&"weighted".to_string()).unwrap();
+        // all 8 arguments are live at once
+        assert!(weighted.registers() + weighted.stack_slots() >= 8);
+        if registers == 64 {
+            assert_eq!(weighted.stack_slots(), 0);
+        }
+    }
+}
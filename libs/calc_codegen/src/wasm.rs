@@ -0,0 +1,450 @@
+//! Lowering to a WebAssembly module in the text format
+//!
+//! Every function becomes a function of the module that's exported under its [`symbol`], takes an `i64` for each argument and returns an `i64`,
+//! and every register becomes an `i64` local. Functions that the program doesn't define are imported from the `env` module.
+//!
+//! WebAssembly has no `goto`, so the structured control flow is recovered from the graph of each function, following
+//! Norman Ramsey's "Beyond Relooper": blocks that several blocks jump to are placed after a `block` that the jumps break out of,
+//! blocks that are jumped back to are the head of a `loop`, and every other block is placed right where the only jump to it is.
+//! This only works if every loop has a single way in. Functions where one doesn't are lowered to a loop around a `br_table`
+//! that picks the block to run next instead, which is correct for any graph but much harder for the engine to optimize.
+//!
+//! Numbers are always 64 bits wide in the output, so it matches the interpreter wherever [`calc_ir::Number`] is as well.
+
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Display, Write};
+use std::hash::Hash;
+
+use calc_ir::arithmetic::Operation;
+use calc_ir::{Instruction, Number, Program, Register};
+use calc_optimizer::analysis::DominatorTree;
+use calc_optimizer::{FunctionGraph, Graph};
+
+use crate::{
+    block_params, functions, jump_moves, outside_functions, program_graph, symbol, used_registers,
+    CodegenError, Function,
+};
+
+/// The helpers for operations that WebAssembly has no instruction with the same behaviour for
+const PRELUDE: &str = "\
+  ;; the minimum divided by -1 overflows, which div_s traps on instead of wrapping back to the minimum
+  (func $calc_divide (param $lhs i64) (param $rhs i64) (result i64)
+    local.get $rhs
+    i64.const -1
+    i64.eq
+    if (result i64)
+      i64.const 0
+      local.get $lhs
+      i64.sub
+    else
+      local.get $lhs
+      local.get $rhs
+      i64.div_s
+    end)
+";
+
+/// The instruction that performs `operation` on the two values on top of the stack
+fn operation_instruction(operation: Operation) -> &'static str {
+    match operation {
+        Operation::Add => "i64.add",
+        Operation::Subtract => "i64.sub",
+        Operation::Multiply => "i64.mul",
+        Operation::Divide => "call $calc_divide",
+        // rem_s already gives 0 for the minimum and -1
+        Operation::Modulo => "i64.rem_s",
+        Operation::BitOr => "i64.or",
+        Operation::BitNotOr => "i64.xor",
+        Operation::BitAnd => "i64.and",
+        // shifts only use the low 6 bits of the amount, just like wrapping shifts
+        Operation::ShiftL => "i64.shl",
+        Operation::ShiftR => "i64.shr_s",
+    }
+}
+
+/// The signature of a function taking `count` arguments
+fn signature(count: usize) -> String {
+    let mut signature: String = (0..count).map(|_| "(param i64) ").collect();
+    signature.push_str("(result i64)");
+    signature
+}
+
+fn register(register: Register) -> String {
+    format!("$r{}", register.0)
+}
+
+/// Lower every function in `program` to a WebAssembly module in the text format
+///
+/// # Errors
+/// If the program can't be lowered, see [`CodegenError`]
+pub fn emit<
+    BlockPointerT: Eq + Debug + Clone,
+    FunctionPointerT: Eq + Debug + Clone + Hash + Display,
+    ProgramT: Program<BlockPointer = BlockPointerT, FunctionPointer = FunctionPointerT, Number = Number>,
+>(
+    program: &ProgramT,
+) -> Result<String, CodegenError<FunctionPointerT>> {
+    let graph = program_graph(program);
+    let functions = functions(&graph)?;
+
+    let mut module = String::from("(module\n");
+    // imports have to come before any function
+    let outside = outside_functions(&graph, &functions);
+    for (symbol, arity) in &outside {
+        writeln!(
+            module,
+            "  (import \"env\" \"{symbol}\" (func ${symbol} {}))",
+            signature(*arity)
+        )
+        .expect("writing to a String can't fail");
+    }
+    module.push_str(PRELUDE);
+
+    for function in &functions {
+        FunctionEmitter {
+            out: &mut module,
+            graph: &graph,
+            function,
+            outside: &outside,
+            shape: Shape::of(function.graph),
+            depth: 1,
+        }
+        .emit();
+    }
+    module.push_str(")\n");
+    Ok(module)
+}
+
+/// What the stackifier needs to know about the graph of a function
+struct Shape {
+    /// the position of every block reachable from the entry in reverse postorder, which every jump that isn't to an earlier block goes forward in
+    order: Vec<Option<usize>>,
+    dominators: DominatorTree,
+    /// whether several jumps go forward to a block, so it needs a `block` to break out of
+    merge: Vec<bool>,
+    /// whether any jump goes back to a block, so it needs a `loop` to continue
+    header: Vec<bool>,
+    /// whether every jump back goes to a block that dominates the one it's from, so that every loop has a single way in
+    reducible: bool,
+}
+
+impl Shape {
+    fn of<FunctionPointerT: Eq + Debug + Clone + Hash>(
+        function: &FunctionGraph<FunctionPointerT>,
+    ) -> Self {
+        let count = function.blocks.len();
+        let jumps = |block: usize| {
+            function.blocks[block]
+                .iter()
+                .filter_map(Instruction::jump_target)
+                .map(|(to, _)| *to)
+        };
+
+        let dominators = DominatorTree::dominators(function);
+        let reachable = dominators.reverse_postorder();
+        let mut order = vec![None; count];
+        for (position, block) in reachable.iter().enumerate() {
+            order[*block] = Some(position);
+        }
+
+        let mut merge = vec![false; count];
+        let mut header = vec![false; count];
+        let mut forward = vec![0_usize; count];
+        for &block in reachable {
+            for to in jumps(block) {
+                if order[to] <= order[block] {
+                    header[to] = true;
+                } else {
+                    forward[to] += 1;
+                    merge[to] |= forward[to] > 1;
+                }
+            }
+        }
+
+        let reducible = reachable.iter().all(|&block| {
+            jumps(block).all(|to| order[to] > order[block] || dominators.dominates(to, block))
+        });
+
+        Self {
+            order,
+            dominators,
+            merge,
+            header,
+            reducible,
+        }
+    }
+
+    /// The blocks that `block` immediately dominates which need a `block` of their own, in reverse postorder
+    fn merge_children(&self, block: usize) -> Vec<usize> {
+        self.dominators
+            .children(block)
+            .iter()
+            .copied()
+            .filter(|child| self.merge[*child])
+            .collect()
+    }
+}
+
+/// Emits a single function
+struct FunctionEmitter<'a, 'g, FunctionPointerT: Eq + Debug + Clone + Hash> {
+    out: &'a mut String,
+    graph: &'g Graph<FunctionPointerT>,
+    function: &'a Function<'g, FunctionPointerT>,
+    /// the arity that every function the program doesn't import is declared with
+    outside: &'a BTreeMap<String, usize>,
+    shape: Shape,
+    /// how far the current line is indented
+    depth: usize,
+}
+
+impl<FunctionPointerT: Eq + Debug + Clone + Hash + Display>
+    FunctionEmitter<'_, '_, FunctionPointerT>
+{
+    fn line(&mut self, line: impl Display) {
+        writeln!(self.out, "{:1$}{line}", "", self.depth * 2)
+            .expect("writing to a String can't fail");
+    }
+
+    fn emit(mut self) {
+        let symbol = &self.function.symbol;
+        let mut header = format!("(func ${symbol} (export \"{symbol}\")");
+        for argument in 0..self.function.arity {
+            write!(header, " (param $a{argument} i64)").expect("writing to a String can't fail");
+        }
+        header.push_str(" (result i64)");
+        self.line(header);
+        self.depth += 1;
+
+        let registers = used_registers(self.function.graph);
+        for register in registers {
+            let register = self::register(register);
+            self.line(format!("(local {register} i64)"));
+        }
+
+        // the function was called without the arguments its entry block loads
+        if block_params(
+            self.function.graph,
+            FunctionGraph::<FunctionPointerT>::ENTRY,
+        )
+        .is_some_and(|params| !params.is_empty())
+        {
+            self.line("unreachable");
+        } else if self.shape.reducible {
+            self.tree(FunctionGraph::<FunctionPointerT>::ENTRY);
+        } else {
+            self.dispatch();
+        }
+        // the end of a `block` or `loop` can be reached as far as validation is concerned, even though every path through it branches away
+        self.line("unreachable)");
+        self.depth -= 1;
+    }
+
+    /// The code for `block` and every block that it dominates
+    fn tree(&mut self, block: usize) {
+        let children = self.shape.merge_children(block);
+        if self.shape.header[block] {
+            self.line(format!("loop $loop{block}"));
+            self.depth += 1;
+            self.within(block, &children);
+            self.depth -= 1;
+            self.line("end");
+        } else {
+            self.within(block, &children);
+        }
+    }
+
+    /// The code for `block`, with the blocks in `children` placed after it, the last one outermost
+    fn within(&mut self, block: usize, children: &[usize]) {
+        if let Some((last, rest)) = children.split_last() {
+            self.line(format!("block $block{last}"));
+            self.depth += 1;
+            self.within(block, rest);
+            self.depth -= 1;
+            self.line("end");
+            self.tree(*last);
+        } else {
+            self.block(block, &mut |emitter, to| emitter.branch(block, to));
+        }
+    }
+
+    /// Transfer control from `from` to `to`, once the block arguments are in place
+    fn branch(&mut self, from: usize, to: usize) {
+        if self.shape.order[to] <= self.shape.order[from] {
+            self.line(format!("br $loop{to}"));
+        } else if self.shape.merge[to] {
+            self.line(format!("br $block{to}"));
+        } else {
+            self.tree(to);
+        }
+    }
+
+    /// The code for every block in a loop around a `br_table`, which works for any graph
+    fn dispatch(&mut self) {
+        let count = self.function.graph.blocks.len();
+        self.line("(local $next i32)");
+        self.line("loop $dispatch");
+        self.depth += 1;
+        for block in (0..count).rev() {
+            self.line(format!("block $block{block}"));
+            self.depth += 1;
+        }
+        let targets: Vec<_> = (0..count).map(|block| format!("$block{block}")).collect();
+        self.line("local.get $next");
+        self.line(format!("br_table {} $block0", targets.join(" ")));
+        for block in 0..count {
+            self.depth -= 1;
+            self.line("end");
+            self.block(block, &mut |emitter, to| {
+                emitter.line(format!("i32.const {to}"));
+                emitter.line("local.set $next");
+                emitter.line("br $dispatch");
+            });
+        }
+        self.depth -= 1;
+        self.line("end");
+    }
+
+    /// The code for the instructions in `block`, where `branch` transfers control to another block
+    fn block(&mut self, block: usize, branch: &mut dyn FnMut(&mut Self, usize)) {
+        let graph = self.function.graph;
+        let instructions = &graph.blocks[block];
+        for instruction in instructions {
+            self.instruction(instruction, branch);
+        }
+        if !instructions.last().is_some_and(Instruction::is_terminator) {
+            self.line("unreachable");
+        }
+    }
+
+    fn instruction(
+        &mut self,
+        instruction: &Instruction<usize, FunctionPointerT>,
+        branch: &mut dyn FnMut(&mut Self, usize),
+    ) {
+        if let Some((operation, lhs, rhs, out)) = instruction.operation() {
+            self.line(format!("local.get {}", register(lhs)));
+            self.line(format!("local.get {}", register(rhs)));
+            self.line(operation_instruction(operation));
+            self.line(format!("local.set {}", register(out)));
+            return;
+        }
+
+        match instruction {
+            Instruction::LoadImmediate(value, out) => {
+                self.line(format!("i64.const {value}"));
+                self.line(format!("local.set {}", register(*out)));
+            }
+            Instruction::Call {
+                function_id,
+                arguments,
+                out,
+            } => {
+                let symbol = symbol(function_id);
+                let arity = match self.graph.function(function_id) {
+                    Some(callee) => crate::arity(callee),
+                    None => self.outside[&symbol],
+                };
+                if arguments.len() != arity {
+                    self.line("unreachable");
+                    return;
+                }
+                for argument in arguments {
+                    self.line(format!("local.get {}", register(*argument)));
+                }
+                self.line(format!("call ${symbol}"));
+                self.line(format!("local.set {}", register(*out)));
+            }
+            Instruction::Ret(value) => {
+                self.line(format!("local.get {}", register(*value)));
+                self.line("return");
+            }
+            Instruction::LoadArgs(registers) => {
+                if registers.len() != self.function.arity {
+                    self.line("unreachable");
+                    return;
+                }
+                for (argument, register) in registers.iter().enumerate() {
+                    self.line(format!("local.get $a{argument}"));
+                    self.line(format!("local.set {}", self::register(*register)));
+                }
+            }
+            Instruction::LoadBlockArgs(_) => {}
+            Instruction::Jump { to, arguments } => self.jump(*to, arguments, branch),
+            Instruction::JEqual {
+                lhs,
+                rhs,
+                to,
+                arguments,
+            }
+            | Instruction::JNotEqual {
+                lhs,
+                rhs,
+                to,
+                arguments,
+            } => {
+                self.line(format!("local.get {}", register(*lhs)));
+                self.line(format!("local.get {}", register(*rhs)));
+                self.line(if matches!(instruction, Instruction::JEqual { .. }) {
+                    "i64.eq"
+                } else {
+                    "i64.ne"
+                });
+                self.conditional_jump(*to, arguments, branch);
+            }
+            Instruction::JNonZero {
+                check,
+                to,
+                arguments,
+            }
+            | Instruction::JZero {
+                check,
+                to,
+                arguments,
+            } => {
+                self.line(format!("local.get {}", register(*check)));
+                if matches!(instruction, Instruction::JZero { .. }) {
+                    self.line("i64.eqz");
+                } else {
+                    self.line("i64.const 0");
+                    self.line("i64.ne");
+                }
+                self.conditional_jump(*to, arguments, branch);
+            }
+            Instruction::Invalid => self.line("unreachable"),
+            _ => unreachable!("arithmetic instructions are handled above"),
+        }
+    }
+
+    /// Move `arguments` into the registers that `to` loads them into, and transfer control to it
+    fn jump(
+        &mut self,
+        to: usize,
+        arguments: &[Register],
+        branch: &mut dyn FnMut(&mut Self, usize),
+    ) {
+        let Some(moves) = jump_moves(self.function.graph, to, arguments) else {
+            self.line("unreachable");
+            return;
+        };
+        for (argument, _) in &moves {
+            self.line(format!("local.get {}", register(*argument)));
+        }
+        for (_, param) in moves.iter().rev() {
+            self.line(format!("local.set {}", register(*param)));
+        }
+        branch(self, to);
+    }
+
+    /// Jump if the condition on top of the stack holds
+    fn conditional_jump(
+        &mut self,
+        to: usize,
+        arguments: &[Register],
+        branch: &mut dyn FnMut(&mut Self, usize),
+    ) {
+        self.line("if");
+        self.depth += 1;
+        self.jump(to, arguments, branch);
+        self.depth -= 1;
+        self.line("end");
+    }
+}
@@ -0,0 +1,402 @@
+//! The x86-64 instructions that code is lowered to, which can be written out as assembly or encoded to machine code.
+//!
+//! Only the forms that lowering actually uses exist, and every instruction that can be built can be encoded.
+//! Every jump and call is encoded with a 32-bit displacement, so none of them ever has to be relaxed once their labels are known.
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::hash::Hash;
+
+/// A general purpose register, numbered as it is in encodings, leaving out those that lowering never uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Gpr {
+    Rax = 0,
+    Rcx = 1,
+    Rdx = 2,
+    Rbx = 3,
+    Rsp = 4,
+    Rbp = 5,
+    Rsi = 6,
+    Rdi = 7,
+    R8 = 8,
+    R9 = 9,
+    R12 = 12,
+    R13 = 13,
+    R14 = 14,
+    R15 = 15,
+}
+
+impl Gpr {
+    fn number(self) -> u8 {
+        self as u8
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Gpr::Rax => "rax",
+            Gpr::Rcx => "rcx",
+            Gpr::Rdx => "rdx",
+            Gpr::Rbx => "rbx",
+            Gpr::Rsp => "rsp",
+            Gpr::Rbp => "rbp",
+            Gpr::Rsi => "rsi",
+            Gpr::Rdi => "rdi",
+            Gpr::R8 => "r8",
+            Gpr::R9 => "r9",
+            Gpr::R12 => "r12",
+            Gpr::R13 => "r13",
+            Gpr::R14 => "r14",
+            Gpr::R15 => "r15",
+        }
+    }
+
+    /// The name of the low 32 bits of the register
+    fn name32(self) -> String {
+        match self.number() {
+            0..=7 => format!("e{}", &self.name()[1..]),
+            _ => format!("{}d", self.name()),
+        }
+    }
+}
+
+impl Display for Gpr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "%{}", self.name())
+    }
+}
+
+/// An operand that goes in the `r/m` field of the `ModRM` byte, a register or memory at a displacement from one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Rm {
+    Register(Gpr),
+    Memory { base: Gpr, displacement: i32 },
+}
+
+impl Rm {
+    fn base(self) -> Gpr {
+        match self {
+            Rm::Register(register) | Rm::Memory { base: register, .. } => register,
+        }
+    }
+}
+
+impl Display for Rm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rm::Register(register) => write!(f, "{register}"),
+            Rm::Memory { base, displacement } => write!(f, "{displacement}({base})"),
+        }
+    }
+}
+
+/// The arithmetic instructions that share their encodings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Alu {
+    Add,
+    Or,
+    And,
+    Subtract,
+    Xor,
+    Compare,
+}
+
+impl Alu {
+    /// The opcode extension of the instruction with an immediate, which also picks the opcode of the other forms
+    fn extension(self) -> u8 {
+        match self {
+            Alu::Add => 0,
+            Alu::Or => 1,
+            Alu::And => 4,
+            Alu::Subtract => 5,
+            Alu::Xor => 6,
+            Alu::Compare => 7,
+        }
+    }
+
+    fn mnemonic(self) -> &'static str {
+        match self {
+            Alu::Add => "addq",
+            Alu::Or => "orq",
+            Alu::And => "andq",
+            Alu::Subtract => "subq",
+            Alu::Xor => "xorq",
+            Alu::Compare => "cmpq",
+        }
+    }
+}
+
+/// The conditions that conditional jumps are taken on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Condition {
+    Equal,
+    NotEqual,
+    /// unsigned less than
+    Below,
+}
+
+impl Condition {
+    fn code(self) -> u8 {
+        match self {
+            Condition::Below => 0x2,
+            Condition::Equal => 0x4,
+            Condition::NotEqual => 0x5,
+        }
+    }
+
+    fn suffix(self) -> &'static str {
+        match self {
+            Condition::Below => "b",
+            Condition::Equal => "e",
+            Condition::NotEqual => "ne",
+        }
+    }
+}
+
+/// A single instruction, or a label marking where the next one starts. Jumps and calls go to labels of type `LabelT`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Instruction<LabelT> {
+    Label(LabelT),
+    /// `register = register <op> rm`, or just setting the flags for [`Alu::Compare`]
+    Alu(Alu, Rm, Gpr),
+    /// `rm = rm <op> immediate`, or just setting the flags for [`Alu::Compare`]
+    AluImmediate(Alu, i32, Rm),
+    Load(Rm, Gpr),
+    Store(Gpr, Rm),
+    /// move an immediate that's sign extended from 32 bits
+    MoveImmediate(i32, Rm),
+    MoveAbsolute(i64, Gpr),
+    /// `register = register * rm`
+    Multiply(Rm, Gpr),
+    /// set the flags for `register & rm`
+    Test(Gpr, Rm),
+    LoadAddress {
+        base: Gpr,
+        displacement: i32,
+        to: Gpr,
+    },
+    /// xor the low 32 bits of two registers, zeroing the upper 32 bits of the second
+    Xor32(Gpr, Gpr),
+    /// shift left by `%cl`
+    ShiftLeft(Rm),
+    /// arithmetic shift right by `%cl`
+    ShiftRight(Rm),
+    Negate(Rm),
+    /// sign extend `%rax` into `%rdx`
+    SignExtend,
+    /// divide `%rdx:%rax` by `rm`, the quotient goes into `%rax` and the remainder into `%rdx`
+    Divide(Rm),
+    Push(Rm),
+    Pop(Rm),
+    Jump(LabelT),
+    JumpIf(Condition, LabelT),
+    Call(LabelT),
+    Trap,
+    Return,
+}
+
+/// Written in GNU assembler syntax, labels are written as `label:` and everything else without indentation
+impl<LabelT: Display> Display for Instruction<LabelT> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Instruction::Label(label) => write!(f, "{label}:"),
+            Instruction::Alu(alu, rm, register) => {
+                write!(f, "{} {rm}, {register}", alu.mnemonic())
+            }
+            Instruction::AluImmediate(alu, immediate, rm) => {
+                write!(f, "{} ${immediate}, {rm}", alu.mnemonic())
+            }
+            Instruction::Load(rm, register) => write!(f, "movq {rm}, {register}"),
+            Instruction::Store(register, rm) => write!(f, "movq {register}, {rm}"),
+            Instruction::MoveImmediate(immediate, rm) => write!(f, "movq ${immediate}, {rm}"),
+            Instruction::MoveAbsolute(immediate, register) => {
+                write!(f, "movabsq ${immediate}, {register}")
+            }
+            Instruction::Multiply(rm, register) => write!(f, "imulq {rm}, {register}"),
+            Instruction::Test(register, rm) => write!(f, "testq {register}, {rm}"),
+            Instruction::LoadAddress {
+                base,
+                displacement,
+                to,
+            } => write!(f, "leaq {displacement}({base}), {to}"),
+            Instruction::Xor32(source, destination) => {
+                write!(f, "xorl %{}, %{}", source.name32(), destination.name32())
+            }
+            Instruction::ShiftLeft(rm) => write!(f, "shlq %cl, {rm}"),
+            Instruction::ShiftRight(rm) => write!(f, "sarq %cl, {rm}"),
+            Instruction::Negate(rm) => write!(f, "negq {rm}"),
+            Instruction::SignExtend => write!(f, "cqto"),
+            Instruction::Divide(rm) => write!(f, "idivq {rm}"),
+            Instruction::Push(rm) => write!(f, "pushq {rm}"),
+            Instruction::Pop(rm) => write!(f, "popq {rm}"),
+            Instruction::Jump(label) => write!(f, "jmp {label}"),
+            Instruction::JumpIf(condition, label) => write!(f, "j{} {label}", condition.suffix()),
+            Instruction::Call(label) => write!(f, "call {label}"),
+            Instruction::Trap => write!(f, "ud2"),
+            Instruction::Return => write!(f, "ret"),
+        }
+    }
+}
+
+/// Encodes instructions to machine code, leaving the 32-bit displacements of jumps and calls for its owner to fill in
+pub(crate) struct Encoder<LabelT> {
+    pub(crate) code: Vec<u8>,
+    /// where every label that's been encoded is in the code
+    pub(crate) labels: Vec<(LabelT, usize)>,
+    /// the position of every 32-bit displacement to a label, relative to the end of the displacement
+    pub(crate) fixups: Vec<(usize, LabelT)>,
+}
+
+impl<LabelT> Default for Encoder<LabelT> {
+    fn default() -> Self {
+        Self {
+            code: Vec::new(),
+            labels: Vec::new(),
+            fixups: Vec::new(),
+        }
+    }
+}
+
+impl<LabelT> Encoder<LabelT> {
+    /// Append machine code that was encoded by hand
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    /// Point the 32-bit displacement at `at` to `to`
+    pub(crate) fn patch(&mut self, at: usize, to: usize) {
+        let relative = i32::try_from(to.wrapping_sub(at + 4).cast_signed())
+            .expect("code is smaller than 2GiB");
+        self.code[at..at + 4].copy_from_slice(&relative.to_le_bytes());
+    }
+
+    /// Point every jump and call at the label it goes to, except those to labels that `external` maps to something else,
+    /// which are returned for the owner of the encoder to resolve
+    pub(crate) fn link<ExternalT>(
+        &mut self,
+        external: impl Fn(&LabelT) -> Option<ExternalT>,
+    ) -> Vec<(usize, ExternalT)>
+    where
+        LabelT: Eq + Hash,
+    {
+        let labels: HashMap<_, _> = std::mem::take(&mut self.labels).into_iter().collect();
+        let mut unresolved = Vec::new();
+        for (at, label) in std::mem::take(&mut self.fixups) {
+            if let Some(external) = external(&label) {
+                unresolved.push((at, external));
+            } else {
+                let to = *labels
+                    .get(&label)
+                    .expect("lowering only jumps to labels that it places");
+                self.patch(at, to);
+            }
+        }
+        unresolved
+    }
+
+    pub(crate) fn encode(&mut self, instruction: Instruction<LabelT>) {
+        match instruction {
+            Instruction::Label(label) => self.labels.push((label, self.code.len())),
+            Instruction::Alu(alu, rm, register) => {
+                self.modrm(true, &[alu.extension() << 3 | 0x03], register.number(), rm);
+            }
+            Instruction::AluImmediate(alu, immediate, rm) => {
+                if let Ok(immediate) = i8::try_from(immediate) {
+                    self.modrm(true, &[0x83], alu.extension(), rm);
+                    self.code.extend(immediate.to_le_bytes());
+                } else {
+                    self.modrm(true, &[0x81], alu.extension(), rm);
+                    self.code.extend(immediate.to_le_bytes());
+                }
+            }
+            Instruction::Load(rm, register) => self.modrm(true, &[0x8b], register.number(), rm),
+            Instruction::Store(register, rm) => self.modrm(true, &[0x89], register.number(), rm),
+            Instruction::MoveImmediate(immediate, rm) => {
+                self.modrm(true, &[0xc7], 0, rm);
+                self.code.extend(immediate.to_le_bytes());
+            }
+            Instruction::MoveAbsolute(immediate, register) => {
+                self.rex(true, 0, register.number());
+                self.code.push(0xb8 + (register.number() & 7));
+                self.code.extend(immediate.to_le_bytes());
+            }
+            Instruction::Multiply(rm, register) => {
+                self.modrm(true, &[0x0f, 0xaf], register.number(), rm);
+            }
+            Instruction::Test(register, rm) => self.modrm(true, &[0x85], register.number(), rm),
+            Instruction::LoadAddress {
+                base,
+                displacement,
+                to,
+            } => self.modrm(
+                true,
+                &[0x8d],
+                to.number(),
+                Rm::Memory { base, displacement },
+            ),
+            Instruction::Xor32(source, destination) => {
+                self.modrm(false, &[0x31], source.number(), Rm::Register(destination));
+            }
+            Instruction::ShiftLeft(rm) => self.modrm(true, &[0xd3], 4, rm),
+            Instruction::ShiftRight(rm) => self.modrm(true, &[0xd3], 7, rm),
+            Instruction::Negate(rm) => self.modrm(true, &[0xf7], 3, rm),
+            Instruction::SignExtend => self.code.extend([0x48, 0x99]),
+            Instruction::Divide(rm) => self.modrm(true, &[0xf7], 7, rm),
+            Instruction::Push(Rm::Register(register)) => {
+                self.rex(false, 0, register.number());
+                self.code.push(0x50 + (register.number() & 7));
+            }
+            Instruction::Push(rm) => self.modrm(false, &[0xff], 6, rm),
+            Instruction::Pop(Rm::Register(register)) => {
+                self.rex(false, 0, register.number());
+                self.code.push(0x58 + (register.number() & 7));
+            }
+            Instruction::Pop(rm) => self.modrm(false, &[0x8f], 0, rm),
+            Instruction::Jump(label) => self.rel32(&[0xe9], label),
+            Instruction::JumpIf(condition, label) => {
+                self.rel32(&[0x0f, 0x80 | condition.code()], label);
+            }
+            Instruction::Call(label) => self.rel32(&[0xe8], label),
+            Instruction::Trap => self.code.extend([0x0f, 0x0b]),
+            Instruction::Return => self.code.push(0xc3),
+        }
+    }
+
+    /// A REX prefix with `w` set for 64 bit operands, extending the `reg` and `r/m` fields to reach `reg` and `rm`, if it's needed at all
+    fn rex(&mut self, w: bool, reg: u8, rm: u8) {
+        let rex = 0x40 | (u8::from(w) << 3) | ((reg >> 3) << 2) | (rm >> 3);
+        if rex != 0x40 {
+            self.code.push(rex);
+        }
+    }
+
+    /// `opcode` followed by the `ModRM` byte addressing `rm`, along with the SIB byte and displacement it needs,
+    /// where `reg` is either a register or an opcode extension
+    fn modrm(&mut self, w: bool, opcode: &[u8], reg: u8, rm: Rm) {
+        self.rex(w, reg, rm.base().number());
+        self.code.extend(opcode);
+        let reg = (reg & 7) << 3;
+        match rm {
+            Rm::Register(register) => self.code.push(0xc0 | reg | (register.number() & 7)),
+            Rm::Memory { base, displacement } => {
+                let short = i8::try_from(displacement).ok();
+                let mode = if short.is_some() { 0x40 } else { 0x80 };
+                self.code.push(mode | reg | (base.number() & 7));
+                // %rsp and %r12 can only be a base through a SIB byte
+                if base.number() & 7 == 4 {
+                    self.code.push(0x24);
+                }
+                match short {
+                    Some(short) => self.code.extend(short.to_le_bytes()),
+                    None => self.code.extend(displacement.to_le_bytes()),
+                }
+            }
+        }
+    }
+
+    /// `opcode` followed by a 32-bit displacement to `label`, which is left for the owner of the encoder to fill in
+    fn rel32(&mut self, opcode: &[u8], label: LabelT) {
+        self.code.extend(opcode);
+        self.fixups.push((self.code.len(), label));
+        self.code.extend([0; 4]);
+    }
+}
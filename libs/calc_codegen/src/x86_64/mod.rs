@@ -0,0 +1,484 @@
+//! Lowering to x86-64 assembly, in GNU syntax for the System V ABI
+//!
+//! Every function becomes a global symbol that can be called from C as `intptr_t name(intptr_t, ...)`, taking as many arguments as its arity.
+//!
+//! Registers are allocated for the whole function: the most used registers of a function live in the callee saved registers,
+//! so they survive calls without having to be saved around them, and every other register gets a stack slot.
+//! Instructions work through `%rax`, `%rcx` and `%rdx`, which are never allocated.
+//!
+//! Functions are lowered to [`instruction::Instruction`]s, which [`emit`] writes out as assembly and [`crate::elf`] encodes itself.
+
+pub(crate) mod instruction;
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter, Write};
+use std::hash::Hash;
+
+use calc_ir::arithmetic::Operation;
+use calc_ir::{Instruction, Number, Program, Register};
+use calc_optimizer::{FunctionGraph, Graph};
+
+use crate::{
+    block_params, functions, jump_moves, program_graph, registers, symbol, CodegenError, Function,
+};
+use instruction::{Alu, Condition, Gpr, Instruction as Asm, Rm};
+
+/// The registers that IR registers are allocated to, these are all callee saved
+const ALLOCATABLE: [Gpr; 5] = [Gpr::Rbx, Gpr::R12, Gpr::R13, Gpr::R14, Gpr::R15];
+
+/// The registers that the first arguments of a call are passed in
+const ARGUMENTS: [Gpr; 6] = [Gpr::Rdi, Gpr::Rsi, Gpr::Rdx, Gpr::Rcx, Gpr::R8, Gpr::R9];
+
+/// Lower every function in `program` to assembly that can be assembled into an object file with `as` or `cc -c`
+///
+/// # Errors
+/// If the program can't be lowered, see [`CodegenError`]
+pub fn emit<
+    BlockPointerT: Eq + Debug + Clone,
+    FunctionPointerT: Eq + Debug + Clone + Hash + Display,
+    ProgramT: Program<BlockPointer = BlockPointerT, FunctionPointer = FunctionPointerT, Number = Number>,
+>(
+    program: &ProgramT,
+) -> Result<String, CodegenError<FunctionPointerT>> {
+    let mut assembly = String::from("\t.text\n");
+    for LoweredFunction {
+        symbol,
+        instructions,
+    } in lower(program)?
+    {
+        writeln!(
+            assembly,
+            "\t.globl {symbol}\n\t.type {symbol}, @function\n{symbol}:"
+        )
+        .expect("writing to a String can't fail");
+        for instruction in instructions {
+            let indent = if matches!(instruction, Asm::Label(_)) {
+                ""
+            } else {
+                "\t"
+            };
+            writeln!(assembly, "{indent}{instruction}").expect("writing to a String can't fail");
+        }
+        writeln!(assembly, "\t.size {symbol}, .-{symbol}").expect("writing to a String can't fail");
+    }
+    assembly.push_str("\t.section .note.GNU-stack,\"\",@progbits\n");
+    Ok(assembly)
+}
+
+/// Lower every function in `program` to instructions, in the order they're laid out in
+pub(crate) fn lower<
+    BlockPointerT: Eq + Debug + Clone,
+    FunctionPointerT: Eq + Debug + Clone + Hash + Display,
+    ProgramT: Program<BlockPointer = BlockPointerT, FunctionPointer = FunctionPointerT, Number = Number>,
+>(
+    program: &ProgramT,
+) -> Result<Vec<LoweredFunction>, CodegenError<FunctionPointerT>> {
+    let graph = program_graph(program);
+    let functions = functions(&graph)?;
+    Ok(functions
+        .iter()
+        .enumerate()
+        .map(|(index, function)| FunctionEmitter::new(&graph, function, index).emit())
+        .collect())
+}
+
+/// A function lowered to instructions, which starts at its global symbol
+pub(crate) struct LoweredFunction {
+    pub(crate) symbol: String,
+    pub(crate) instructions: Vec<Asm<Label>>,
+}
+
+/// Where jumps and calls go to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum Label {
+    /// a block of the function at an index in the output
+    Block { function: usize, block: usize },
+    /// the epilogue of the function at an index in the output
+    Return(usize),
+    /// a label that's only used once, for jumps within the code of an instruction
+    Local { function: usize, label: usize },
+    /// the global symbol of a function that the program defines
+    Function(String),
+    /// a function that the program doesn't define, which it's up to the linker to find
+    Outside(String),
+}
+
+impl Display for Label {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Label::Block { function, block } => write!(f, ".L{function}_{block}"),
+            Label::Return(function) => write!(f, ".L{function}_return"),
+            Label::Local { function, label } => write!(f, ".L{function}_local{label}"),
+            Label::Function(symbol) => write!(f, "{symbol}"),
+            Label::Outside(symbol) => write!(f, "{symbol}@PLT"),
+        }
+    }
+}
+
+/// Lowers a single function
+struct FunctionEmitter<'a, 'g, FunctionPointerT: Eq + Debug + Clone + Hash> {
+    out: Vec<Asm<Label>>,
+    graph: &'g Graph<FunctionPointerT>,
+    function: &'a Function<'g, FunctionPointerT>,
+    /// the position of the function in the output, which keeps its labels apart from those of other functions
+    index: usize,
+    next_label: usize,
+    locations: HashMap<Register, Rm>,
+    /// the callee saved registers that the function uses, in the order they're pushed
+    saved: Vec<Gpr>,
+    /// how far `%rsp` is moved down after the callee saved registers are pushed
+    frame_size: usize,
+    /// where the arguments that were passed in registers are stored
+    argument_slots: Vec<i32>,
+}
+
+/// A slot in the frame, at an offset from `%rbp`
+fn stack(displacement: i32) -> Rm {
+    Rm::Memory {
+        base: Gpr::Rbp,
+        displacement,
+    }
+}
+
+impl<'a, 'g, FunctionPointerT: Eq + Debug + Clone + Hash + Display>
+    FunctionEmitter<'a, 'g, FunctionPointerT>
+{
+    fn new(
+        graph: &'g Graph<FunctionPointerT>,
+        function: &'a Function<'g, FunctionPointerT>,
+        index: usize,
+    ) -> Self {
+        let mut uses: HashMap<Register, usize> = HashMap::new();
+        for instruction in function.graph.blocks.iter().flatten() {
+            for register in registers(instruction) {
+                *uses.entry(register).or_default() += 1;
+            }
+        }
+        let mut by_uses: Vec<_> = uses.into_iter().collect();
+        by_uses
+            .sort_by(|(lhs, lhs_uses), (rhs, rhs_uses)| rhs_uses.cmp(lhs_uses).then(lhs.cmp(rhs)));
+
+        let saved: Vec<_> = ALLOCATABLE.iter().copied().take(by_uses.len()).collect();
+        let mut slots = 0;
+        let mut slot = || {
+            slots += 1;
+            -8 * (i32::try_from(saved.len() + slots).expect("frames are small"))
+        };
+        let argument_slots = (0..function.arity.min(ARGUMENTS.len()))
+            .map(|_| slot())
+            .collect();
+        let locations = by_uses
+            .iter()
+            .enumerate()
+            .map(|(rank, (register, _))| {
+                let location = match saved.get(rank) {
+                    Some(allocated) => Rm::Register(*allocated),
+                    None => stack(slot()),
+                };
+                (*register, location)
+            })
+            .collect();
+
+        // the return address and %rbp are 16 bytes, so %rsp is aligned for calls when the rest is a multiple of 16
+        let frame_size = (8 * (saved.len() + slots)).next_multiple_of(16) - 8 * saved.len();
+        Self {
+            out: Vec::new(),
+            graph,
+            function,
+            index,
+            next_label: 0,
+            locations,
+            saved,
+            frame_size,
+            argument_slots,
+        }
+    }
+
+    fn add(&mut self, instruction: Asm<Label>) {
+        self.out.push(instruction);
+    }
+
+    fn block_label(&self, block: usize) -> Label {
+        Label::Block {
+            function: self.index,
+            block,
+        }
+    }
+
+    /// A label that's only used once, for jumps within the code of an instruction
+    fn fresh_label(&mut self) -> Label {
+        self.next_label += 1;
+        Label::Local {
+            function: self.index,
+            label: self.next_label,
+        }
+    }
+
+    fn location(&self, register: Register) -> Rm {
+        self.locations[&register]
+    }
+
+    fn trap(&mut self) {
+        self.add(Asm::Trap);
+    }
+
+    fn emit(mut self) -> LoweredFunction {
+        self.add(Asm::Push(Rm::Register(Gpr::Rbp)));
+        self.add(Asm::Store(Gpr::Rsp, Rm::Register(Gpr::Rbp)));
+        for register in self.saved.clone() {
+            self.add(Asm::Push(Rm::Register(register)));
+        }
+        if self.frame_size != 0 {
+            let frame_size = i32::try_from(self.frame_size).expect("frames are small");
+            self.add(Asm::AluImmediate(
+                Alu::Subtract,
+                frame_size,
+                Rm::Register(Gpr::Rsp),
+            ));
+        }
+        for (slot, register) in self.argument_slots.clone().into_iter().zip(ARGUMENTS) {
+            self.add(Asm::Store(register, stack(slot)));
+        }
+        // the function was called without the arguments its entry block loads
+        if block_params(
+            self.function.graph,
+            FunctionGraph::<FunctionPointerT>::ENTRY,
+        )
+        .is_some_and(|params| !params.is_empty())
+        {
+            self.trap();
+        }
+
+        for (block, instructions) in self.function.graph.blocks.iter().enumerate() {
+            let label = self.block_label(block);
+            self.add(Asm::Label(label));
+            for instruction in instructions {
+                self.instruction(instruction);
+            }
+            if !instructions.last().is_some_and(Instruction::is_terminator) {
+                self.trap();
+            }
+        }
+
+        self.add(Asm::Label(Label::Return(self.index)));
+        if self.frame_size != 0 {
+            let saved_size = i32::try_from(8 * self.saved.len()).expect("frames are small");
+            self.add(Asm::LoadAddress {
+                base: Gpr::Rbp,
+                displacement: -saved_size,
+                to: Gpr::Rsp,
+            });
+        }
+        for register in self.saved.clone().into_iter().rev() {
+            self.add(Asm::Pop(Rm::Register(register)));
+        }
+        self.add(Asm::Pop(Rm::Register(Gpr::Rbp)));
+        self.add(Asm::Return);
+        LoweredFunction {
+            symbol: self.function.symbol.clone(),
+            instructions: self.out,
+        }
+    }
+
+    fn instruction(&mut self, instruction: &Instruction<usize, FunctionPointerT>) {
+        if let Some((operation, lhs, rhs, out)) = instruction.operation() {
+            self.operation(operation, lhs, rhs, out);
+            return;
+        }
+
+        match instruction {
+            Instruction::LoadImmediate(value, out) => {
+                let out = self.location(*out);
+                if let Ok(value) = i32::try_from(*value) {
+                    self.add(Asm::MoveImmediate(value, out));
+                } else {
+                    let value = i64::try_from(*value).expect("numbers are at most 64 bits");
+                    self.add(Asm::MoveAbsolute(value, Gpr::Rax));
+                    self.add(Asm::Store(Gpr::Rax, out));
+                }
+            }
+            Instruction::Call {
+                function_id,
+                arguments,
+                out,
+            } => self.call(function_id, arguments, *out),
+            Instruction::Ret(value) => {
+                let value = self.location(*value);
+                self.add(Asm::Load(value, Gpr::Rax));
+                self.add(Asm::Jump(Label::Return(self.index)));
+            }
+            Instruction::LoadArgs(registers) => {
+                if registers.len() != self.function.arity {
+                    self.trap();
+                    return;
+                }
+                for (argument, register) in registers.iter().enumerate() {
+                    let from = match self.argument_slots.get(argument) {
+                        Some(slot) => stack(*slot),
+                        // past the return address and %rbp
+                        None => stack(
+                            16 + 8 * i32::try_from(argument - ARGUMENTS.len())
+                                .expect("frames are small"),
+                        ),
+                    };
+                    let to = self.location(*register);
+                    self.add(Asm::Load(from, Gpr::Rax));
+                    self.add(Asm::Store(Gpr::Rax, to));
+                }
+            }
+            Instruction::LoadBlockArgs(_) => {}
+            Instruction::Jump { to, arguments } => self.jump(*to, arguments),
+            Instruction::JEqual {
+                lhs,
+                rhs,
+                to,
+                arguments,
+            } => self.conditional_jump(*lhs, Some(*rhs), Condition::NotEqual, *to, arguments),
+            Instruction::JNotEqual {
+                lhs,
+                rhs,
+                to,
+                arguments,
+            } => self.conditional_jump(*lhs, Some(*rhs), Condition::Equal, *to, arguments),
+            Instruction::JNonZero {
+                check,
+                to,
+                arguments,
+            } => self.conditional_jump(*check, None, Condition::Equal, *to, arguments),
+            Instruction::JZero {
+                check,
+                to,
+                arguments,
+            } => self.conditional_jump(*check, None, Condition::NotEqual, *to, arguments),
+            Instruction::Invalid => self.trap(),
+            _ => unreachable!("arithmetic instructions are handled above"),
+        }
+    }
+
+    fn operation(&mut self, operation: Operation, lhs: Register, rhs: Register, out: Register) {
+        let (lhs, rhs, out) = (self.location(lhs), self.location(rhs), self.location(out));
+        self.add(Asm::Load(lhs, Gpr::Rax));
+        match operation {
+            Operation::Add => self.add(Asm::Alu(Alu::Add, rhs, Gpr::Rax)),
+            Operation::Subtract => self.add(Asm::Alu(Alu::Subtract, rhs, Gpr::Rax)),
+            Operation::Multiply => self.add(Asm::Multiply(rhs, Gpr::Rax)),
+            Operation::BitOr => self.add(Asm::Alu(Alu::Or, rhs, Gpr::Rax)),
+            Operation::BitNotOr => self.add(Asm::Alu(Alu::Xor, rhs, Gpr::Rax)),
+            Operation::BitAnd => self.add(Asm::Alu(Alu::And, rhs, Gpr::Rax)),
+            // shifts only use the low 6 bits of %cl, which is the same as wrapping the amount
+            Operation::ShiftL => {
+                self.add(Asm::Load(rhs, Gpr::Rcx));
+                self.add(Asm::ShiftLeft(Rm::Register(Gpr::Rax)));
+            }
+            Operation::ShiftR => {
+                self.add(Asm::Load(rhs, Gpr::Rcx));
+                self.add(Asm::ShiftRight(Rm::Register(Gpr::Rax)));
+            }
+            Operation::Divide | Operation::Modulo => {
+                let (nonzero, divide, done) =
+                    (self.fresh_label(), self.fresh_label(), self.fresh_label());
+                self.add(Asm::Load(rhs, Gpr::Rcx));
+                self.add(Asm::Test(Gpr::Rcx, Rm::Register(Gpr::Rcx)));
+                self.add(Asm::JumpIf(Condition::NotEqual, nonzero.clone()));
+                self.trap();
+                self.add(Asm::Label(nonzero));
+                // idiv faults on MIN / -1, which wraps to MIN, and whose remainder is 0
+                self.add(Asm::AluImmediate(Alu::Compare, -1, Rm::Register(Gpr::Rcx)));
+                self.add(Asm::JumpIf(Condition::NotEqual, divide.clone()));
+                if operation == Operation::Divide {
+                    self.add(Asm::Negate(Rm::Register(Gpr::Rax)));
+                } else {
+                    self.add(Asm::Xor32(Gpr::Rax, Gpr::Rax));
+                }
+                self.add(Asm::Jump(done.clone()));
+                self.add(Asm::Label(divide));
+                self.add(Asm::SignExtend);
+                self.add(Asm::Divide(Rm::Register(Gpr::Rcx)));
+                if operation == Operation::Modulo {
+                    self.add(Asm::Load(Rm::Register(Gpr::Rdx), Gpr::Rax));
+                }
+                self.add(Asm::Label(done));
+            }
+        }
+        self.add(Asm::Store(Gpr::Rax, out));
+    }
+
+    fn call(&mut self, function: &FunctionPointerT, arguments: &[Register], out: Register) {
+        let target = match self.graph.function(function) {
+            Some(callee) => {
+                if crate::arity(callee) != arguments.len() {
+                    self.trap();
+                    return;
+                }
+                Label::Function(symbol(function))
+            }
+            // it's up to the linker to find a function that the program doesn't define
+            None => Label::Outside(symbol(function)),
+        };
+
+        let on_stack = arguments.len().saturating_sub(ARGUMENTS.len());
+        // keep %rsp aligned to 16 bytes at the call
+        let padding = if on_stack % 2 == 1 { 8 } else { 0 };
+        if padding != 0 {
+            self.add(Asm::AluImmediate(Alu::Subtract, 8, Rm::Register(Gpr::Rsp)));
+        }
+        for argument in arguments.iter().skip(ARGUMENTS.len()).rev() {
+            let argument = self.location(*argument);
+            self.add(Asm::Push(argument));
+        }
+        for (argument, register) in arguments.iter().zip(ARGUMENTS) {
+            let argument = self.location(*argument);
+            self.add(Asm::Load(argument, register));
+        }
+        self.add(Asm::Call(target));
+        if on_stack != 0 {
+            let popped = i32::try_from(8 * on_stack + padding).expect("frames are small");
+            self.add(Asm::AluImmediate(Alu::Add, popped, Rm::Register(Gpr::Rsp)));
+        }
+        let out = self.location(out);
+        self.add(Asm::Store(Gpr::Rax, out));
+    }
+
+    /// Jump to `to`, moving `arguments` into the registers it loads them into
+    fn jump(&mut self, to: usize, arguments: &[Register]) {
+        let Some(moves) = jump_moves(self.function.graph, to, arguments) else {
+            self.trap();
+            return;
+        };
+        // going through the stack reads every argument before any parameter is written
+        for (argument, _) in &moves {
+            let argument = self.location(*argument);
+            self.add(Asm::Push(argument));
+        }
+        for (_, param) in moves.iter().rev() {
+            let param = self.location(*param);
+            self.add(Asm::Pop(param));
+        }
+        let label = self.block_label(to);
+        self.add(Asm::Jump(label));
+    }
+
+    /// Compare `lhs` against `rhs`, or against zero if there isn't one, and jump to `to` unless `skip` holds
+    fn conditional_jump(
+        &mut self,
+        lhs: Register,
+        rhs: Option<Register>,
+        skip: Condition,
+        to: usize,
+        arguments: &[Register],
+    ) {
+        let lhs = self.location(lhs);
+        self.add(Asm::Load(lhs, Gpr::Rax));
+        match rhs {
+            Some(rhs) => {
+                let rhs = self.location(rhs);
+                self.add(Asm::Alu(Alu::Compare, rhs, Gpr::Rax));
+            }
+            None => self.add(Asm::Test(Gpr::Rax, Rm::Register(Gpr::Rax))),
+        }
+        let skipped = self.fresh_label();
+        self.add(Asm::JumpIf(skip, skipped.clone()));
+        self.jump(to, arguments);
+        self.add(Asm::Label(skipped));
+    }
+}
@@ -0,0 +1,124 @@
+//! Compares [`calc_interpreter::interpret_function`] against compiling to bytecode first and running that.
+//!
+//! This only uses the standard library, so it reports the best of a few runs rather than anything more rigorous
+
+use std::time::{Duration, Instant};
+
+use calc_interpreter::{Bytecode, BytecodeInterpreter};
+use calc_ir::builder::instructions::{Arithmetic, BlockJump};
+use calc_ir::builder::Program;
+use calc_ir::program::implementations::BasicProgram;
+
+const RUNS: usize = 5;
+
+/// sums 1 through its argument in a loop
+fn build_sum(builder: &mut Program, name: &str) {
+    let mut function = builder.make_fn(name.to_string());
+    let loop_block_id = function.reserve_block();
+    let exit_block_id = function.reserve_block();
+
+    let mut entry_block = function.build_block();
+    let n = entry_block.add_load_args(1)[0];
+    let zero = entry_block.add_immediate(0);
+    let one = entry_block.add_immediate(1);
+    entry_block.add_cond_jump_with_args(BlockJump::Unconditional, loop_block_id, vec![n, zero]);
+    let (entry_block_id, function) = entry_block.finalize();
+
+    let mut loop_block = function.build_reserved_block(loop_block_id);
+    let params = loop_block.add_block_params(2);
+    let (counter, total) = (params[0], params[1]);
+    loop_block.add_cond_jump_with_args(BlockJump::Zero(counter), exit_block_id, vec![total]);
+    let next_total = loop_block.add_arithmetic(Arithmetic::Add, total, counter);
+    let next_counter = loop_block.add_arithmetic(Arithmetic::Subtract, counter, one);
+    loop_block.add_cond_jump_with_args(
+        BlockJump::Unconditional,
+        loop_block_id,
+        vec![next_counter, next_total],
+    );
+    let (_, function) = loop_block.finalize();
+
+    let mut exit_block = function.build_reserved_block(exit_block_id);
+    let result = exit_block.add_block_params(1)[0];
+    exit_block.add_ret(result);
+    let (_, function) = exit_block.finalize();
+    function.finalize(entry_block_id);
+}
+
+/// the naive recursive fibonacci, which is mostly calls
+fn build_fibonacci(builder: &mut Program, name: &str) {
+    let mut function = builder.make_fn(name.to_string());
+    let base_block_id = function.reserve_block();
+
+    let mut entry_block = function.build_block();
+    let n = entry_block.add_load_args(1)[0];
+    let one = entry_block.add_immediate(1);
+    let two = entry_block.add_immediate(2);
+    entry_block.add_cond_jump_with_args(BlockJump::Zero(n), base_block_id, vec![n]);
+    entry_block.add_cond_jump_with_args(BlockJump::Equal(n, one), base_block_id, vec![n]);
+    let n_minus_one = entry_block.add_arithmetic(Arithmetic::Subtract, n, one);
+    let n_minus_two = entry_block.add_arithmetic(Arithmetic::Subtract, n, two);
+    let lhs = entry_block.add_fn_call(name.to_string(), vec![n_minus_one]);
+    let rhs = entry_block.add_fn_call(name.to_string(), vec![n_minus_two]);
+    let result = entry_block.add_arithmetic(Arithmetic::Add, lhs, rhs);
+    entry_block.add_ret(result);
+    let (entry_block_id, function) = entry_block.finalize();
+
+    let mut base_block = function.build_reserved_block(base_block_id);
+    let result = base_block.add_block_params(1)[0];
+    base_block.add_ret(result);
+    let (_, function) = base_block.finalize();
+    function.finalize(entry_block_id);
+}
+
+/// the fastest of [`RUNS`] runs of `run`, which must return `expected` every time
+fn best_of(expected: isize, mut run: impl FnMut() -> isize) -> Duration {
+    (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            assert_eq!(run(), expected);
+            start.elapsed()
+        })
+        .min()
+        .expect("RUNS isn't 0")
+}
+
+fn bench(
+    program: &BasicProgram,
+    bytecode: &Bytecode<String>,
+    function: &str,
+    argument: isize,
+    expected: isize,
+) {
+    let function = function.to_string();
+    let interpreted = best_of(expected, || {
+        calc_interpreter::interpret_function(&function, program, &[argument]).unwrap()
+    });
+    let interpreter = BytecodeInterpreter::new(bytecode);
+    let compiled = best_of(expected, || {
+        interpreter.interpret(&function, &[argument]).unwrap()
+    });
+
+    println!(
+        "{:<24} interpreter {interpreted:>12.2?}   bytecode {compiled:>12.2?}   {:.1}x",
+        format!("{function}({argument})"),
+        interpreted.as_secs_f64() / compiled.as_secs_f64()
+    );
+}
+
+fn main() {
+    let mut builder = Program::new();
+    build_sum(&mut builder, "sum");
+    build_fibonacci(&mut builder, "fibonacci");
+    let program = builder.finalize();
+
+    let start = Instant::now();
+    let bytecode = Bytecode::compile(&program).unwrap();
+    println!(
+        "compiled {} instructions in {:.2?}",
+        bytecode.len(),
+        start.elapsed()
+    );
+
+    bench(&program, &bytecode, "sum", 1_000_000, 500_000_500_000);
+    bench(&program, &bytecode, "fibonacci", 25, 75_025);
+}
@@ -0,0 +1,858 @@
+//! Compiling a [`Program`] to a dense bytecode, and interpreting it
+//!
+//! [`crate::Interpreter`] goes through [`Program::get_ir`] for every instruction, and looks functions up by their pointer on every call.
+//! [`Bytecode::compile`] resolves all of that ahead of time: every function of a program is laid out in a single array of fixed-size
+//! instructions, calls refer to functions by their index, jumps refer to blocks by their offset, and every register file has a known size.
+//!
+//! Running bytecode behaves exactly like running the program it was compiled from with [`crate::Interpreter`], it returns the same results,
+//! the same errors, and respects the same [`Limits`], [`ArithmeticMode`] and [`Natives`]. The exception is programs that use registers outside of the
+//! count that [`Program::get_register_count`] reports, where the instruction using the register fails with [`Error::RegisterOutOfBounds`]
+//! before it does anything else.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::Instant;
+
+use calc_ir::arithmetic::Operation;
+use calc_ir::{ArithmeticMode, Instruction, Number, Numeric, Program, Register};
+
+use crate::limits::{self, Limit, Progress};
+use crate::{Error, Limits, Natives};
+
+/// The index of something in one of [`Bytecode`]'s tables, or of a register
+type Index = u32;
+
+/// A single bytecode instruction, register lists are stored in [`Bytecode::operands`] as their length followed by the registers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Op {
+    LoadConstant {
+        constant: Index,
+        out: Index,
+    },
+    Call {
+        function: Index,
+        arguments: Index,
+        out: Index,
+    },
+    /// a call to a function that the program doesn't have, which calls the native function it resolves to,
+    /// or is an error once its arguments have been read if there isn't one
+    CallUnknown {
+        function: Index,
+        arguments: Index,
+        out: Index,
+    },
+    Ret {
+        value: Index,
+    },
+    LoadArgs {
+        registers: Index,
+    },
+    LoadBlockArgs {
+        registers: Index,
+    },
+    Jump {
+        to: Index,
+        arguments: Index,
+    },
+    JEqual {
+        lhs: Index,
+        rhs: Index,
+        to: Index,
+        arguments: Index,
+    },
+    JNotEqual {
+        lhs: Index,
+        rhs: Index,
+        to: Index,
+        arguments: Index,
+    },
+    JNonZero {
+        check: Index,
+        to: Index,
+        arguments: Index,
+    },
+    JZero {
+        check: Index,
+        to: Index,
+        arguments: Index,
+    },
+    Binary {
+        operation: Operation,
+        lhs: Index,
+        rhs: Index,
+        out: Index,
+    },
+    Invalid,
+    /// the end of a block that doesn't end with a [`Instruction::Ret`] or [`Instruction::Jump`]
+    NoReturn,
+    /// an instruction that used a register outside of its function's register file
+    RegisterOutOfBounds {
+        register: Index,
+    },
+}
+
+/// Everything that's known about a function before it's run
+#[derive(Debug, Clone)]
+struct FunctionInfo<FunctionPointerT> {
+    pointer: FunctionPointerT,
+    /// the offset of the first instruction of the entry block
+    entry: usize,
+    register_count: usize,
+    arity: Option<usize>,
+}
+
+/// Why a program couldn't be compiled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompileError {
+    /// the program has more instructions, registers, functions or constants than fit in the fixed size operands of the bytecode
+    TooLarge,
+}
+
+impl std::fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompileError::TooLarge => write!(f, "the program is too large to compile to bytecode"),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+fn index(value: usize) -> Result<Index, CompileError> {
+    Index::try_from(value).map_err(|_| CompileError::TooLarge)
+}
+
+/// A whole program compiled to bytecode, see the module documentation
+#[derive(Debug, Clone)]
+pub struct Bytecode<FunctionPointerT, NumberT = Number> {
+    code: Vec<Op>,
+    operands: Vec<Index>,
+    constants: Vec<NumberT>,
+    functions: Vec<FunctionInfo<FunctionPointerT>>,
+    /// the index in `functions` of every function, so that neither compiling a call nor starting to interpret a function has to search for it
+    function_indices: HashMap<FunctionPointerT, usize>,
+    /// the functions that are called but that the program doesn't have
+    unknown_functions: Vec<FunctionPointerT>,
+}
+
+impl<FunctionPointerT: Eq + Hash + Clone + std::fmt::Debug, NumberT: Numeric>
+    Bytecode<FunctionPointerT, NumberT>
+{
+    /// Compile every function in `program`, as well as every function that they call
+    ///
+    /// # Errors
+    /// If the program is too large, see [`CompileError`]
+    pub fn compile<
+        BlockPointerT: Eq + Clone,
+        ProgramT: Program<BlockPointer = BlockPointerT, FunctionPointer = FunctionPointerT, Number = NumberT>,
+    >(
+        program: &ProgramT,
+    ) -> Result<Self, CompileError> {
+        let mut compiler = Compiler {
+            program,
+            bytecode: Bytecode {
+                code: Vec::new(),
+                operands: Vec::new(),
+                constants: Vec::new(),
+                functions: Vec::new(),
+                function_indices: HashMap::new(),
+                unknown_functions: Vec::new(),
+            },
+            to_compile: Vec::new(),
+        };
+
+        for (function, _) in program.get_all_functions() {
+            compiler.function_index(function)?;
+        }
+        while let Some((function, entry)) = compiler.to_compile.pop() {
+            compiler.compile_function(function, entry)?;
+        }
+
+        Ok(compiler.bytecode)
+    }
+
+    /// The amount of instructions in the bytecode
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.code.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty()
+    }
+}
+
+struct Compiler<'p, ProgramT: Program> {
+    program: &'p ProgramT,
+    bytecode: Bytecode<ProgramT::FunctionPointer, ProgramT::Number>,
+    /// functions that have been given an index, but haven't been compiled yet
+    to_compile: Vec<(usize, ProgramT::BlockPointer)>,
+}
+
+impl<
+        BlockPointerT: Eq + Clone,
+        FunctionPointerT: Eq + Hash + Clone,
+        NumberT: Numeric,
+        ProgramT: Program<BlockPointer = BlockPointerT, FunctionPointer = FunctionPointerT, Number = NumberT>,
+    > Compiler<'_, ProgramT>
+{
+    /// The index of `function`, or None if the program doesn't have it
+    fn function_index(
+        &mut self,
+        function: &FunctionPointerT,
+    ) -> Result<Option<Index>, CompileError> {
+        if let Some(found) = self.bytecode.function_indices.get(function) {
+            return Ok(Some(index(*found)?));
+        }
+        let Some(entry) = self.program.get_function_entry(function) else {
+            return Ok(None);
+        };
+
+        let function_index = self.bytecode.functions.len();
+        self.bytecode
+            .function_indices
+            .insert(function.clone(), function_index);
+        self.bytecode.functions.push(FunctionInfo {
+            pointer: function.clone(),
+            entry: 0,
+            register_count: 0,
+            arity: self.program.get_arity(function),
+        });
+        self.to_compile.push((function_index, entry));
+        Ok(Some(index(function_index)?))
+    }
+
+    fn register_list(&mut self, registers: &[Register]) -> Result<Index, CompileError> {
+        let start = index(self.bytecode.operands.len())?;
+        self.bytecode.operands.push(index(registers.len())?);
+        for register in registers {
+            self.bytecode.operands.push(index(register.0)?);
+        }
+        Ok(start)
+    }
+
+    fn compile_function(
+        &mut self,
+        function: usize,
+        entry: BlockPointerT,
+    ) -> Result<(), CompileError> {
+        let pointer = self.bytecode.functions[function].pointer.clone();
+        let reported_registers = self.program.get_register_count(&pointer);
+
+        // blocks are only Eq, so they're numbered in the order they're found, and jumps are fixed up once every block has an offset
+        let mut blocks = vec![entry];
+        let mut offsets = Vec::new();
+        let mut fixups = Vec::new();
+        let mut used_registers = 0;
+
+        while offsets.len() < blocks.len() {
+            offsets.push(self.bytecode.code.len());
+            let instructions = self.program.get_ir(&blocks[offsets.len() - 1]);
+            // nothing past a terminator can run, and for programs whose blocks fall through into the next, it belongs to another block
+            let length = instructions
+                .iter()
+                .position(Instruction::is_terminator)
+                .map_or(instructions.len(), |end| end + 1);
+
+            for instruction in &instructions[..length] {
+                let registers = instruction_registers(instruction);
+                used_registers = registers
+                    .iter()
+                    .map(|register| register.0 + 1)
+                    .fold(used_registers, usize::max);
+                if let Some(out_of_bounds) = reported_registers
+                    .and_then(|count| registers.iter().find(|register| register.0 >= count))
+                {
+                    let register = index(out_of_bounds.0)?;
+                    self.bytecode
+                        .code
+                        .push(Op::RegisterOutOfBounds { register });
+                    continue;
+                }
+
+                if let Some((to, _)) = instruction.jump_target() {
+                    let block = blocks
+                        .iter()
+                        .position(|block| block == to)
+                        .unwrap_or_else(|| {
+                            blocks.push(to.clone());
+                            blocks.len() - 1
+                        });
+                    fixups.push((self.bytecode.code.len(), block));
+                }
+                let op = self.compile_instruction(instruction)?;
+                self.bytecode.code.push(op);
+            }
+            if !instructions[..length]
+                .last()
+                .is_some_and(Instruction::is_terminator)
+            {
+                self.bytecode.code.push(Op::NoReturn);
+            }
+        }
+
+        for (at, block) in fixups {
+            let offset = index(offsets[block])?;
+            match &mut self.bytecode.code[at] {
+                Op::Jump { to, .. }
+                | Op::JEqual { to, .. }
+                | Op::JNotEqual { to, .. }
+                | Op::JNonZero { to, .. }
+                | Op::JZero { to, .. } => *to = offset,
+                op => unreachable!("{op:?} was recorded as a jump"),
+            }
+        }
+
+        let info = &mut self.bytecode.functions[function];
+        info.entry = offsets[0];
+        info.register_count = reported_registers.unwrap_or(used_registers);
+        Ok(())
+    }
+
+    /// Compile a single instruction, jumps are given a target of 0 which is fixed up later
+    fn compile_instruction(
+        &mut self,
+        instruction: &Instruction<BlockPointerT, FunctionPointerT, NumberT>,
+    ) -> Result<Op, CompileError> {
+        if let Some((operation, lhs, rhs, out)) = instruction.operation() {
+            return Ok(Op::Binary {
+                operation,
+                lhs: index(lhs.0)?,
+                rhs: index(rhs.0)?,
+                out: index(out.0)?,
+            });
+        }
+
+        Ok(match instruction {
+            Instruction::LoadImmediate(value, out) => {
+                self.bytecode.constants.push(value.clone());
+                Op::LoadConstant {
+                    constant: index(self.bytecode.constants.len() - 1)?,
+                    out: index(out.0)?,
+                }
+            }
+            Instruction::Call {
+                function_id,
+                arguments,
+                out,
+            } => {
+                let arguments = self.register_list(arguments)?;
+                if let Some(function) = self.function_index(function_id)? {
+                    Op::Call {
+                        function,
+                        arguments,
+                        out: index(out.0)?,
+                    }
+                } else {
+                    self.bytecode.unknown_functions.push(function_id.clone());
+                    Op::CallUnknown {
+                        function: index(self.bytecode.unknown_functions.len() - 1)?,
+                        arguments,
+                        out: index(out.0)?,
+                    }
+                }
+            }
+            Instruction::Ret(value) => Op::Ret {
+                value: index(value.0)?,
+            },
+            Instruction::LoadArgs(registers) => Op::LoadArgs {
+                registers: self.register_list(registers)?,
+            },
+            Instruction::LoadBlockArgs(registers) => Op::LoadBlockArgs {
+                registers: self.register_list(registers)?,
+            },
+            Instruction::Jump { arguments, .. } => Op::Jump {
+                to: 0,
+                arguments: self.register_list(arguments)?,
+            },
+            Instruction::JEqual {
+                lhs,
+                rhs,
+                arguments,
+                ..
+            } => Op::JEqual {
+                lhs: index(lhs.0)?,
+                rhs: index(rhs.0)?,
+                to: 0,
+                arguments: self.register_list(arguments)?,
+            },
+            Instruction::JNotEqual {
+                lhs,
+                rhs,
+                arguments,
+                ..
+            } => Op::JNotEqual {
+                lhs: index(lhs.0)?,
+                rhs: index(rhs.0)?,
+                to: 0,
+                arguments: self.register_list(arguments)?,
+            },
+            Instruction::JNonZero {
+                check, arguments, ..
+            } => Op::JNonZero {
+                check: index(check.0)?,
+                to: 0,
+                arguments: self.register_list(arguments)?,
+            },
+            Instruction::JZero {
+                check, arguments, ..
+            } => Op::JZero {
+                check: index(check.0)?,
+                to: 0,
+                arguments: self.register_list(arguments)?,
+            },
+            Instruction::Invalid => Op::Invalid,
+            _ => unreachable!("arithmetic instructions are handled above"),
+        })
+    }
+}
+
+/// Every register that `instruction` reads or writes
+fn instruction_registers<B: Eq + Clone, F: Eq + Clone, N>(
+    instruction: &Instruction<B, F, N>,
+) -> Vec<Register> {
+    if let Some((_, lhs, rhs, out)) = instruction.operation() {
+        return vec![lhs, rhs, out];
+    }
+    match instruction {
+        Instruction::LoadImmediate(_, register) | Instruction::Ret(register) => vec![*register],
+        Instruction::Call { arguments, out, .. } => {
+            let mut registers = arguments.clone();
+            registers.push(*out);
+            registers
+        }
+        Instruction::LoadArgs(registers) | Instruction::LoadBlockArgs(registers) => {
+            registers.clone()
+        }
+        Instruction::Jump { arguments, .. } => arguments.clone(),
+        Instruction::JEqual {
+            lhs,
+            rhs,
+            arguments,
+            ..
+        }
+        | Instruction::JNotEqual {
+            lhs,
+            rhs,
+            arguments,
+            ..
+        } => [*lhs, *rhs]
+            .into_iter()
+            .chain(arguments.iter().copied())
+            .collect(),
+        Instruction::JNonZero {
+            check, arguments, ..
+        }
+        | Instruction::JZero {
+            check, arguments, ..
+        } => std::iter::once(*check)
+            .chain(arguments.iter().copied())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// A function call on the bytecode interpreter's call stack
+struct CallFrame {
+    function: usize,
+    /// where this frame's registers start in the register stack
+    base: usize,
+    /// where this frame's arguments start in the argument stack
+    arguments: usize,
+    /// where the arguments of the last jump this frame made start in the block argument stack
+    block_arguments: usize,
+    /// the instruction to continue at in the caller
+    return_to: usize,
+    /// the register in the caller that the result is written to
+    out: Index,
+}
+
+/// Runs [`Bytecode`], configured like [`crate::Interpreter`]
+pub struct BytecodeInterpreter<'b, FunctionPointerT, NumberT> {
+    bytecode: &'b Bytecode<FunctionPointerT, NumberT>,
+    limits: Limits,
+    arithmetic: ArithmeticMode,
+    natives: Option<&'b Natives<FunctionPointerT, NumberT>>,
+    /// the index in `natives` of each of the bytecode's unknown functions, if it's there
+    resolved: Vec<Option<usize>>,
+}
+
+impl<'b, FunctionPointerT: Eq + Hash + Clone + std::fmt::Debug, NumberT: Numeric>
+    BytecodeInterpreter<'b, FunctionPointerT, NumberT>
+{
+    /// Create an interpreter for `bytecode`, without any limits, using [`ArithmeticMode::Wrapping`]
+    #[must_use]
+    pub fn new(bytecode: &'b Bytecode<FunctionPointerT, NumberT>) -> Self {
+        Self {
+            bytecode,
+            limits: Limits::default(),
+            arithmetic: ArithmeticMode::default(),
+            natives: None,
+            resolved: vec![None; bytecode.unknown_functions.len()],
+        }
+    }
+
+    /// Run every function within the budget given by `limits`
+    #[must_use]
+    pub fn with_limits(self, limits: Limits) -> Self {
+        Self { limits, ..self }
+    }
+
+    /// Evaluate arithmetic according to `arithmetic`
+    #[must_use]
+    pub fn with_arithmetic(self, arithmetic: ArithmeticMode) -> Self {
+        Self { arithmetic, ..self }
+    }
+
+    /// Call the functions in `natives` whenever the program calls a function that it doesn't define itself
+    ///
+    /// The calls are resolved here, rather than every time they're made
+    #[must_use]
+    pub fn with_natives(self, natives: &'b Natives<FunctionPointerT, NumberT>) -> Self {
+        let resolved = self
+            .bytecode
+            .unknown_functions
+            .iter()
+            .map(|function| natives.index(function))
+            .collect();
+        Self {
+            natives: Some(natives),
+            resolved,
+            ..self
+        }
+    }
+
+    /// Interpret `function` with `arguments`, see [`crate::Interpreter::interpret`]
+    ///
+    /// # Errors
+    /// In the same cases as [`crate::Interpreter::interpret`]
+    pub fn interpret(
+        &self,
+        function: &FunctionPointerT,
+        arguments: &[NumberT],
+    ) -> Result<NumberT, Error<FunctionPointerT>> {
+        let Some(&function) = self.bytecode.function_indices.get(function) else {
+            return self
+                .natives
+                .and_then(|natives| natives.call(function, arguments))
+                .unwrap_or_else(|| Err(Error::UnknownFunction(function.clone())));
+        };
+
+        let mut run = Run {
+            bytecode: self.bytecode,
+            limits: self.limits,
+            arithmetic: self.arithmetic,
+            natives: self.natives,
+            resolved: &self.resolved,
+            frames: Vec::new(),
+            registers: Vec::new(),
+            arguments: arguments.to_vec(),
+            block_arguments: Vec::new(),
+            executed: 0,
+        };
+        let pc = run.call(function, 0, 0, 0)?;
+        run.run(pc)
+    }
+}
+
+/// The state of a single [`BytecodeInterpreter::interpret`]
+struct Run<'b, FunctionPointerT, NumberT> {
+    bytecode: &'b Bytecode<FunctionPointerT, NumberT>,
+    limits: Limits,
+    arithmetic: ArithmeticMode,
+    natives: Option<&'b Natives<FunctionPointerT, NumberT>>,
+    resolved: &'b [Option<usize>],
+    frames: Vec<CallFrame>,
+    /// the register files of every frame, one after another
+    registers: Vec<Option<NumberT>>,
+    /// the arguments of every frame, one after another
+    arguments: Vec<NumberT>,
+    /// the arguments passed by the last jump of every frame, one after another, which stay there until the frame jumps again
+    /// so that every [`Op::LoadBlockArgs`] in a block loads the same ones
+    block_arguments: Vec<NumberT>,
+    executed: u64,
+}
+
+impl<'b, FunctionPointerT: Eq + Clone + std::fmt::Debug, NumberT: Numeric>
+    Run<'b, FunctionPointerT, NumberT>
+{
+    fn limit_exceeded(&self, limit: Limit, function: usize) -> Error<FunctionPointerT> {
+        Error::LimitExceeded {
+            limit,
+            progress: Progress {
+                instructions: self.executed,
+                call_depth: self.frames.len(),
+                function: self.bytecode.functions[function].pointer.clone(),
+            },
+        }
+    }
+
+    /// Push a frame for `function`, whose arguments have already been pushed starting at `arguments`, returning where it starts
+    fn call(
+        &mut self,
+        function: usize,
+        arguments: usize,
+        return_to: usize,
+        out: Index,
+    ) -> Result<usize, Error<FunctionPointerT>> {
+        let info = &self.bytecode.functions[function];
+        if self
+            .limits
+            .max_call_depth
+            .is_some_and(|max| self.frames.len() >= max)
+        {
+            return Err(self.limit_exceeded(Limit::CallDepth, function));
+        }
+        let provided = self.arguments.len() - arguments;
+        if let Some(arity) = info.arity {
+            if arity != provided {
+                return Err(Error::ArgumentMismatch {
+                    expected: arity,
+                    provided,
+                });
+            }
+        }
+
+        let base = self.registers.len();
+        self.registers.resize(base + info.register_count, None);
+        self.frames.push(CallFrame {
+            function,
+            base,
+            arguments,
+            block_arguments: self.block_arguments.len(),
+            return_to,
+            out,
+        });
+        Ok(info.entry)
+    }
+
+    /// The register list stored at `at` in the operand table
+    fn register_list(&self, at: Index) -> &'b [Index] {
+        let operands = &self.bytecode.operands;
+        let at = at as usize;
+        let length = operands[at] as usize;
+        &operands[at + 1..=at + length]
+    }
+
+    /// Push the values of the registers at `list` onto `into`
+    fn read_list(
+        registers: &[Option<NumberT>],
+        base: usize,
+        list: &[Index],
+        into: &mut Vec<NumberT>,
+    ) -> Result<(), Error<FunctionPointerT>> {
+        for register in list {
+            into.push(read(registers, base, *register)?);
+        }
+        Ok(())
+    }
+
+    /// Jump to `to`, passing the registers at `arguments`, returning where execution continues
+    fn jump(
+        &mut self,
+        base: usize,
+        to: Index,
+        arguments: Index,
+    ) -> Result<usize, Error<FunctionPointerT>> {
+        let list = self.register_list(arguments);
+        // arguments can only be taken through a LoadBlockArgs at the start of the block
+        if !list.is_empty() && !matches!(self.bytecode.code[to as usize], Op::LoadBlockArgs { .. })
+        {
+            return Err(Error::BlockArgumentMismatch {
+                expected: 0,
+                provided: list.len(),
+            });
+        }
+        let frame = self.frames.last().expect("a frame is running");
+        self.block_arguments.truncate(frame.block_arguments);
+        Self::read_list(&self.registers, base, list, &mut self.block_arguments)?;
+        Ok(to as usize)
+    }
+
+    /// The dispatch loop
+    #[allow(clippy::too_many_lines)]
+    fn run(mut self, mut pc: usize) -> Result<NumberT, Error<FunctionPointerT>> {
+        let code = &self.bytecode.code;
+        let mode = self.arithmetic;
+        let mut base = 0;
+        let mut function = self.frames[0].function;
+        let fuel = self.limits.fuel.unwrap_or(u64::MAX);
+        // the limits only need to be checked once this many instructions have been executed, starting with before the first one
+        let mut next_check = 0;
+
+        loop {
+            if self.executed >= next_check {
+                if self.executed >= fuel {
+                    return Err(self.limit_exceeded(Limit::Fuel, function));
+                }
+                if self
+                    .limits
+                    .deadline
+                    .is_some_and(|deadline| Instant::now() >= deadline)
+                {
+                    return Err(self.limit_exceeded(Limit::Deadline, function));
+                }
+                next_check = self.next_check(fuel);
+            }
+            self.executed += 1;
+
+            let op = code[pc];
+            pc += 1;
+            match op {
+                Op::LoadConstant { constant, out } => {
+                    self.registers[base + out as usize] =
+                        Some(self.bytecode.constants[constant as usize].clone());
+                }
+                Op::Binary {
+                    operation,
+                    lhs,
+                    rhs,
+                    out,
+                } => {
+                    let lhs = read(&self.registers, base, lhs)?;
+                    let rhs = read(&self.registers, base, rhs)?;
+                    let result = operation
+                        .evaluate(&lhs, &rhs, mode)
+                        .map_err(Error::Arithmetic)?;
+                    self.registers[base + out as usize] = Some(result);
+                }
+                Op::Call {
+                    function: callee,
+                    arguments,
+                    out,
+                } => {
+                    let start = self.arguments.len();
+                    let list = self.register_list(arguments);
+                    Self::read_list(&self.registers, base, list, &mut self.arguments)?;
+                    pc = self.call(callee as usize, start, pc, out)?;
+                    function = callee as usize;
+                    base = self.frames.last().expect("just called").base;
+                }
+                Op::CallUnknown {
+                    function: unknown,
+                    arguments,
+                    out,
+                } => {
+                    let list = self.register_list(arguments);
+                    let mut values = Vec::with_capacity(list.len());
+                    Self::read_list(&self.registers, base, list, &mut values)?;
+                    let (Some(natives), Some(native)) =
+                        (self.natives, self.resolved[unknown as usize])
+                    else {
+                        return Err(Error::UnknownFunction(
+                            self.bytecode.unknown_functions[unknown as usize].clone(),
+                        ));
+                    };
+                    self.registers[base + out as usize] =
+                        Some(natives.call_index(native, &values)?);
+                }
+                Op::Ret { value } => {
+                    let result = read(&self.registers, base, value)?;
+                    let returning = self.frames.pop().expect("a frame is running");
+                    let Some(caller) = self.frames.last() else {
+                        return Ok(result);
+                    };
+                    self.registers.truncate(returning.base);
+                    self.arguments.truncate(returning.arguments);
+                    self.block_arguments.truncate(returning.block_arguments);
+                    base = caller.base;
+                    function = caller.function;
+                    pc = returning.return_to;
+                    self.registers[base + returning.out as usize] = Some(result);
+                }
+                Op::LoadArgs { registers } => {
+                    let list = self.register_list(registers);
+                    let frame = self.frames.last().expect("a frame is running");
+                    let arguments = &self.arguments[frame.arguments..];
+                    if list.len() != arguments.len() {
+                        return Err(Error::ArgumentMismatch {
+                            expected: list.len(),
+                            provided: arguments.len(),
+                        });
+                    }
+                    for (register, value) in list.iter().zip(arguments) {
+                        self.registers[base + *register as usize] = Some(value.clone());
+                    }
+                }
+                Op::LoadBlockArgs { registers } => {
+                    let list = self.register_list(registers);
+                    let frame = self.frames.last().expect("a frame is running");
+                    let arguments = &self.block_arguments[frame.block_arguments..];
+                    if list.len() != arguments.len() {
+                        return Err(Error::BlockArgumentMismatch {
+                            expected: list.len(),
+                            provided: arguments.len(),
+                        });
+                    }
+                    for (register, value) in list.iter().zip(arguments) {
+                        self.registers[base + *register as usize] = Some(value.clone());
+                    }
+                }
+                Op::Jump { to, arguments } => pc = self.jump(base, to, arguments)?,
+                Op::JEqual {
+                    lhs,
+                    rhs,
+                    to,
+                    arguments,
+                } => {
+                    if read(&self.registers, base, lhs)? == read(&self.registers, base, rhs)? {
+                        pc = self.jump(base, to, arguments)?;
+                    }
+                }
+                Op::JNotEqual {
+                    lhs,
+                    rhs,
+                    to,
+                    arguments,
+                } => {
+                    if read(&self.registers, base, lhs)? != read(&self.registers, base, rhs)? {
+                        pc = self.jump(base, to, arguments)?;
+                    }
+                }
+                Op::JNonZero {
+                    check,
+                    to,
+                    arguments,
+                } => {
+                    if !read(&self.registers, base, check)?.is_zero() {
+                        pc = self.jump(base, to, arguments)?;
+                    }
+                }
+                Op::JZero {
+                    check,
+                    to,
+                    arguments,
+                } => {
+                    if read(&self.registers, base, check)?.is_zero() {
+                        pc = self.jump(base, to, arguments)?;
+                    }
+                }
+                Op::Invalid => return Err(Error::InvalidInstruction),
+                Op::NoReturn => return Err(Error::NoReturn),
+                Op::RegisterOutOfBounds { register } => {
+                    return Err(Error::RegisterOutOfBounds(Register(register as usize)))
+                }
+            }
+        }
+    }
+
+    /// The next amount of executed instructions at which a limit could be exceeded, after they've just been checked
+    fn next_check(&self, fuel: u64) -> u64 {
+        if self.limits.deadline.is_none() {
+            return fuel;
+        }
+        let interval = limits::DEADLINE_CHECK_INTERVAL;
+        fuel.min((self.executed / interval + 1) * interval)
+    }
+}
+
+fn read<FunctionPointerT, NumberT: Clone>(
+    registers: &[Option<NumberT>],
+    base: usize,
+    register: Index,
+) -> Result<NumberT, Error<FunctionPointerT>> {
+    registers[base + register as usize]
+        .clone()
+        .ok_or(Error::UnsetRegister(Register(register as usize)))
+}
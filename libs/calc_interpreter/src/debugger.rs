@@ -0,0 +1,193 @@
+//! Running a program under the control of a debugger, pausing at breakpoints and stepping through it
+//!
+//! Start debugging with [`crate::Interpreter::debug`], which gives a [`Debugger`] paused before the first instruction.
+
+use calc_ir::{Instruction, Numeric, Program};
+
+use crate::{Error, Frame, Machine, Step};
+
+/// A place in the program where execution pauses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Breakpoint<BlockPointerT, FunctionPointerT> {
+    /// pause every time the function is called, before its first instruction
+    Function(FunctionPointerT),
+    /// pause every time the block of the function is entered, either by a jump or because it's the function's entry
+    Block(FunctionPointerT, BlockPointerT),
+}
+
+/// Why a [`Debugger`] paused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stop<BlockPointerT, FunctionPointerT, NumberT> {
+    /// the step that was requested is done
+    Stepped,
+    /// a breakpoint was reached
+    Breakpoint(Breakpoint<BlockPointerT, FunctionPointerT>),
+    /// the function that was being debugged returned, there is nothing left to run
+    Finished(NumberT),
+}
+
+/// A program paused partway through being interpreted
+///
+/// Breakpoints are checked after every instruction, so stepping over a call or out of a function still pauses at any breakpoint on the way
+pub struct Debugger<'p, ProgramT: Program> {
+    machine: Machine<'p, 'p, ProgramT>,
+    breakpoints: Vec<Breakpoint<ProgramT::BlockPointer, ProgramT::FunctionPointer>>,
+    /// how the program ended, once it has
+    outcome: Option<Result<ProgramT::Number, Error<ProgramT::FunctionPointer>>>,
+}
+
+/// What [`Debugger`]'s methods return
+type StopResult<BlockPointerT, FunctionPointerT, NumberT> =
+    Result<Stop<BlockPointerT, FunctionPointerT, NumberT>, Error<FunctionPointerT>>;
+
+impl<
+        'p,
+        BlockPointerT: Eq + std::fmt::Debug + Clone,
+        FunctionPointerT: Eq + std::fmt::Debug + Clone,
+        NumberT: Numeric,
+        ProgramT: Program<FunctionPointer = FunctionPointerT, BlockPointer = BlockPointerT, Number = NumberT>,
+    > Debugger<'p, ProgramT>
+{
+    pub(crate) fn new(machine: Machine<'p, 'p, ProgramT>) -> Self {
+        Self {
+            machine,
+            breakpoints: Vec::new(),
+            outcome: None,
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint<BlockPointerT, FunctionPointerT>) {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+    }
+
+    /// Remove `breakpoint`, returning whether it was set
+    pub fn remove_breakpoint(
+        &mut self,
+        breakpoint: &Breakpoint<BlockPointerT, FunctionPointerT>,
+    ) -> bool {
+        let before = self.breakpoints.len();
+        self.breakpoints.retain(|set| set != breakpoint);
+        self.breakpoints.len() != before
+    }
+
+    /// Every breakpoint that's set, in the order they were added
+    pub fn breakpoints(&self) -> &[Breakpoint<BlockPointerT, FunctionPointerT>] {
+        &self.breakpoints
+    }
+
+    /// The frames on the call stack, starting with the function that's being debugged and ending with the one that's running.
+    ///
+    /// This is empty once the program has finished
+    pub fn call_stack(&self) -> &[Frame<BlockPointerT, FunctionPointerT, NumberT>] {
+        &self.machine.frames
+    }
+
+    /// The instruction that will run next, or None if the program has ended or the block has run out of instructions
+    pub fn current_instruction(
+        &self,
+    ) -> Option<&'p Instruction<BlockPointerT, FunctionPointerT, NumberT>> {
+        if self.outcome.is_some() {
+            return None;
+        }
+        let frame = self.machine.frames.last()?;
+        self.machine
+            .program
+            .get_ir(&frame.block)
+            .get(frame.instruction)
+    }
+
+    /// The result of the program if it has ended, either what the debugged function returned or the error it failed with
+    pub fn outcome(&self) -> Option<&Result<NumberT, Error<FunctionPointerT>>> {
+        self.outcome.as_ref()
+    }
+
+    /// Run a single instruction
+    ///
+    /// # Errors
+    /// If the instruction fails, see [`Error`]. Once the program has failed, every step returns the same error
+    pub fn step(&mut self) -> StopResult<BlockPointerT, FunctionPointerT, NumberT> {
+        Ok(self.advance()?.unwrap_or(Stop::Stepped))
+    }
+
+    /// Run a single instruction, if it's a call, keep running until the call returns
+    ///
+    /// # Errors
+    /// If an instruction fails, see [`Self::step`]
+    pub fn step_over(&mut self) -> StopResult<BlockPointerT, FunctionPointerT, NumberT> {
+        let depth = self.machine.frames.len();
+        self.run_while(|debugger| debugger.machine.frames.len() > depth)
+    }
+
+    /// Keep running until the current function returns
+    ///
+    /// # Errors
+    /// If an instruction fails, see [`Self::step`]
+    pub fn step_out(&mut self) -> StopResult<BlockPointerT, FunctionPointerT, NumberT> {
+        let depth = self.machine.frames.len();
+        self.run_while(|debugger| debugger.machine.frames.len() >= depth)
+    }
+
+    /// Keep running until a breakpoint is reached or the program ends
+    ///
+    /// # Errors
+    /// If an instruction fails, see [`Self::step`]
+    pub fn resume(&mut self) -> StopResult<BlockPointerT, FunctionPointerT, NumberT> {
+        self.run_while(|_| true)
+    }
+
+    /// Run at least one instruction, and then keep running while `condition` holds
+    fn run_while(
+        &mut self,
+        condition: impl Fn(&Self) -> bool,
+    ) -> StopResult<BlockPointerT, FunctionPointerT, NumberT> {
+        loop {
+            if let Some(stop) = self.advance()? {
+                return Ok(stop);
+            }
+            if !condition(self) {
+                return Ok(Stop::Stepped);
+            }
+        }
+    }
+
+    /// Run a single instruction, returning why execution has to stop if it does
+    fn advance(
+        &mut self,
+    ) -> Result<Option<Stop<BlockPointerT, FunctionPointerT, NumberT>>, Error<FunctionPointerT>>
+    {
+        match &self.outcome {
+            Some(Ok(result)) => return Ok(Some(Stop::Finished(result.clone()))),
+            Some(Err(error)) => return Err(error.clone()),
+            None => {}
+        }
+
+        let depth = self.machine.frames.len();
+        match self.machine.step() {
+            Ok(Step::Running) => {}
+            Ok(Step::Finished(result)) => {
+                self.outcome = Some(Ok(result.clone()));
+                return Ok(Some(Stop::Finished(result)));
+            }
+            Err(error) => {
+                self.outcome = Some(Err(error.clone()));
+                return Err(error);
+            }
+        }
+
+        let frame = self
+            .machine
+            .frames
+            .last()
+            .expect("the program is still running");
+        let called = self.machine.frames.len() > depth;
+        let hit = self.breakpoints.iter().find(|breakpoint| match breakpoint {
+            Breakpoint::Function(function) => called && frame.function == *function,
+            Breakpoint::Block(function, block) => {
+                frame.instruction == 0 && frame.function == *function && frame.block == *block
+            }
+        });
+        Ok(hit.cloned().map(Stop::Breakpoint))
+    }
+}
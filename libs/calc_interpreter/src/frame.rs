@@ -0,0 +1,94 @@
+//! The state of a single function call
+
+use calc_ir::{Number, Numeric, Register};
+
+use crate::Error;
+
+/// A single function call on the interpreter's call stack, holding the function's register file and where it is in the function.
+///
+/// The register file is an array with one slot per register, every slot starts out unset and reading an unset register is an error.
+/// Writing to a register overwrites whatever was in it before, which is needed for [`calc_ir::Instruction::LoadBlockArgs`].
+#[derive(Debug, Clone)]
+pub struct Frame<BlockPointerT, FunctionPointerT, NumberT = Number> {
+    pub(crate) function: FunctionPointerT,
+    pub(crate) block: BlockPointerT,
+    /// the index of the next instruction to run in `block`
+    pub(crate) instruction: usize,
+    registers: Vec<Option<NumberT>>,
+    /// whether `registers` is the size reported by [`calc_ir::Program::get_register_count`], if it isn't it grows as needed
+    fixed_size: bool,
+    pub(crate) arguments: Vec<NumberT>,
+    /// the arguments passed by the jump into `block`
+    pub(crate) block_arguments: Vec<NumberT>,
+    /// the register in the calling frame that the result of this call is stored in
+    pub(crate) return_register: Option<Register>,
+}
+
+impl<BlockPointerT, FunctionPointerT, NumberT: Numeric>
+    Frame<BlockPointerT, FunctionPointerT, NumberT>
+{
+    pub(crate) fn new(
+        function: FunctionPointerT,
+        entry: BlockPointerT,
+        register_count: Option<usize>,
+        arguments: Vec<NumberT>,
+        return_register: Option<Register>,
+    ) -> Self {
+        Self {
+            function,
+            block: entry,
+            instruction: 0,
+            registers: vec![None; register_count.unwrap_or(0)],
+            fixed_size: register_count.is_some(),
+            arguments,
+            block_arguments: Vec::new(),
+            return_register,
+        }
+    }
+
+    /// The function this frame is running
+    pub fn function(&self) -> &FunctionPointerT {
+        &self.function
+    }
+
+    /// The block this frame is currently in
+    pub fn block(&self) -> &BlockPointerT {
+        &self.block
+    }
+
+    /// The index of the next instruction that will be run in [`Self::block`]
+    pub fn instruction(&self) -> usize {
+        self.instruction
+    }
+
+    /// The value of `register`, or None if it's never been written to
+    pub fn register(&self, register: Register) -> Option<&NumberT> {
+        self.registers.get(register.0).and_then(Option::as_ref)
+    }
+
+    /// Every register in the frame, in order
+    pub fn registers(&self) -> &[Option<NumberT>] {
+        &self.registers
+    }
+
+    /// Read `register`, erroring if it's never been written to
+    pub(crate) fn read<F>(&self, register: Register) -> Result<NumberT, Error<F>> {
+        match self.registers.get(register.0) {
+            Some(Some(value)) => Ok(value.clone()),
+            None if self.fixed_size => Err(Error::RegisterOutOfBounds(register)),
+            _ => Err(Error::UnsetRegister(register)),
+        }
+    }
+
+    /// Overwrite `register` with `value`
+    pub(crate) fn write<F>(&mut self, register: Register, value: NumberT) -> Result<(), Error<F>> {
+        if self.registers.len() <= register.0 {
+            if self.fixed_size {
+                return Err(Error::RegisterOutOfBounds(register));
+            }
+            self.registers.resize(register.0 + 1, None);
+        }
+        self.registers[register.0] = Some(value);
+        Ok(())
+    }
+}
@@ -2,34 +2,366 @@
 
 //! A basic interpreter for programs built by [`calc_ir`]
 
-use calc_ir::{Number, Program};
+use calc_ir::arithmetic::{ArithmeticError, Operation};
+use calc_ir::{ArithmeticMode, Numeric, Program, Register};
 
-type State = Vec<Number>;
+pub mod bytecode;
+pub mod debugger;
+pub mod frame;
+pub mod limits;
+pub mod native;
+pub mod profile;
+pub mod trace;
+pub use bytecode::{Bytecode, BytecodeInterpreter};
+pub use debugger::Debugger;
+pub use frame::Frame;
+pub use limits::Limits;
+pub use native::Natives;
+pub use profile::Profiler;
+pub use trace::Tracer;
+
+use std::cell::RefCell;
+use trace::Accesses;
 
 #[cfg(test)]
 mod test;
 
-/// interprets a block, returning Some(Number) if [`calc_ir::Instruction::Ret`] is called,
-/// otherwise returns None if end of block is reached with no return
-fn interpret_block<
-    BlockPointerT: Eq + std::fmt::Debug + Clone,
-    FunctionPointerT: Eq + std::fmt::Debug + Clone,
-    ProgramT: Program<FunctionPointer = FunctionPointerT, BlockPointer = BlockPointerT>,
->(
-    block: &ProgramT::BlockPointer,
-    program: &ProgramT,
-    state: &mut State,
-    arguments: Option<&[Number]>,
-) -> Option<Number> {
-    // yeah this is bad code idcidc
-    let registers = state;
-    let to_interpret = program.get_ir(block);
-
-    for instruction in to_interpret {
+/// The ways that interpreting a program can fail
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Error<FunctionPointerT> {
+    /// A function was called that isn't registered to the program
+    UnknownFunction(FunctionPointerT),
+    /// Execution reached the end of a block without a [`calc_ir::Instruction::Ret`] or [`calc_ir::Instruction::Jump`]
+    NoReturn,
+    /// A jump passed a different amount of arguments than the block it jumped to loads with [`calc_ir::Instruction::LoadBlockArgs`]
+    BlockArgumentMismatch { expected: usize, provided: usize },
+    /// A function was called with a different amount of arguments than its arity, or than it loaded with [`calc_ir::Instruction::LoadArgs`]
+    ArgumentMismatch { expected: usize, provided: usize },
+    /// A register was read before anything was written to it
+    UnsetRegister(Register),
+    /// A register was used that is outside of the amount of registers that [`Program::get_register_count`] reported for its function
+    RegisterOutOfBounds(Register),
+    /// An arithmetic instruction failed under the [`ArithmeticMode`] it was run with
+    Arithmetic(ArithmeticError),
+    /// A [`calc_ir::Instruction::Invalid`] was executed
+    InvalidInstruction,
+    /// A function registered with [`Natives`] returned an error
+    Native {
+        function: FunctionPointerT,
+        message: String,
+    },
+    /// The program ran out of the budget it was given with [`Limits`]
+    LimitExceeded {
+        limit: limits::Limit,
+        progress: limits::Progress<FunctionPointerT>,
+    },
+}
+
+impl<FunctionPointerT: std::fmt::Debug> std::fmt::Display for Error<FunctionPointerT> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::UnknownFunction(function) => write!(f, "call to unknown function {function:?}"),
+            Error::NoReturn => write!(f, "reached the end of a block without a return or jump"),
+            Error::BlockArgumentMismatch { expected, provided } => write!(
+                f,
+                "block expected {expected} arguments, but the jump into it provided {provided}"
+            ),
+            Error::ArgumentMismatch { expected, provided } => write!(
+                f,
+                "function expected {expected} arguments, but was called with {provided}"
+            ),
+            Error::UnsetRegister(register) => {
+                write!(f, "read from {register:?} before it was written to")
+            }
+            Error::RegisterOutOfBounds(register) => write!(
+                f,
+                "{register:?} is outside of the register file of its function"
+            ),
+            Error::Arithmetic(error) => write!(f, "{error}"),
+            Error::InvalidInstruction => write!(f, "executed an invalid instruction"),
+            Error::Native { function, message } => {
+                write!(f, "native function {function:?} failed: {message}")
+            }
+            Error::LimitExceeded { limit, progress } => write!(
+                f,
+                "exceeded the {limit:?} limit in {:?} after {} instructions, at a call depth of {}",
+                progress.function, progress.instructions, progress.call_depth
+            ),
+        }
+    }
+}
+
+impl<FunctionPointerT: std::fmt::Debug> std::error::Error for Error<FunctionPointerT> {}
+
+/// What happened after running a single instruction
+enum Step<NumberT> {
+    Running,
+    /// the outermost function returned
+    Finished(NumberT),
+}
+
+/// The tracer that a [`Machine`] reports to
+type DynTracer<'t, ProgramT> = dyn Tracer<
+        <ProgramT as Program>::BlockPointer,
+        <ProgramT as Program>::FunctionPointer,
+        <ProgramT as Program>::Number,
+    > + 't;
+
+/// Runs a program one instruction at a time, keeping its own call stack so that deep recursion can't overflow the native stack
+struct Machine<'p, 't, ProgramT: Program> {
+    program: &'p ProgramT,
+    frames: Vec<Frame<ProgramT::BlockPointer, ProgramT::FunctionPointer, ProgramT::Number>>,
+    limits: Limits,
+    arithmetic: ArithmeticMode,
+    natives: Option<&'p Natives<ProgramT::FunctionPointer, ProgramT::Number>>,
+    /// the amount of instructions executed so far
+    executed: u64,
+    tracer: Option<&'t mut DynTracer<'t, ProgramT>>,
+    /// what the current instruction read and wrote, for `tracer`
+    accesses: Accesses<ProgramT::Number>,
+}
+
+impl<
+        'p,
+        BlockPointerT: Eq + std::fmt::Debug + Clone,
+        FunctionPointerT: Eq + std::fmt::Debug + Clone,
+        NumberT: Numeric,
+        ProgramT: Program<FunctionPointer = FunctionPointerT, BlockPointer = BlockPointerT, Number = NumberT>,
+    > Machine<'p, '_, ProgramT>
+{
+    fn new(
+        interpreter: &Interpreter<'p, '_, ProgramT>,
+        function: &FunctionPointerT,
+        arguments: Vec<NumberT>,
+    ) -> Result<Self, Error<FunctionPointerT>> {
+        let mut machine = Self {
+            program: interpreter.program,
+            frames: Vec::new(),
+            limits: interpreter.limits,
+            arithmetic: interpreter.arithmetic,
+            natives: interpreter.natives,
+            executed: 0,
+            tracer: None,
+            accesses: Accesses::new(false),
+        };
+        machine.call(function, arguments, None)?;
+        Ok(machine)
+    }
+
+    /// Push a new frame for `function` onto the call stack, checking `arguments` against its arity if the program knows it
+    fn call(
+        &mut self,
+        function: &FunctionPointerT,
+        arguments: Vec<NumberT>,
+        return_register: Option<Register>,
+    ) -> Result<(), Error<FunctionPointerT>> {
+        let entry = self
+            .program
+            .get_function_entry(function)
+            .ok_or_else(|| Error::UnknownFunction(function.clone()))?;
+        if self
+            .limits
+            .max_call_depth
+            .is_some_and(|max| self.frames.len() >= max)
+        {
+            return Err(self.limit_exceeded(limits::Limit::CallDepth, function.clone()));
+        }
+        if let Some(arity) = self.program.get_arity(function) {
+            if arity != arguments.len() {
+                return Err(Error::ArgumentMismatch {
+                    expected: arity,
+                    provided: arguments.len(),
+                });
+            }
+        }
+        self.frames.push(Frame::new(
+            function.clone(),
+            entry,
+            self.program.get_register_count(function),
+            arguments,
+            return_register,
+        ));
+        Ok(())
+    }
+
+    /// Run `function` if it's a native function rather than one of the program's, returning None if it isn't
+    fn call_native(
+        &self,
+        function: &FunctionPointerT,
+        arguments: &[NumberT],
+    ) -> Option<Result<NumberT, Error<FunctionPointerT>>> {
+        if self.program.get_function_entry(function).is_some() {
+            return None;
+        }
+        self.natives?.call(function, arguments)
+    }
+
+    /// Transfer control of the current frame to the start of `to`
+    fn jump(
+        &mut self,
+        to: &BlockPointerT,
+        arguments: Vec<NumberT>,
+    ) -> Result<(), Error<FunctionPointerT>> {
+        // arguments can only be taken through a LoadBlockArgs at the start of the block
+        if !arguments.is_empty()
+            && !matches!(
+                self.program.get_ir(to).first(),
+                Some(calc_ir::Instruction::LoadBlockArgs(_))
+            )
+        {
+            return Err(Error::BlockArgumentMismatch {
+                expected: 0,
+                provided: arguments.len(),
+            });
+        }
+
+        let frame = self.current_frame();
+        frame.block = to.clone();
+        frame.instruction = 0;
+        frame.block_arguments = arguments;
+        Ok(())
+    }
+
+    fn limit_exceeded(
+        &self,
+        limit: limits::Limit,
+        function: FunctionPointerT,
+    ) -> Error<FunctionPointerT> {
+        Error::LimitExceeded {
+            limit,
+            progress: limits::Progress {
+                instructions: self.executed,
+                call_depth: self.frames.len(),
+                function,
+            },
+        }
+    }
+
+    /// Error if running another instruction would go over the budget
+    fn check_limits(&self) -> Result<(), Error<FunctionPointerT>> {
+        let limit = if self.limits.fuel.is_some_and(|fuel| self.executed >= fuel) {
+            limits::Limit::Fuel
+        } else if self.limits.deadline.is_some_and(|deadline| {
+            self.executed
+                .is_multiple_of(limits::DEADLINE_CHECK_INTERVAL)
+                && std::time::Instant::now() >= deadline
+        }) {
+            limits::Limit::Deadline
+        } else {
+            return Ok(());
+        };
+
+        let function = self
+            .frames
+            .last()
+            .expect("limits are only checked while a frame is running")
+            .function
+            .clone();
+        Err(self.limit_exceeded(limit, function))
+    }
+
+    fn current_frame(&mut self) -> &mut Frame<BlockPointerT, FunctionPointerT, NumberT> {
+        self.frames
+            .last_mut()
+            .expect("the machine should stop running once its call stack is empty")
+    }
+
+    /// Run the rest of the program
+    fn run(mut self) -> Result<NumberT, Error<FunctionPointerT>> {
+        loop {
+            if let Step::Finished(result) = self.step()? {
+                return Ok(result);
+            }
+        }
+    }
+
+    /// Report everything that happens to `tracer`
+    fn with_tracer<'t>(self, tracer: &'t mut DynTracer<'t, ProgramT>) -> Machine<'p, 't, ProgramT> {
+        Machine {
+            tracer: Some(tracer),
+            accesses: Accesses::new(true),
+            program: self.program,
+            frames: self.frames,
+            limits: self.limits,
+            arithmetic: self.arithmetic,
+            natives: self.natives,
+            executed: self.executed,
+        }
+    }
+
+    /// Run a single instruction of the current frame, and tell the tracer about it if there is one
+    fn step(&mut self) -> Result<Step<NumberT>, Error<FunctionPointerT>> {
+        if self.tracer.is_none() {
+            return self.execute();
+        }
+
+        let frame = self.current_frame();
+        let (function, block, index) = (
+            frame.function.clone(),
+            frame.block.clone(),
+            frame.instruction,
+        );
+        let call_depth = self.frames.len();
+        self.accesses.clear();
+
+        let step = self.execute();
+
+        // a block that ends without a jump or return fails without there being an instruction to trace
+        if let Some(instruction) = self.program.get_ir(&block).get(index) {
+            let tracer = self.tracer.as_mut().expect("checked above");
+            tracer.trace(&trace::Event {
+                function: &function,
+                block: &block,
+                index,
+                instruction,
+                reads: &self.accesses.reads,
+                writes: &self.accesses.writes,
+                call_depth,
+                error: step.as_ref().err(),
+            });
+        }
+        step
+    }
+
+    /// Run a single instruction of the current frame
+    #[allow(clippy::too_many_lines)]
+    fn execute(&mut self) -> Result<Step<NumberT>, Error<FunctionPointerT>> {
         use calc_ir::Instruction;
+
+        self.check_limits()?;
+        self.executed += 1;
+
+        let program = self.program;
+        let mode = self.arithmetic;
+        let accesses = &mut self.accesses;
+        let frame = self
+            .frames
+            .last_mut()
+            .expect("the machine should stop running once its call stack is empty");
+        // end of block with no ret or jump
+        let instruction = program
+            .get_ir(&frame.block)
+            .get(frame.instruction)
+            .ok_or(Error::NoReturn)?;
+        frame.instruction += 1;
+
+        let binary = |accesses: &mut Accesses<NumberT>,
+                      frame: &mut Frame<_, _, NumberT>,
+                      lhs: &Register,
+                      rhs: &Register,
+                      out: &Register,
+                      operation: Operation| {
+            let lhs = accesses.read(frame, *lhs)?;
+            let rhs = accesses.read(frame, *rhs)?;
+            let result = operation
+                .evaluate(&lhs, &rhs, mode)
+                .map_err(Error::Arithmetic)?;
+            accesses.write(frame, *out, result)
+        };
+
         match instruction {
             Instruction::LoadImmediate(value, register) => {
-                registers.insert(register.0, *value);
+                accesses.write(frame, *register, value.clone())?;
             }
             Instruction::Call {
                 function_id,
@@ -37,127 +369,270 @@ fn interpret_block<
                 out,
             } => {
                 // look up all arguments before passing them
-                let arguments: Vec<Number> = arguments.iter().map(|r| registers[r.0]).collect();
-                let result = interpret_function(function_id, program, &*arguments);
-                registers.insert(out.0, result.unwrap());
-            }
-            Instruction::Ret(register) => return Some(registers[register.0]),
-            Instruction::LoadArgs(load_into) => match arguments {
-                Some(arguments) => {
-                    let _ = load_into
-                        .iter()
-                        .zip(0..)
-                        .map(|(r, i)| registers.insert(r.0, arguments[i]));
+                let arguments = accesses.read_all(frame, arguments)?;
+                match self.call_native(function_id, &arguments) {
+                    Some(result) => {
+                        // natives don't get a frame, so the result goes straight into the caller's register
+                        let frame = self.frames.last_mut().expect("a frame is running");
+                        self.accesses.write(frame, *out, result?)?;
+                    }
+                    None => self.call(function_id, arguments, Some(*out))?,
                 }
-                None => panic!("attempting to load arguments outside of a function call!"),
-            },
+            }
+            Instruction::Ret(register) => {
+                let result = accesses.read(frame, *register)?;
+                let returning = self.frames.pop().expect("a frame is running");
+                match (self.frames.last_mut(), returning.return_register) {
+                    (Some(caller), Some(out)) => accesses.write(caller, out, result)?,
+                    _ => return Ok(Step::Finished(result)),
+                }
+            }
+            Instruction::LoadArgs(load_into) => {
+                if load_into.len() != frame.arguments.len() {
+                    return Err(Error::ArgumentMismatch {
+                        expected: load_into.len(),
+                        provided: frame.arguments.len(),
+                    });
+                }
+                for (register, value) in load_into.iter().zip(frame.arguments.clone()) {
+                    accesses.write(frame, *register, value)?;
+                }
+            }
+            Instruction::LoadBlockArgs(load_into) => {
+                if load_into.len() != frame.block_arguments.len() {
+                    return Err(Error::BlockArgumentMismatch {
+                        expected: load_into.len(),
+                        provided: frame.block_arguments.len(),
+                    });
+                }
+                // these are reassigned on every entry into the block, so they must overwrite
+                for (register, value) in load_into.iter().zip(frame.block_arguments.clone()) {
+                    accesses.write(frame, *register, value)?;
+                }
+            }
 
-            // TODO: figure out how to handle jumps
-            // we should probably refactor 'interpret_function' into interpret_function and interpret_block
-            Instruction::Jump(to) => {
-                interpret_block(to, program, registers, arguments);
+            Instruction::Jump { to, arguments } => {
+                let arguments = accesses.read_all(frame, arguments)?;
+                self.jump(to, arguments)?;
             }
-            Instruction::JEqual { lhs, rhs, to } => {
-                if registers[lhs.0] == registers[rhs.0] {
-                    match interpret_block(to, program, registers, arguments) {
-                        Some(ret) => return Some(ret),
-                        None => continue,
-                    }
-                };
+            Instruction::JEqual {
+                lhs,
+                rhs,
+                to,
+                arguments,
+            } => {
+                if accesses.read(frame, *lhs)? == accesses.read(frame, *rhs)? {
+                    let arguments = accesses.read_all(frame, arguments)?;
+                    self.jump(to, arguments)?;
+                }
             }
-            Instruction::JNotEqual { lhs, rhs, to } => {
-                if registers[lhs.0] != registers[rhs.0] {
-                    match interpret_block(to, program, registers, arguments) {
-                        Some(ret) => return Some(ret),
-                        None => continue,
-                    }
-                };
+            Instruction::JNotEqual {
+                lhs,
+                rhs,
+                to,
+                arguments,
+            } => {
+                if accesses.read(frame, *lhs)? != accesses.read(frame, *rhs)? {
+                    let arguments = accesses.read_all(frame, arguments)?;
+                    self.jump(to, arguments)?;
+                }
             }
-            Instruction::JNonZero { check, to } => {
-                if registers[check.0] != 0 {
-                    match interpret_block(to, program, registers, arguments) {
-                        Some(ret) => return Some(ret),
-                        None => continue,
-                    }
-                };
+            Instruction::JNonZero {
+                check,
+                to,
+                arguments,
+            } => {
+                if !accesses.read(frame, *check)?.is_zero() {
+                    let arguments = accesses.read_all(frame, arguments)?;
+                    self.jump(to, arguments)?;
+                }
             }
-            Instruction::JZero { check, to } => {
-                if registers[check.0] == 0 {
-                    match interpret_block(to, program, registers, arguments) {
-                        Some(ret) => return Some(ret),
-                        None => continue,
-                    }
-                };
+            Instruction::JZero {
+                check,
+                to,
+                arguments,
+            } => {
+                if accesses.read(frame, *check)?.is_zero() {
+                    let arguments = accesses.read_all(frame, arguments)?;
+                    self.jump(to, arguments)?;
+                }
             }
 
             Instruction::Add { lhs, rhs, out } => {
-                registers.insert(out.0, registers[lhs.0] + registers[rhs.0]);
+                binary(accesses, frame, lhs, rhs, out, Operation::Add)?;
             }
             Instruction::Subtract { lhs, rhs, out } => {
-                registers.insert(out.0, registers[lhs.0] - registers[rhs.0]);
+                binary(accesses, frame, lhs, rhs, out, Operation::Subtract)?;
             }
             Instruction::Multiply { lhs, rhs, out } => {
-                registers.insert(out.0, registers[lhs.0] * registers[rhs.0]);
+                binary(accesses, frame, lhs, rhs, out, Operation::Multiply)?;
             }
             Instruction::Divide { lhs, rhs, out } => {
-                registers.insert(out.0, registers[lhs.0] / registers[rhs.0]);
+                binary(accesses, frame, lhs, rhs, out, Operation::Divide)?;
             }
             Instruction::Modulo { lhs, rhs, out } => {
-                registers.insert(out.0, registers[lhs.0] % registers[rhs.0]);
+                binary(accesses, frame, lhs, rhs, out, Operation::Modulo)?;
             }
 
             Instruction::BitOr { lhs, rhs, out } => {
-                registers.insert(out.0, registers[lhs.0] | registers[rhs.0]);
+                binary(accesses, frame, lhs, rhs, out, Operation::BitOr)?;
             }
             Instruction::BitNotOr { lhs, rhs, out } => {
-                registers.insert(out.0, registers[lhs.0] ^ registers[rhs.0]);
+                binary(accesses, frame, lhs, rhs, out, Operation::BitNotOr)?;
             }
             Instruction::BitAnd { lhs, rhs, out } => {
-                registers.insert(out.0, registers[lhs.0] & registers[rhs.0]);
+                binary(accesses, frame, lhs, rhs, out, Operation::BitAnd)?;
             }
             Instruction::ShiftL { lhs, rhs, out } => {
-                registers.insert(out.0, registers[lhs.0] << registers[rhs.0]);
+                binary(accesses, frame, lhs, rhs, out, Operation::ShiftL)?;
             }
             Instruction::ShiftR { lhs, rhs, out } => {
-                registers.insert(out.0, registers[lhs.0] >> registers[rhs.0]);
+                binary(accesses, frame, lhs, rhs, out, Operation::ShiftR)?;
             }
 
-            Instruction::Invalid => {
-                panic!("Invalid instruction in block with pointer {:?}", &block)
-            }
-        };
+            Instruction::Invalid => return Err(Error::InvalidInstruction),
+        }
+
+        Ok(Step::Running)
+    }
+}
+
+/// An interpreter for a single program, configured with the options that every function it interprets will be run with
+///
+/// The program and natives are borrowed for `'p`, and the tracer, if there is one, for `'t`
+pub struct Interpreter<'p, 't, ProgramT: Program> {
+    program: &'p ProgramT,
+    limits: Limits,
+    arithmetic: ArithmeticMode,
+    natives: Option<&'p Natives<ProgramT::FunctionPointer, ProgramT::Number>>,
+    tracer: Option<RefCell<&'t mut DynTracer<'t, ProgramT>>>,
+}
+
+impl<
+        'p,
+        't,
+        BlockPointerT: Eq + std::fmt::Debug + Clone,
+        FunctionPointerT: Eq + std::fmt::Debug + Clone,
+        NumberT: Numeric,
+        ProgramT: Program<FunctionPointer = FunctionPointerT, BlockPointer = BlockPointerT, Number = NumberT>,
+    > Interpreter<'p, 't, ProgramT>
+{
+    /// Create an interpreter for `program`, without any limits, using [`ArithmeticMode::Wrapping`]
+    pub fn new(program: &'p ProgramT) -> Self {
+        Self {
+            program,
+            limits: Limits::default(),
+            arithmetic: ArithmeticMode::default(),
+            natives: None,
+            tracer: None,
+        }
+    }
+
+    /// Run every function within the budget given by `limits`
+    #[must_use]
+    pub fn with_limits(self, limits: Limits) -> Self {
+        Self { limits, ..self }
+    }
+
+    /// Evaluate arithmetic according to `arithmetic`
+    #[must_use]
+    pub fn with_arithmetic(self, arithmetic: ArithmeticMode) -> Self {
+        Self { arithmetic, ..self }
+    }
+
+    /// Call the functions in `natives` whenever the program calls a function that it doesn't define itself
+    #[must_use]
+    pub fn with_natives(self, natives: &'p Natives<FunctionPointerT, NumberT>) -> Self {
+        Self {
+            natives: Some(natives),
+            ..self
+        }
     }
 
-    // end of block with no ret or jump
-    // TODO: this implicitly continues the calling block, but this might not be desired behaviour
-    None
+    /// Report every instruction that's executed to `tracer`, see [`trace`] for the tracers that come with the interpreter
+    ///
+    /// If a function is interpreted while another one is already being traced, for example by a native function that calls
+    /// back into the interpreter, only the outer one is traced
+    #[must_use]
+    pub fn with_tracer(self, tracer: &'t mut DynTracer<'t, ProgramT>) -> Self {
+        Self {
+            tracer: Some(RefCell::new(tracer)),
+            ..self
+        }
+    }
+
+    /// Start debugging `function`, called with `arguments`, the returned [`Debugger`] is paused before the first instruction runs.
+    ///
+    /// The program is run with this interpreter's limits, arithmetic and natives, but not its tracer
+    ///
+    /// # Errors
+    /// If `function` can't be called, for example if it doesn't exist or is native, see [`Error`]
+    pub fn debug(
+        &self,
+        function: &FunctionPointerT,
+        arguments: &[NumberT],
+    ) -> Result<Debugger<'p, ProgramT>, Error<FunctionPointerT>> {
+        Ok(Debugger::new(Machine::new(
+            self,
+            function,
+            arguments.to_vec(),
+        )?))
+    }
+
+    /// interprets the function `function`, passing in the arguments in `arguments` and returns its result,
+    /// as returned by [`calc_ir::Instruction::Ret`]
+    ///
+    /// Jumps transfer control to the block they point to, so a function runs block after block until one of them returns.
+    /// Every call gets its own [`Frame`], see its documentation for how registers behave.
+    ///
+    /// # Errors
+    /// The function can fail in various ways, such as if it's told to interpret a function that doesn't exist,
+    /// or if it exceeds its [`Limits`], see [`Error`]
+    pub fn interpret(
+        &self,
+        function: &FunctionPointerT,
+        arguments: &[NumberT],
+    ) -> Result<NumberT, Error<FunctionPointerT>> {
+        if self.program.get_function_entry(function).is_none() {
+            if let Some(result) = self
+                .natives
+                .and_then(|natives| natives.call(function, arguments))
+            {
+                return result;
+            }
+        }
+
+        let machine = Machine::new(self, function, arguments.to_vec())?;
+        match self
+            .tracer
+            .as_ref()
+            .and_then(|tracer| tracer.try_borrow_mut().ok())
+        {
+            Some(mut tracer) => {
+                tracer.start(function);
+                machine.with_tracer(&mut **tracer).run()
+            }
+            None => machine.run(),
+        }
+    }
 }
 
 /// interprets a function that's been registered to `program` with the name `function`, passing in the arguments in `arguments` and returns its result,
 /// as returned by [`calc_ir::Instruction::Ret`]
 ///
+/// This runs without any [`Limits`] and with wrapping arithmetic, use an [`Interpreter`] to configure how the function is run.
+///
 /// # Errors
-/// The function can fail in various ways, such as if it's told to interpret a function that doesn't exist
-// TODO: Proper error type
+/// The function can fail in various ways, such as if it's told to interpret a function that doesn't exist, see [`Error`]
 pub fn interpret_function<
     BlockPointerT: Eq + std::fmt::Debug + Clone,
     FunctionPointerT: Eq + std::fmt::Debug + Clone,
-    ProgramT: Program<FunctionPointer = FunctionPointerT, BlockPointer = BlockPointerT>,
+    NumberT: Numeric,
+    ProgramT: Program<FunctionPointer = FunctionPointerT, BlockPointer = BlockPointerT, Number = NumberT>,
 >(
     function: &ProgramT::FunctionPointer,
     program: &ProgramT,
-    arguments: &[Number],
-) -> Result<Number, ()> {
-    let mut registers: State = Vec::new();
-    let to_interpret = {
-        match program.get_function_entry(function) {
-            Some(function_block) => function_block,
-            None => return Err(()),
-        }
-    };
-
-    match interpret_block(&to_interpret, program, &mut registers, Some(arguments)) {
-        Some(num) => Ok(num),
-        None => Err(()),
-    }
+    arguments: &[NumberT],
+) -> Result<NumberT, Error<FunctionPointerT>> {
+    Interpreter::new(program).interpret(function, arguments)
 }
@@ -0,0 +1,75 @@
+//! Execution budgets, for running programs that might never terminate
+
+use std::time::{Duration, Instant};
+
+/// How often the deadline is checked, reading the clock on every instruction would slow down the interpreter a lot
+pub(crate) const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+
+/// The budget that a program is given to run in, when it's exceeded interpretation stops with [`crate::Error::LimitExceeded`].
+///
+/// Every limit defaults to being unlimited
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Limits {
+    /// the maximum amount of instructions that will be executed
+    pub fuel: Option<u64>,
+    /// the maximum amount of frames on the call stack, including the function that was called first
+    pub max_call_depth: Option<usize>,
+    /// the point in time after which execution stops
+    ///
+    /// This is only checked every so often, so execution may run slightly past it
+    pub deadline: Option<Instant>,
+}
+
+impl Limits {
+    /// Limit the amount of instructions that will be executed
+    #[must_use]
+    pub fn with_fuel(self, fuel: u64) -> Self {
+        Self {
+            fuel: Some(fuel),
+            ..self
+        }
+    }
+
+    /// Limit how deep the call stack can get
+    #[must_use]
+    pub fn with_max_call_depth(self, max_call_depth: usize) -> Self {
+        Self {
+            max_call_depth: Some(max_call_depth),
+            ..self
+        }
+    }
+
+    /// Stop execution at `deadline`
+    #[must_use]
+    pub fn with_deadline(self, deadline: Instant) -> Self {
+        Self {
+            deadline: Some(deadline),
+            ..self
+        }
+    }
+
+    /// Stop execution once `timeout` has passed from now
+    #[must_use]
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.with_deadline(Instant::now() + timeout)
+    }
+}
+
+/// Which of the [`Limits`] was exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Fuel,
+    CallDepth,
+    Deadline,
+}
+
+/// How far a program got before it exceeded its [`Limits`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Progress<FunctionPointerT> {
+    /// the amount of instructions that were executed
+    pub instructions: u64,
+    /// the depth of the call stack when execution stopped
+    pub call_depth: usize,
+    /// the function that was running when execution stopped, or for [`Limit::CallDepth`], the function that couldn't be called
+    pub function: FunctionPointerT,
+}
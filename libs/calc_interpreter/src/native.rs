@@ -0,0 +1,130 @@
+//! Functions implemented by the host, that programs can call like any other function
+//!
+//! A [`Natives`] is given to [`crate::Interpreter::with_natives`] or [`crate::BytecodeInterpreter::with_natives`],
+//! and is consulted whenever a program calls a function that [`calc_ir::Program::get_function_entry`] doesn't define,
+//! so a program's own functions always take precedence over native ones with the same name.
+//!
+//! A native call runs to completion as part of the [`calc_ir::Instruction::Call`] that made it, it doesn't get a [`crate::Frame`],
+//! and doesn't count towards the call depth or use any fuel beyond that of the call itself.
+
+use calc_ir::Number;
+
+use crate::Error;
+
+/// The signature of a native function, errors are reported as [`Error::Native`] with the message they display as
+type NativeFn<NumberT> = dyn Fn(&[NumberT]) -> Result<NumberT, String>;
+
+struct NativeFunction<FunctionPointerT, NumberT> {
+    pointer: FunctionPointerT,
+    /// the amount of arguments the function must be called with, or None if it takes any amount
+    arity: Option<usize>,
+    function: Box<NativeFn<NumberT>>,
+}
+
+/// A registry of native functions, see the module documentation
+pub struct Natives<FunctionPointerT, NumberT = Number> {
+    functions: Vec<NativeFunction<FunctionPointerT, NumberT>>,
+}
+
+impl<FunctionPointerT, NumberT> Default for Natives<FunctionPointerT, NumberT> {
+    fn default() -> Self {
+        Self {
+            functions: Vec::new(),
+        }
+    }
+}
+
+impl<FunctionPointerT: Eq + Clone, NumberT> Natives<FunctionPointerT, NumberT> {
+    /// Create a registry without any functions
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `native` as `function`, which must be called with exactly `arity` arguments.
+    ///
+    /// Registering a function that's already registered replaces it
+    #[must_use]
+    pub fn with_function<E: std::fmt::Display>(
+        self,
+        function: FunctionPointerT,
+        arity: usize,
+        native: impl Fn(&[NumberT]) -> Result<NumberT, E> + 'static,
+    ) -> Self {
+        self.with(function, Some(arity), native)
+    }
+
+    /// Register `native` as `function`, which can be called with any amount of arguments
+    #[must_use]
+    pub fn with_variadic_function<E: std::fmt::Display>(
+        self,
+        function: FunctionPointerT,
+        native: impl Fn(&[NumberT]) -> Result<NumberT, E> + 'static,
+    ) -> Self {
+        self.with(function, None, native)
+    }
+
+    fn with<E: std::fmt::Display>(
+        mut self,
+        function: FunctionPointerT,
+        arity: Option<usize>,
+        native: impl Fn(&[NumberT]) -> Result<NumberT, E> + 'static,
+    ) -> Self {
+        self.functions.retain(|native| native.pointer != function);
+        self.functions.push(NativeFunction {
+            pointer: function,
+            arity,
+            function: Box::new(move |arguments| native(arguments).map_err(|e| e.to_string())),
+        });
+        self
+    }
+
+    /// Whether `function` is registered
+    pub fn contains(&self, function: &FunctionPointerT) -> bool {
+        self.index(function).is_some()
+    }
+
+    /// The amount of arguments `function` must be called with, or None if it isn't registered or takes any amount
+    pub fn get_arity(&self, function: &FunctionPointerT) -> Option<usize> {
+        self.index(function)
+            .and_then(|index| self.functions[index].arity)
+    }
+
+    /// The position of `function` in the registry, which is what [`Self::call_index`] takes
+    pub(crate) fn index(&self, function: &FunctionPointerT) -> Option<usize> {
+        self.functions
+            .iter()
+            .position(|native| native.pointer == *function)
+    }
+
+    /// Call `function` with `arguments`, or return None if it isn't registered
+    pub(crate) fn call(
+        &self,
+        function: &FunctionPointerT,
+        arguments: &[NumberT],
+    ) -> Option<Result<NumberT, Error<FunctionPointerT>>> {
+        self.index(function)
+            .map(|index| self.call_index(index, arguments))
+    }
+
+    /// Call the function at `index` with `arguments`, checking them against its arity
+    pub(crate) fn call_index(
+        &self,
+        index: usize,
+        arguments: &[NumberT],
+    ) -> Result<NumberT, Error<FunctionPointerT>> {
+        let native = &self.functions[index];
+        if let Some(arity) = native.arity {
+            if arity != arguments.len() {
+                return Err(Error::ArgumentMismatch {
+                    expected: arity,
+                    provided: arguments.len(),
+                });
+            }
+        }
+        (native.function)(arguments).map_err(|message| Error::Native {
+            function: native.pointer.clone(),
+            message,
+        })
+    }
+}
@@ -0,0 +1,337 @@
+//! Counting where a program spends its instructions
+//!
+//! A [`Profiler`] is a [`Tracer`], so it's attached with [`crate::Interpreter::with_tracer`].
+//! Once the program has run, its counts can be read directly, for example by profile guided optimization passes,
+//! or written out as a report with [`Profiler::write_report`], or as folded stacks for flamegraph tools with [`Profiler::write_folded`].
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+use std::io::{self, Write};
+
+use calc_ir::Instruction;
+
+use crate::trace::{Event, Tracer};
+
+/// The instruction counts of a single function
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FunctionProfile {
+    /// how many times the function was called
+    pub calls: u64,
+    /// the instructions executed while the function was on the call stack, including those of the functions it called.
+    ///
+    /// Recursive calls are only counted once, so this is never more than the total amount of instructions
+    pub inclusive: u64,
+    /// the instructions executed in the function itself
+    pub exclusive: u64,
+}
+
+/// A function on the call stack
+struct Active<FunctionPointerT> {
+    function: FunctionPointerT,
+    /// the amount of instructions that had been executed when the function was called
+    called_at: u64,
+    /// this frame's node in [`Profiler::stacks`]
+    node: usize,
+}
+
+/// A distinct call stack, stored as a tree where each node is its parent's stack with one more function called
+struct StackNode<FunctionPointerT> {
+    parent: Option<usize>,
+    function: FunctionPointerT,
+    /// the instructions executed with exactly this call stack
+    instructions: u64,
+}
+
+/// Collects instruction counts per opcode, per function and per block, as well as per call stack.
+///
+/// A profiler can be attached to several runs one after another, and reports on all of them together.
+/// When a run fails, every function that was running when it failed is counted as having returned there.
+pub struct Profiler<BlockPointerT, FunctionPointerT> {
+    instructions: u64,
+    opcodes: HashMap<&'static str, u64>,
+    functions: HashMap<FunctionPointerT, FunctionProfile>,
+    /// how many times each block was entered
+    blocks: HashMap<(FunctionPointerT, BlockPointerT), u64>,
+    stack: Vec<Active<FunctionPointerT>>,
+    /// how many frames of each function are on `stack`, so that recursion only counts towards the inclusive count once
+    active: HashMap<FunctionPointerT, usize>,
+    stacks: Vec<StackNode<FunctionPointerT>>,
+    /// the index into `stacks` of the stack made by calling a function from a parent stack
+    stack_nodes: HashMap<(Option<usize>, FunctionPointerT), usize>,
+}
+
+impl<BlockPointerT, FunctionPointerT> Default for Profiler<BlockPointerT, FunctionPointerT> {
+    fn default() -> Self {
+        Self {
+            instructions: 0,
+            opcodes: HashMap::new(),
+            functions: HashMap::new(),
+            blocks: HashMap::new(),
+            stack: Vec::new(),
+            active: HashMap::new(),
+            stacks: Vec::new(),
+            stack_nodes: HashMap::new(),
+        }
+    }
+}
+
+impl<BlockPointerT: Eq + Clone + Hash, FunctionPointerT: Eq + Clone + Hash>
+    Profiler<BlockPointerT, FunctionPointerT>
+{
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The total amount of instructions executed
+    #[must_use]
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// How many times each kind of instruction was executed, most executed first, see [`Instruction::name`]
+    #[must_use]
+    pub fn opcodes(&self) -> Vec<(&'static str, u64)> {
+        let mut opcodes: Vec<_> = self
+            .opcodes
+            .iter()
+            .map(|(name, count)| (*name, *count))
+            .collect();
+        opcodes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        opcodes
+    }
+
+    /// The counts of `function`, or None if it never ran
+    #[must_use]
+    pub fn function(&self, function: &FunctionPointerT) -> Option<FunctionProfile> {
+        let mut profile = *self.functions.get(function)?;
+        // functions that are still running haven't had their inclusive count added yet
+        if let Some(outermost) = self
+            .stack
+            .iter()
+            .find(|active| active.function == *function)
+        {
+            profile.inclusive += self.instructions - outermost.called_at;
+        }
+        Some(profile)
+    }
+
+    /// How many times `block` of `function` was entered
+    #[must_use]
+    pub fn block(&self, function: &FunctionPointerT, block: &BlockPointerT) -> u64 {
+        self.blocks
+            .get(&(function.clone(), block.clone()))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Every function that ran, the one with the most exclusive instructions first
+    #[must_use]
+    pub fn functions(&self) -> Vec<(&FunctionPointerT, FunctionProfile)>
+    where
+        FunctionPointerT: Debug,
+    {
+        let mut functions: Vec<_> = self
+            .functions
+            .keys()
+            .filter_map(|function| Some((function, self.function(function)?)))
+            .collect();
+        sort_by_count(&mut functions, |(function, profile)| {
+            (profile.exclusive, format!("{function:?}"))
+        });
+        functions
+    }
+
+    /// Every block that was entered, the most entered first
+    #[must_use]
+    pub fn blocks(&self) -> Vec<(&FunctionPointerT, &BlockPointerT, u64)>
+    where
+        BlockPointerT: Debug,
+        FunctionPointerT: Debug,
+    {
+        let mut blocks: Vec<_> = self
+            .blocks
+            .iter()
+            .map(|((function, block), hits)| (function, block, *hits))
+            .collect();
+        sort_by_count(&mut blocks, |(function, block, hits)| {
+            (*hits, format!("{function:?} {block:?}"))
+        });
+        blocks
+    }
+
+    /// Write a human readable report of every count, sorted so that the most executed things come first
+    ///
+    /// # Errors
+    /// If writing to `writer` fails
+    pub fn write_report(&self, writer: &mut impl Write) -> io::Result<()>
+    where
+        BlockPointerT: Debug,
+        FunctionPointerT: Debug + Display,
+    {
+        writeln!(writer, "{} instructions", self.instructions)?;
+
+        writeln!(writer)?;
+        writeln!(
+            writer,
+            "{:<24} {:>10} {:>12} {:>12}",
+            "function", "calls", "inclusive", "exclusive"
+        )?;
+        for (function, profile) in self.functions() {
+            writeln!(
+                writer,
+                "{:<24} {:>10} {:>12} {:>12}",
+                function.to_string(),
+                profile.calls,
+                profile.inclusive,
+                profile.exclusive
+            )?;
+        }
+
+        writeln!(writer)?;
+        writeln!(writer, "{:<24} {:>10}", "opcode", "count")?;
+        for (opcode, count) in self.opcodes() {
+            writeln!(writer, "{opcode:<24} {count:>10}")?;
+        }
+
+        writeln!(writer)?;
+        writeln!(writer, "{:<24} {:>10}", "block", "hits")?;
+        for (function, block, hits) in self.blocks() {
+            writeln!(writer, "{:<24} {hits:>10}", format!("{function} {block:?}"))?;
+        }
+        Ok(())
+    }
+
+    /// Write every call stack as a line of functions separated by `;`, followed by how many instructions ran with exactly that stack.
+    ///
+    /// This is the "folded stacks" format that flamegraph tools take as input
+    ///
+    /// # Errors
+    /// If writing to `writer` fails
+    pub fn write_folded(&self, writer: &mut impl Write) -> io::Result<()>
+    where
+        FunctionPointerT: Display,
+    {
+        let mut lines: Vec<String> = self
+            .stacks
+            .iter()
+            .filter(|node| node.instructions > 0)
+            .map(|node| {
+                let mut functions = vec![node.function.to_string()];
+                let mut parent = node.parent;
+                while let Some(index) = parent {
+                    functions.push(self.stacks[index].function.to_string());
+                    parent = self.stacks[index].parent;
+                }
+                functions.reverse();
+                format!("{} {}", functions.join(";"), node.instructions)
+            })
+            .collect();
+        lines.sort();
+        for line in lines {
+            writeln!(writer, "{line}")?;
+        }
+        Ok(())
+    }
+
+    fn enter(&mut self, function: &FunctionPointerT) {
+        let parent = self.stack.last().map(|active| active.node);
+        let node = *self
+            .stack_nodes
+            .entry((parent, function.clone()))
+            .or_insert_with(|| {
+                self.stacks.push(StackNode {
+                    parent,
+                    function: function.clone(),
+                    instructions: 0,
+                });
+                self.stacks.len() - 1
+            });
+
+        self.functions.entry(function.clone()).or_default().calls += 1;
+        *self.active.entry(function.clone()).or_default() += 1;
+        self.stack.push(Active {
+            function: function.clone(),
+            called_at: self.instructions,
+            node,
+        });
+    }
+
+    /// Count every function that's still running as having returned
+    fn unwind(&mut self) {
+        while !self.stack.is_empty() {
+            self.leave();
+        }
+    }
+
+    fn leave(&mut self) {
+        let Some(returning) = self.stack.pop() else {
+            return;
+        };
+        let active = self
+            .active
+            .get_mut(&returning.function)
+            .expect("every function on the stack is active");
+        *active -= 1;
+        // only the outermost call of a recursive function counts towards its inclusive count
+        if *active == 0 {
+            self.functions
+                .get_mut(&returning.function)
+                .expect("functions are added when they're called")
+                .inclusive += self.instructions - returning.called_at;
+        }
+    }
+}
+
+impl<BlockPointerT, FunctionPointerT, NumberT> Tracer<BlockPointerT, FunctionPointerT, NumberT>
+    for Profiler<BlockPointerT, FunctionPointerT>
+where
+    BlockPointerT: Eq + Clone + Hash,
+    FunctionPointerT: Eq + Clone + Hash,
+{
+    fn trace(&mut self, event: &Event<'_, BlockPointerT, FunctionPointerT, NumberT>) {
+        // the program stops at an instruction that fails, so it doesn't count towards the profile, and nothing that was running will return
+        if event.error.is_some() {
+            self.unwind();
+            return;
+        }
+
+        // a frame is popped as soon as it returns, so the only way to get deeper than the stack is by being called
+        if event.call_depth > self.stack.len() {
+            self.enter(event.function);
+        }
+
+        self.instructions += 1;
+        *self.opcodes.entry(event.instruction.name()).or_default() += 1;
+        let running = self.stack.last().expect("entered above");
+        self.stacks[running.node].instructions += 1;
+        self.functions
+            .get_mut(&running.function)
+            .expect("functions are added when they're called")
+            .exclusive += 1;
+        if event.index == 0 {
+            *self
+                .blocks
+                .entry((event.function.clone(), event.block.clone()))
+                .or_default() += 1;
+        }
+
+        if matches!(event.instruction, Instruction::Ret(_)) {
+            self.leave();
+        }
+    }
+
+    /// A run can fail without a failing instruction to trace, for example by running off the end of a block, so whatever's left of the last run is unwound here
+    fn start(&mut self, _function: &FunctionPointerT) {
+        self.unwind();
+    }
+}
+
+/// Sort `items` by the count that `key` returns, largest first, breaking ties with the name that `key` returns
+fn sort_by_count<T>(items: &mut [T], key: impl Fn(&T) -> (u64, String)) {
+    items.sort_by_cached_key(|item| {
+        let (count, name) = key(item);
+        (std::cmp::Reverse(count), name)
+    });
+}
@@ -1,5 +1,5 @@
 #[allow(unused_imports)]
-use calc_ir::{builder::instructions::*, builder::Program};
+use calc_ir::{builder::instructions::*, builder::Program, Register};
 
 // test that a basic function (adding 1 and 2) can be built and interpreted correctly
 #[test]
@@ -25,7 +25,7 @@ fn basic_add() {
     let program = builder.finalize();
     let result = crate::interpret_function(&MAIN_FN_NAME.to_string(), &program, &[]);
 
-    assert_eq!(result, Ok(3))
+    assert_eq!(result, Ok(3));
 }
 
 /// make the same  function as above and then call it from another function
@@ -55,7 +55,7 @@ fn two_functions() {
 
     let mut entry_block = main_function.build_block();
     let two = entry_block.add_immediate(2);
-    let call = entry_block.add_fn_call(FIRST_FUNCTION_NAME.clone().to_string(), Vec::new());
+    let call = entry_block.add_fn_call(FIRST_FUNCTION_NAME.to_string(), Vec::new());
     let ret_minus_two = entry_block.add_arithmetic(Arithmetic::Subtract, call, two);
     entry_block.add_ret(ret_minus_two);
 
@@ -66,5 +66,1214 @@ fn two_functions() {
 
     let result = crate::interpret_function(&MAIN_FUNCTION_NAME.to_string(), &program, &[]);
 
-    assert_eq!(result, Ok(1))
+    assert_eq!(result, Ok(1));
+}
+
+/// build an if/else diamond from the top down, jumping to blocks before they're built
+#[test]
+fn reserved_blocks() {
+    const MAIN_FUNCTION_NAME: &str = "zero_is_zero";
+
+    let mut builder = Program::new();
+    let mut main_function = builder.make_fn(MAIN_FUNCTION_NAME.to_string());
+
+    let then_block_id = main_function.reserve_block();
+    let else_block_id = main_function.reserve_block();
+
+    let mut entry_block = main_function.build_block();
+    let zero = entry_block.add_immediate(0);
+    entry_block.add_cond_jump(BlockJump::Zero(zero), then_block_id);
+    entry_block.add_cond_jump(BlockJump::Unconditional, else_block_id);
+    let (entry_block_id, main_function) = entry_block.finalize();
+
+    let mut then_block = main_function.build_reserved_block(then_block_id);
+    let one = then_block.add_immediate(1);
+    then_block.add_ret(one);
+    let (id, main_function) = then_block.finalize();
+    assert_eq!(id, then_block_id);
+
+    let mut else_block = main_function.build_reserved_block(else_block_id);
+    let two = else_block.add_immediate(2);
+    else_block.add_ret(two);
+    let (_, main_function) = else_block.finalize();
+
+    let builder = main_function.finalize(entry_block_id);
+    let program = builder.finalize();
+
+    let result = crate::interpret_function(&MAIN_FUNCTION_NAME.to_string(), &program, &[]);
+
+    assert_eq!(result, Ok(1));
+}
+
+/// a program can't be finished while a block it jumps to was reserved but never built
+#[test]
+#[should_panic(expected = "was reserved by main but never built")]
+fn unbuilt_reserved_block() {
+    let mut builder = Program::new();
+    let mut main_function = builder.make_fn("main".to_string());
+    let exit_block_id = main_function.reserve_block();
+
+    let mut entry_block = main_function.build_block();
+    entry_block.add_cond_jump(BlockJump::Unconditional, exit_block_id);
+    let (entry_block_id, main_function) = entry_block.finalize();
+    let _ = main_function.finalize(entry_block_id).finalize();
+}
+
+/// a block reserved by one function can't be built by another
+#[test]
+#[should_panic(expected = "was reserved by first, not second")]
+fn reserved_block_of_another_function() {
+    let mut builder = Program::new();
+    let mut first = builder.make_fn("first".to_string());
+    let reserved = first.reserve_block();
+    let mut entry_block = first.build_block();
+    entry_block.add_cond_jump(BlockJump::Unconditional, reserved);
+    let (entry_block_id, first) = entry_block.finalize();
+    let builder = first.finalize(entry_block_id);
+
+    let mut second = builder.make_fn("second".to_string());
+    let _ = second.build_reserved_block(reserved);
+}
+
+/// sum the numbers 1 through 10 with a loop, carrying the counter and the total through block parameters
+#[test]
+fn block_params_loop() {
+    const MAIN_FUNCTION_NAME: &str = "sum_to_ten";
+
+    let mut builder = Program::new();
+    let mut main_function = builder.make_fn(MAIN_FUNCTION_NAME.to_string());
+
+    let loop_block_id = main_function.reserve_block();
+    let exit_block_id = main_function.reserve_block();
+
+    let mut entry_block = main_function.build_block();
+    let zero = entry_block.add_immediate(0);
+    let one = entry_block.add_immediate(1);
+    let ten = entry_block.add_immediate(10);
+    entry_block.add_cond_jump_with_args(BlockJump::Unconditional, loop_block_id, vec![zero, zero]);
+    let (entry_block_id, main_function) = entry_block.finalize();
+
+    let mut loop_block = main_function.build_reserved_block(loop_block_id);
+    let params = loop_block.add_block_params(2);
+    let (counter, total) = (params[0], params[1]);
+    let next_counter = loop_block.add_arithmetic(Arithmetic::Add, counter, one);
+    let next_total = loop_block.add_arithmetic(Arithmetic::Add, total, next_counter);
+    loop_block.add_cond_jump_with_args(
+        BlockJump::Equal(next_counter, ten),
+        exit_block_id,
+        vec![next_total],
+    );
+    loop_block.add_cond_jump_with_args(
+        BlockJump::Unconditional,
+        loop_block_id,
+        vec![next_counter, next_total],
+    );
+    let (_, main_function) = loop_block.finalize();
+
+    let mut exit_block = main_function.build_reserved_block(exit_block_id);
+    let result = exit_block.add_block_params(1)[0];
+    exit_block.add_ret(result);
+    let (_, main_function) = exit_block.finalize();
+
+    let builder = main_function.finalize(entry_block_id);
+    let program = builder.finalize();
+
+    let result = interpret_both(&program, crate::Limits::default(), MAIN_FUNCTION_NAME, &[]);
+
+    assert_eq!(result, Ok(55));
+}
+
+/// jumping to a block with the wrong amount of arguments is an error rather than garbage
+#[test]
+fn block_argument_mismatch() {
+    const MAIN_FUNCTION_NAME: &str = "mismatch";
+
+    let mut builder = Program::new();
+    let mut main_function = builder.make_fn(MAIN_FUNCTION_NAME.to_string());
+
+    let exit_block_id = main_function.reserve_block();
+
+    let mut entry_block = main_function.build_block();
+    let one = entry_block.add_immediate(1);
+    entry_block.add_cond_jump_with_args(BlockJump::Unconditional, exit_block_id, vec![one, one]);
+    let (entry_block_id, main_function) = entry_block.finalize();
+
+    let mut exit_block = main_function.build_reserved_block(exit_block_id);
+    let result = exit_block.add_block_params(1)[0];
+    exit_block.add_ret(result);
+    let (_, main_function) = exit_block.finalize();
+
+    let builder = main_function.finalize(entry_block_id);
+    let program = builder.finalize();
+
+    let result = crate::interpret_function(&MAIN_FUNCTION_NAME.to_string(), &program, &[]);
+
+    assert_eq!(
+        result,
+        Err(crate::Error::BlockArgumentMismatch {
+            expected: 1,
+            provided: 2
+        })
+    );
+}
+
+/// build a function that subtracts its second argument from its first
+fn build_subtract(builder: &mut Program, name: &str) {
+    let mut function = builder.make_fn(name.to_string());
+    let mut entry_block = function.build_block();
+    let args = entry_block.add_load_args(2);
+    let difference = entry_block.add_arithmetic(Arithmetic::Subtract, args[0], args[1]);
+    entry_block.add_ret(difference);
+    let (entry_block_id, function) = entry_block.finalize();
+    function.finalize(entry_block_id);
+}
+
+/// arguments passed to `interpret_function` are loaded in order
+#[test]
+fn load_args() {
+    let mut builder = Program::new();
+    build_subtract(&mut builder, "subtract");
+    let program = builder.finalize();
+
+    let result = crate::interpret_function(&"subtract".to_string(), &program, &[10, 3]);
+
+    assert_eq!(result, Ok(7));
+}
+
+/// arguments passed through a Call are loaded in order, and the caller's registers aren't disturbed by the callee
+#[test]
+fn call_with_args() {
+    const MAIN_FUNCTION_NAME: &str = "main";
+
+    let mut builder = Program::new();
+    build_subtract(&mut builder, "subtract");
+
+    let mut main_function = builder.make_fn(MAIN_FUNCTION_NAME.to_string());
+    let mut entry_block = main_function.build_block();
+    let five = entry_block.add_immediate(5);
+    let twenty = entry_block.add_immediate(20);
+    let fifteen = entry_block.add_fn_call("subtract".to_string(), vec![twenty, five]);
+    let ten = entry_block.add_fn_call("subtract".to_string(), vec![fifteen, five]);
+    let result = entry_block.add_arithmetic(Arithmetic::Multiply, ten, twenty);
+    entry_block.add_ret(result);
+    let (entry_block_id, main_function) = entry_block.finalize();
+    let builder = main_function.finalize(entry_block_id);
+    let program = builder.finalize();
+
+    let result = crate::interpret_function(&MAIN_FUNCTION_NAME.to_string(), &program, &[]);
+
+    assert_eq!(result, Ok(200));
+}
+
+#[test]
+fn argument_count_mismatch() {
+    let mut builder = Program::new();
+    build_subtract(&mut builder, "subtract");
+    let program = builder.finalize();
+
+    let result = crate::interpret_function(&"subtract".to_string(), &program, &[1]);
+
+    assert_eq!(
+        result,
+        Err(crate::Error::ArgumentMismatch {
+            expected: 2,
+            provided: 1
+        })
+    );
+}
+
+/// the builder reports the metadata of a function, so the register file can be sized up front and calls can be checked
+#[test]
+fn function_metadata() {
+    use calc_ir::{FunctionAttributes, Program as _};
+
+    let attributes = FunctionAttributes {
+        pure: true,
+        memoize: true,
+        ..FunctionAttributes::default()
+    };
+
+    let mut builder = Program::new();
+    build_subtract(&mut builder, "subtract");
+    let mut constant = builder.make_fn("constant".to_string());
+    constant.set_attributes(attributes);
+    let mut entry_block = constant.build_block();
+    let one = entry_block.add_immediate(1);
+    entry_block.add_ret(one);
+    let (entry_block_id, constant) = entry_block.finalize();
+    constant.finalize(entry_block_id);
+
+    // the arity comes from the entry block, even if it's built after the blocks it jumps to
+    let mut second = builder.make_fn("second".to_string());
+    let entry_block_id = second.reserve_block();
+    let mut exit_block = second.build_block();
+    let parameters = exit_block.add_block_params(2);
+    exit_block.add_ret(parameters[1]);
+    let (exit_block_id, second) = exit_block.finalize();
+    let mut entry_block = second.build_reserved_block(entry_block_id);
+    let arguments = entry_block.add_load_args(2);
+    entry_block.add_cond_jump_with_args(BlockJump::Unconditional, exit_block_id, arguments);
+    let (entry_block_id, second) = entry_block.finalize();
+    second.finalize(entry_block_id);
+
+    // or even after the function has been finalized
+    let mut third = builder.make_fn("third".to_string());
+    let entry_block_id = third.reserve_block();
+    third.finalize(entry_block_id);
+    let mut entry_block = third.build_reserved_block(entry_block_id);
+    let arguments = entry_block.add_load_args(3);
+    entry_block.add_ret(arguments[2]);
+    let _ = entry_block.finalize();
+    let program = builder.finalize();
+
+    assert_eq!(program.get_arity(&"third".to_string()), Some(3));
+    assert_eq!(program.get_register_count(&"third".to_string()), Some(3));
+
+    assert_eq!(program.get_arity(&"second".to_string()), Some(2));
+    assert_eq!(
+        crate::interpret_function(&"second".to_string(), &program, &[1, 2]),
+        Ok(2)
+    );
+    assert_eq!(program.get_register_count(&"subtract".to_string()), Some(3));
+    assert_eq!(program.get_arity(&"subtract".to_string()), Some(2));
+    assert_eq!(
+        program.get_attributes(&"subtract".to_string()),
+        FunctionAttributes::default()
+    );
+    assert_eq!(program.get_arity(&"constant".to_string()), Some(0));
+    assert_eq!(program.get_attributes(&"constant".to_string()), attributes);
+    assert_eq!(program.get_register_count(&"missing".to_string()), None);
+
+    // calls are checked against the arity even if the function never loads its arguments
+    assert_eq!(
+        crate::interpret_function(&"constant".to_string(), &program, &[1]),
+        Err(crate::Error::ArgumentMismatch {
+            expected: 0,
+            provided: 1
+        })
+    );
+}
+
+/// reading a register that's never been written is an error, even when jumping skipped the write
+#[test]
+fn unset_register() {
+    const MAIN_FUNCTION_NAME: &str = "main";
+
+    let mut builder = Program::new();
+    let mut main_function = builder.make_fn(MAIN_FUNCTION_NAME.to_string());
+
+    let exit_block_id = main_function.reserve_block();
+
+    let mut entry_block = main_function.build_block();
+    entry_block.add_cond_jump(BlockJump::Unconditional, exit_block_id);
+    let skipped = entry_block.add_immediate(1);
+    let (entry_block_id, main_function) = entry_block.finalize();
+
+    let mut exit_block = main_function.build_reserved_block(exit_block_id);
+    exit_block.add_ret(skipped);
+    let (_, main_function) = exit_block.finalize();
+
+    let builder = main_function.finalize(entry_block_id);
+    let program = builder.finalize();
+
+    let result = crate::interpret_function(&MAIN_FUNCTION_NAME.to_string(), &program, &[]);
+
+    assert_eq!(result, Err(crate::Error::UnsetRegister(skipped)));
+}
+
+/// a function that calls itself forever
+fn build_infinite_recursion(builder: &mut Program, name: &str) {
+    let mut function = builder.make_fn(name.to_string());
+    let mut entry_block = function.build_block();
+    let result = entry_block.add_fn_call(name.to_string(), Vec::new());
+    entry_block.add_ret(result);
+    let (entry_block_id, function) = entry_block.finalize();
+    function.finalize(entry_block_id);
+}
+
+/// a function that jumps back to its own block forever
+fn build_infinite_loop(builder: &mut Program, name: &str) {
+    let mut function = builder.make_fn(name.to_string());
+    let loop_block_id = function.reserve_block();
+    let mut loop_block = function.build_reserved_block(loop_block_id);
+    loop_block.add_cond_jump(BlockJump::Unconditional, loop_block_id);
+    let (_, function) = loop_block.finalize();
+    function.finalize(loop_block_id);
+}
+
+#[test]
+fn call_depth_limit() {
+    use crate::limits::{Limit, Progress};
+
+    let mut builder = Program::new();
+    build_infinite_recursion(&mut builder, "forever");
+    let program = builder.finalize();
+
+    let result = crate::Interpreter::new(&program)
+        .with_limits(crate::Limits::default().with_max_call_depth(100))
+        .interpret(&"forever".to_string(), &[]);
+
+    assert_eq!(
+        result,
+        Err(crate::Error::LimitExceeded {
+            limit: Limit::CallDepth,
+            progress: Progress {
+                instructions: 100,
+                call_depth: 100,
+                function: "forever".to_string()
+            }
+        })
+    );
+}
+
+#[test]
+fn fuel_limit() {
+    use crate::limits::{Limit, Progress};
+
+    let mut builder = Program::new();
+    build_infinite_loop(&mut builder, "forever");
+    let program = builder.finalize();
+
+    let result = crate::Interpreter::new(&program)
+        .with_limits(crate::Limits::default().with_fuel(1000))
+        .interpret(&"forever".to_string(), &[]);
+
+    assert_eq!(
+        result,
+        Err(crate::Error::LimitExceeded {
+            limit: Limit::Fuel,
+            progress: Progress {
+                instructions: 1000,
+                call_depth: 1,
+                function: "forever".to_string()
+            }
+        })
+    );
+}
+
+#[test]
+fn deadline_limit() {
+    let mut builder = Program::new();
+    build_infinite_loop(&mut builder, "forever");
+    let program = builder.finalize();
+
+    let result = crate::Interpreter::new(&program)
+        .with_limits(crate::Limits::default().with_timeout(std::time::Duration::from_millis(10)))
+        .interpret(&"forever".to_string(), &[]);
+
+    assert!(matches!(
+        result,
+        Err(crate::Error::LimitExceeded {
+            limit: crate::limits::Limit::Deadline,
+            ..
+        })
+    ));
+}
+
+/// a program that finishes within its budget isn't affected by it
+#[test]
+fn within_limits() {
+    let mut builder = Program::new();
+    build_subtract(&mut builder, "subtract");
+    let program = builder.finalize();
+
+    let limits = crate::Limits::default().with_fuel(3).with_max_call_depth(1);
+    let result = crate::Interpreter::new(&program)
+        .with_limits(limits)
+        .interpret(&"subtract".to_string(), &[3, 2]);
+
+    assert_eq!(result, Ok(1));
+}
+
+/// build a function that applies `operation` to its two arguments
+fn build_arithmetic(builder: &mut Program, name: &str, operation: Arithmetic) {
+    let mut function = builder.make_fn(name.to_string());
+    let mut entry_block = function.build_block();
+    let args = entry_block.add_load_args(2);
+    let result = entry_block.add_arithmetic(operation, args[0], args[1]);
+    entry_block.add_ret(result);
+    let (entry_block_id, function) = entry_block.finalize();
+    function.finalize(entry_block_id);
+}
+
+/// overflow behaves the same no matter which profile the interpreter was built with
+#[test]
+fn arithmetic_modes() {
+    use calc_ir::arithmetic::ArithmeticError;
+    use calc_ir::ArithmeticMode;
+
+    let mut builder = Program::new();
+    build_arithmetic(&mut builder, "add", Arithmetic::Add);
+    build_arithmetic(&mut builder, "divide", Arithmetic::Divide);
+    let program = builder.finalize();
+
+    let run = |mode, function: &str, arguments: &[calc_ir::Number]| {
+        crate::Interpreter::new(&program)
+            .with_arithmetic(mode)
+            .interpret(&function.to_string(), arguments)
+    };
+
+    assert_eq!(
+        run(ArithmeticMode::Wrapping, "add", &[isize::MAX, 1]),
+        Ok(isize::MIN)
+    );
+    assert_eq!(
+        run(ArithmeticMode::Checked, "add", &[isize::MAX, 1]),
+        Err(crate::Error::Arithmetic(ArithmeticError::Overflow))
+    );
+    assert_eq!(
+        run(ArithmeticMode::Saturating, "add", &[isize::MAX, 1]),
+        Ok(isize::MAX)
+    );
+
+    // this used to abort the whole process
+    assert_eq!(
+        run(ArithmeticMode::Wrapping, "divide", &[isize::MIN, -1]),
+        Ok(isize::MIN)
+    );
+    assert_eq!(
+        run(ArithmeticMode::Checked, "divide", &[isize::MIN, -1]),
+        Err(crate::Error::Arithmetic(ArithmeticError::Overflow))
+    );
+    assert_eq!(
+        run(ArithmeticMode::Saturating, "divide", &[isize::MIN, -1]),
+        Ok(isize::MAX)
+    );
+
+    for mode in [
+        ArithmeticMode::Wrapping,
+        ArithmeticMode::Checked,
+        ArithmeticMode::Saturating,
+    ] {
+        assert_eq!(
+            run(mode, "divide", &[1, 0]),
+            Err(crate::Error::Arithmetic(ArithmeticError::DivisionByZero))
+        );
+    }
+}
+
+/// 30! doesn't fit in an isize, but it does in a `BigInt`, and the same builder and interpreter work for both
+#[test]
+fn bignum_factorial() {
+    use calc_ir::numeric::BigInt;
+
+    const MAIN_FUNCTION_NAME: &str = "factorial";
+
+    let mut builder = Program::<BigInt>::default();
+    let mut main_function = builder.make_fn(MAIN_FUNCTION_NAME.to_string());
+
+    let loop_block_id = main_function.reserve_block();
+    let exit_block_id = main_function.reserve_block();
+
+    let mut entry_block = main_function.build_block();
+    let n = entry_block.add_load_args(1)[0];
+    let one = entry_block.add_immediate(BigInt::from(1));
+    entry_block.add_cond_jump_with_args(BlockJump::Unconditional, loop_block_id, vec![n, one]);
+    let (entry_block_id, main_function) = entry_block.finalize();
+
+    let mut loop_block = main_function.build_reserved_block(loop_block_id);
+    let params = loop_block.add_block_params(2);
+    let (counter, total) = (params[0], params[1]);
+    loop_block.add_cond_jump_with_args(BlockJump::Zero(counter), exit_block_id, vec![total]);
+    let next_total = loop_block.add_arithmetic(Arithmetic::Multiply, total, counter);
+    let next_counter = loop_block.add_arithmetic(Arithmetic::Subtract, counter, one);
+    loop_block.add_cond_jump_with_args(
+        BlockJump::Unconditional,
+        loop_block_id,
+        vec![next_counter, next_total],
+    );
+    let (_, main_function) = loop_block.finalize();
+
+    let mut exit_block = main_function.build_reserved_block(exit_block_id);
+    let result = exit_block.add_block_params(1)[0];
+    exit_block.add_ret(result);
+    let (_, main_function) = exit_block.finalize();
+
+    let builder = main_function.finalize(entry_block_id);
+    let program = builder.finalize();
+
+    let result = crate::interpret_function(
+        &MAIN_FUNCTION_NAME.to_string(),
+        &program,
+        &[BigInt::from(30)],
+    );
+
+    assert_eq!(
+        result,
+        Ok("265252859812191058636308480000000".parse().unwrap())
+    );
+}
+
+/// shifting a `BigInt` left allocates memory for the result, so the amount is capped instead of allowing any `u32`
+#[test]
+fn bignum_shift_limit() {
+    use calc_ir::arithmetic::{ArithmeticError, Operation};
+    use calc_ir::numeric::{BigInt, MAX_BIGINT_SHIFT};
+    use calc_ir::ArithmeticMode;
+
+    let one = BigInt::from(1);
+    let shift = |amount: u32| {
+        Operation::ShiftL.evaluate(&one, &BigInt::from(amount), ArithmeticMode::Wrapping)
+    };
+
+    assert_eq!(shift(MAX_BIGINT_SHIFT), Ok(&one << MAX_BIGINT_SHIFT));
+    assert_eq!(
+        shift(MAX_BIGINT_SHIFT + 1),
+        Err(ArithmeticError::InvalidShift)
+    );
+    assert_eq!(shift(u32::MAX), Err(ArithmeticError::InvalidShift));
+    // shifting right can only make the number smaller, so it accepts any amount
+    assert_eq!(
+        Operation::ShiftR.evaluate(&one, &BigInt::from(u32::MAX), ArithmeticMode::Wrapping),
+        Ok(BigInt::from(0))
+    );
+}
+
+/// the call depth, function, reads and writes of a traced instruction
+type TracedInstruction = (
+    usize,
+    String,
+    Vec<(Register, isize)>,
+    Vec<(Register, isize)>,
+);
+
+/// a tracer that keeps a summary of every event
+#[derive(Default)]
+struct RecordingTracer {
+    events: Vec<TracedInstruction>,
+}
+
+impl<B: Eq + Clone> crate::Tracer<B, String, isize> for RecordingTracer {
+    fn trace(&mut self, event: &crate::trace::Event<'_, B, String, isize>) {
+        self.events.push((
+            event.call_depth,
+            event.function.clone(),
+            event.reads.to_vec(),
+            event.writes.to_vec(),
+        ));
+    }
+}
+
+/// tracers see every instruction, including the reads of a call's arguments and the write of its result into the caller
+#[test]
+fn tracer_sees_accesses() {
+    let mut builder = Program::new();
+    build_subtract(&mut builder, "subtract");
+
+    let mut main_function = builder.make_fn("main".to_string());
+    let mut entry_block = main_function.build_block();
+    let five = entry_block.add_immediate(5);
+    let three = entry_block.add_immediate(3);
+    let two = entry_block.add_fn_call("subtract".to_string(), vec![five, three]);
+    entry_block.add_ret(two);
+    let (entry_block_id, main_function) = entry_block.finalize();
+    let program = main_function.finalize(entry_block_id).finalize();
+
+    let mut tracer = RecordingTracer::default();
+    let result = crate::Interpreter::new(&program)
+        .with_tracer(&mut tracer)
+        .interpret(&"main".to_string(), &[]);
+    assert_eq!(result, Ok(2));
+
+    let main = || "main".to_string();
+    let subtract = || "subtract".to_string();
+    assert_eq!(
+        tracer.events,
+        vec![
+            (1, main(), vec![], vec![(five, 5)]),
+            (1, main(), vec![], vec![(three, 3)]),
+            (1, main(), vec![(five, 5), (three, 3)], vec![]),
+            (
+                2,
+                subtract(),
+                vec![],
+                vec![(Register(0), 5), (Register(1), 3)]
+            ),
+            (
+                2,
+                subtract(),
+                vec![(Register(0), 5), (Register(1), 3)],
+                vec![(Register(2), 2)]
+            ),
+            // the result is written into the caller's register
+            (2, subtract(), vec![(Register(2), 2)], vec![(two, 2)]),
+            (1, main(), vec![(two, 2)], vec![]),
+        ]
+    );
+}
+
+/// the built in tracers' output formats
+#[test]
+fn built_in_tracers() {
+    use crate::trace::{JsonLinesTracer, PrintTracer};
+
+    let mut builder = Program::new();
+    build_subtract(&mut builder, "subtract");
+    let program = builder.finalize();
+    let subtract = "subtract".to_string();
+
+    let mut print = PrintTracer::new(Vec::new());
+    let result = crate::Interpreter::new(&program)
+        .with_tracer(&mut print)
+        .interpret(&subtract, &[10, 3]);
+    assert_eq!(result, Ok(7));
+    let print = String::from_utf8(print.finish().unwrap()).unwrap();
+    assert_eq!(
+        print,
+        "\"subtract\" BlockID(0)[0]: LoadArgs([Register(0), Register(1)]) writes r0=10, r1=3\n\
+         \"subtract\" BlockID(0)[1]: Subtract { lhs: Register(0), rhs: Register(1), out: Register(2) } reads r0=10, r1=3 writes r2=7\n\
+         \"subtract\" BlockID(0)[2]: Ret(Register(2)) reads r2=7\n"
+    );
+
+    let mut json = JsonLinesTracer::new(Vec::new());
+    let result = crate::Interpreter::new(&program)
+        .with_tracer(&mut json)
+        .interpret(&subtract, &[10, 3]);
+    assert_eq!(result, Ok(7));
+    let json = String::from_utf8(json.finish().unwrap()).unwrap();
+    let lines: Vec<&str> = json.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(
+        lines[1],
+        r#"{"depth":1,"function":"\"subtract\"","block":"BlockID(0)","index":1,"instruction":"Subtract { lhs: Register(0), rhs: Register(1), out: Register(2) }","reads":[{"register":0,"value":10},{"register":1,"value":3}],"writes":[{"register":2,"value":7}]}"#
+    );
+}
+
+/// the instruction that fails is traced along with its error, before the error is returned
+#[test]
+fn tracer_sees_failures() {
+    use crate::trace::{JsonLinesTracer, PrintTracer};
+    use calc_ir::arithmetic::ArithmeticError;
+
+    let mut builder = Program::new();
+    build_arithmetic(&mut builder, "divide", Arithmetic::Divide);
+    let program = builder.finalize();
+    let divide = "divide".to_string();
+    let division_by_zero = Err(crate::Error::Arithmetic(ArithmeticError::DivisionByZero));
+
+    let mut print = PrintTracer::new(Vec::new());
+    let result = crate::Interpreter::new(&program)
+        .with_tracer(&mut print)
+        .interpret(&divide, &[1, 0]);
+    assert_eq!(result, division_by_zero);
+    let print = String::from_utf8(print.finish().unwrap()).unwrap();
+    assert_eq!(
+        print.lines().last().unwrap(),
+        "\"divide\" BlockID(0)[1]: Divide { lhs: Register(0), rhs: Register(1), out: Register(2) } reads r0=1, r1=0 failed: division by zero"
+    );
+
+    let mut json = JsonLinesTracer::new(Vec::new());
+    let result = crate::Interpreter::new(&program)
+        .with_tracer(&mut json)
+        .interpret(&divide, &[1, 0]);
+    assert_eq!(result, division_by_zero);
+    let json = String::from_utf8(json.finish().unwrap()).unwrap();
+    assert!(json
+        .lines()
+        .last()
+        .unwrap()
+        .ends_with(r#""writes":[],"error":"division by zero"}"#));
+}
+
+/// main calls subtract twice and returns 1, returns the program along with the registers of the results of both calls
+fn build_subtract_twice() -> (
+    calc_ir::program::implementations::BasicProgram,
+    Register,
+    Register,
+) {
+    let mut builder = Program::new();
+    build_subtract(&mut builder, "subtract");
+
+    let mut main_function = builder.make_fn("main".to_string());
+    let mut entry_block = main_function.build_block();
+    let five = entry_block.add_immediate(5);
+    let three = entry_block.add_immediate(3);
+    let two = entry_block.add_fn_call("subtract".to_string(), vec![five, three]);
+    let one = entry_block.add_fn_call("subtract".to_string(), vec![three, two]);
+    entry_block.add_ret(one);
+    let (entry_block_id, main_function) = entry_block.finalize();
+    (main_function.finalize(entry_block_id).finalize(), two, one)
+}
+
+/// breakpoints pause on every call, stepping over a call runs all of it, and stepping out returns to the caller
+#[test]
+fn debugger() {
+    use crate::debugger::{Breakpoint, Stop};
+
+    let (program, two, one) = build_subtract_twice();
+
+    let interpreter = crate::Interpreter::new(&program);
+    let mut debugger = interpreter.debug(&"main".to_string(), &[]).unwrap();
+    let subtract = Breakpoint::Function("subtract".to_string());
+    debugger.add_breakpoint(subtract.clone());
+
+    // the first call
+    assert_eq!(debugger.resume(), Ok(Stop::Breakpoint(subtract.clone())));
+    assert_eq!(debugger.call_stack().len(), 2);
+    assert_eq!(debugger.call_stack()[0].register(two), None);
+    assert!(matches!(
+        debugger.current_instruction(),
+        Some(calc_ir::Instruction::LoadArgs(_))
+    ));
+    assert_eq!(debugger.step(), Ok(Stop::Stepped));
+    assert_eq!(debugger.call_stack()[1].register(Register(1)), Some(&3));
+    assert_eq!(debugger.step_out(), Ok(Stop::Stepped));
+    assert_eq!(debugger.call_stack().len(), 1);
+    assert_eq!(debugger.call_stack()[0].register(two), Some(&2));
+
+    // stepping over the second call stops at nothing, even though it calls subtract
+    assert!(debugger.remove_breakpoint(&subtract));
+    assert_eq!(debugger.step_over(), Ok(Stop::Stepped));
+    assert_eq!(debugger.call_stack().len(), 1);
+    assert_eq!(debugger.call_stack()[0].register(one), Some(&1));
+
+    assert_eq!(debugger.resume(), Ok(Stop::Finished(1)));
+    assert!(debugger.call_stack().is_empty());
+    assert_eq!(debugger.outcome(), Some(&Ok(1)));
+    assert_eq!(debugger.step(), Ok(Stop::Finished(1)));
+}
+
+#[test]
+fn profiler() {
+    use crate::profile::FunctionProfile;
+
+    let (program, _, _) = build_subtract_twice();
+    let mut profiler = crate::Profiler::new();
+    let result = crate::Interpreter::new(&program)
+        .with_tracer(&mut profiler)
+        .interpret(&"main".to_string(), &[]);
+    assert_eq!(result, Ok(1));
+
+    let (main, subtract) = ("main".to_string(), "subtract".to_string());
+    assert_eq!(profiler.instructions(), 11);
+    assert_eq!(
+        profiler.functions(),
+        vec![
+            (
+                &subtract,
+                FunctionProfile {
+                    calls: 2,
+                    inclusive: 6,
+                    exclusive: 6
+                }
+            ),
+            (
+                &main,
+                FunctionProfile {
+                    calls: 1,
+                    inclusive: 11,
+                    exclusive: 5
+                }
+            ),
+        ]
+    );
+    assert_eq!(
+        profiler.opcodes(),
+        vec![
+            ("Ret", 3),
+            ("Call", 2),
+            ("LoadArgs", 2),
+            ("LoadImmediate", 2),
+            ("Subtract", 2)
+        ]
+    );
+    let entry =
+        |function: &String| calc_ir::Program::get_function_entry(&program, function).unwrap();
+    assert_eq!(profiler.block(&main, &entry(&main)), 1);
+    assert_eq!(profiler.block(&subtract, &entry(&subtract)), 2);
+
+    let mut folded = Vec::new();
+    profiler.write_folded(&mut folded).unwrap();
+    assert_eq!(
+        String::from_utf8(folded).unwrap(),
+        "main 5\nmain;subtract 6\n"
+    );
+
+    let mut report = Vec::new();
+    profiler.write_report(&mut report).unwrap();
+    let report = String::from_utf8(report).unwrap();
+    assert!(report.starts_with("11 instructions\n"));
+    assert!(report.contains(
+        "subtract                          2            6            6\n\
+         main                              1           11            5\n"
+    ));
+}
+
+/// runs that fail part way through don't leave their frames behind for the runs after them
+#[test]
+fn profiler_after_failures() {
+    use crate::profile::FunctionProfile;
+
+    let mut builder = Program::new();
+    build_subtract(&mut builder, "subtract");
+    let mut main_function = builder.make_fn("main".to_string());
+    let mut entry_block = main_function.build_block();
+    let five = entry_block.add_immediate(5);
+    let three = entry_block.add_immediate(3);
+    let two = entry_block.add_fn_call("subtract".to_string(), vec![five, three]);
+    let one = entry_block.add_fn_call("subtract".to_string(), vec![three, two]);
+    entry_block.add_ret(one);
+    let (entry_block_id, main_function) = entry_block.finalize();
+    main_function.finalize(entry_block_id);
+
+    // fails on an instruction, which is traced
+    let mut quotient = builder.make_fn("quotient".to_string());
+    let mut entry_block = quotient.build_block();
+    let args = entry_block.add_load_args(2);
+    let result = entry_block.add_arithmetic(Arithmetic::Divide, args[0], args[1]);
+    entry_block.add_ret(result);
+    let (entry_block_id, quotient) = entry_block.finalize();
+    quotient.finalize(entry_block_id);
+    let mut divide_by_zero = builder.make_fn("divide_by_zero".to_string());
+    let mut entry_block = divide_by_zero.build_block();
+    let one = entry_block.add_immediate(1);
+    let zero = entry_block.add_immediate(0);
+    let result = entry_block.add_fn_call("quotient".to_string(), vec![one, zero]);
+    entry_block.add_ret(result);
+    let (entry_block_id, divide_by_zero) = entry_block.finalize();
+    divide_by_zero.finalize(entry_block_id);
+
+    // fails by running off the end of a block, which isn't
+    let mut no_return = builder.make_fn("no_return".to_string());
+    let mut entry_block = no_return.build_block();
+    entry_block.add_immediate(1);
+    let (entry_block_id, no_return) = entry_block.finalize();
+    no_return.finalize(entry_block_id);
+    let mut unfinished = builder.make_fn("unfinished".to_string());
+    let mut entry_block = unfinished.build_block();
+    let result = entry_block.add_fn_call("no_return".to_string(), vec![]);
+    entry_block.add_ret(result);
+    let (entry_block_id, unfinished) = entry_block.finalize();
+    unfinished.finalize(entry_block_id);
+    let program = builder.finalize();
+
+    let mut profiler = crate::Profiler::new();
+    let interpreter = crate::Interpreter::new(&program).with_tracer(&mut profiler);
+    assert!(interpreter
+        .interpret(&"divide_by_zero".to_string(), &[])
+        .is_err());
+    assert!(interpreter
+        .interpret(&"unfinished".to_string(), &[])
+        .is_err());
+    assert_eq!(interpreter.interpret(&"main".to_string(), &[]), Ok(1));
+
+    assert_eq!(
+        profiler.function(&"divide_by_zero".to_string()),
+        Some(FunctionProfile {
+            calls: 1,
+            inclusive: 4,
+            exclusive: 3
+        })
+    );
+    assert_eq!(
+        profiler.function(&"unfinished".to_string()),
+        Some(FunctionProfile {
+            calls: 1,
+            inclusive: 2,
+            exclusive: 1
+        })
+    );
+    assert_eq!(
+        profiler.function(&"main".to_string()),
+        Some(FunctionProfile {
+            calls: 1,
+            inclusive: 11,
+            exclusive: 5
+        })
+    );
+
+    let mut folded = Vec::new();
+    profiler.write_folded(&mut folded).unwrap();
+    assert_eq!(
+        String::from_utf8(folded).unwrap(),
+        "divide_by_zero 3\n\
+         divide_by_zero;quotient 1\n\
+         main 5\n\
+         main;subtract 6\n\
+         unfinished 1\n\
+         unfinished;no_return 1\n"
+    );
+}
+
+/// recursion only counts once towards the inclusive count, and functions that are still running are counted
+#[test]
+fn profiler_recursion() {
+    let mut builder = Program::new();
+    build_infinite_recursion(&mut builder, "forever");
+    let program = builder.finalize();
+
+    let mut profiler = crate::Profiler::new();
+    let result = crate::Interpreter::new(&program)
+        .with_limits(crate::Limits::default().with_fuel(10))
+        .with_tracer(&mut profiler)
+        .interpret(&"forever".to_string(), &[]);
+    assert!(result.is_err());
+
+    let profile = profiler.function(&"forever".to_string()).unwrap();
+    assert_eq!(profile.calls, 10);
+    assert_eq!(profile.inclusive, 10);
+    assert_eq!(profile.exclusive, 10);
+}
+
+/// bytecode instructions stay small, the largest are the comparing jumps, with four operands
+#[test]
+fn op_size() {
+    assert_eq!(std::mem::size_of::<crate::bytecode::Op>(), 20);
+}
+
+/// run `function` both through the interpreter and through bytecode, asserting that they agree, and return the result
+fn interpret_both<ProgramT>(
+    program: &ProgramT,
+    limits: crate::Limits,
+    function: &str,
+    arguments: &[isize],
+) -> Result<isize, crate::Error<String>>
+where
+    ProgramT: calc_ir::Program<FunctionPointer = String, Number = isize>,
+    ProgramT::BlockPointer: Eq + Clone + std::fmt::Debug,
+{
+    let function = function.to_string();
+    let expected = crate::Interpreter::new(program)
+        .with_limits(limits)
+        .interpret(&function, arguments);
+
+    let bytecode = crate::Bytecode::compile(program).unwrap();
+    let result = crate::BytecodeInterpreter::new(&bytecode)
+        .with_limits(limits)
+        .interpret(&function, arguments);
+    assert_eq!(result, expected, "bytecode disagrees on {function}");
+    result
+}
+
+/// bytecode returns the same results and errors as interpreting the program it was compiled from
+#[test]
+fn bytecode_matches_interpreter() {
+    let unlimited = crate::Limits::default();
+
+    let (program, _, _) = build_subtract_twice();
+    assert_eq!(interpret_both(&program, unlimited, "main", &[]), Ok(1));
+    assert_eq!(
+        interpret_both(&program, unlimited, "subtract", &[10, 3]),
+        Ok(7)
+    );
+    assert_eq!(
+        interpret_both(&program, unlimited, "subtract", &[1]),
+        Err(crate::Error::ArgumentMismatch {
+            expected: 2,
+            provided: 1
+        })
+    );
+    assert_eq!(
+        interpret_both(&program, unlimited, "missing", &[]),
+        Err(crate::Error::UnknownFunction("missing".to_string()))
+    );
+
+    let mut builder = Program::new();
+    let mut main_function = builder.make_fn("main".to_string());
+    let exit_block_id = main_function.reserve_block();
+    let mut entry_block = main_function.build_block();
+    let one = entry_block.add_immediate(1);
+    entry_block.add_cond_jump_with_args(BlockJump::Unconditional, exit_block_id, vec![one, one]);
+    let (entry_block_id, main_function) = entry_block.finalize();
+    let mut exit_block = main_function.build_reserved_block(exit_block_id);
+    let result = exit_block.add_block_params(1)[0];
+    exit_block.add_ret(result);
+    let (_, main_function) = exit_block.finalize();
+    let builder = main_function.finalize(entry_block_id);
+
+    let mut calls_missing = builder.make_fn("calls_missing".to_string());
+    let mut entry_block = calls_missing.build_block();
+    let result = entry_block.add_fn_call("missing".to_string(), Vec::new());
+    entry_block.add_ret(result);
+    let (entry_block_id, calls_missing) = entry_block.finalize();
+    let program = calls_missing.finalize(entry_block_id).finalize();
+
+    assert_eq!(
+        interpret_both(&program, unlimited, "main", &[]),
+        Err(crate::Error::BlockArgumentMismatch {
+            expected: 1,
+            provided: 2
+        })
+    );
+    assert_eq!(
+        interpret_both(&program, unlimited, "calls_missing", &[]),
+        Err(crate::Error::UnknownFunction("missing".to_string()))
+    );
+}
+
+/// block arguments belong to the frame that jumped, so loading them again after a call sees the same ones in both engines
+#[test]
+fn bytecode_block_arguments() {
+    use calc_ir::program::implementations::BasicProgram;
+    use calc_ir::zir::{FunctionEntry, Layout, Module};
+    use calc_ir::{FunctionAttributes, Instruction};
+
+    let function = |name: &str, entry, arity| FunctionEntry {
+        name: name.to_string(),
+        entry,
+        arity: Some(arity),
+        register_count: None,
+        attributes: FunctionAttributes::default(),
+    };
+    let module = Module {
+        layout: Layout::Blocks,
+        functions: vec![function("main", 0, 0), function("identity", 2, 1)],
+        blocks: vec![
+            vec![
+                Instruction::LoadImmediate(5, Register(0)),
+                Instruction::LoadImmediate(7, Register(1)),
+                Instruction::Jump {
+                    to: 1,
+                    arguments: vec![Register(0), Register(1)],
+                },
+            ],
+            // the callee jumps with arguments of its own before the second load
+            vec![
+                Instruction::LoadBlockArgs(vec![Register(2), Register(3)]),
+                Instruction::Call {
+                    function_id: "identity".to_string(),
+                    arguments: vec![Register(2)],
+                    out: Register(4),
+                },
+                Instruction::LoadBlockArgs(vec![Register(5), Register(6)]),
+                Instruction::Subtract {
+                    lhs: Register(5),
+                    rhs: Register(6),
+                    out: Register(7),
+                },
+                Instruction::Add {
+                    lhs: Register(7),
+                    rhs: Register(4),
+                    out: Register(8),
+                },
+                Instruction::Ret(Register(8)),
+            ],
+            vec![
+                Instruction::LoadArgs(vec![Register(0)]),
+                Instruction::Jump {
+                    to: 3,
+                    arguments: vec![Register(0)],
+                },
+            ],
+            vec![
+                Instruction::LoadBlockArgs(vec![Register(1)]),
+                Instruction::Ret(Register(1)),
+            ],
+        ],
+    };
+    let program = BasicProgram::from_module(module).unwrap();
+
+    assert_eq!(
+        interpret_both(&program, crate::Limits::default(), "main", &[]),
+        Ok(3)
+    );
+}
+
+/// bytecode runs out of its limits at exactly the same point as the interpreter
+#[test]
+fn bytecode_limits() {
+    let mut builder = Program::new();
+    build_infinite_loop(&mut builder, "loop");
+    build_infinite_recursion(&mut builder, "recurse");
+    let program = builder.finalize();
+
+    let limits = crate::Limits::default()
+        .with_fuel(1000)
+        .with_max_call_depth(100);
+    assert!(matches!(
+        interpret_both(&program, limits, "loop", &[]),
+        Err(crate::Error::LimitExceeded {
+            limit: crate::limits::Limit::Fuel,
+            ..
+        })
+    ));
+    assert!(matches!(
+        interpret_both(&program, limits, "recurse", &[]),
+        Err(crate::Error::LimitExceeded {
+            limit: crate::limits::Limit::CallDepth,
+            ..
+        })
+    ));
+
+    let bytecode = crate::Bytecode::compile(&program).unwrap();
+    let result = crate::BytecodeInterpreter::new(&bytecode)
+        .with_limits(crate::Limits::default().with_timeout(std::time::Duration::from_millis(10)))
+        .interpret(&"loop".to_string(), &[]);
+    assert!(matches!(
+        result,
+        Err(crate::Error::LimitExceeded {
+            limit: crate::limits::Limit::Deadline,
+            ..
+        })
+    ));
+}
+
+/// programs can call functions implemented by the host, which are checked against their arity, and can fail
+#[test]
+fn natives() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let mut builder = Program::new();
+    build_subtract(&mut builder, "subtract");
+    let mut main_function = builder.make_fn("main".to_string());
+    let mut entry_block = main_function.build_block();
+    let args = entry_block.add_load_args(1);
+    let root = entry_block.add_fn_call("isqrt".to_string(), args.clone());
+    let three = entry_block.add_immediate(3);
+    let difference = entry_block.add_fn_call("subtract".to_string(), vec![root, three]);
+    entry_block.add_fn_call("log".to_string(), vec![root, difference]);
+    entry_block.add_ret(difference);
+    let (entry_block_id, main_function) = entry_block.finalize();
+    let program = main_function.finalize(entry_block_id).finalize();
+
+    let logged = Rc::new(RefCell::new(Vec::new()));
+    let log = Rc::clone(&logged);
+    let natives = crate::Natives::new()
+        .with_function("isqrt".to_string(), 1, |arguments: &[isize]| {
+            if arguments[0] < 0 {
+                return Err("negative square root");
+            }
+            Ok(arguments[0].isqrt())
+        })
+        .with_variadic_function("log".to_string(), move |arguments: &[isize]| {
+            log.borrow_mut().extend_from_slice(arguments);
+            Ok::<_, std::convert::Infallible>(0)
+        })
+        // the program's own functions take precedence
+        .with_function("subtract".to_string(), 2, |_: &[isize]| Ok::<_, String>(0));
+
+    let bytecode = crate::Bytecode::compile(&program).unwrap();
+    let interpreter = crate::Interpreter::new(&program).with_natives(&natives);
+    let bytecode_interpreter = crate::BytecodeInterpreter::new(&bytecode).with_natives(&natives);
+    let run = |function: &str, arguments: &[isize]| {
+        let function = function.to_string();
+        let result = interpreter.interpret(&function, arguments);
+        assert_eq!(bytecode_interpreter.interpret(&function, arguments), result);
+        result
+    };
+
+    assert_eq!(run("main", &[50]), Ok(4));
+    assert_eq!(*logged.borrow(), [7, 4, 7, 4]);
+    assert_eq!(run("isqrt", &[16]), Ok(4));
+    assert_eq!(
+        run("main", &[-1]),
+        Err(crate::Error::Native {
+            function: "isqrt".to_string(),
+            message: "negative square root".to_string()
+        })
+    );
+    assert_eq!(
+        run("isqrt", &[1, 2]),
+        Err(crate::Error::ArgumentMismatch {
+            expected: 1,
+            provided: 2
+        })
+    );
+
+    // without the natives, the calls are to unknown functions
+    assert_eq!(
+        crate::interpret_function(&"main".to_string(), &program, &[50]),
+        Err(crate::Error::UnknownFunction("isqrt".to_string()))
+    );
 }
@@ -0,0 +1,294 @@
+//! Hooks for watching a program run, one instruction at a time
+//!
+//! Attach a [`Tracer`] with [`crate::Interpreter::with_tracer`], there are two built in ones:
+//! [`PrintTracer`] which writes a human readable trace, and [`JsonLinesTracer`] which writes one JSON object per instruction.
+
+use std::fmt::{Debug, Display, Write as _};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use calc_ir::{Instruction, Numeric, Register};
+
+use crate::{Error, Frame};
+
+/// A single instruction that was executed, along with everything it read and wrote
+#[derive(Debug)]
+pub struct Event<'a, BlockPointerT: Eq + Clone, FunctionPointerT: Eq + Clone, NumberT> {
+    /// the function the instruction is in
+    pub function: &'a FunctionPointerT,
+    /// the block the instruction is in
+    pub block: &'a BlockPointerT,
+    /// the index of the instruction in `block`
+    pub index: usize,
+    pub instruction: &'a Instruction<BlockPointerT, FunctionPointerT, NumberT>,
+    /// the registers read by the instruction and the values they held, in the order they were read
+    pub reads: &'a [(Register, NumberT)],
+    /// the registers written by the instruction and the values written to them, in the order they were written
+    ///
+    /// A [`Instruction::Ret`] writes to the register of the calling frame that the call stores its result in
+    pub writes: &'a [(Register, NumberT)],
+    /// how many frames were on the call stack when the instruction ran, the function that was interpreted first has a depth of 1
+    pub call_depth: usize,
+    /// the error the instruction failed with, which is also what [`crate::Interpreter::interpret`] returns
+    ///
+    /// The reads and writes of a failed instruction are the ones it made before it failed
+    pub error: Option<&'a Error<FunctionPointerT>>,
+}
+
+/// Receives every instruction that the interpreter executes, after it's executed.
+///
+/// An instruction that fails is traced with its [`Event::error`] set, and is the last one to be traced
+pub trait Tracer<BlockPointerT: Eq + Clone, FunctionPointerT: Eq + Clone, NumberT> {
+    fn trace(&mut self, event: &Event<'_, BlockPointerT, FunctionPointerT, NumberT>);
+
+    /// Called before the first instruction of every run of [`crate::Interpreter::interpret`], with the function that's being run.
+    ///
+    /// A tracer that's attached to several runs can use this to forget what it knew about the one before, which may have failed part way through
+    fn start(&mut self, _function: &FunctionPointerT) {}
+}
+
+/// The reads and writes of the instruction that's currently running, only recorded when a [`Tracer`] is attached
+pub(crate) struct Accesses<NumberT> {
+    enabled: bool,
+    pub(crate) reads: Vec<(Register, NumberT)>,
+    pub(crate) writes: Vec<(Register, NumberT)>,
+}
+
+impl<NumberT: Numeric> Accesses<NumberT> {
+    pub(crate) fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            reads: Vec::new(),
+            writes: Vec::new(),
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.reads.clear();
+        self.writes.clear();
+    }
+
+    /// [`Frame::read`], recording the read
+    pub(crate) fn read<B, F>(
+        &mut self,
+        frame: &Frame<B, F, NumberT>,
+        register: Register,
+    ) -> Result<NumberT, Error<F>> {
+        let value = frame.read(register)?;
+        if self.enabled {
+            self.reads.push((register, value.clone()));
+        }
+        Ok(value)
+    }
+
+    /// Read every register in `registers`, in order, recording every read
+    pub(crate) fn read_all<B, F>(
+        &mut self,
+        frame: &Frame<B, F, NumberT>,
+        registers: &[Register],
+    ) -> Result<Vec<NumberT>, Error<F>> {
+        registers.iter().map(|r| self.read(frame, *r)).collect()
+    }
+
+    /// [`Frame::write`], recording the write
+    pub(crate) fn write<B, F>(
+        &mut self,
+        frame: &mut Frame<B, F, NumberT>,
+        register: Register,
+        value: NumberT,
+    ) -> Result<(), Error<F>> {
+        if self.enabled {
+            self.writes.push((register, value.clone()));
+        }
+        frame.write(register, value)
+    }
+}
+
+/// Writes every instruction on its own line, indented by the depth of the call stack
+///
+/// Write errors don't stop the program, the first one is kept and returned by [`Self::finish`]
+pub struct PrintTracer<WriterT: Write> {
+    writer: WriterT,
+    error: Option<io::Error>,
+}
+
+impl<WriterT: Write> PrintTracer<WriterT> {
+    pub fn new(writer: WriterT) -> Self {
+        Self {
+            writer,
+            error: None,
+        }
+    }
+
+    /// Flush the trace, returning the writer or the first error that happened while writing the trace
+    ///
+    /// # Errors
+    /// If writing to the writer failed at any point
+    pub fn finish(mut self) -> io::Result<WriterT> {
+        finish(&mut self.writer, self.error)?;
+        Ok(self.writer)
+    }
+}
+
+impl Default for PrintTracer<io::Stdout> {
+    fn default() -> Self {
+        Self::new(io::stdout())
+    }
+}
+
+impl<BlockPointerT, FunctionPointerT, NumberT, WriterT>
+    Tracer<BlockPointerT, FunctionPointerT, NumberT> for PrintTracer<WriterT>
+where
+    BlockPointerT: Eq + Clone + Debug,
+    FunctionPointerT: Eq + Clone + Debug,
+    NumberT: Debug + Display,
+    WriterT: Write,
+{
+    fn trace(&mut self, event: &Event<'_, BlockPointerT, FunctionPointerT, NumberT>) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let mut line = format!(
+            "{:indent$}{:?} {:?}[{}]: {:?}",
+            "",
+            event.function,
+            event.block,
+            event.index,
+            event.instruction,
+            indent = event.call_depth.saturating_sub(1) * 2
+        );
+        for (label, accesses) in [("reads", event.reads), ("writes", event.writes)] {
+            if accesses.is_empty() {
+                continue;
+            }
+            let accesses: Vec<String> = accesses
+                .iter()
+                .map(|(register, value)| format!("r{}={value}", register.0))
+                .collect();
+            let _ = write!(line, " {label} {}", accesses.join(", "));
+        }
+        if let Some(error) = event.error {
+            let _ = write!(line, " failed: {error}");
+        }
+
+        if let Err(error) = writeln!(self.writer, "{line}") {
+            self.error = Some(error);
+        }
+    }
+}
+
+/// Records every instruction as a JSON object on its own line, for loading into other tools.
+///
+/// Each object has the keys `depth`, `function`, `block`, `index`, `instruction`, `reads` and `writes`.
+/// The function, block and instruction are their [`Debug`] representations as strings,
+/// and reads and writes are arrays of `{"register": <number>, "value": <number>}` objects.
+/// An instruction that failed also has an `error` key, with the error as a string.
+///
+/// Write errors don't stop the program, the first one is kept and returned by [`Self::finish`]
+pub struct JsonLinesTracer<WriterT: Write> {
+    writer: WriterT,
+    error: Option<io::Error>,
+}
+
+impl<WriterT: Write> JsonLinesTracer<WriterT> {
+    pub fn new(writer: WriterT) -> Self {
+        Self {
+            writer,
+            error: None,
+        }
+    }
+
+    /// Flush the trace, returning the writer or the first error that happened while writing the trace
+    ///
+    /// # Errors
+    /// If writing to the writer failed at any point
+    pub fn finish(mut self) -> io::Result<WriterT> {
+        finish(&mut self.writer, self.error)?;
+        Ok(self.writer)
+    }
+}
+
+impl JsonLinesTracer<BufWriter<File>> {
+    /// Record the trace to the file at `path`, replacing it if it already exists
+    ///
+    /// # Errors
+    /// If the file can't be created
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<BlockPointerT, FunctionPointerT, NumberT, WriterT>
+    Tracer<BlockPointerT, FunctionPointerT, NumberT> for JsonLinesTracer<WriterT>
+where
+    BlockPointerT: Eq + Clone + Debug,
+    FunctionPointerT: Eq + Clone + Debug,
+    NumberT: Debug + Display,
+    WriterT: Write,
+{
+    fn trace(&mut self, event: &Event<'_, BlockPointerT, FunctionPointerT, NumberT>) {
+        if self.error.is_some() {
+            return;
+        }
+
+        // numbers are written with Display, which for every Numeric is a plain decimal integer, so it's a valid JSON number
+        let accesses = |accesses: &[(Register, NumberT)]| {
+            let accesses: Vec<String> = accesses
+                .iter()
+                .map(|(register, value)| {
+                    format!(r#"{{"register":{},"value":{value}}}"#, register.0)
+                })
+                .collect();
+            format!("[{}]", accesses.join(","))
+        };
+        let error = event
+            .error
+            .map(|error| format!(r#","error":{}"#, json_string(&error.to_string())))
+            .unwrap_or_default();
+        let result = writeln!(
+            self.writer,
+            r#"{{"depth":{},"function":{},"block":{},"index":{},"instruction":{},"reads":{},"writes":{}{error}}}"#,
+            event.call_depth,
+            json_string(&format!("{:?}", event.function)),
+            json_string(&format!("{:?}", event.block)),
+            event.index,
+            json_string(&format!("{:?}", event.instruction)),
+            accesses(event.reads),
+            accesses(event.writes),
+        );
+
+        if let Err(error) = result {
+            self.error = Some(error);
+        }
+    }
+}
+
+fn finish(writer: &mut impl Write, error: Option<io::Error>) -> io::Result<()> {
+    if let Some(error) = error {
+        return Err(error);
+    }
+    writer.flush()
+}
+
+/// `string` as a quoted and escaped JSON string
+fn json_string(string: &str) -> String {
+    let mut json = String::with_capacity(string.len() + 2);
+    json.push('"');
+    for character in string.chars() {
+        match character {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            control if control.is_control() => {
+                let _ = write!(json, "\\u{:04x}", u32::from(control));
+            }
+            character => json.push(character),
+        }
+    }
+    json.push('"');
+    json
+}
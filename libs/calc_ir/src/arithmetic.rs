@@ -0,0 +1,105 @@
+//! The semantics of the arithmetic and bitwise instructions.
+//!
+//! Every consumer that evaluates these instructions, such as the interpreter and constant folding in the optimizer, should go through
+//! [`Operation::evaluate`] so that they produce the exact same results, no matter which profile they were built with.
+//!
+//! The modes describe what happens with a fixed size [`crate::Number`], see [`Numeric`] for how other number types treat them.
+
+use crate::numeric::Numeric;
+use crate::{Instruction, Register};
+
+/// What happens when the result of an operation doesn't fit in a [`crate::Number`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArithmeticMode {
+    /// results wrap around in two's complement, shift amounts are taken modulo the width of a [`crate::Number`]
+    #[default]
+    Wrapping,
+    /// results that don't fit, and shift amounts that are negative or at least the width of a [`crate::Number`], are an error
+    Checked,
+    /// results are clamped to [`crate::Number::MIN`] and [`crate::Number::MAX`], shifting by at least the width of a [`crate::Number`] shifts every bit out,
+    /// and negative shift amounts are an error
+    Saturating,
+}
+
+/// The ways an operation can fail
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithmeticError {
+    /// the result didn't fit in a [`crate::Number`], only returned in [`ArithmeticMode::Checked`]
+    Overflow,
+    /// the right hand side of a division or modulo was zero, this is an error in every mode
+    DivisionByZero,
+    /// a shift amount was negative, in [`ArithmeticMode::Checked`] at least the width of a [`crate::Number`],
+    /// or too large for an arbitrary precision number to shift left by
+    InvalidShift,
+}
+
+impl std::fmt::Display for ArithmeticError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArithmeticError::Overflow => write!(f, "arithmetic overflow"),
+            ArithmeticError::DivisionByZero => write!(f, "division by zero"),
+            ArithmeticError::InvalidShift => write!(f, "invalid shift amount"),
+        }
+    }
+}
+
+impl std::error::Error for ArithmeticError {}
+
+/// An operation performed by one of the instructions that take a `lhs` and `rhs` register and write to an `out` register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    BitOr,
+    BitNotOr,
+    BitAnd,
+    ShiftL,
+    ShiftR,
+}
+
+impl Operation {
+    /// Whether evaluating this operation on `NumberT`s can fail in `mode`, regardless of its operands
+    #[must_use]
+    pub fn can_fail<NumberT: Numeric>(self, mode: ArithmeticMode) -> bool {
+        NumberT::can_fail(self, mode)
+    }
+
+    /// Evaluate `lhs <operation> rhs` according to `mode`
+    ///
+    /// # Errors
+    /// See [`ArithmeticError`] and [`ArithmeticMode`] for when this fails
+    pub fn evaluate<NumberT: Numeric>(
+        self,
+        lhs: &NumberT,
+        rhs: &NumberT,
+        mode: ArithmeticMode,
+    ) -> Result<NumberT, ArithmeticError> {
+        NumberT::evaluate(self, lhs, rhs, mode)
+    }
+}
+
+impl<BlockId: Eq + Clone, FunctionId: Eq + Clone, NumberT>
+    Instruction<BlockId, FunctionId, NumberT>
+{
+    /// The [`Operation`] this instruction performs, along with its `lhs`, `rhs` and `out` registers, or None if it isn't an arithmetic
+    /// or bitwise instruction
+    pub fn operation(&self) -> Option<(Operation, Register, Register, Register)> {
+        let (operation, lhs, rhs, out) = match self {
+            Instruction::Add { lhs, rhs, out } => (Operation::Add, lhs, rhs, out),
+            Instruction::Subtract { lhs, rhs, out } => (Operation::Subtract, lhs, rhs, out),
+            Instruction::Multiply { lhs, rhs, out } => (Operation::Multiply, lhs, rhs, out),
+            Instruction::Divide { lhs, rhs, out } => (Operation::Divide, lhs, rhs, out),
+            Instruction::Modulo { lhs, rhs, out } => (Operation::Modulo, lhs, rhs, out),
+            Instruction::BitOr { lhs, rhs, out } => (Operation::BitOr, lhs, rhs, out),
+            Instruction::BitNotOr { lhs, rhs, out } => (Operation::BitNotOr, lhs, rhs, out),
+            Instruction::BitAnd { lhs, rhs, out } => (Operation::BitAnd, lhs, rhs, out),
+            Instruction::ShiftL { lhs, rhs, out } => (Operation::ShiftL, lhs, rhs, out),
+            Instruction::ShiftR { lhs, rhs, out } => (Operation::ShiftR, lhs, rhs, out),
+            _ => return None,
+        };
+        Some((operation, *lhs, *rhs, *out))
+    }
+}
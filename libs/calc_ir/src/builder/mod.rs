@@ -5,27 +5,44 @@ pub mod instructions;
 
 use std::collections::HashMap;
 
-use crate::program::implementations::*;
-use crate::{Number, Register};
+use crate::program::implementations::{BasicProgram, BlockID, FunctionMetadata};
+use crate::program::FunctionAttributes;
+use crate::{Number, Numeric, Register};
 
 /// a "real" instruction type as opposed to the generic type
-type Instruction = crate::Instruction<BlockID, String>;
+type Instruction<NumberT> = crate::Instruction<BlockID, String, NumberT>;
 /// this is just easier to read in my opinion
-type IRBlock = Vec<Instruction>;
+type IRBlock<NumberT> = Vec<Instruction<NumberT>>;
 
-pub struct Block<'a, 'b> {
-    instructions: Vec<Instruction>,
-    function: &'a mut Function<'b>,
+pub struct Block<'a, 'b, NumberT: Numeric = Number> {
+    instructions: Vec<Instruction<NumberT>>,
+    function: &'a mut Function<'b, NumberT>,
+    /// the slot handed out by [`Function::reserve_block`] that this block will fill, if any
+    reserved: Option<BlockID>,
 }
 
-impl<'a, 'b> Block<'a, 'b> {
+impl<'a, 'b, NumberT: Numeric> Block<'a, 'b, NumberT> {
+    /// Register the block with the program, returning its [`BlockID`].
+    ///
+    /// If the block was started with [`Function::build_reserved_block`], the returned id is the one that was reserved.
     #[must_use = "If you're creating a Block, it's useless not to use it and will be destroyed during optimization regardless"]
-    pub fn finalize(self) -> (BlockID, &'a mut Function<'b>) {
-        let id = self.function.program.register_block(self.instructions);
+    pub fn finalize(self) -> (BlockID, &'a mut Function<'b, NumberT>) {
+        let id = match self.reserved {
+            Some(id) => {
+                self.function.program.fill_block(id, self.instructions);
+                id
+            }
+            None => self.function.program.register_block(self.instructions),
+        };
         (id, self.function)
     }
 
-    pub fn add_immediate(&mut self, immediate: Number) -> Register {
+    /// [`Function::reserve_block`], for reserving blocks to jump to while this one is being built
+    pub fn reserve_block(&mut self) -> BlockID {
+        self.function.reserve_block()
+    }
+
+    pub fn add_immediate(&mut self, immediate: NumberT) -> Register {
         let out = self.function.allocate_register();
         self.instructions
             .push(Instruction::LoadImmediate(immediate, out));
@@ -47,27 +64,84 @@ impl<'a, 'b> Block<'a, 'b> {
         ret_reg
     }
 
-    /// Add a jump to another, already existing, Block
+    /// Add a jump to another Block
     ///
-    /// In the future we might provide a way to jump to a block that is created in the future, but right now you need to build functions "from the bottom up".
+    /// `to` can either be an already finalized block, or a block that has been reserved with [`Function::reserve_block`] and will be built later,
+    /// so functions can be built "from the top down".
     pub fn add_cond_jump(&mut self, jump_type: instructions::BlockJump, to: BlockID) {
+        self.add_cond_jump_with_args(jump_type, to, Vec::new());
+    }
+
+    /// Add a jump to another Block, passing `arguments` to the parameters that it declared with [`Self::add_block_params`]
+    pub fn add_cond_jump_with_args(
+        &mut self,
+        jump_type: instructions::BlockJump,
+        to: BlockID,
+        arguments: Vec<Register>,
+    ) {
         match jump_type {
-            instructions::BlockJump::Unconditional => self.instructions.push(Instruction::Jump(to)),
+            instructions::BlockJump::Unconditional => {
+                self.instructions.push(Instruction::Jump { to, arguments });
+            }
             instructions::BlockJump::Equal(lhs, rhs) => {
-                self.instructions.push(Instruction::JEqual { to, lhs, rhs });
+                self.instructions.push(Instruction::JEqual {
+                    lhs,
+                    rhs,
+                    to,
+                    arguments,
+                });
             }
-            instructions::BlockJump::NotEqual(lhs, rhs) => self
-                .instructions
-                .push(Instruction::JNotEqual { to, lhs, rhs }),
-            instructions::BlockJump::NoneZero(r) => self
-                .instructions
-                .push(Instruction::JNonZero { check: r, to }),
-            instructions::BlockJump::Zero(r) => {
-                self.instructions.push(Instruction::JZero { check: r, to });
+            instructions::BlockJump::NotEqual(lhs, rhs) => {
+                self.instructions.push(Instruction::JNotEqual {
+                    lhs,
+                    rhs,
+                    to,
+                    arguments,
+                });
             }
+            instructions::BlockJump::NoneZero(r) => self.instructions.push(Instruction::JNonZero {
+                check: r,
+                to,
+                arguments,
+            }),
+            instructions::BlockJump::Zero(r) => self.instructions.push(Instruction::JZero {
+                check: r,
+                to,
+                arguments,
+            }),
         }
     }
 
+    /// Load the `count` arguments that the function was called with, returning the registers they are loaded into
+    ///
+    /// If this is the entry block of the function, `count` becomes its arity when the program is finalized
+    pub fn add_load_args(&mut self, count: usize) -> Vec<Register> {
+        let args: Vec<Register> = (0..count)
+            .map(|_| self.function.allocate_register())
+            .collect();
+        self.instructions.push(Instruction::LoadArgs(args.clone()));
+        args
+    }
+
+    /// Declare `count` parameters for this block, returning the registers that the arguments of the jump into this block will be loaded into.
+    ///
+    /// These registers are reassigned every time the block is entered, which is how loops carry values between iterations.
+    ///
+    /// # Panics
+    /// If this isn't the first instruction added to the block
+    pub fn add_block_params(&mut self, count: usize) -> Vec<Register> {
+        assert!(
+            self.instructions.is_empty(),
+            "block parameters must be the first instruction of a block"
+        );
+        let params: Vec<Register> = (0..count)
+            .map(|_| self.function.allocate_register())
+            .collect();
+        self.instructions
+            .push(Instruction::LoadBlockArgs(params.clone()));
+        params
+    }
+
     pub fn add_arithmetic(
         &mut self,
         operation: instructions::Arithmetic,
@@ -136,76 +210,188 @@ impl<'a, 'b> Block<'a, 'b> {
     }
 }
 
-pub struct Function<'a> {
-    used_registers: usize,
-    pub(self) program: &'a mut Program,
+pub struct Function<'a, NumberT: Numeric = Number> {
+    pub(self) program: &'a mut Program<NumberT>,
     name: String,
 }
 
-impl<'a> Function<'a> {
+impl<'a, NumberT: Numeric> Function<'a, NumberT> {
     /// create a new function, with its own enclosing "scope"
-    pub(self) fn new(name: String, program: &'a mut Program) -> Self {
-        Self {
-            used_registers: 0,
-            program,
-            name,
-        }
+    pub(self) fn new(name: String, program: &'a mut Program<NumberT>) -> Self {
+        program
+            .building
+            .insert(name.clone(), FunctionInProgress::default());
+        Self { program, name }
+    }
+
+    pub(self) fn building(&mut self) -> &mut FunctionInProgress {
+        self.program
+            .building
+            .get_mut(&self.name)
+            .expect("every function is added to the program when it's made")
     }
 
     pub(self) fn allocate_register(&mut self) -> Register {
-        let ret_reg = Register(self.used_registers);
-        self.used_registers += 1;
+        let building = self.building();
+        let ret_reg = Register(building.used_registers);
+        building.used_registers += 1;
         ret_reg
     }
 
-    pub fn build_block<'b>(&'b mut self) -> Block<'a, 'b> {
+    pub fn build_block<'b>(&'b mut self) -> Block<'a, 'b, NumberT> {
+        Block {
+            instructions: Vec::new(),
+            function: self,
+            reserved: None,
+        }
+    }
+
+    /// Reserve a [`BlockID`] for a block that hasn't been built yet, so that it can be jumped to before it exists.
+    ///
+    /// The block must later be built by this function with [`Self::build_reserved_block`], [`Program::finalize`] panics if it isn't.
+    pub fn reserve_block(&mut self) -> BlockID {
+        self.program.reserve_block(&self.name)
+    }
+
+    /// Start building the block previously reserved as `id` by [`Self::reserve_block`]
+    ///
+    /// # Panics
+    /// If `id` wasn't reserved by this function, or the reserved block has already been built
+    pub fn build_reserved_block<'b>(&'b mut self, id: BlockID) -> Block<'a, 'b, NumberT> {
+        let owner = self
+            .program
+            .reserved
+            .iter()
+            .find(|(reserved, _)| *reserved == id)
+            .map(|(_, owner)| owner);
+        match owner {
+            Some(owner) => assert!(
+                *owner == self.name,
+                "{id:?} was reserved by {owner}, not {}",
+                self.name
+            ),
+            None => panic!("{id:?} was never reserved, or has already been built"),
+        }
         Block {
             instructions: Vec::new(),
             function: self,
+            reserved: Some(id),
         }
     }
 
+    /// Set the attributes that the finished function will have, see [`FunctionAttributes`]
+    pub fn set_attributes(&mut self, attributes: FunctionAttributes) {
+        self.building().attributes = attributes;
+    }
+
     /// Finalize the function with the entry `entry_block`
-    pub fn finalize(&mut self, entry_block: BlockID) -> &mut Program {
-        self.program.register_function(&self.name, entry_block);
+    ///
+    /// The entry block may still be a reserved one that's built later, the arity of the function is the amount of arguments its entry block
+    /// loads once [`Program::finalize`] is called, or 0 if it doesn't load any
+    pub fn finalize(&mut self, entry_block: BlockID) -> &mut Program<NumberT> {
+        self.program
+            .functions
+            .insert(self.name.clone(), entry_block);
         self.program
     }
 }
 
+/// What the builder knows about a function until the program is finalized
+#[derive(Default)]
+struct FunctionInProgress {
+    used_registers: usize,
+    attributes: FunctionAttributes,
+}
+
 /// Builds a whole program, probably the first thing you want to get your hands on to start
 /// building a [`crate::program::implementations::BasicProgram`]
-pub struct Program {
-    blocks: Vec<IRBlock>,
+///
+/// [`Program::new`] builds a program that computes with [`Number`]s, to use any other [`Numeric`] type,
+/// create the builder with [`Default::default`] instead
+pub struct Program<NumberT: Numeric = Number> {
+    blocks: Vec<IRBlock<NumberT>>,
     functions: HashMap<String, BlockID>,
+    /// every function that has been made, finalized or not
+    building: HashMap<String, FunctionInProgress>,
+    /// blocks that have been reserved but not built yet, along with the function that reserved them
+    reserved: Vec<(BlockID, String)>,
+}
+
+impl<NumberT: Numeric> Default for Program<NumberT> {
+    fn default() -> Self {
+        Self {
+            blocks: Vec::new(),
+            functions: HashMap::new(),
+            building: HashMap::new(),
+            reserved: Vec::new(),
+        }
+    }
 }
 
 impl Program {
-    pub(self) fn register_block(&mut self, block: IRBlock) -> BlockID {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<NumberT: Numeric> Program<NumberT> {
+    pub(self) fn register_block(&mut self, block: IRBlock<NumberT>) -> BlockID {
         let block_id = self.blocks.len();
         self.blocks.push(block);
         BlockID(block_id)
     }
 
-    pub(self) fn register_function(&mut self, name: &str, entry: BlockID) {
-        self.functions.insert(name.to_string(), entry);
+    /// push a placeholder block for `function`, which is replaced in [`Self::fill_block`]
+    pub(self) fn reserve_block(&mut self, function: &str) -> BlockID {
+        let id = self.register_block(vec![Instruction::Invalid]);
+        self.reserved.push((id, function.to_string()));
+        id
     }
 
-    pub fn new() -> Self {
-        Self {
-            blocks: Vec::new(),
-            functions: HashMap::new(),
-        }
+    pub(self) fn fill_block(&mut self, id: BlockID, block: IRBlock<NumberT>) {
+        self.reserved.retain(|(reserved, _)| *reserved != id);
+        self.blocks[id.0] = block;
     }
 
+    /// Build the finished program
+    ///
+    /// # Panics
+    /// If a block reserved with [`Function::reserve_block`] was never built
     #[must_use = "You shouldn't call finalize if you're not ready to use the created Program"]
-    pub fn finalize(&mut self) -> BasicProgram {
+    pub fn finalize(&mut self) -> BasicProgram<NumberT> {
+        if let Some((id, function)) = self.reserved.first() {
+            panic!("{id:?} was reserved by {function} but never built");
+        }
+        // every block exists by now, so the entry blocks are the ones that will be run
+        let metadata = self
+            .functions
+            .iter()
+            .map(|(name, entry)| {
+                let arity = self.blocks[entry.0]
+                    .iter()
+                    .find_map(|instruction| match instruction {
+                        Instruction::LoadArgs(registers) => Some(registers.len()),
+                        _ => None,
+                    })
+                    .unwrap_or(0);
+                let building = &self.building[name];
+                let metadata = FunctionMetadata {
+                    arity,
+                    register_count: building.used_registers,
+                    attributes: building.attributes,
+                };
+                (name.clone(), metadata)
+            })
+            .collect();
         BasicProgram {
             function_list: self.functions.clone(),
+            metadata,
             blocks: self.blocks.clone(),
         }
     }
 
-    pub fn make_fn(&mut self, function_name: String) -> Function {
+    pub fn make_fn(&mut self, function_name: String) -> Function<'_, NumberT> {
         Function::new(function_name, self)
     }
 }
@@ -1,13 +1,21 @@
 // clippy configuration
 #![warn(clippy::pedantic, clippy::all, clippy::perf)]
 
+pub mod arithmetic;
 pub mod builder;
+pub mod numeric;
 pub mod program;
-pub use program::Program;
+pub mod zir;
+pub use arithmetic::ArithmeticMode;
+pub use numeric::Numeric;
+pub use program::{FunctionAttributes, Program};
+
+#[cfg(test)]
+mod test;
 
 /// The basic value of any variable in the calculator, a natively sized signed integer
-/// You could easily expand this to be an arbitrarily sized integer, or have the IR be able to represent mulitple types,
-/// but that might add some complexity
+/// Programs can use any other [`Numeric`] type instead, such as an arbitrarily sized integer with the `bignum` feature,
+/// but this is the default, and the fastest
 ///
 /// This isn't a newtype because newtypes are ANNOYING AS HELL when you want them to just have the exact same semantics as the underlying type.
 /// in a real IR you'd want the contents of registers to be newtypes with their own IR specific semantics, but that's unnecassary to implement a simple calculator.
@@ -15,13 +23,16 @@ pub type Number = isize;
 
 /// SAFETY: any register should only be assigned to once
 /// A register represents a "pointer" to a Number, Registers should be assigned to once
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+///
+/// The exception to this are the registers of a [`Instruction::LoadBlockArgs`], which are assigned to every time their block is entered.
+/// This is what allows loops to be expressed without breaking the single assignment rule anywhere else.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
 pub struct Register(pub usize);
 
-/// An enum to represent a single Intermediate representation instruction
+/// An enum to represent a single Intermediate representation instruction, where immediates are `NumberT`s
 #[derive(Debug, PartialEq, Eq, Clone)]
-pub enum Instruction<BlockId: Eq + Clone, FunctionId: Eq + Clone> {
-    LoadImmediate(Number, Register),
+pub enum Instruction<BlockId: Eq + Clone, FunctionId: Eq + Clone, NumberT = Number> {
+    LoadImmediate(NumberT, Register),
 
     // block navigation commands
     // call a block with the provided arguments
@@ -39,27 +50,39 @@ pub enum Instruction<BlockId: Eq + Clone, FunctionId: Eq + Clone> {
     // Errors if the amount of requested and provided arguments are inequal
     LoadArgs(Vec<Register>),
 
+    // Loads the arguments passed by the jump that entered the current block into the provided registers, in the same order that they
+    // were provided. This must be the first instruction of a block, and errors if the amount of requested and provided arguments are inequal
+    LoadBlockArgs(Vec<Register>),
+
+    // every jump transfers control to `to`, passing the values of `arguments` to its LoadBlockArgs, control never comes back to the jumping block
     // jump to a block unconditionally
-    Jump(BlockId),
-    // Commands to jump conditionally
+    Jump {
+        to: BlockId,
+        arguments: Vec<Register>,
+    },
+    // Commands to jump conditionally, if the condition isn't met execution continues with the next instruction
     // I might add more later if necesarry
     JEqual {
         lhs: Register,
         rhs: Register,
         to: BlockId,
+        arguments: Vec<Register>,
     },
     JNotEqual {
         lhs: Register,
         rhs: Register,
         to: BlockId,
+        arguments: Vec<Register>,
     },
     JNonZero {
         check: Register,
         to: BlockId,
+        arguments: Vec<Register>,
     },
     JZero {
         check: Register,
         to: BlockId,
+        arguments: Vec<Register>,
     },
 
     // basic arithmetic
@@ -118,3 +141,238 @@ pub enum Instruction<BlockId: Eq + Clone, FunctionId: Eq + Clone> {
 
     Invalid,
 }
+
+impl<BlockId: Eq + Clone, FunctionId: Eq + Clone, NumberT>
+    Instruction<BlockId, FunctionId, NumberT>
+{
+    /// The block this instruction may jump to, along with the arguments it passes to it, or None if it isn't a jump
+    pub fn jump_target(&self) -> Option<(&BlockId, &[Register])> {
+        match self {
+            Instruction::Jump { to, arguments }
+            | Instruction::JEqual { to, arguments, .. }
+            | Instruction::JNotEqual { to, arguments, .. }
+            | Instruction::JNonZero { to, arguments, .. }
+            | Instruction::JZero { to, arguments, .. } => Some((to, arguments)),
+            _ => None,
+        }
+    }
+
+    /// The registers this instruction reads from, in the order it reads them
+    #[must_use]
+    pub fn reads(&self) -> Vec<Register> {
+        if let Some((_, lhs, rhs, _)) = self.operation() {
+            return vec![lhs, rhs];
+        }
+        match self {
+            Instruction::Ret(register) => vec![*register],
+            Instruction::Call { arguments, .. } | Instruction::Jump { arguments, .. } => {
+                arguments.clone()
+            }
+            Instruction::JEqual {
+                lhs,
+                rhs,
+                arguments,
+                ..
+            }
+            | Instruction::JNotEqual {
+                lhs,
+                rhs,
+                arguments,
+                ..
+            } => [*lhs, *rhs]
+                .into_iter()
+                .chain(arguments.iter().copied())
+                .collect(),
+            Instruction::JNonZero {
+                check, arguments, ..
+            }
+            | Instruction::JZero {
+                check, arguments, ..
+            } => std::iter::once(*check)
+                .chain(arguments.iter().copied())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The registers this instruction writes to, which it does after reading everything in [`Self::reads`]
+    #[must_use]
+    pub fn writes(&self) -> Vec<Register> {
+        if let Some((_, _, _, out)) = self.operation() {
+            return vec![out];
+        }
+        match self {
+            Instruction::LoadImmediate(_, out) | Instruction::Call { out, .. } => vec![*out],
+            Instruction::LoadArgs(registers) | Instruction::LoadBlockArgs(registers) => {
+                registers.clone()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// The name of this kind of instruction, which is the name of its variant
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Instruction::LoadImmediate(..) => "LoadImmediate",
+            Instruction::Call { .. } => "Call",
+            Instruction::Ret(_) => "Ret",
+            Instruction::LoadArgs(_) => "LoadArgs",
+            Instruction::LoadBlockArgs(_) => "LoadBlockArgs",
+            Instruction::Jump { .. } => "Jump",
+            Instruction::JEqual { .. } => "JEqual",
+            Instruction::JNotEqual { .. } => "JNotEqual",
+            Instruction::JNonZero { .. } => "JNonZero",
+            Instruction::JZero { .. } => "JZero",
+            Instruction::Add { .. } => "Add",
+            Instruction::Subtract { .. } => "Subtract",
+            Instruction::Multiply { .. } => "Multiply",
+            Instruction::Divide { .. } => "Divide",
+            Instruction::Modulo { .. } => "Modulo",
+            Instruction::BitOr { .. } => "BitOr",
+            Instruction::BitNotOr { .. } => "BitNotOr",
+            Instruction::BitAnd { .. } => "BitAnd",
+            Instruction::ShiftL { .. } => "ShiftL",
+            Instruction::ShiftR { .. } => "ShiftR",
+            Instruction::Invalid => "Invalid",
+        }
+    }
+
+    /// Whether execution can never continue past this instruction to the next one in the block
+    #[must_use]
+    pub fn is_terminator(&self) -> bool {
+        matches!(
+            self,
+            Instruction::Ret(_) | Instruction::Jump { .. } | Instruction::Invalid
+        )
+    }
+
+    /// Replace every register in this instruction with what `map` gives for it, leaving everything else untouched
+    #[must_use]
+    pub fn map_registers(mut self, mut map: impl FnMut(Register) -> Register) -> Self {
+        let registers: Vec<&mut Register> = match &mut self {
+            Instruction::LoadImmediate(_, register) | Instruction::Ret(register) => {
+                vec![register]
+            }
+            Instruction::Call { arguments, out, .. } => {
+                arguments.iter_mut().chain(std::iter::once(out)).collect()
+            }
+            Instruction::LoadArgs(registers)
+            | Instruction::LoadBlockArgs(registers)
+            | Instruction::Jump {
+                arguments: registers,
+                ..
+            } => registers.iter_mut().collect(),
+            Instruction::JEqual {
+                lhs,
+                rhs,
+                arguments,
+                ..
+            }
+            | Instruction::JNotEqual {
+                lhs,
+                rhs,
+                arguments,
+                ..
+            } => [lhs, rhs].into_iter().chain(arguments.iter_mut()).collect(),
+            Instruction::JNonZero {
+                check, arguments, ..
+            }
+            | Instruction::JZero {
+                check, arguments, ..
+            } => std::iter::once(check).chain(arguments.iter_mut()).collect(),
+            Instruction::Add { lhs, rhs, out }
+            | Instruction::Subtract { lhs, rhs, out }
+            | Instruction::Multiply { lhs, rhs, out }
+            | Instruction::Divide { lhs, rhs, out }
+            | Instruction::Modulo { lhs, rhs, out }
+            | Instruction::BitOr { lhs, rhs, out }
+            | Instruction::BitNotOr { lhs, rhs, out }
+            | Instruction::BitAnd { lhs, rhs, out }
+            | Instruction::ShiftL { lhs, rhs, out }
+            | Instruction::ShiftR { lhs, rhs, out } => vec![lhs, rhs, out],
+            Instruction::Invalid => Vec::new(),
+        };
+        for register in registers {
+            *register = map(*register);
+        }
+        self
+    }
+
+    /// Convert the block ids in this instruction with `map`, leaving everything else untouched
+    pub fn map_blocks<NewBlockId: Eq + Clone>(
+        self,
+        mut map: impl FnMut(BlockId) -> NewBlockId,
+    ) -> Instruction<NewBlockId, FunctionId, NumberT> {
+        match self {
+            Instruction::LoadImmediate(value, out) => Instruction::LoadImmediate(value, out),
+            Instruction::Call {
+                function_id,
+                arguments,
+                out,
+            } => Instruction::Call {
+                function_id,
+                arguments,
+                out,
+            },
+            Instruction::Ret(r) => Instruction::Ret(r),
+            Instruction::LoadArgs(registers) => Instruction::LoadArgs(registers),
+            Instruction::LoadBlockArgs(registers) => Instruction::LoadBlockArgs(registers),
+            Instruction::Jump { to, arguments } => Instruction::Jump {
+                to: map(to),
+                arguments,
+            },
+            Instruction::JEqual {
+                lhs,
+                rhs,
+                to,
+                arguments,
+            } => Instruction::JEqual {
+                lhs,
+                rhs,
+                to: map(to),
+                arguments,
+            },
+            Instruction::JNotEqual {
+                lhs,
+                rhs,
+                to,
+                arguments,
+            } => Instruction::JNotEqual {
+                lhs,
+                rhs,
+                to: map(to),
+                arguments,
+            },
+            Instruction::JNonZero {
+                check,
+                to,
+                arguments,
+            } => Instruction::JNonZero {
+                check,
+                to: map(to),
+                arguments,
+            },
+            Instruction::JZero {
+                check,
+                to,
+                arguments,
+            } => Instruction::JZero {
+                check,
+                to: map(to),
+                arguments,
+            },
+            Instruction::Add { lhs, rhs, out } => Instruction::Add { lhs, rhs, out },
+            Instruction::Subtract { lhs, rhs, out } => Instruction::Subtract { lhs, rhs, out },
+            Instruction::Multiply { lhs, rhs, out } => Instruction::Multiply { lhs, rhs, out },
+            Instruction::Divide { lhs, rhs, out } => Instruction::Divide { lhs, rhs, out },
+            Instruction::Modulo { lhs, rhs, out } => Instruction::Modulo { lhs, rhs, out },
+            Instruction::BitOr { lhs, rhs, out } => Instruction::BitOr { lhs, rhs, out },
+            Instruction::BitNotOr { lhs, rhs, out } => Instruction::BitNotOr { lhs, rhs, out },
+            Instruction::BitAnd { lhs, rhs, out } => Instruction::BitAnd { lhs, rhs, out },
+            Instruction::ShiftL { lhs, rhs, out } => Instruction::ShiftL { lhs, rhs, out },
+            Instruction::ShiftR { lhs, rhs, out } => Instruction::ShiftR { lhs, rhs, out },
+            Instruction::Invalid => Instruction::Invalid,
+        }
+    }
+}
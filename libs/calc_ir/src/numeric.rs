@@ -0,0 +1,187 @@
+//! The types of number that a program can compute with.
+//!
+//! Programs are generic over a [`Numeric`] type, which defaults to the fast [`crate::Number`]. With the `bignum` feature enabled,
+//! [`BigInt`] can be used instead for programs whose results don't fit in a machine integer.
+
+use crate::arithmetic::{ArithmeticError, ArithmeticMode, Operation};
+
+#[cfg(feature = "bignum")]
+pub use num_bigint::BigInt;
+
+/// A number that registers can hold, and that the arithmetic instructions can be evaluated on
+///
+/// Numbers can be parsed from and displayed as decimal strings, which is how frontends get immediates into a program
+pub trait Numeric:
+    Clone + Eq + std::hash::Hash + std::fmt::Debug + std::fmt::Display + std::str::FromStr
+{
+    /// Evaluate `lhs <operation> rhs` according to `mode`, this is what [`Operation::evaluate`] calls
+    ///
+    /// # Errors
+    /// See [`ArithmeticError`] for the ways this can fail
+    fn evaluate(
+        operation: Operation,
+        lhs: &Self,
+        rhs: &Self,
+        mode: ArithmeticMode,
+    ) -> Result<Self, ArithmeticError>;
+
+    /// Whether evaluating `operation` can fail in `mode`, regardless of its operands
+    fn can_fail(operation: Operation, mode: ArithmeticMode) -> bool;
+
+    /// Whether this is zero, which is what [`crate::Instruction::JZero`] and [`crate::Instruction::JNonZero`] check for
+    fn is_zero(&self) -> bool;
+}
+
+impl Numeric for isize {
+    fn evaluate(
+        operation: Operation,
+        lhs: &Self,
+        rhs: &Self,
+        mode: ArithmeticMode,
+    ) -> Result<Self, ArithmeticError> {
+        use ArithmeticMode::{Checked, Saturating, Wrapping};
+
+        let (lhs, rhs) = (*lhs, *rhs);
+        let overflowed = |result: Option<Self>| result.ok_or(ArithmeticError::Overflow);
+
+        match (operation, mode) {
+            (Operation::Add, Wrapping) => Ok(lhs.wrapping_add(rhs)),
+            (Operation::Add, Checked) => overflowed(lhs.checked_add(rhs)),
+            (Operation::Add, Saturating) => Ok(lhs.saturating_add(rhs)),
+
+            (Operation::Subtract, Wrapping) => Ok(lhs.wrapping_sub(rhs)),
+            (Operation::Subtract, Checked) => overflowed(lhs.checked_sub(rhs)),
+            (Operation::Subtract, Saturating) => Ok(lhs.saturating_sub(rhs)),
+
+            (Operation::Multiply, Wrapping) => Ok(lhs.wrapping_mul(rhs)),
+            (Operation::Multiply, Checked) => overflowed(lhs.checked_mul(rhs)),
+            (Operation::Multiply, Saturating) => Ok(lhs.saturating_mul(rhs)),
+
+            (Operation::Divide | Operation::Modulo, _) if rhs == 0 => {
+                Err(ArithmeticError::DivisionByZero)
+            }
+            (Operation::Divide, Wrapping) => Ok(lhs.wrapping_div(rhs)),
+            (Operation::Divide, Checked) => overflowed(lhs.checked_div(rhs)),
+            (Operation::Divide, Saturating) => Ok(lhs.saturating_div(rhs)),
+
+            // the only overflowing remainder is MIN % -1, which is mathematically 0
+            (Operation::Modulo, Wrapping | Saturating) => Ok(lhs.wrapping_rem(rhs)),
+            (Operation::Modulo, Checked) => overflowed(lhs.checked_rem(rhs)),
+
+            (Operation::BitOr, _) => Ok(lhs | rhs),
+            (Operation::BitNotOr, _) => Ok(lhs ^ rhs),
+            (Operation::BitAnd, _) => Ok(lhs & rhs),
+
+            // truncating the shift amount is intended, it wraps the same way the shift does
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            (Operation::ShiftL, Wrapping) => Ok(lhs.wrapping_shl(rhs as u32)),
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            (Operation::ShiftR, Wrapping) => Ok(lhs.wrapping_shr(rhs as u32)),
+
+            (Operation::ShiftL | Operation::ShiftR, Checked) => {
+                let amount = shift_amount(rhs).filter(|amount| *amount < Self::BITS);
+                let amount = amount.ok_or(ArithmeticError::InvalidShift)?;
+                if operation == Operation::ShiftR {
+                    return Ok(lhs >> amount);
+                }
+                let result = lhs << amount;
+                // any bit that was shifted out, including the sign bit changing, is an overflow
+                if result >> amount == lhs {
+                    Ok(result)
+                } else {
+                    Err(ArithmeticError::Overflow)
+                }
+            }
+            (Operation::ShiftL | Operation::ShiftR, Saturating) => {
+                let amount = shift_amount(rhs).ok_or(ArithmeticError::InvalidShift)?;
+                let saturated = if lhs < 0 { Self::MIN } else { Self::MAX };
+                match operation {
+                    Operation::ShiftR => Ok(lhs >> amount.min(Self::BITS - 1)),
+                    _ if lhs == 0 => Ok(0),
+                    _ if amount >= Self::BITS => Ok(saturated),
+                    _ if (lhs << amount) >> amount == lhs => Ok(lhs << amount),
+                    _ => Ok(saturated),
+                }
+            }
+        }
+    }
+
+    fn can_fail(operation: Operation, mode: ArithmeticMode) -> bool {
+        match operation {
+            Operation::Divide | Operation::Modulo => true,
+            Operation::ShiftL | Operation::ShiftR => mode != ArithmeticMode::Wrapping,
+            Operation::Add | Operation::Subtract | Operation::Multiply => {
+                mode == ArithmeticMode::Checked
+            }
+            Operation::BitOr | Operation::BitNotOr | Operation::BitAnd => false,
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == 0
+    }
+}
+
+/// A shift amount as the type the standard library's shifts take, if it isn't negative
+fn shift_amount(rhs: isize) -> Option<u32> {
+    u32::try_from(rhs)
+        .ok()
+        .or(if rhs < 0 { None } else { Some(u32::MAX) })
+}
+
+/// The largest amount that a [`BigInt`] can be shifted left by, so that a single instruction can't allocate an unbounded
+/// amount of memory. The result of a shift this large takes up 8 KiB more than its left hand side.
+#[cfg(feature = "bignum")]
+pub const MAX_BIGINT_SHIFT: u32 = 1 << 16;
+
+/// Arbitrary precision integers never overflow, so every [`ArithmeticMode`] behaves the same way.
+///
+/// Division and modulo by zero are still errors, and so are shift amounts that are negative, don't fit in a `u32`, or shift
+/// left by more than [`MAX_BIGINT_SHIFT`]
+#[cfg(feature = "bignum")]
+impl Numeric for BigInt {
+    fn evaluate(
+        operation: Operation,
+        lhs: &Self,
+        rhs: &Self,
+        _mode: ArithmeticMode,
+    ) -> Result<Self, ArithmeticError> {
+        use num_traits::ToPrimitive;
+
+        match operation {
+            Operation::Add => Ok(lhs + rhs),
+            Operation::Subtract => Ok(lhs - rhs),
+            Operation::Multiply => Ok(lhs * rhs),
+            Operation::Divide | Operation::Modulo if Numeric::is_zero(rhs) => {
+                Err(ArithmeticError::DivisionByZero)
+            }
+            Operation::Divide => Ok(lhs / rhs),
+            Operation::Modulo => Ok(lhs % rhs),
+            Operation::BitOr => Ok(lhs | rhs),
+            Operation::BitNotOr => Ok(lhs ^ rhs),
+            Operation::BitAnd => Ok(lhs & rhs),
+            Operation::ShiftL | Operation::ShiftR => {
+                let amount = rhs.to_u32().ok_or(ArithmeticError::InvalidShift)?;
+                if operation == Operation::ShiftL {
+                    if amount > MAX_BIGINT_SHIFT {
+                        return Err(ArithmeticError::InvalidShift);
+                    }
+                    Ok(lhs << amount)
+                } else {
+                    Ok(lhs >> amount)
+                }
+            }
+        }
+    }
+
+    fn can_fail(operation: Operation, _mode: ArithmeticMode) -> bool {
+        matches!(
+            operation,
+            Operation::Divide | Operation::Modulo | Operation::ShiftL | Operation::ShiftR
+        )
+    }
+
+    fn is_zero(&self) -> bool {
+        num_traits::Zero::is_zero(self)
+    }
+}
@@ -4,8 +4,26 @@
 //! The `calc_interpeter` crate will accept any struct that implements [`Program`] to interpret, as well as the JIT for lowering,
 //! however, the optimizer may require additional trait implementations in order to optimize code
 
+/// Flags that change how a function is allowed to be treated by consumers of a [`Program`].
+///
+/// These are advisory hints, and a consumer that ignores them must still run the program correctly.
+/// The optimizer removes repeated calls to `pure` functions, the other attributes are only carried through for now
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FunctionAttributes {
+    /// the function has no side effects, and always returns the same result for the same arguments
+    pub pure: bool,
+    /// the results of the function should be cached by its arguments, this only makes sense for `pure` functions
+    pub memoize: bool,
+    /// the function should be inlined into its callers wherever possible
+    pub inline: bool,
+    /// the function should never be inlined, this takes precedence over `inline`
+    pub noinline: bool,
+}
+
 /// A trait for any program constructed out of Zach IR
-/// Any structure which implements the two functions correctly will be able to be run through the interpreter and jit
+/// Any structure which implements the required functions correctly will be able to be run through the interpreter and jit,
+/// the provided functions give consumers extra information about the program, which they can use to run it more efficiently
 ///
 /// See module documentation for more information about getting started
 pub trait Program {
@@ -16,7 +34,7 @@ pub trait Program {
     /// have the same content, although they may have different locations in memory.
     type BlockPointer: Eq + Clone;
 
-    /// The type used to identify a function, this can be anything as long as it can be used to look up a BlockPointer to
+    /// The type used to identify a function, this can be anything as long as it can be used to look up a `BlockPointer` to
     /// the correct function. It must also implement Eq for the consumer's benefit.
     ///
     /// This type must follow the a similar rule to [`Self::BlockPointer`], where if two [`Self::FunctionPointer`]s, `f1` and `f2` compare equal, then
@@ -24,6 +42,9 @@ pub trait Program {
     /// [`Self::BlockPointer`]
     type FunctionPointer: Eq + Clone;
 
+    /// The type of number that the program computes with, this is usually [`crate::Number`]
+    type Number: crate::Numeric;
+
     /// Get a function's beginning block from its function pointer.
     /// If a given `function_id` is not registered to a function, then return None
     ///
@@ -37,61 +58,95 @@ pub trait Program {
     fn get_ir(
         &self,
         block_id: &Self::BlockPointer,
-    ) -> &[crate::Instruction<Self::BlockPointer, Self::FunctionPointer>];
+    ) -> &[crate::Instruction<Self::BlockPointer, Self::FunctionPointer, Self::Number>];
 
     /// This should return an iterator of any functions necesarry in the final Progam
     ///
     /// This is mainly implemented for optimization
     ///
-    /// TODO: when GAT stabilized use this for [`get_all_functions`]:
+    /// TODO: when GAT stabilized use this for [`Self::get_all_functions`]:
     ///
+    /// ```ignore
     /// type FunctionListIter<'a>: Iterator<Item = (&'a Self::FunctionPointer, &'a Self::BlockPointer)>;
     /// fn get_all_functions(&'a self) -> Self::FucntionListIter<'a>;
-    fn get_all_functions<'a>(&'a self) -> Vec<(&'a Self::FunctionPointer, &'a Self::BlockPointer)>;
+    /// ```
+    fn get_all_functions(&self) -> Vec<(&Self::FunctionPointer, &Self::BlockPointer)>;
+
+    /// The amount of registers that a function uses, such that every [`crate::Register`] in the function is lower than the returned count,
+    /// or None if it isn't known.
+    ///
+    /// The interpreter uses this to size a function's register file up front, and treats any register outside of it as an error
+    fn get_register_count(&self, _function_id: &Self::FunctionPointer) -> Option<usize> {
+        None
+    }
+
+    /// The amount of arguments that a function must be called with, or None if it isn't known.
+    ///
+    /// The interpreter checks every call against this before running the function
+    fn get_arity(&self, _function_id: &Self::FunctionPointer) -> Option<usize> {
+        None
+    }
+
+    /// The [`FunctionAttributes`] of a function, which defaults to having none set
+    fn get_attributes(&self, _function_id: &Self::FunctionPointer) -> FunctionAttributes {
+        FunctionAttributes::default()
+    }
 }
 
 /// A module with structures necesarry for the implementation of [`crate::builder::Program`]
 pub mod implementations {
-    use crate::Program;
+    use super::FunctionAttributes;
+    use crate::{Number, Numeric, Program};
     use std::collections::HashMap;
-    /// A non-generic, solid implementation of [`crate::Instruction`], used to build this program
-    type Instruction = crate::Instruction<BlockID, String>;
+    /// A solid implementation of [`crate::Instruction`], used to build this program
+    type Instruction<NumberT> = crate::Instruction<BlockID, String, NumberT>;
 
     /// A newtype wrapper around usize to force consumers to call [`Program::get_function_entry()`] first, ensuring that we only ever lookup an
     /// existing Block
     ///
     /// this is only used in the implementation of [`BasicProgram`]
-    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
     pub struct BlockID(pub(crate) usize);
 
+    /// Everything [`BasicProgram`] knows about a function other than where it starts
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub(crate) struct FunctionMetadata {
+        pub(crate) arity: usize,
+        pub(crate) register_count: usize,
+        pub(crate) attributes: FunctionAttributes,
+    }
+
     /// A struct that implements [`Program`] in a simple way, the best way to acquire one of these is
     /// through a [`crate::builder::Program`]
     #[allow(clippy::module_name_repetitions)]
-    pub struct BasicProgram {
+    pub struct BasicProgram<NumberT: Numeric = Number> {
         pub(crate) function_list: HashMap<String, BlockID>,
+        /// the metadata of each function in `function_list`
+        pub(crate) metadata: HashMap<String, FunctionMetadata>,
         // you could simplify this by having a Vec<Instruction> where a BlocKPointer is an offset to the first Instruction of the block
         // but this would make optimization far more complex.. it might be a good idea to have a pass at the end of the optimization
         // that "flattens" it from Vec<Vec<Instruction>> to Vec<Instruction> after the transformations have been made
-        pub(crate) blocks: Vec<Vec<Instruction>>,
+        pub(crate) blocks: Vec<Vec<Instruction<NumberT>>>,
     }
 
-    impl Program for BasicProgram {
+    impl<NumberT: Numeric> Program for BasicProgram<NumberT> {
         type FunctionPointer = String;
         type BlockPointer = BlockID;
+        type Number = NumberT;
 
         fn get_function_entry(
             &self,
             function_id: &Self::FunctionPointer,
         ) -> Option<Self::BlockPointer> {
             // Self::BlockPointer is trivially copiable
-            self.function_list.get(function_id).map(Clone::clone)
+            self.function_list.get(function_id).copied()
         }
 
         /// Safety: `block_id` is only attainable through this module, and every path in this module must ensure that all
         /// publicly available [`BlockID`]s are valid
         ///
         /// This should be guaranteed if you obtain your [`BasicProgram`] through a [`crate::builder::Program`]
-        fn get_ir(&self, block_id: &Self::BlockPointer) -> &[Instruction] {
+        fn get_ir(&self, block_id: &Self::BlockPointer) -> &[Instruction<NumberT>] {
             // SAFETY: block_id is only attainable through this module, and every path in this module must ensure that all
             // publicly available BlockIDs are valid
             unsafe { self.blocks.get_unchecked(block_id.0) }
@@ -100,5 +155,24 @@ pub mod implementations {
         fn get_all_functions(&self) -> Vec<(&Self::FunctionPointer, &Self::BlockPointer)> {
             self.function_list.iter().collect()
         }
+
+        fn get_register_count(&self, function_id: &Self::FunctionPointer) -> Option<usize> {
+            self.metadata
+                .get(function_id)
+                .map(|metadata| metadata.register_count)
+        }
+
+        fn get_arity(&self, function_id: &Self::FunctionPointer) -> Option<usize> {
+            self.metadata
+                .get(function_id)
+                .map(|metadata| metadata.arity)
+        }
+
+        fn get_attributes(&self, function_id: &Self::FunctionPointer) -> FunctionAttributes {
+            self.metadata
+                .get(function_id)
+                .map(|metadata| metadata.attributes)
+                .unwrap_or_default()
+        }
     }
 }
@@ -0,0 +1,237 @@
+use std::path::Path;
+
+use crate::builder::{
+    instructions::{Arithmetic, BitWise, BlockJump},
+    Program,
+};
+use crate::program::implementations::BasicProgram;
+use crate::zir::{Layout, Module, ZirError, MAX_REGISTERS, MAX_UNUSED_REGISTERS};
+use crate::{FunctionAttributes, Instruction, Program as _};
+
+/// the contents of the file `name` in the regressions directory, which holds files that once broke reading
+fn regression(name: &str) -> Vec<u8> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("regressions")
+        .join(name);
+    std::fs::read(path).unwrap()
+}
+
+/// a program with calls, loops, attributes and extreme immediates, to write as a `.zir` file
+fn build_zir_program() -> BasicProgram {
+    let mut builder = Program::new();
+    let mut subtract = builder.make_fn("subtract".to_string());
+    let mut entry_block = subtract.build_block();
+    let args = entry_block.add_load_args(2);
+    let difference = entry_block.add_arithmetic(Arithmetic::Subtract, args[0], args[1]);
+    entry_block.add_ret(difference);
+    let (entry_block_id, subtract) = entry_block.finalize();
+    let builder = subtract.finalize(entry_block_id);
+
+    // sums 1 through its argument
+    let mut sum = builder.make_fn("sum".to_string());
+    sum.set_attributes(FunctionAttributes {
+        pure: true,
+        noinline: true,
+        ..FunctionAttributes::default()
+    });
+    let loop_block_id = sum.reserve_block();
+    let mut entry_block = sum.build_block();
+    let n = entry_block.add_load_args(1)[0];
+    let zero = entry_block.add_immediate(0);
+    entry_block.add_cond_jump_with_args(BlockJump::Unconditional, loop_block_id, vec![n, zero]);
+    let (entry_block_id, sum) = entry_block.finalize();
+    let mut loop_block = sum.build_reserved_block(loop_block_id);
+    let params = loop_block.add_block_params(2);
+    let (counter, total) = (params[0], params[1]);
+    let one = loop_block.add_immediate(1);
+    let next_total = loop_block.add_arithmetic(Arithmetic::Add, total, counter);
+    let next_counter = loop_block.add_fn_call("subtract".to_string(), vec![counter, one]);
+    loop_block.add_cond_jump_with_args(
+        BlockJump::NoneZero(next_counter),
+        loop_block_id,
+        vec![next_counter, next_total],
+    );
+    loop_block.add_ret(next_total);
+    let (_, sum) = loop_block.finalize();
+    let builder = sum.finalize(entry_block_id);
+
+    let mut main_function = builder.make_fn("main".to_string());
+    let mut entry_block = main_function.build_block();
+    let min = entry_block.add_immediate(isize::MIN);
+    let ten = entry_block.add_immediate(10);
+    let fifty_five = entry_block.add_fn_call("sum".to_string(), vec![ten]);
+    let result = entry_block.add_bitwise(BitWise::NotOr, min, fifty_five);
+    entry_block.add_ret(result);
+    let (entry_block_id, main_function) = entry_block.finalize();
+    main_function.finalize(entry_block_id).finalize()
+}
+
+/// a program read back from its `.zir` file is the same program
+#[test]
+fn zir_round_trip() {
+    let program = build_zir_program();
+    let bytes = program.to_zir();
+    let read = BasicProgram::from_zir(&bytes).unwrap();
+
+    assert_eq!(read.to_module(), program.to_module());
+    assert_eq!(read.to_zir(), bytes);
+    for function in ["main", "sum", "subtract"] {
+        let function = function.to_string();
+        assert_eq!(
+            read.get_attributes(&function),
+            program.get_attributes(&function)
+        );
+        assert_eq!(read.get_arity(&function), program.get_arity(&function));
+    }
+    assert_eq!(read.get_register_count(&"sum".to_string()), Some(7));
+}
+
+/// malformed files are errors rather than panics, and nothing they refer to is trusted
+#[test]
+fn zir_rejects_malformed() {
+    let bytes = build_zir_program().to_zir();
+    let error = |bytes: &[u8]| BasicProgram::<isize>::from_zir(bytes).err();
+
+    for length in 0..bytes.len() {
+        assert!(error(&bytes[..length]).is_some());
+    }
+    // flipping bits may still give a valid program, but never a panic
+    for index in 0..bytes.len() {
+        for flip in [0x01, 0x40, 0x80, 0xff] {
+            let mut corrupted = bytes.clone();
+            corrupted[index] ^= flip;
+            let _ = error(&corrupted);
+        }
+    }
+
+    assert_eq!(error(b"ELF"), Some(ZirError::NotZir));
+    let mut newer = bytes.clone();
+    newer[4] = 2;
+    assert_eq!(error(&newer), Some(ZirError::UnsupportedVersion(2)));
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert_eq!(
+        error(&trailing),
+        Some(ZirError::TrailingBytes {
+            offset: bytes.len()
+        })
+    );
+
+    let mut module: Module = BasicProgram::from_zir(&bytes).unwrap().to_module();
+    let missing = module.blocks.len() + 1;
+    module.blocks.push(vec![Instruction::Jump {
+        to: missing,
+        arguments: Vec::new(),
+    }]);
+    assert_eq!(
+        error(&module.to_bytes()),
+        Some(ZirError::BlockOutOfRange { block: missing })
+    );
+    module.blocks.pop();
+    module.layout = Layout::Flat;
+    assert_eq!(
+        BasicProgram::from_module(module).err(),
+        Some(ZirError::WrongLayout {
+            expected: Layout::Blocks,
+            found: Layout::Flat
+        })
+    );
+}
+
+/// a register count has to cover every register a function uses, without being so large that every call allocates a huge frame
+#[test]
+fn zir_register_counts() {
+    let module = build_zir_program().to_module();
+    let with_sum_count = |register_count| {
+        let mut module = module.clone();
+        let sum = module
+            .functions
+            .iter_mut()
+            .find(|function| function.name == "sum")
+            .unwrap();
+        sum.register_count = Some(register_count);
+        BasicProgram::from_module(module)
+    };
+
+    assert!(with_sum_count(7).is_ok());
+    assert!(with_sum_count(7 + MAX_UNUSED_REGISTERS).is_ok());
+    for register_count in [6, 0, 8 + MAX_UNUSED_REGISTERS, usize::MAX] {
+        assert_eq!(
+            with_sum_count(register_count).err(),
+            Some(ZirError::RegisterCount {
+                function: "sum".to_string(),
+                register_count,
+                used: 7,
+            })
+        );
+    }
+}
+
+/// no register or register count in a file can be so large that a frame holding it can't be allocated
+#[test]
+fn zir_register_limit() {
+    // `main` loads an immediate into register 2^40 and returns it, without a register count
+    let huge = regression("huge_register.zir");
+    assert_eq!(huge.len(), 35);
+    assert_eq!(
+        BasicProgram::<isize>::from_zir(&huge).err(),
+        Some(ZirError::TooManyRegisters {
+            offset: 22,
+            registers: (1 << 40) + 1
+        })
+    );
+
+    let single_register = |register| Module::<isize> {
+        layout: Layout::Blocks,
+        functions: Vec::new(),
+        blocks: vec![vec![Instruction::Ret(crate::Register(register))]],
+    };
+    assert!(
+        BasicProgram::<isize>::from_zir(&single_register(MAX_REGISTERS - 1).to_bytes()).is_ok()
+    );
+    assert!(matches!(
+        BasicProgram::<isize>::from_zir(&single_register(MAX_REGISTERS).to_bytes()),
+        Err(ZirError::TooManyRegisters { registers, .. }) if registers == MAX_REGISTERS + 1
+    ));
+    assert!(matches!(
+        BasicProgram::<isize>::from_zir(&single_register(usize::MAX).to_bytes()),
+        Err(ZirError::TooManyRegisters {
+            registers: usize::MAX,
+            ..
+        })
+    ));
+
+    // a register count is limited the same way, even if the registers it's for would be allowed
+    let mut module = build_zir_program().to_module();
+    module.functions[0].register_count = Some(MAX_REGISTERS + 1);
+    assert!(matches!(
+        BasicProgram::<isize>::from_zir(&module.to_bytes()),
+        Err(ZirError::TooManyRegisters { registers, .. }) if registers == MAX_REGISTERS + 1
+    ));
+}
+
+/// immediates are stored in decimal, so a file can be read with any number type that fits them
+#[cfg(feature = "bignum")]
+#[test]
+fn zir_numbers() {
+    use crate::numeric::BigInt;
+
+    let mut builder = Program::<BigInt>::default();
+    let mut main_function = builder.make_fn("main".to_string());
+    let mut entry_block = main_function.build_block();
+    let huge = entry_block.add_immediate("-265252859812191058636308480000000".parse().unwrap());
+    entry_block.add_ret(huge);
+    let (entry_block_id, main_function) = entry_block.finalize();
+    let program = main_function.finalize(entry_block_id).finalize();
+
+    let bytes = program.to_zir();
+    let read = BasicProgram::<BigInt>::from_zir(&bytes).unwrap();
+    assert_eq!(read.to_module(), program.to_module());
+    assert!(matches!(
+        BasicProgram::<isize>::from_zir(&bytes),
+        Err(ZirError::InvalidImmediate { .. })
+    ));
+
+    let small = build_zir_program().to_zir();
+    assert!(BasicProgram::<BigInt>::from_zir(&small).is_ok());
+}
@@ -0,0 +1,804 @@
+//! A versioned binary format for programs, which is what `.zir` files contain.
+//!
+//! This lets a program be built, optimized and run by separate processes, and lets compiled programs be cached.
+//! A file is read into a [`Module`], which any [`crate::Program`] can be built from, such as with [`BasicProgram::from_zir`].
+//! Reading never panics, every malformed file is a [`ZirError`], every block or string that a file refers to is checked to exist,
+//! and no function can use more than [`MAX_REGISTERS`] registers.
+//!
+//! Every integer below is an unsigned LEB128 number unless it says otherwise. A file is laid out as:
+//!
+//! ```text
+//! magic      the bytes "ZIR\0"
+//! version    a little endian u16, which is VERSION
+//! layout     a byte, 0 for Layout::Blocks and 1 for Layout::Flat
+//! strings    a count, then every string as its length and UTF-8 bytes
+//! functions  a count, then every function as the index of its name in the strings, its entry, a byte of flags,
+//!            then its arity and register count if the flags say they're known
+//! blocks     a count, then every block as its length and its instructions
+//! ```
+//!
+//! An instruction is a byte for the variant it is, in the order they're declared in [`Instruction`], followed by its fields in order.
+//! Registers and blocks are numbers, lists of registers are a count and the registers, and functions are the index of their name in the strings.
+//! Immediates are written in decimal, as a length and the ASCII digits, so that a file can be read with any [`Numeric`] type that fits its numbers.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::program::implementations::{BasicProgram, BlockID, FunctionMetadata};
+use crate::{FunctionAttributes, Instruction, Number, Numeric, Program, Register};
+
+/// The bytes every `.zir` file starts with
+pub const MAGIC: [u8; 4] = *b"ZIR\0";
+/// The version of the format that this module reads and writes, which is bumped whenever the format changes
+pub const VERSION: u16 = 1;
+
+/// How many registers a function in a file can use, every register has to be lower than this, and every register count at most this,
+/// since a frame holds every register up to the highest one it uses
+pub const MAX_REGISTERS: usize = 1 << 16;
+
+/// How many more registers a function's register count may have than the highest register that its blocks use,
+/// the interpreter allocates all of them for every call, so a file can't make calls arbitrarily expensive
+pub const MAX_UNUSED_REGISTERS: usize = 1024;
+
+/// How the blocks of a [`Module`] are pointed to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// a block pointer is the index of a block, like a [`BlockID`]
+    Blocks,
+    /// there's a single block, and a block pointer is the index of the instruction in it that execution starts at
+    Flat,
+}
+
+/// A function in a [`Module`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionEntry {
+    pub name: String,
+    /// the block pointer to the start of the function, see [`Layout`]
+    pub entry: usize,
+    pub arity: Option<usize>,
+    pub register_count: Option<usize>,
+    pub attributes: FunctionAttributes,
+}
+
+/// The contents of a `.zir` file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Module<NumberT = Number> {
+    pub layout: Layout,
+    pub functions: Vec<FunctionEntry>,
+    /// the instructions of the program, where jumps point to blocks according to `layout`
+    pub blocks: Vec<Vec<Instruction<usize, String, NumberT>>>,
+}
+
+/// The ways that reading a `.zir` file can fail, offsets are in bytes from the start of the file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ZirError {
+    /// the file doesn't start with [`MAGIC`]
+    NotZir,
+    /// the file was written with a version of the format that this one can't read
+    UnsupportedVersion(u16),
+    /// the file ended in the middle of something
+    UnexpectedEnd,
+    /// there's more after the last block
+    TrailingBytes {
+        offset: usize,
+    },
+    /// a number didn't fit in a `usize`
+    Overflow {
+        offset: usize,
+    },
+    UnknownLayout {
+        offset: usize,
+        layout: u8,
+    },
+    UnknownOpcode {
+        offset: usize,
+        opcode: u8,
+    },
+    UnknownFlags {
+        offset: usize,
+        flags: u8,
+    },
+    /// a string wasn't UTF-8
+    InvalidString {
+        offset: usize,
+    },
+    /// an immediate couldn't be parsed as the program's number type
+    InvalidImmediate {
+        offset: usize,
+    },
+    /// an index into the strings that there isn't a string for
+    StringOutOfRange {
+        offset: usize,
+        index: usize,
+    },
+    /// a function entry or jump to a block that doesn't exist
+    BlockOutOfRange {
+        block: usize,
+    },
+    /// a flat module that doesn't have exactly one block
+    NotFlat {
+        blocks: usize,
+    },
+    DuplicateFunction(String),
+    /// a register, or a function's register count, needs `registers` registers, which is more than [`MAX_REGISTERS`]
+    TooManyRegisters {
+        offset: usize,
+        registers: usize,
+    },
+    /// a function's register count is lower than a register that its blocks use, or more than [`MAX_UNUSED_REGISTERS`] above it,
+    /// where `used` is one past the highest register
+    RegisterCount {
+        function: String,
+        register_count: usize,
+        used: usize,
+    },
+    /// the file has a different [`Layout`] than the program it's being read into
+    WrongLayout {
+        expected: Layout,
+        found: Layout,
+    },
+}
+
+impl std::fmt::Display for ZirError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ZirError::NotZir => write!(f, "not a zir file"),
+            ZirError::UnsupportedVersion(version) => {
+                write!(f, "unsupported zir version {version}, expected {VERSION}")
+            }
+            ZirError::UnexpectedEnd => write!(f, "unexpected end of file"),
+            ZirError::TrailingBytes { offset } => write!(f, "trailing bytes at {offset}"),
+            ZirError::Overflow { offset } => write!(f, "number too large at {offset}"),
+            ZirError::UnknownLayout { offset, layout } => {
+                write!(f, "unknown layout {layout} at {offset}")
+            }
+            ZirError::UnknownOpcode { offset, opcode } => {
+                write!(f, "unknown opcode {opcode} at {offset}")
+            }
+            ZirError::UnknownFlags { offset, flags } => {
+                write!(f, "unknown function flags {flags:#04x} at {offset}")
+            }
+            ZirError::InvalidString { offset } => write!(f, "invalid UTF-8 at {offset}"),
+            ZirError::InvalidImmediate { offset } => write!(f, "invalid immediate at {offset}"),
+            ZirError::StringOutOfRange { offset, index } => {
+                write!(f, "string {index} out of range at {offset}")
+            }
+            ZirError::BlockOutOfRange { block } => write!(f, "block {block} out of range"),
+            ZirError::NotFlat { blocks } => {
+                write!(f, "flat program has {blocks} blocks instead of one")
+            }
+            ZirError::DuplicateFunction(name) => write!(f, "function {name} defined twice"),
+            ZirError::TooManyRegisters { offset, registers } => write!(
+                f,
+                "{registers} registers needed at {offset}, at most {MAX_REGISTERS} are allowed"
+            ),
+            ZirError::RegisterCount {
+                function,
+                register_count,
+                used,
+            } => write!(
+                f,
+                "function {function} has a register count of {register_count} but uses {used} registers"
+            ),
+            ZirError::WrongLayout { expected, found } => {
+                write!(f, "expected a {expected:?} layout, found {found:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ZirError {}
+
+// the flags of a function
+const HAS_ARITY: u8 = 1;
+const HAS_REGISTER_COUNT: u8 = 1 << 1;
+const PURE: u8 = 1 << 2;
+const MEMOIZE: u8 = 1 << 3;
+const INLINE: u8 = 1 << 4;
+const NOINLINE: u8 = 1 << 5;
+
+/// Builds a file up, keeping track of the strings it refers to
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+    strings: Vec<String>,
+    /// the index of every string in `strings`
+    indices: HashMap<String, usize>,
+}
+
+impl Writer {
+    fn number(&mut self, mut value: usize) {
+        loop {
+            // the low 7 bits always fit in a byte
+            #[allow(clippy::cast_possible_truncation)]
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.bytes.push(byte);
+                return;
+            }
+            self.bytes.push(byte | 0x80);
+        }
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.number(bytes.len());
+        self.bytes.extend(bytes);
+    }
+
+    /// Write the index of `string`, which is added to the strings if it isn't in them yet
+    fn string(&mut self, string: &str) {
+        let next = self.strings.len();
+        let index = *self.indices.entry(string.to_string()).or_insert(next);
+        if index == next {
+            self.strings.push(string.to_string());
+        }
+        self.number(index);
+    }
+
+    fn registers(&mut self, registers: &[Register]) {
+        self.number(registers.len());
+        for register in registers {
+            self.number(register.0);
+        }
+    }
+}
+
+/// Reads a file, keeping track of where it is for errors
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, ZirError> {
+        let byte = *self.bytes.get(self.offset).ok_or(ZirError::UnexpectedEnd)?;
+        self.offset += 1;
+        Ok(byte)
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], ZirError> {
+        let end = self
+            .offset
+            .checked_add(length)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(ZirError::UnexpectedEnd)?;
+        let taken = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(taken)
+    }
+
+    fn number(&mut self) -> Result<usize, ZirError> {
+        let offset = self.offset;
+        let mut value: usize = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            let bits = usize::from(byte & 0x7f);
+            if shift >= usize::BITS || (bits << shift) >> shift != bits {
+                return Err(ZirError::Overflow { offset });
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], ZirError> {
+        let length = self.number()?;
+        self.take(length)
+    }
+
+    fn string(&mut self, strings: &[String]) -> Result<String, ZirError> {
+        let offset = self.offset;
+        let index = self.number()?;
+        strings
+            .get(index)
+            .cloned()
+            .ok_or(ZirError::StringOutOfRange { offset, index })
+    }
+
+    fn register(&mut self) -> Result<Register, ZirError> {
+        let offset = self.offset;
+        let register = self.number()?;
+        let registers = register.saturating_add(1);
+        if registers > MAX_REGISTERS {
+            return Err(ZirError::TooManyRegisters { offset, registers });
+        }
+        Ok(Register(register))
+    }
+
+    /// A function's register count, which has to be at most [`MAX_REGISTERS`]
+    fn register_count(&mut self) -> Result<usize, ZirError> {
+        let offset = self.offset;
+        let registers = self.number()?;
+        if registers > MAX_REGISTERS {
+            return Err(ZirError::TooManyRegisters { offset, registers });
+        }
+        Ok(registers)
+    }
+
+    fn registers(&mut self) -> Result<Vec<Register>, ZirError> {
+        let count = self.number()?;
+        // every register takes at least a byte, so this can't be made to allocate more than the file does
+        (0..count).map(|_| self.register()).collect()
+    }
+}
+
+impl<NumberT: Numeric> Module<NumberT> {
+    /// Write this module as a `.zir` file
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // the strings go before everything that refers to them, so they're collected while writing the rest
+        let mut body = Writer::default();
+        body.number(self.functions.len());
+        for function in &self.functions {
+            body.string(&function.name);
+            body.number(function.entry);
+            let attributes = function.attributes;
+            let flags = [
+                (function.arity.is_some(), HAS_ARITY),
+                (function.register_count.is_some(), HAS_REGISTER_COUNT),
+                (attributes.pure, PURE),
+                (attributes.memoize, MEMOIZE),
+                (attributes.inline, INLINE),
+                (attributes.noinline, NOINLINE),
+            ]
+            .into_iter()
+            .filter(|(set, _)| *set)
+            .fold(0, |flags, (_, flag)| flags | flag);
+            body.bytes.push(flags);
+            for known in [function.arity, function.register_count]
+                .into_iter()
+                .flatten()
+            {
+                body.number(known);
+            }
+        }
+        body.number(self.blocks.len());
+        for block in &self.blocks {
+            body.number(block.len());
+            for instruction in block {
+                write_instruction(&mut body, instruction);
+            }
+        }
+
+        let mut file = Writer::default();
+        file.bytes.extend(MAGIC);
+        file.bytes.extend(VERSION.to_le_bytes());
+        file.bytes.push(match self.layout {
+            Layout::Blocks => 0,
+            Layout::Flat => 1,
+        });
+        file.number(body.strings.len());
+        for string in &body.strings {
+            file.bytes(string.as_bytes());
+        }
+        file.bytes.extend(body.bytes);
+        file.bytes
+    }
+
+    /// Read a `.zir` file, checking that everything in it is valid
+    ///
+    /// # Errors
+    /// If the file is malformed in any way, see [`ZirError`]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ZirError> {
+        let mut reader = Reader { bytes, offset: 0 };
+        if reader.take(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err(ZirError::NotZir);
+        }
+        let version = reader.take(2)?;
+        let version = u16::from_le_bytes([version[0], version[1]]);
+        if version != VERSION {
+            return Err(ZirError::UnsupportedVersion(version));
+        }
+        let offset = reader.offset;
+        let layout = match reader.byte()? {
+            0 => Layout::Blocks,
+            1 => Layout::Flat,
+            layout => return Err(ZirError::UnknownLayout { offset, layout }),
+        };
+
+        let count = reader.number()?;
+        let strings = (0..count)
+            .map(|_| {
+                let offset = reader.offset;
+                let string = reader.bytes()?;
+                String::from_utf8(string.to_vec()).map_err(|_| ZirError::InvalidString { offset })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // entries can only be checked once the blocks are read
+        let count = reader.number()?;
+        let mut functions = Vec::new();
+        let mut names = HashSet::new();
+        for _ in 0..count {
+            let name = reader.string(&strings)?;
+            if !names.insert(name.clone()) {
+                return Err(ZirError::DuplicateFunction(name));
+            }
+            let entry = reader.number()?;
+            let offset = reader.offset;
+            let flags = reader.byte()?;
+            if flags & !(HAS_ARITY | HAS_REGISTER_COUNT | PURE | MEMOIZE | INLINE | NOINLINE) != 0 {
+                return Err(ZirError::UnknownFlags { offset, flags });
+            }
+            let arity = (flags & HAS_ARITY != 0)
+                .then(|| reader.number())
+                .transpose()?;
+            let register_count = (flags & HAS_REGISTER_COUNT != 0)
+                .then(|| reader.register_count())
+                .transpose()?;
+            functions.push(FunctionEntry {
+                name,
+                entry,
+                arity,
+                register_count,
+                attributes: FunctionAttributes {
+                    pure: flags & PURE != 0,
+                    memoize: flags & MEMOIZE != 0,
+                    inline: flags & INLINE != 0,
+                    noinline: flags & NOINLINE != 0,
+                },
+            });
+        }
+
+        let count = reader.number()?;
+        let mut blocks = Vec::new();
+        for _ in 0..count {
+            let length = reader.number()?;
+            let block = (0..length)
+                .map(|_| read_instruction(&mut reader, &strings))
+                .collect::<Result<Vec<_>, _>>()?;
+            blocks.push(block);
+        }
+        if reader.offset != bytes.len() {
+            return Err(ZirError::TrailingBytes {
+                offset: reader.offset,
+            });
+        }
+
+        let module = Self {
+            layout,
+            functions,
+            blocks,
+        };
+        module.check_blocks()?;
+        Ok(module)
+    }
+
+    /// Check that every function entry and jump points to a block that exists according to the module's [`Layout`]
+    ///
+    /// # Errors
+    /// [`ZirError::BlockOutOfRange`] for the first one that doesn't, or [`ZirError::NotFlat`] if a flat module doesn't have exactly one block
+    pub fn check_blocks(&self) -> Result<(), ZirError> {
+        let pointers = match self.layout {
+            Layout::Blocks => self.blocks.len(),
+            Layout::Flat if self.blocks.len() == 1 => self.blocks[0].len(),
+            Layout::Flat => {
+                return Err(ZirError::NotFlat {
+                    blocks: self.blocks.len(),
+                })
+            }
+        };
+        let entries = self.functions.iter().map(|function| function.entry);
+        let jumps = self
+            .blocks
+            .iter()
+            .flatten()
+            .filter_map(Instruction::jump_target)
+            .map(|(to, _)| *to);
+        match entries.chain(jumps).find(|block| *block >= pointers) {
+            Some(block) => Err(ZirError::BlockOutOfRange { block }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// One past the highest register used by the blocks reachable from `entry`, which have to exist
+fn used_registers<NumberT>(
+    blocks: &[Vec<Instruction<usize, String, NumberT>>],
+    entry: usize,
+) -> usize {
+    let mut seen = HashSet::from([entry]);
+    let mut stack = vec![entry];
+    let mut used = 0;
+    while let Some(block) = stack.pop() {
+        for instruction in &blocks[block] {
+            for Register(register) in instruction.reads().into_iter().chain(instruction.writes()) {
+                used = used.max(register + 1);
+            }
+            if let Some((to, _)) = instruction.jump_target() {
+                if seen.insert(*to) {
+                    stack.push(*to);
+                }
+            }
+        }
+    }
+    used
+}
+
+fn write_instruction<NumberT: Numeric>(
+    writer: &mut Writer,
+    instruction: &Instruction<usize, String, NumberT>,
+) {
+    let opcode = OPCODES
+        .iter()
+        .position(|name| *name == instruction.name())
+        .expect("every instruction has an opcode");
+    writer.number(opcode);
+    if let Some((_, lhs, rhs, out)) = instruction.operation() {
+        for register in [lhs, rhs, out] {
+            writer.number(register.0);
+        }
+        return;
+    }
+    match instruction {
+        Instruction::LoadImmediate(value, out) => {
+            writer.bytes(value.to_string().as_bytes());
+            writer.number(out.0);
+        }
+        Instruction::Call {
+            function_id,
+            arguments,
+            out,
+        } => {
+            writer.string(function_id);
+            writer.registers(arguments);
+            writer.number(out.0);
+        }
+        Instruction::Ret(register) => writer.number(register.0),
+        Instruction::LoadArgs(registers) | Instruction::LoadBlockArgs(registers) => {
+            writer.registers(registers);
+        }
+        Instruction::Jump { to, arguments } => {
+            writer.number(*to);
+            writer.registers(arguments);
+        }
+        Instruction::JEqual {
+            lhs,
+            rhs,
+            to,
+            arguments,
+        }
+        | Instruction::JNotEqual {
+            lhs,
+            rhs,
+            to,
+            arguments,
+        } => {
+            writer.number(lhs.0);
+            writer.number(rhs.0);
+            writer.number(*to);
+            writer.registers(arguments);
+        }
+        Instruction::JNonZero {
+            check,
+            to,
+            arguments,
+        }
+        | Instruction::JZero {
+            check,
+            to,
+            arguments,
+        } => {
+            writer.number(check.0);
+            writer.number(*to);
+            writer.registers(arguments);
+        }
+        _ => {}
+    }
+}
+
+/// The names of the instructions, where the index of each one is its opcode
+const OPCODES: [&str; 21] = [
+    "LoadImmediate",
+    "Call",
+    "Ret",
+    "LoadArgs",
+    "LoadBlockArgs",
+    "Jump",
+    "JEqual",
+    "JNotEqual",
+    "JNonZero",
+    "JZero",
+    "Add",
+    "Subtract",
+    "Multiply",
+    "Divide",
+    "Modulo",
+    "BitOr",
+    "BitNotOr",
+    "BitAnd",
+    "ShiftL",
+    "ShiftR",
+    "Invalid",
+];
+
+/// Read an instruction, the blocks it jumps to are checked once every block has been read
+fn read_instruction<NumberT: Numeric>(
+    reader: &mut Reader,
+    strings: &[String],
+) -> Result<Instruction<usize, String, NumberT>, ZirError> {
+    let offset = reader.offset;
+    let opcode = reader.byte()?;
+    let instruction = match OPCODES.get(usize::from(opcode)).copied() {
+        Some("LoadImmediate") => {
+            let offset = reader.offset;
+            let digits = reader.bytes()?;
+            let value = std::str::from_utf8(digits)
+                .ok()
+                .and_then(|digits| digits.parse().ok())
+                .ok_or(ZirError::InvalidImmediate { offset })?;
+            Instruction::LoadImmediate(value, reader.register()?)
+        }
+        Some("Call") => Instruction::Call {
+            function_id: reader.string(strings)?,
+            arguments: reader.registers()?,
+            out: reader.register()?,
+        },
+        Some("Ret") => Instruction::Ret(reader.register()?),
+        Some("LoadArgs") => Instruction::LoadArgs(reader.registers()?),
+        Some("LoadBlockArgs") => Instruction::LoadBlockArgs(reader.registers()?),
+        Some("Jump") => Instruction::Jump {
+            to: reader.number()?,
+            arguments: reader.registers()?,
+        },
+        Some("JEqual") => Instruction::JEqual {
+            lhs: reader.register()?,
+            rhs: reader.register()?,
+            to: reader.number()?,
+            arguments: reader.registers()?,
+        },
+        Some("JNotEqual") => Instruction::JNotEqual {
+            lhs: reader.register()?,
+            rhs: reader.register()?,
+            to: reader.number()?,
+            arguments: reader.registers()?,
+        },
+        Some("JNonZero") => Instruction::JNonZero {
+            check: reader.register()?,
+            to: reader.number()?,
+            arguments: reader.registers()?,
+        },
+        Some("JZero") => Instruction::JZero {
+            check: reader.register()?,
+            to: reader.number()?,
+            arguments: reader.registers()?,
+        },
+        Some("Invalid") => Instruction::Invalid,
+        Some(name) => {
+            let (lhs, rhs, out) = (reader.register()?, reader.register()?, reader.register()?);
+            match name {
+                "Add" => Instruction::Add { lhs, rhs, out },
+                "Subtract" => Instruction::Subtract { lhs, rhs, out },
+                "Multiply" => Instruction::Multiply { lhs, rhs, out },
+                "Divide" => Instruction::Divide { lhs, rhs, out },
+                "Modulo" => Instruction::Modulo { lhs, rhs, out },
+                "BitOr" => Instruction::BitOr { lhs, rhs, out },
+                "BitNotOr" => Instruction::BitNotOr { lhs, rhs, out },
+                "BitAnd" => Instruction::BitAnd { lhs, rhs, out },
+                "ShiftL" => Instruction::ShiftL { lhs, rhs, out },
+                _ => Instruction::ShiftR { lhs, rhs, out },
+            }
+        }
+        None => return Err(ZirError::UnknownOpcode { offset, opcode }),
+    };
+    Ok(instruction)
+}
+
+impl<NumberT: Numeric> BasicProgram<NumberT> {
+    /// This program as a [`Module`] with [`Layout::Blocks`], with its functions sorted by name so that the same program is always
+    /// written the same way
+    #[must_use]
+    pub fn to_module(&self) -> Module<NumberT> {
+        let mut functions: Vec<_> = self
+            .get_all_functions()
+            .into_iter()
+            .map(|(name, entry)| FunctionEntry {
+                name: name.clone(),
+                entry: entry.0,
+                arity: self.get_arity(name),
+                register_count: self.get_register_count(name),
+                attributes: self.get_attributes(name),
+            })
+            .collect();
+        functions.sort_by(|a, b| a.name.cmp(&b.name));
+        Module {
+            layout: Layout::Blocks,
+            functions,
+            blocks: self
+                .blocks
+                .iter()
+                .map(|block| {
+                    block
+                        .iter()
+                        .map(|instruction| instruction.clone().map_blocks(|block| block.0))
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+
+    /// Write this program as a `.zir` file, see [`Self::to_module`]
+    #[must_use]
+    pub fn to_zir(&self) -> Vec<u8> {
+        self.to_module().to_bytes()
+    }
+
+    /// Build a program from a [`Module`] with [`Layout::Blocks`]
+    ///
+    /// The arity, register count and attributes of a function are only kept if both its arity and register count are known
+    ///
+    /// # Errors
+    /// [`ZirError::WrongLayout`] if the module has [`Layout::Flat`], [`ZirError::RegisterCount`] if a function's register count
+    /// doesn't fit the registers that the blocks reachable from its entry use, or any other [`ZirError`] if it isn't valid
+    pub fn from_module(module: Module<NumberT>) -> Result<Self, ZirError> {
+        if module.layout != Layout::Blocks {
+            return Err(ZirError::WrongLayout {
+                expected: Layout::Blocks,
+                found: module.layout,
+            });
+        }
+        // `get_ir` doesn't check block ids, so every one of them has to exist
+        module.check_blocks()?;
+        // the interpreter sizes a function's registers by its count up front
+        for function in &module.functions {
+            let Some(register_count) = function.register_count else {
+                continue;
+            };
+            let used = used_registers(&module.blocks, function.entry);
+            if register_count < used || register_count - used > MAX_UNUSED_REGISTERS {
+                return Err(ZirError::RegisterCount {
+                    function: function.name.clone(),
+                    register_count,
+                    used,
+                });
+            }
+        }
+
+        let mut program = BasicProgram {
+            function_list: HashMap::new(),
+            metadata: HashMap::new(),
+            blocks: module
+                .blocks
+                .into_iter()
+                .map(|block| {
+                    block
+                        .into_iter()
+                        .map(|instruction| instruction.map_blocks(BlockID))
+                        .collect()
+                })
+                .collect(),
+        };
+        for function in module.functions {
+            if let (Some(arity), Some(register_count)) = (function.arity, function.register_count) {
+                program.metadata.insert(
+                    function.name.clone(),
+                    FunctionMetadata {
+                        arity,
+                        register_count,
+                        attributes: function.attributes,
+                    },
+                );
+            }
+            if program
+                .function_list
+                .insert(function.name.clone(), BlockID(function.entry))
+                .is_some()
+            {
+                return Err(ZirError::DuplicateFunction(function.name));
+            }
+        }
+        Ok(program)
+    }
+
+    /// Read a program from a `.zir` file with [`Layout::Blocks`]
+    ///
+    /// # Errors
+    /// If the file is malformed, or has a different layout, see [`ZirError`]
+    pub fn from_zir(bytes: &[u8]) -> Result<Self, ZirError> {
+        Self::from_module(Module::from_bytes(bytes)?)
+    }
+}
@@ -0,0 +1,429 @@
+//! Analyses of the control flow graph of a function, which passes can get from [`crate::Graph::dominance`] instead of computing them themselves
+//!
+//! Dominator trees are built with "A Simple, Fast Dominance Algorithm" by Cooper, Harvey and Kennedy, and their frontiers with the algorithm
+//! from the same paper. [`Liveness`] is the usual backward analysis, solved with [`crate::dataflow`].
+//!
+//! [`LoopNest`] finds the natural loops of a function from its back edges, which are the jumps to a block that dominates the jumping block.
+//! Jumps into the middle of a cycle that aren't back edges, which make a graph irreducible, don't form loops.
+
+use std::collections::BTreeSet;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use calc_ir::{Instruction, Register};
+
+use crate::dataflow::{Analysis, Dataflow, Direction};
+use crate::FunctionGraph;
+
+/// Which nodes dominate which in a graph, along with the dominance frontier of every node
+///
+/// A node dominates another if every path from the root to the other goes through it, so every node dominates itself.
+/// Nodes that can't be reached from the root aren't part of the tree at all.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DominatorTree {
+    root: usize,
+    /// the immediate dominator of every node, None for the root and nodes that can't be reached
+    immediate: Vec<Option<usize>>,
+    /// the reachable nodes in reverse postorder, where every node comes after its dominators
+    order: Vec<usize>,
+    children: Vec<Vec<usize>>,
+    frontiers: Vec<Vec<usize>>,
+}
+
+impl DominatorTree {
+    /// The dominator tree of the `count` nodes of a graph, where `successors` are the edges out of every node
+    fn new(count: usize, root: usize, successors: &[Vec<usize>]) -> Self {
+        let mut predecessors = vec![Vec::new(); count];
+        for (node, successors) in successors.iter().enumerate() {
+            for successor in successors {
+                predecessors[*successor].push(node);
+            }
+        }
+
+        let mut postorder = Vec::with_capacity(count);
+        let mut visited = vec![false; count];
+        visited[root] = true;
+        let mut stack = vec![(root, 0)];
+        while let Some((node, next)) = stack.last_mut() {
+            if let Some(successor) = successors[*node].get(*next).copied() {
+                *next += 1;
+                if !visited[successor] {
+                    visited[successor] = true;
+                    stack.push((successor, 0));
+                }
+            } else {
+                postorder.push(*node);
+                stack.pop();
+            }
+        }
+        let mut position = vec![None; count];
+        for (index, node) in postorder.iter().enumerate() {
+            position[*node] = Some(index);
+        }
+
+        // the root is its own dominator while the tree is built, so walking up from any processed node ends there
+        let mut dominator: Vec<Option<usize>> = vec![None; count];
+        dominator[root] = Some(root);
+        let intersect = |dominator: &[Option<usize>], mut lhs: usize, mut rhs: usize| {
+            while lhs != rhs {
+                while position[lhs] < position[rhs] {
+                    lhs =
+                        dominator[lhs].expect("nodes are only intersected once they're processed");
+                }
+                while position[rhs] < position[lhs] {
+                    rhs =
+                        dominator[rhs].expect("nodes are only intersected once they're processed");
+                }
+            }
+            lhs
+        };
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &node in postorder.iter().rev().skip(1) {
+                let immediate = predecessors[node]
+                    .iter()
+                    .copied()
+                    .filter(|predecessor| dominator[*predecessor].is_some())
+                    .reduce(|immediate, predecessor| intersect(&dominator, predecessor, immediate));
+                if immediate.is_some() && dominator[node] != immediate {
+                    dominator[node] = immediate;
+                    changed = true;
+                }
+            }
+        }
+        dominator[root] = None;
+
+        let mut children = vec![Vec::new(); count];
+        let order: Vec<usize> = postorder.into_iter().rev().collect();
+        for &node in &order {
+            if let Some(immediate) = dominator[node] {
+                children[immediate].push(node);
+            }
+        }
+
+        // a node is in the frontier of everything on the way up from its predecessors to its immediate dominator, not including that
+        let mut frontiers: Vec<Vec<usize>> = vec![Vec::new(); count];
+        for &node in &order {
+            for &predecessor in &predecessors[node] {
+                let mut runner = position[predecessor].map(|_| predecessor);
+                while let Some(current) = runner.filter(|current| Some(*current) != dominator[node])
+                {
+                    if !frontiers[current].contains(&node) {
+                        frontiers[current].push(node);
+                    }
+                    runner = dominator[current];
+                }
+            }
+        }
+
+        Self {
+            root,
+            immediate: dominator,
+            order,
+            children,
+            frontiers,
+        }
+    }
+
+    /// The dominator tree of `function`, rooted at its entry
+    #[must_use]
+    pub fn dominators<FunctionPointerT: Eq + Debug + Clone + Hash, NumberT>(
+        function: &FunctionGraph<FunctionPointerT, NumberT>,
+    ) -> Self {
+        let successors: Vec<_> = (0..function.blocks.len())
+            .map(|block| function.successors(block))
+            .collect();
+        Self::new(
+            function.blocks.len(),
+            FunctionGraph::<FunctionPointerT, NumberT>::ENTRY,
+            &successors,
+        )
+    }
+
+    /// The post-dominator tree of `function`, which is the dominator tree of its graph with every edge reversed.
+    ///
+    /// It's rooted at a virtual exit numbered `function.blocks.len()`, which every block that doesn't end with a jump, and so returns or fails,
+    /// goes to. Blocks that can never leave the function, such as the blocks of an infinite loop, aren't part of the tree.
+    /// The frontiers of this tree are the blocks that a block is control dependent on.
+    #[must_use]
+    pub fn post_dominators<FunctionPointerT: Eq + Debug + Clone + Hash, NumberT>(
+        function: &FunctionGraph<FunctionPointerT, NumberT>,
+    ) -> Self {
+        let exit = function.blocks.len();
+        let mut successors = vec![Vec::new(); exit + 1];
+        for (block, instructions) in function.blocks.iter().enumerate() {
+            for successor in function.successors(block) {
+                successors[successor].push(block);
+            }
+            if !matches!(instructions.last(), Some(Instruction::Jump { .. })) {
+                successors[exit].push(block);
+            }
+        }
+        Self::new(exit + 1, exit, &successors)
+    }
+
+    /// The node that the tree starts at, which dominates every other node in it
+    #[must_use]
+    pub fn root(&self) -> usize {
+        self.root
+    }
+
+    /// The closest node that strictly dominates `node`, or None for the root and nodes that aren't in the tree
+    #[must_use]
+    pub fn immediate(&self, node: usize) -> Option<usize> {
+        self.immediate[node]
+    }
+
+    /// Whether `node` can be reached from the root, which is what it takes to be part of the tree
+    #[must_use]
+    pub fn contains(&self, node: usize) -> bool {
+        node == self.root || self.immediate[node].is_some()
+    }
+
+    /// Whether every path from the root to `node` goes through `dominator`, which is always true for `node` itself as long as it's in the tree
+    #[must_use]
+    pub fn dominates(&self, dominator: usize, node: usize) -> bool {
+        if !self.contains(node) {
+            return false;
+        }
+        let mut current = Some(node);
+        while let Some(node) = current {
+            if node == dominator {
+                return true;
+            }
+            current = self.immediate[node];
+        }
+        false
+    }
+
+    /// The nodes that `node` immediately dominates, in reverse postorder
+    #[must_use]
+    pub fn children(&self, node: usize) -> &[usize] {
+        &self.children[node]
+    }
+
+    /// Every node in the tree in reverse postorder, so every node comes after the nodes that dominate it
+    #[must_use]
+    pub fn reverse_postorder(&self) -> &[usize] {
+        &self.order
+    }
+
+    /// The dominance frontier of `node`, which is every node that `node` doesn't strictly dominate but dominates a predecessor of
+    #[must_use]
+    pub fn frontier(&self, node: usize) -> &[usize] {
+        &self.frontiers[node]
+    }
+}
+
+/// Both dominator trees of a function, see [`crate::Graph::dominance`] for getting them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dominance {
+    pub dominators: DominatorTree,
+    pub post_dominators: DominatorTree,
+}
+
+impl Dominance {
+    /// Compute both dominator trees of `function`, without caching them
+    #[must_use]
+    pub fn of<FunctionPointerT: Eq + Debug + Clone + Hash, NumberT>(
+        function: &FunctionGraph<FunctionPointerT, NumberT>,
+    ) -> Self {
+        Self {
+            dominators: DominatorTree::dominators(function),
+            post_dominators: DominatorTree::post_dominators(function),
+        }
+    }
+}
+
+/// The backward [`Analysis`] behind [`Liveness`], where the state is the set of live registers
+pub struct LiveRegisters;
+
+impl<FunctionPointerT: Eq + Debug + Clone + Hash, NumberT> Analysis<FunctionPointerT, NumberT>
+    for LiveRegisters
+{
+    type State = BTreeSet<Register>;
+    const DIRECTION: Direction = Direction::Backward;
+
+    fn boundary(&self, _: &FunctionGraph<FunctionPointerT, NumberT>) -> Self::State {
+        BTreeSet::new()
+    }
+
+    fn transfer(
+        &self,
+        instruction: &Instruction<usize, FunctionPointerT, NumberT>,
+        state: &mut Self::State,
+    ) {
+        for register in instruction.writes() {
+            state.remove(&register);
+        }
+        state.extend(instruction.reads());
+    }
+}
+
+/// Which registers of a function are live, meaning that a value in them may still be read, at the start and end of every block
+///
+/// Registers are shared by every block of a function, so a jump keeps everything live at its target alive along with the arguments it passes.
+/// A register that's read before anything is written to it is live from the start of the function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Liveness {
+    dataflow: Dataflow<BTreeSet<Register>>,
+    live_out: Vec<BTreeSet<Register>>,
+}
+
+impl Liveness {
+    /// Compute which registers are live in every block of `function`
+    #[must_use]
+    pub fn of<FunctionPointerT: Eq + Debug + Clone + Hash, NumberT>(
+        function: &FunctionGraph<FunctionPointerT, NumberT>,
+    ) -> Self {
+        let dataflow = Dataflow::solve(&LiveRegisters, function);
+        let live_out = (0..function.blocks.len())
+            .map(|block| {
+                function
+                    .successors(block)
+                    .into_iter()
+                    .flat_map(|successor| dataflow.before(successor).iter().copied())
+                    .collect()
+            })
+            .collect();
+        Self { dataflow, live_out }
+    }
+
+    /// The registers that are live when control enters `block`
+    #[must_use]
+    pub fn live_in(&self, block: usize) -> &BTreeSet<Register> {
+        self.dataflow.before(block)
+    }
+
+    /// The registers that are live at the start of any block that `block` can transfer control to
+    #[must_use]
+    pub fn live_out(&self, block: usize) -> &BTreeSet<Register> {
+        &self.live_out[block]
+    }
+
+    /// The registers that are live right before each instruction of `block` in `function`, which has to be the function this was computed for
+    #[must_use]
+    pub fn before_each<FunctionPointerT: Eq + Debug + Clone + Hash, NumberT>(
+        &self,
+        function: &FunctionGraph<FunctionPointerT, NumberT>,
+        block: usize,
+    ) -> Vec<BTreeSet<Register>> {
+        let mut before = self
+            .dataflow
+            .per_instruction(&LiveRegisters, function, block);
+        before.pop();
+        before
+    }
+}
+
+/// A natural loop of a function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+    /// the block every path into the loop goes through, which dominates every block in it
+    pub header: usize,
+    /// the blocks in the loop that jump back to the header
+    pub latches: Vec<usize>,
+    /// every block in the loop, including the header and the blocks of loops nested in it
+    pub blocks: BTreeSet<usize>,
+    /// the index of the innermost loop this one is nested in
+    pub parent: Option<usize>,
+    /// the indexes of the loops directly nested in this one
+    pub children: Vec<usize>,
+    /// how many loops this one is nested in, counting itself, so 1 for loops that aren't nested
+    pub depth: usize,
+}
+
+/// Every natural loop of a function, and how they're nested
+///
+/// Back edges to the same header make up a single loop. Loops are ordered by the reverse postorder of their headers,
+/// so a loop always comes before the loops nested in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoopNest {
+    loops: Vec<Loop>,
+    /// the innermost loop of every block
+    innermost: Vec<Option<usize>>,
+}
+
+impl LoopNest {
+    /// Find the loops of `function`, where `dominators` has to be its [`DominatorTree::dominators`]
+    #[must_use]
+    pub fn of<FunctionPointerT: Eq + Debug + Clone + Hash, NumberT>(
+        function: &FunctionGraph<FunctionPointerT, NumberT>,
+        dominators: &DominatorTree,
+    ) -> Self {
+        let mut predecessors = vec![Vec::new(); function.blocks.len()];
+        for block in 0..function.blocks.len() {
+            for successor in function.successors(block) {
+                predecessors[successor].push(block);
+            }
+        }
+
+        let mut loops: Vec<Loop> = Vec::new();
+        for &header in dominators.reverse_postorder() {
+            let latches: Vec<usize> = predecessors[header]
+                .iter()
+                .copied()
+                .filter(|latch| dominators.dominates(header, *latch))
+                .collect();
+            if latches.is_empty() {
+                continue;
+            }
+            // everything that can reach a latch without going through the header
+            let mut blocks = BTreeSet::from([header]);
+            let mut to_visit = latches.clone();
+            while let Some(block) = to_visit.pop() {
+                if dominators.contains(block) && blocks.insert(block) {
+                    to_visit.extend(predecessors[block].iter().copied());
+                }
+            }
+            loops.push(Loop {
+                header,
+                latches,
+                blocks,
+                parent: None,
+                children: Vec::new(),
+                depth: 1,
+            });
+        }
+
+        // natural loops are either disjoint or nested, and the innermost loop around one is the last one before it that has its header
+        for index in 0..loops.len() {
+            let parent = (0..index)
+                .rev()
+                .find(|outer| loops[*outer].blocks.contains(&loops[index].header));
+            if let Some(parent) = parent {
+                loops[index].parent = Some(parent);
+                loops[index].depth = loops[parent].depth + 1;
+                loops[parent].children.push(index);
+            }
+        }
+        let mut innermost = vec![None; function.blocks.len()];
+        for (index, natural_loop) in loops.iter().enumerate() {
+            for block in &natural_loop.blocks {
+                innermost[*block] = Some(index);
+            }
+        }
+
+        Self { loops, innermost }
+    }
+
+    /// Every loop, with outer loops before the loops nested in them
+    #[must_use]
+    pub fn loops(&self) -> &[Loop] {
+        &self.loops
+    }
+
+    /// The index of the innermost loop that `block` is in, if it's in one
+    #[must_use]
+    pub fn innermost(&self, block: usize) -> Option<usize> {
+        self.innermost[block]
+    }
+
+    /// How many loops `block` is in
+    #[must_use]
+    pub fn depth(&self, block: usize) -> usize {
+        self.innermost(block)
+            .map_or(0, |natural_loop| self.loops[natural_loop].depth)
+    }
+}
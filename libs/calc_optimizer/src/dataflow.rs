@@ -0,0 +1,377 @@
+//! A framework for dataflow analyses over the control flow graph of a function, so passes don't have to write their own worklists
+//!
+//! An analysis is a [`Lattice`] of states, a [`Direction`] that states flow in, and an [`Analysis::transfer`] function giving the effect of a single
+//! instruction on a state. [`Dataflow::solve`] iterates the transfer functions over the blocks of a function until no state changes, joining states
+//! where control flow meets, and keeps the state at the start and end of every block. The states inside a block are computed again from those
+//! when they're asked for with [`Dataflow::per_instruction`].
+//!
+//! Blocks can jump out of the middle with conditional jumps, so states flow along an edge at the instruction that jumps, not only at the end
+//! of the block. For analyses with lattices that can grow for a long time, such as ranges of values, [`Lattice::widen`] is used instead of
+//! [`Lattice::join`] once a block has changed [`WIDEN_AFTER`] times.
+//!
+//! [`crate::analysis::Liveness`] is built with this, and [`ConstantPropagation`] is a ready-made forward analysis.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use calc_ir::{ArithmeticMode, Instruction, Number, Numeric, Register};
+
+use crate::FunctionGraph;
+
+/// How many times the state at the start of a block can change by joining before it's widened instead
+pub const WIDEN_AFTER: usize = 3;
+
+/// The states of an analysis, ordered by how much they say could be true
+pub trait Lattice: Clone + Eq {
+    /// The least state, where nothing has flowed in yet
+    fn bottom() -> Self;
+    /// Set this state to the least state that's at least both it and `other`, returning whether it changed
+    fn join(&mut self, other: &Self) -> bool;
+    /// Like [`Self::join`], but allowed to go further up so that repeatedly widening a state stops changing it after a few steps.
+    /// This only has to be implemented for lattices that are very tall or infinite, by default it just joins
+    fn widen(&mut self, other: &Self) -> bool {
+        self.join(other)
+    }
+}
+
+/// Sets are joined by their union
+impl<T: Ord + Clone> Lattice for BTreeSet<T> {
+    fn bottom() -> Self {
+        BTreeSet::new()
+    }
+
+    fn join(&mut self, other: &Self) -> bool {
+        let length = self.len();
+        self.extend(other.iter().cloned());
+        self.len() != length
+    }
+}
+
+/// Maps are joined key by key, where a missing key is the bottom of the values
+impl<K: Ord + Clone, V: Lattice> Lattice for BTreeMap<K, V> {
+    fn bottom() -> Self {
+        BTreeMap::new()
+    }
+
+    fn join(&mut self, other: &Self) -> bool {
+        let mut changed = false;
+        for (key, value) in other {
+            changed |= self
+                .entry(key.clone())
+                .or_insert_with(V::bottom)
+                .join(value);
+        }
+        changed
+    }
+
+    fn widen(&mut self, other: &Self) -> bool {
+        let mut changed = false;
+        for (key, value) in other {
+            changed |= self
+                .entry(key.clone())
+                .or_insert_with(V::bottom)
+                .widen(value);
+        }
+        changed
+    }
+}
+
+/// Which way states flow through a function
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// from the entry block along jumps, the state at the start of a block is the join of every jump to it
+    Forward,
+    /// from the ends of the function against jumps, the state at the end of a block is the join of every block it can go to
+    Backward,
+}
+
+/// A dataflow analysis, see the [module documentation](self) for how it's run
+pub trait Analysis<FunctionPointerT: Eq + Debug + Clone + Hash, NumberT = Number> {
+    /// The lattice of states the analysis computes
+    type State: Lattice;
+    /// Which way states flow
+    const DIRECTION: Direction;
+
+    /// The state at the start of the function for forward analyses, or at the end of every block that leaves the function for backward ones
+    fn boundary(&self, function: &FunctionGraph<FunctionPointerT, NumberT>) -> Self::State;
+
+    /// Apply the effect of `instruction` to `state`, going over it in [`Self::DIRECTION`]
+    fn transfer(
+        &self,
+        instruction: &Instruction<usize, FunctionPointerT, NumberT>,
+        state: &mut Self::State,
+    );
+
+    /// Refine `state` for control going along a jump, `taken` if the jump of `instruction` is taken and not if control continues past it.
+    ///
+    /// This is applied after [`Self::transfer`] for forward analyses, and before it for backward ones. By default it does nothing
+    fn branch(
+        &self,
+        _instruction: &Instruction<usize, FunctionPointerT, NumberT>,
+        _taken: bool,
+        _state: &mut Self::State,
+    ) {
+    }
+}
+
+/// The result of running an [`Analysis`] on a function, which holds the state at the start and end of every block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dataflow<StateT> {
+    before: Vec<StateT>,
+    after: Vec<StateT>,
+}
+
+impl<StateT: Lattice> Dataflow<StateT> {
+    /// Run `analysis` on `function` until it reaches a fixpoint.
+    ///
+    /// Forward analyses only look at blocks that can be reached from the entry, the states of other blocks stay at [`Lattice::bottom`]
+    pub fn solve<
+        FunctionPointerT: Eq + Debug + Clone + Hash,
+        NumberT,
+        AnalysisT: Analysis<FunctionPointerT, NumberT, State = StateT>,
+    >(
+        analysis: &AnalysisT,
+        function: &FunctionGraph<FunctionPointerT, NumberT>,
+    ) -> Self {
+        let count = function.blocks.len();
+        let mut dataflow = Self {
+            before: vec![StateT::bottom(); count],
+            after: vec![StateT::bottom(); count],
+        };
+        if count == 0 {
+            return dataflow;
+        }
+        let mut changes = vec![0; count];
+        let mut queued = vec![false; count];
+        let mut worklist = VecDeque::new();
+        let queue = |worklist: &mut VecDeque<usize>, queued: &mut [bool], block: usize| {
+            if !queued[block] {
+                queued[block] = true;
+                worklist.push_back(block);
+            }
+        };
+        // joins `state` into the state a block starts or ends with, returning whether that changed
+        let mut merge = |states: &mut [StateT], block: usize, state: &StateT| {
+            let changed = if changes[block] >= WIDEN_AFTER {
+                states[block].widen(state)
+            } else {
+                states[block].join(state)
+            };
+            changes[block] += usize::from(changed);
+            changed
+        };
+
+        match AnalysisT::DIRECTION {
+            Direction::Forward => {
+                let entry = FunctionGraph::<FunctionPointerT, NumberT>::ENTRY;
+                dataflow.before[entry] = analysis.boundary(function);
+                queue(&mut worklist, &mut queued, entry);
+                let mut visited = vec![false; count];
+                while let Some(block) = worklist.pop_front() {
+                    queued[block] = false;
+                    visited[block] = true;
+                    let mut state = dataflow.before[block].clone();
+                    for instruction in &function.blocks[block] {
+                        analysis.transfer(instruction, &mut state);
+                        let Some((to, _)) = instruction.jump_target() else {
+                            continue;
+                        };
+                        let mut taken = state.clone();
+                        analysis.branch(instruction, true, &mut taken);
+                        if merge(&mut dataflow.before, *to, &taken) || !visited[*to] {
+                            queue(&mut worklist, &mut queued, *to);
+                        }
+                        if !matches!(instruction, Instruction::Jump { .. }) {
+                            analysis.branch(instruction, false, &mut state);
+                        }
+                    }
+                    dataflow.after[block] = state;
+                }
+            }
+            Direction::Backward => {
+                let boundary = analysis.boundary(function);
+                let mut predecessors = vec![Vec::new(); count];
+                for block in 0..count {
+                    for successor in function.successors(block) {
+                        predecessors[successor].push(block);
+                    }
+                }
+                for block in (0..count).rev() {
+                    queue(&mut worklist, &mut queued, block);
+                }
+                while let Some(block) = worklist.pop_front() {
+                    queued[block] = false;
+                    let states = dataflow.backward(analysis, function, block, &boundary);
+                    let (first, last) = (&states[0], &states[states.len() - 1]);
+                    dataflow.after[block] = last.clone();
+                    if merge(&mut dataflow.before, block, first) {
+                        for predecessor in &predecessors[block] {
+                            queue(&mut worklist, &mut queued, *predecessor);
+                        }
+                    }
+                }
+            }
+        }
+        dataflow
+    }
+
+    /// The state at the start of `block`
+    #[must_use]
+    pub fn before(&self, block: usize) -> &StateT {
+        &self.before[block]
+    }
+
+    /// The state at the end of `block`, after its last instruction
+    #[must_use]
+    pub fn after(&self, block: usize) -> &StateT {
+        &self.after[block]
+    }
+
+    /// The state before every instruction of `block`, followed by the state after the last one.
+    ///
+    /// This has to be given the same analysis and function that this was solved for
+    pub fn per_instruction<
+        FunctionPointerT: Eq + Debug + Clone + Hash,
+        NumberT,
+        AnalysisT: Analysis<FunctionPointerT, NumberT, State = StateT>,
+    >(
+        &self,
+        analysis: &AnalysisT,
+        function: &FunctionGraph<FunctionPointerT, NumberT>,
+        block: usize,
+    ) -> Vec<StateT> {
+        match AnalysisT::DIRECTION {
+            Direction::Forward => {
+                let mut state = self.before[block].clone();
+                let mut states = vec![state.clone()];
+                for instruction in &function.blocks[block] {
+                    analysis.transfer(instruction, &mut state);
+                    if instruction.jump_target().is_some()
+                        && !matches!(instruction, Instruction::Jump { .. })
+                    {
+                        analysis.branch(instruction, false, &mut state);
+                    }
+                    states.push(state.clone());
+                }
+                states
+            }
+            Direction::Backward => {
+                self.backward(analysis, function, block, &analysis.boundary(function))
+            }
+        }
+    }
+
+    /// Go backwards over `block` from its end, starting from `boundary` if it leaves the function, with the states that its jumps go to
+    fn backward<
+        FunctionPointerT: Eq + Debug + Clone + Hash,
+        NumberT,
+        AnalysisT: Analysis<FunctionPointerT, NumberT, State = StateT>,
+    >(
+        &self,
+        analysis: &AnalysisT,
+        function: &FunctionGraph<FunctionPointerT, NumberT>,
+        block: usize,
+        boundary: &StateT,
+    ) -> Vec<StateT> {
+        let instructions = &function.blocks[block];
+        let mut state = if matches!(instructions.last(), Some(Instruction::Jump { .. })) {
+            StateT::bottom()
+        } else {
+            boundary.clone()
+        };
+        let mut states = vec![StateT::bottom(); instructions.len() + 1];
+        for (index, instruction) in instructions.iter().enumerate().rev() {
+            if let Some((to, _)) = instruction.jump_target() {
+                let mut taken = self.before[*to].clone();
+                analysis.branch(instruction, true, &mut taken);
+                if matches!(instruction, Instruction::Jump { .. }) {
+                    state = taken;
+                } else {
+                    analysis.branch(instruction, false, &mut state);
+                    state.join(&taken);
+                }
+            }
+            // a jump that ends the block decides what the state at the end is
+            if index + 1 == instructions.len() {
+                states[index + 1] = state.clone();
+            }
+            analysis.transfer(instruction, &mut state);
+            states[index] = state.clone();
+        }
+        if instructions.is_empty() {
+            states[0] = state;
+        }
+        states
+    }
+}
+
+/// What's known about the value of a register at some point
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Constant<NumberT = Number> {
+    /// nothing has been written to it on any path that got here so far
+    Undefined,
+    /// it always holds this value
+    Known(NumberT),
+    /// it can hold different values, or a value that isn't known
+    Varying,
+}
+
+impl<NumberT: Clone + Eq> Lattice for Constant<NumberT> {
+    fn bottom() -> Self {
+        Constant::Undefined
+    }
+
+    fn join(&mut self, other: &Self) -> bool {
+        let joined = match (&*self, other) {
+            (_, Constant::Undefined) => return false,
+            (Constant::Undefined, _) => other.clone(),
+            (Constant::Known(lhs), Constant::Known(rhs)) if lhs == rhs => return false,
+            (Constant::Varying, _) => return false,
+            _ => Constant::Varying,
+        };
+        *self = joined;
+        true
+    }
+}
+
+/// Finds the registers that hold the same value every time an instruction runs, along every path through the function.
+///
+/// Arithmetic is evaluated in [`Self::arithmetic`], and operations that would fail make their result [`Constant::Varying`].
+/// Registers aren't in the state until something is written to them, and values passed to blocks or functions are always varying
+pub struct ConstantPropagation {
+    pub arithmetic: ArithmeticMode,
+}
+
+impl<FunctionPointerT: Eq + Debug + Clone + Hash, NumberT: Numeric>
+    Analysis<FunctionPointerT, NumberT> for ConstantPropagation
+{
+    type State = BTreeMap<Register, Constant<NumberT>>;
+    const DIRECTION: Direction = Direction::Forward;
+
+    fn boundary(&self, _: &FunctionGraph<FunctionPointerT, NumberT>) -> Self::State {
+        BTreeMap::new()
+    }
+
+    fn transfer(
+        &self,
+        instruction: &Instruction<usize, FunctionPointerT, NumberT>,
+        state: &mut Self::State,
+    ) {
+        let value = match instruction {
+            Instruction::LoadImmediate(value, _) => Constant::Known(value.clone()),
+            _ => match instruction.operation() {
+                Some((operation, lhs, rhs, _)) => match (state.get(&lhs), state.get(&rhs)) {
+                    (Some(Constant::Known(lhs)), Some(Constant::Known(rhs))) => operation
+                        .evaluate(lhs, rhs, self.arithmetic)
+                        .map_or(Constant::Varying, Constant::Known),
+                    _ => Constant::Varying,
+                },
+                None => Constant::Varying,
+            },
+        };
+        for register in instruction.writes() {
+            state.insert(register, value.clone());
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! Exporting graphs as Graphviz DOT, to look at what passes do to a program
+//!
+//! [`control_flow`] draws every function as a cluster, with a node for each block that lists its instructions. A block's edges are labeled
+//! `taken` for its conditional jumps, and for the jump it ends with, `fallthrough` when there are conditional jumps before it, since that's where
+//! control goes when none of them are taken, or just `jump` when there aren't. [`call_graph`] draws a node for every function, with an edge to every
+//! function it calls labeled by how many calls there are, functions that aren't part of the graph are dashed.
+//!
+//! Functions are drawn in the order of their names, so the same graph is always drawn the same way.
+
+use std::fmt::{Debug, Display, Write};
+use std::hash::Hash;
+
+use calc_ir::{Instruction, Numeric, Program};
+
+use crate::{FunctionGraph, Graph};
+
+/// Add a line to `dot`
+fn line(dot: &mut String, line: std::fmt::Arguments) {
+    writeln!(dot, "{line}").expect("writing to a String can't fail");
+}
+
+/// Escape `text` to go in a quoted DOT string
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// The functions of `graph` in the order of their names
+fn sorted<FunctionPointerT: Eq + Debug + Clone + Hash + Display, NumberT: Numeric>(
+    graph: &Graph<FunctionPointerT, NumberT>,
+) -> Vec<(String, &FunctionGraph<FunctionPointerT, NumberT>)> {
+    let mut functions: Vec<_> = graph
+        .functions()
+        .map(|(function, function_graph)| (function.to_string(), function_graph))
+        .collect();
+    functions.sort_by(|(a, _), (b, _)| a.cmp(b));
+    functions
+}
+
+/// Draw the control flow graph of every function in `graph`
+#[must_use]
+pub fn control_flow<FunctionPointerT: Eq + Debug + Clone + Hash + Display, NumberT: Numeric>(
+    graph: &Graph<FunctionPointerT, NumberT>,
+) -> String {
+    let mut dot =
+        String::from("digraph control_flow {\n    node [shape=box, fontname=monospace];\n");
+    for (index, (name, function)) in sorted(graph).into_iter().enumerate() {
+        line(&mut dot, format_args!("    subgraph cluster_{index} {{"));
+        line(
+            &mut dot,
+            format_args!("        label=\"{}\";", escape(&name)),
+        );
+        for (block, instructions) in function.blocks.iter().enumerate() {
+            let mut label = format!("block {block}\\l");
+            for instruction in instructions {
+                label.push_str(&escape(&format!("{instruction:?}")));
+                label.push_str("\\l");
+            }
+            line(
+                &mut dot,
+                format_args!("        f{index}_b{block} [label=\"{label}\"];"),
+            );
+        }
+        for (block, instructions) in function.blocks.iter().enumerate() {
+            let mut conditional = false;
+            for instruction in instructions {
+                let Some((to, _)) = instruction.jump_target() else {
+                    continue;
+                };
+                let kind = match instruction {
+                    Instruction::Jump { .. } if conditional => "fallthrough",
+                    Instruction::Jump { .. } => "jump",
+                    _ => "taken",
+                };
+                conditional = true;
+                line(
+                    &mut dot,
+                    format_args!("        f{index}_b{block} -> f{index}_b{to} [label=\"{kind}\"];"),
+                );
+            }
+        }
+        dot.push_str("    }\n");
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Draw which functions in `graph` call which
+#[must_use]
+pub fn call_graph<FunctionPointerT: Eq + Debug + Clone + Hash + Display, NumberT: Numeric>(
+    graph: &Graph<FunctionPointerT, NumberT>,
+) -> String {
+    let functions = sorted(graph);
+    let mut dot = String::from("digraph calls {\n    node [shape=ellipse, fontname=monospace];\n");
+    let mut outside = Vec::new();
+    for (name, _) in &functions {
+        line(&mut dot, format_args!("    \"{}\";", escape(name)));
+    }
+    for (name, function) in &functions {
+        // callees in the order they're first called, along with how many times they're called
+        let mut calls: Vec<(String, usize)> = Vec::new();
+        for instruction in function.blocks.iter().flatten() {
+            let Instruction::Call { function_id, .. } = instruction else {
+                continue;
+            };
+            let callee = function_id.to_string();
+            match calls.iter_mut().find(|(called, _)| *called == callee) {
+                Some((_, count)) => *count += 1,
+                None => calls.push((callee, 1)),
+            }
+        }
+        for (callee, count) in calls {
+            if !functions.iter().any(|(name, _)| *name == callee) && !outside.contains(&callee) {
+                line(
+                    &mut dot,
+                    format_args!("    \"{}\" [style=dashed];", escape(&callee)),
+                );
+                outside.push(callee.clone());
+            }
+            line(
+                &mut dot,
+                format_args!(
+                    "    \"{}\" -> \"{}\" [label=\"{count}\"];",
+                    escape(name),
+                    escape(&callee)
+                ),
+            );
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// The graph of every function in `program`, which is what the DOT of a program is drawn from
+fn whole_program<
+    BlockPointerT: Eq + Debug + Clone,
+    FunctionPointerT: Eq + Debug + Clone + Hash + Display,
+    ProgramT: Program<BlockPointer = BlockPointerT, FunctionPointer = FunctionPointerT>,
+>(
+    program: &ProgramT,
+) -> Graph<FunctionPointerT, ProgramT::Number> {
+    let functions = program
+        .get_all_functions()
+        .into_iter()
+        .map(|(function, _)| function.clone())
+        .collect();
+    Graph::from_program(program, functions)
+}
+
+/// [`control_flow`] for every function in `program`
+#[must_use]
+pub fn program_control_flow<
+    BlockPointerT: Eq + Debug + Clone,
+    FunctionPointerT: Eq + Debug + Clone + Hash + Display,
+    ProgramT: Program<BlockPointer = BlockPointerT, FunctionPointer = FunctionPointerT>,
+>(
+    program: &ProgramT,
+) -> String {
+    control_flow(&whole_program(program))
+}
+
+/// [`call_graph`] for every function in `program`
+#[must_use]
+pub fn program_call_graph<
+    BlockPointerT: Eq + Debug + Clone,
+    FunctionPointerT: Eq + Debug + Clone + Hash + Display,
+    ProgramT: Program<BlockPointer = BlockPointerT, FunctionPointer = FunctionPointerT>,
+>(
+    program: &ProgramT,
+) -> String {
+    call_graph(&whole_program(program))
+}
@@ -1,22 +1,275 @@
-/// The graph built from a program
+//! The control flow graph built from a program, which is what optimization passes work on
+use crate::analysis::{Dominance, Liveness, LoopNest};
+use crate::passes::OptimizationPass;
 use crate::Block;
 use std::collections::HashMap;
 use std::hash::Hash;
+use std::sync::{Arc, Mutex, PoisonError};
 
-use calc_ir::Program;
+use calc_ir::{FunctionAttributes, Instruction, Number, Numeric, Program};
 
-pub struct Graph<FunctionPointerT: Eq + std::fmt::Debug + Clone + Hash> {
-    functions: HashMap<FunctionPointerT, Block<FunctionPointerT, usize>>,
+/// The control flow graph of a single function
+///
+/// Blocks are identified by their index into [`Self::blocks`], which is also what jumps point to, and the entry block is always block 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionGraph<FunctionPointerT: Eq + std::fmt::Debug + Clone + Hash, NumberT = Number> {
+    pub blocks: Vec<Block<usize, FunctionPointerT, NumberT>>,
+    /// the amount of arguments the function takes, if the program it was built from knew it
+    pub arity: Option<usize>,
+    /// the attributes the program gave the function, which are advisory: [`crate::passes::PureCallElimination`] acts on `pure`, the rest are only carried through
+    pub attributes: FunctionAttributes,
 }
 
-impl<FunctionPointerT: Eq + Clone + Hash + std::fmt::Debug> Graph<FunctionPointerT> {
-    fn from_program<
-        BlockPointerT: Eq + std::fmt::Debug + Into<usize>,
-        ProgramT: Program<BlockPointer = BlockPointerT, FunctionPointer = FunctionPointerT>,
+impl<FunctionPointerT: Eq + std::fmt::Debug + Clone + Hash, NumberT>
+    FunctionGraph<FunctionPointerT, NumberT>
+{
+    /// The index of the block that the function starts in
+    pub const ENTRY: usize = 0;
+
+    /// All blocks that `block` can transfer control to, in the order their jumps appear, without duplicates
+    #[must_use]
+    pub fn successors(&self, block: usize) -> Vec<usize> {
+        let mut successors = Vec::new();
+        for (to, _) in self.blocks[block]
+            .iter()
+            .filter_map(Instruction::jump_target)
+        {
+            if !successors.contains(to) {
+                successors.push(*to);
+            }
+        }
+        successors
+    }
+
+    /// All blocks that can transfer control to `block`
+    #[must_use]
+    pub fn predecessors(&self, block: usize) -> Vec<usize> {
+        (0..self.blocks.len())
+            .filter(|from| self.successors(*from).contains(&block))
+            .collect()
+    }
+}
+
+/// The graph built from a program, holding the [`FunctionGraph`] of every function reachable from the entry points it was built with
+///
+/// Analyses of functions are computed when they're first asked for, and kept until the function is borrowed mutably or a pass run with [`Self::run_pass`] reports that it changed something.
+/// The cache is shared by every thread that the graph is shared with.
+pub struct Graph<FunctionPointerT: Eq + std::fmt::Debug + Clone + Hash, NumberT = Number> {
+    functions: HashMap<FunctionPointerT, FunctionGraph<FunctionPointerT, NumberT>>,
+    dominance: AnalysisCache<FunctionPointerT, Dominance>,
+    liveness: AnalysisCache<FunctionPointerT, Liveness>,
+    loops: AnalysisCache<FunctionPointerT, LoopNest>,
+}
+
+/// The analyses of every function that they've been computed for, behind a lock so that a graph can be shared between threads
+type AnalysisCache<FunctionPointerT, AnalysisT> = Mutex<HashMap<FunctionPointerT, Arc<AnalysisT>>>;
+
+/// The analysis of `function` in `cache`, computing it with `compute` if it isn't there yet
+fn cached<FunctionPointerT: Eq + Clone + Hash, AnalysisT>(
+    cache: &AnalysisCache<FunctionPointerT, AnalysisT>,
+    function: &FunctionPointerT,
+    compute: impl FnOnce() -> AnalysisT,
+) -> Arc<AnalysisT> {
+    // an analysis that panicked was never inserted, so whatever is in the cache is still valid
+    let mut cache = cache.lock().unwrap_or_else(PoisonError::into_inner);
+    let analysis = cache
+        .entry(function.clone())
+        .or_insert_with(|| Arc::new(compute()));
+    Arc::clone(analysis)
+}
+
+fn clear<FunctionPointerT, AnalysisT>(cache: &mut AnalysisCache<FunctionPointerT, AnalysisT>) {
+    cache
+        .get_mut()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clear();
+}
+
+fn forget<FunctionPointerT: Eq + Hash, AnalysisT>(
+    cache: &mut AnalysisCache<FunctionPointerT, AnalysisT>,
+    function: &FunctionPointerT,
+) {
+    cache
+        .get_mut()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(function);
+}
+
+impl<FunctionPointerT: Eq + Clone + Hash + std::fmt::Debug, NumberT: Numeric>
+    Graph<FunctionPointerT, NumberT>
+{
+    /// Build the graph of every function in `from` that can be reached by calls from the functions in `entry_pointers`.
+    ///
+    /// A block ends at its first instruction that control can't continue past, so for programs where a block "falls through" into the next,
+    /// such as [`crate::structs::FlatProgram`], the instructions that it falls through into become part of the block.
+    /// Calls to functions that `from` doesn't define are left alone.
+    pub fn from_program<
+        BlockPointerT: Eq + std::fmt::Debug + Clone,
+        ProgramT: Program<BlockPointer = BlockPointerT, FunctionPointer = FunctionPointerT, Number = NumberT>,
     >(
-        from: ProgramT,
+        from: &ProgramT,
         entry_pointers: Vec<FunctionPointerT>,
     ) -> Self {
-        todo!()
+        let mut functions = HashMap::new();
+        let mut to_visit = entry_pointers;
+
+        while let Some(function) = to_visit.pop() {
+            if functions.contains_key(&function) {
+                continue;
+            }
+            let Some(entry) = from.get_function_entry(&function) else {
+                continue;
+            };
+
+            let mut function_graph = Self::build_function(from, entry);
+            function_graph.arity = from.get_arity(&function);
+            function_graph.attributes = from.get_attributes(&function);
+            for instruction in function_graph.blocks.iter().flatten() {
+                if let Instruction::Call { function_id, .. } = instruction {
+                    to_visit.push(function_id.clone());
+                }
+            }
+            functions.insert(function, function_graph);
+        }
+
+        Self {
+            functions,
+            dominance: Mutex::default(),
+            liveness: Mutex::default(),
+            loops: Mutex::default(),
+        }
+    }
+
+    fn build_function<
+        BlockPointerT: Eq + std::fmt::Debug + Clone,
+        ProgramT: Program<BlockPointer = BlockPointerT, FunctionPointer = FunctionPointerT, Number = NumberT>,
+    >(
+        from: &ProgramT,
+        entry: BlockPointerT,
+    ) -> FunctionGraph<FunctionPointerT, NumberT> {
+        // BlockPointers are only Eq, so a linear search has to do for looking up which index a block got
+        let mut discovered = vec![entry];
+        let mut blocks = Vec::new();
+
+        while blocks.len() < discovered.len() {
+            let instructions = from.get_ir(&discovered[blocks.len()]);
+            let length = instructions
+                .iter()
+                .position(Instruction::is_terminator)
+                .map_or(instructions.len(), |end| end + 1);
+
+            let block = instructions[..length]
+                .iter()
+                .cloned()
+                .map(|instruction| {
+                    instruction.map_blocks(|to| {
+                        discovered
+                            .iter()
+                            .position(|block| *block == to)
+                            .unwrap_or_else(|| {
+                                discovered.push(to);
+                                discovered.len() - 1
+                            })
+                    })
+                })
+                .collect();
+            blocks.push(block);
+        }
+
+        FunctionGraph {
+            blocks,
+            arity: None,
+            attributes: FunctionAttributes::default(),
+        }
+    }
+
+    /// The graph of `function`, if it's part of this graph
+    pub fn function(
+        &self,
+        function: &FunctionPointerT,
+    ) -> Option<&FunctionGraph<FunctionPointerT, NumberT>> {
+        self.functions.get(function)
+    }
+
+    /// The graph of `function` for a pass to modify, if it's part of this graph.
+    ///
+    /// The analyses cached for `function` are thrown away, so they're computed again from whatever the pass leaves behind
+    pub fn function_mut(
+        &mut self,
+        function: &FunctionPointerT,
+    ) -> Option<&mut FunctionGraph<FunctionPointerT, NumberT>> {
+        forget(&mut self.dominance, function);
+        forget(&mut self.liveness, function);
+        forget(&mut self.loops, function);
+        self.functions.get_mut(function)
+    }
+
+    /// Iterate over every function in the graph
+    pub fn functions(
+        &self,
+    ) -> impl Iterator<Item = (&FunctionPointerT, &FunctionGraph<FunctionPointerT, NumberT>)> {
+        self.functions.iter()
+    }
+
+    /// Iterate over every function in the graph for a pass to modify, which throws away every cached analysis like [`Self::function_mut`]
+    pub fn functions_mut(
+        &mut self,
+    ) -> impl Iterator<
+        Item = (
+            &FunctionPointerT,
+            &mut FunctionGraph<FunctionPointerT, NumberT>,
+        ),
+    > {
+        self.invalidate_analyses();
+        self.functions.iter_mut()
+    }
+
+    /// The [`Dominance`] of `function`, if it's part of this graph, which is only computed the first time it's asked for.
+    ///
+    /// Changing a function through [`Self::function_mut`] or [`Self::functions_mut`] throws its analyses away, so they're never out of date
+    pub fn dominance(&self, function: &FunctionPointerT) -> Option<Arc<Dominance>> {
+        let function_graph = self.functions.get(function)?;
+        Some(cached(&self.dominance, function, || {
+            Dominance::of(function_graph)
+        }))
+    }
+
+    /// The [`Liveness`] of `function`, if it's part of this graph, which is cached the same way as [`Self::dominance`]
+    pub fn liveness(&self, function: &FunctionPointerT) -> Option<Arc<Liveness>> {
+        let function_graph = self.functions.get(function)?;
+        Some(cached(&self.liveness, function, || {
+            Liveness::of(function_graph)
+        }))
+    }
+
+    /// The [`LoopNest`] of `function`, if it's part of this graph, which is cached the same way as [`Self::dominance`]
+    pub fn loops(&self, function: &FunctionPointerT) -> Option<Arc<LoopNest>> {
+        let dominance = self.dominance(function)?;
+        let function_graph = self.functions.get(function)?;
+        Some(cached(&self.loops, function, || {
+            LoopNest::of(function_graph, &dominance.dominators)
+        }))
+    }
+
+    /// Throw away every cached analysis, so they're computed again from the graph as it is now
+    pub fn invalidate_analyses(&mut self) {
+        clear(&mut self.dominance);
+        clear(&mut self.liveness);
+        clear(&mut self.loops);
+    }
+
+    /// Run `pass` on this graph, invalidating the cached analyses if it reports that it changed anything
+    ///
+    /// # Errors
+    /// If the pass fails, the analyses are invalidated as well, since it may have changed the graph before failing
+    pub fn run_pass<PassT: OptimizationPass<FunctionPointerT, NumberT>>(
+        &mut self,
+        pass: &mut PassT,
+    ) -> Result<bool, PassT::Error> {
+        let result = pass.optimize_program(self);
+        if !matches!(result, Ok(false)) {
+            self.invalidate_analyses();
+        }
+        result
     }
 }
@@ -1,40 +1,67 @@
-use calc_ir::{Instruction, Program, Register};
+use calc_ir::{Instruction, Number, Program, Register};
 
 use self::structs::FlatProgram;
 use std::hash::Hash;
 
+pub mod analysis;
+pub mod dataflow;
+pub mod dot;
 pub mod graph;
 pub mod passes;
 pub mod structs;
 
-pub use graph::Graph;
+pub use graph::{FunctionGraph, Graph};
 
-pub type Block<BPT, FPT> = Vec<Instruction<BPT, FPT>>;
+#[cfg(test)]
+mod test;
+
+pub type Block<BPT, FPT, NumberT = Number> = Vec<Instruction<BPT, FPT, NumberT>>;
 
 //TODO: figure out how to apply optimizations, probably through a builder or smtn
 
-fn depends_on_register<BlockPointerT: Eq + Clone, FunctionPointerT: Eq + Clone>(
+// not used until the first passes land
+#[allow(dead_code)]
+fn depends_on_register<BlockPointerT: Eq + Clone, FunctionPointerT: Eq + Clone, NumberT>(
     register: Register,
-    instruction: &Instruction<BlockPointerT, FunctionPointerT>,
+    instruction: &Instruction<BlockPointerT, FunctionPointerT, NumberT>,
 ) -> bool {
     match instruction {
         // instructions that depend on one register, `r`
-        Instruction::LoadImmediate(_, r)
-        | Instruction::Ret(r)
-        | Instruction::JNonZero { check: r, to: _ }
-        | Instruction::JZero { check: r, to: _ } => *r == register,
+        Instruction::LoadImmediate(_, r) | Instruction::Ret(r) => *r == register,
         // instructions with a vector of registers
         Instruction::Call {
             function_id: _,
             arguments,
             out: _,
-        } => arguments.iter().any(|r| *r == register),
+        }
+        | Instruction::Jump { to: _, arguments } => arguments.contains(&register),
+        // conditional jumps depend on what they check as well as the arguments they pass
+        Instruction::JNonZero {
+            check: r,
+            to: _,
+            arguments,
+        }
+        | Instruction::JZero {
+            check: r,
+            to: _,
+            arguments,
+        } => *r == register || arguments.contains(&register),
+        Instruction::JEqual {
+            lhs,
+            rhs,
+            to: _,
+            arguments,
+        }
+        | Instruction::JNotEqual {
+            lhs,
+            rhs,
+            to: _,
+            arguments,
+        } => *lhs == register || *rhs == register || arguments.contains(&register),
         // instructions that don't depend on any register
-        Instruction::LoadArgs(_) | Instruction::Jump(_) | Instruction::Invalid => false,
+        Instruction::LoadArgs(_) | Instruction::LoadBlockArgs(_) | Instruction::Invalid => false,
         // instructions that depend on a lhs and a rhs register
-        Instruction::JEqual { lhs, rhs, to: _ }
-        | Instruction::JNotEqual { lhs, rhs, to: _ }
-        | Instruction::Add { lhs, rhs, out: _ }
+        Instruction::Add { lhs, rhs, out: _ }
         | Instruction::Subtract { lhs, rhs, out: _ }
         | Instruction::Multiply { lhs, rhs, out: _ }
         | Instruction::Divide { lhs, rhs, out: _ }
@@ -48,12 +75,14 @@ fn depends_on_register<BlockPointerT: Eq + Clone, FunctionPointerT: Eq + Clone>(
 }
 
 /// Finds all Instructions that rely on the value of a given register, returning a vector of their indexes
+#[allow(dead_code)]
 fn find_dependencies<
     BlockPointerT: Eq + std::fmt::Debug + Clone,
     FunctionPointerT: Eq + std::fmt::Debug + Clone + Hash,
+    NumberT,
 >(
     register: Register,
-    block: &Block<BlockPointerT, FunctionPointerT>,
+    block: &Block<BlockPointerT, FunctionPointerT, NumberT>,
 ) -> Vec<usize> {
     block
         .iter()
@@ -72,7 +101,7 @@ pub fn optimize_program<
     FunctionPointerT: Eq + std::fmt::Debug + Clone + Hash,
     ProgramT: Program<FunctionPointer = FunctionPointerT, BlockPointer = BlockPointerT>,
 >(
-    program: ProgramT,
-) -> FlatProgram<FunctionPointerT> {
+    _program: ProgramT,
+) -> FlatProgram<FunctionPointerT, ProgramT::Number> {
     todo!()
 }
@@ -2,34 +2,39 @@
 //!
 //! To get started making a new pass, look at [`OptimizationPass`]
 
-use crate::Graph;
-use calc_ir::Program;
+use crate::analysis::{Liveness, Loop};
+use crate::dataflow::{Constant, ConstantPropagation, Dataflow};
+use crate::{FunctionGraph, Graph};
+use calc_ir::arithmetic::Operation;
+use calc_ir::{ArithmeticMode, Instruction, Number, Numeric, Program, Register};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::{fmt::Debug, hash::Hash};
 
 /// The trait that must be implemented by a struct in order to run an optimization pass, there are example implementations in this module.
-pub trait OptimizationPass<FunctionPointerT: Eq + std::fmt::Debug + Clone + Hash> {
+pub trait OptimizationPass<FunctionPointerT: Eq + std::fmt::Debug + Clone + Hash, NumberT = Number>
+{
     /// The error type returned by this optimization pass if it fails, if it's incapable of failing, then you should consider setting it to
     /// ! or () types
     type Error: std::error::Error;
     /// Optimize a program, returning an Ok(true) if any changes were made to the program, and a [`Self::Error`] if an error occurs
     fn optimize_program(
         &mut self,
-        program: &mut Graph<FunctionPointerT>,
+        program: &mut Graph<FunctionPointerT, NumberT>,
     ) -> Result<bool, Self::Error>;
     //TODO: a way to request/require being run after other OptimizationPasses
 }
 
 /// A pass run at the end of an optimization pipeline to lower a Graph to a Program
-pub trait SolidifyingPass<FunctionPointerT: Eq + std::fmt::Debug + Clone + Hash> {
+pub trait SolidifyingPass<FunctionPointerT: Eq + std::fmt::Debug + Clone + Hash, NumberT = Number> {
     /// The error returned by the pass if it fails
     type Error: std::error::Error;
     /// The actual Program that is returned by the pass
-    type SolidProgram: Program<FunctionPointer = FunctionPointerT>;
+    type SolidProgram: Program<FunctionPointer = FunctionPointerT, Number = NumberT>;
 
     /// A function that either lowers the graph to a real program, or returns an error describing what went wrong
     fn soldify_program(
         &mut self,
-        program: Graph<FunctionPointerT>,
+        program: Graph<FunctionPointerT, NumberT>,
     ) -> Result<Self::SolidProgram, Self::Error>;
 }
 
@@ -74,15 +79,447 @@ impl std::error::Error for NeverErrors {}
 /// Eliminates as much dead code as possible, we recommend running this near the beginning of optimization in order to cut out cruft before other passes look at the Program
 pub struct DeadCodeElimination();
 
-impl<FunctionPointerT: Eq + std::fmt::Debug + Clone + Hash> OptimizationPass<FunctionPointerT>
-    for DeadCodeElimination
+impl<FunctionPointerT: Eq + std::fmt::Debug + Clone + Hash, NumberT>
+    OptimizationPass<FunctionPointerT, NumberT> for DeadCodeElimination
 {
     type Error = NeverErrors;
 
     fn optimize_program(
         &mut self,
-        program: &mut Graph<FunctionPointerT>,
+        _program: &mut Graph<FunctionPointerT, NumberT>,
     ) -> Result<bool, Self::Error> {
         todo!()
     }
 }
+
+/// Replaces arithmetic on registers that are known to be constant with the result of the arithmetic.
+///
+/// Results are computed with [`calc_ir::arithmetic::Operation::evaluate`] in the same [`ArithmeticMode`] that the program will be run with,
+/// so folding gives the same result as the interpreter would. Operations that would fail are left in the program, so they still fail when it's run.
+///
+/// This relies on registers only being assigned to once
+pub struct ConstantFolding {
+    pub arithmetic: ArithmeticMode,
+}
+
+impl<FunctionPointerT: Eq + std::fmt::Debug + Clone + Hash, NumberT: Numeric>
+    OptimizationPass<FunctionPointerT, NumberT> for ConstantFolding
+{
+    type Error = NeverErrors;
+
+    fn optimize_program(
+        &mut self,
+        program: &mut Graph<FunctionPointerT, NumberT>,
+    ) -> Result<bool, Self::Error> {
+        let mut changed = false;
+
+        for (_, function) in program.functions_mut() {
+            let mut constants: HashMap<Register, NumberT> = HashMap::new();
+
+            // folding an instruction can make another constant that's earlier in the function if blocks jump backwards, so repeat until nothing changes
+            loop {
+                let mut folded = false;
+                for instruction in function.blocks.iter_mut().flatten() {
+                    if let Instruction::LoadImmediate(value, out) = instruction {
+                        constants.insert(*out, value.clone());
+                        continue;
+                    }
+
+                    let Some((operation, lhs, rhs, out)) = instruction.operation() else {
+                        continue;
+                    };
+                    let (Some(lhs), Some(rhs)) = (constants.get(&lhs), constants.get(&rhs)) else {
+                        continue;
+                    };
+                    if let Ok(value) = operation.evaluate(lhs, rhs, self.arithmetic) {
+                        *instruction = Instruction::LoadImmediate(value.clone(), out);
+                        constants.insert(out, value);
+                        folded = true;
+                    }
+                }
+
+                if !folded {
+                    break;
+                }
+                changed = true;
+            }
+        }
+
+        Ok(changed)
+    }
+}
+
+/// Removes calls to functions with the [`calc_ir::FunctionAttributes::pure`] attribute that repeat an earlier call in the same block with the
+/// same arguments, and reads the result of the earlier call instead.
+///
+/// The earlier call runs first and fails the same way the repeated one would, so nothing that was an error stops being one.
+/// This relies on registers only being assigned to once, so that the same registers hold the same values at both calls
+pub struct PureCallElimination();
+
+impl<FunctionPointerT: Eq + std::fmt::Debug + Clone + Hash, NumberT: Numeric>
+    OptimizationPass<FunctionPointerT, NumberT> for PureCallElimination
+{
+    type Error = NeverErrors;
+
+    fn optimize_program(
+        &mut self,
+        program: &mut Graph<FunctionPointerT, NumberT>,
+    ) -> Result<bool, Self::Error> {
+        let pure: HashSet<FunctionPointerT> = program
+            .functions()
+            .filter(|(_, function)| function.attributes.pure)
+            .map(|(function, _)| function.clone())
+            .collect();
+        let mut changed = false;
+
+        for (_, function) in program.functions_mut() {
+            let mut renamed: HashMap<Register, Register> = HashMap::new();
+            for block in &mut function.blocks {
+                let mut calls: Vec<(FunctionPointerT, Vec<Register>, Register)> = Vec::new();
+                block.retain(|instruction| {
+                    let Instruction::Call {
+                        function_id,
+                        arguments,
+                        out,
+                    } = instruction
+                    else {
+                        return true;
+                    };
+                    if !pure.contains(function_id) {
+                        return true;
+                    }
+                    // an argument may be the result of a call that was removed already
+                    let arguments: Vec<Register> = arguments
+                        .iter()
+                        .map(|register| *renamed.get(register).unwrap_or(register))
+                        .collect();
+                    if let Some((_, _, earlier)) = calls
+                        .iter()
+                        .find(|(callee, earlier, _)| callee == function_id && *earlier == arguments)
+                    {
+                        renamed.insert(*out, *earlier);
+                        return false;
+                    }
+                    calls.push((function_id.clone(), arguments, *out));
+                    true
+                });
+            }
+
+            if renamed.is_empty() {
+                continue;
+            }
+            changed = true;
+            for instruction in function.blocks.iter_mut().flatten() {
+                *instruction = instruction
+                    .clone()
+                    .map_registers(|register| *renamed.get(&register).unwrap_or(&register));
+            }
+        }
+
+        Ok(changed)
+    }
+}
+
+/// Renumbers the registers of every function densely from 0, so that running a function doesn't need registers that nothing uses.
+///
+/// Registers that are live somewhere come first in the order they first appear, and registers that are never live, because nothing reads
+/// what's written to them, are numbered after them. Every register keeps its own number, so this only closes the gaps between them and
+/// doesn't reuse the register of a value that's no longer live. Registers are still only assigned to once afterwards, so it can run before
+/// or after any other pass
+pub struct RegisterRenumbering();
+
+impl<FunctionPointerT: Eq + std::fmt::Debug + Clone + Hash, NumberT: Numeric>
+    OptimizationPass<FunctionPointerT, NumberT> for RegisterRenumbering
+{
+    type Error = NeverErrors;
+
+    fn optimize_program(
+        &mut self,
+        program: &mut Graph<FunctionPointerT, NumberT>,
+    ) -> Result<bool, Self::Error> {
+        let mut changed = false;
+        let functions: Vec<_> = program
+            .functions()
+            .map(|(function, _)| function.clone())
+            .collect();
+
+        for function in functions {
+            let (Some(liveness), Some(function_graph)) =
+                (program.liveness(&function), program.function(&function))
+            else {
+                continue;
+            };
+            // every register that's read is live right before the instruction that reads it
+            let live: HashSet<Register> = (0..function_graph.blocks.len())
+                .flat_map(|block| liveness.before_each(function_graph, block))
+                .flatten()
+                .collect();
+
+            let mut numbers: HashMap<Register, Register> = HashMap::new();
+            let mut dead: Vec<Register> = Vec::new();
+            for register in function_graph
+                .blocks
+                .iter()
+                .flatten()
+                .flat_map(|instruction| instruction.reads().into_iter().chain(instruction.writes()))
+            {
+                if !live.contains(&register) {
+                    if !dead.contains(&register) {
+                        dead.push(register);
+                    }
+                } else if !numbers.contains_key(&register) {
+                    numbers.insert(register, Register(numbers.len()));
+                }
+            }
+            for register in dead {
+                numbers.insert(register, Register(numbers.len()));
+            }
+
+            if numbers.iter().all(|(from, to)| from == to) {
+                continue;
+            }
+            changed = true;
+            let function_graph = program
+                .function_mut(&function)
+                .expect("the function was part of the graph a moment ago");
+            for instruction in function_graph.blocks.iter_mut().flatten() {
+                *instruction = instruction
+                    .clone()
+                    .map_registers(|register| numbers[&register]);
+            }
+        }
+
+        Ok(changed)
+    }
+}
+
+/// Hoists instructions that compute the same value on every iteration of a loop out of it, into a preheader block that runs once before
+/// the loop is entered and that every jump into the loop from outside of it goes through instead.
+///
+/// Only immediates and arithmetic are hoisted, once their operands aren't written to anywhere in the loop, and only if they're the single
+/// instruction writing to their register and every read of their operands and result comes after a write. Since a hoisted instruction runs
+/// even when the loop wouldn't have gotten to it, operations that can fail in [`Self::arithmetic`] are only hoisted when [`ConstantPropagation`]
+/// shows they won't, which lets a division or modulo by a known non-zero constant through as long as it isn't [`ArithmeticMode::Checked`].
+///
+/// Inner loops are done first, so what's hoisted out of them can be hoisted further out of the loops around them.
+/// Loops headed by the entry block of a function are left alone, since nothing jumps into them that a preheader could go in front of
+pub struct LoopInvariantCodeMotion {
+    pub arithmetic: ArithmeticMode,
+}
+
+impl LoopInvariantCodeMotion {
+    /// Whether running `instruction` where it may not have run before can't make the program fail, given the `constants` before it
+    fn cannot_fail<FunctionPointerT: Eq + Clone, NumberT: Numeric>(
+        &self,
+        instruction: &Instruction<usize, FunctionPointerT, NumberT>,
+        constants: &BTreeMap<Register, Constant<NumberT>>,
+    ) -> bool {
+        let Some((operation, lhs, rhs, _)) = instruction.operation() else {
+            return matches!(instruction, Instruction::LoadImmediate(..));
+        };
+        if !operation.can_fail::<NumberT>(self.arithmetic) {
+            return true;
+        }
+        match (constants.get(&lhs), constants.get(&rhs)) {
+            (Some(Constant::Known(lhs)), Some(Constant::Known(rhs))) => {
+                operation.evaluate(lhs, rhs, self.arithmetic).is_ok()
+            }
+            // dividing by anything but zero can only overflow, which is only an error when it's checked
+            (_, Some(Constant::Known(rhs))) => {
+                matches!(operation, Operation::Divide | Operation::Modulo)
+                    && self.arithmetic != ArithmeticMode::Checked
+                    && !rhs.is_zero()
+            }
+            _ => false,
+        }
+    }
+
+    /// The instructions to hoist out of `natural_loop`, as their block and index, in the order they have to run in
+    fn invariants<FunctionPointerT: Eq + Debug + Clone + Hash, NumberT: Numeric>(
+        &self,
+        function: &FunctionGraph<FunctionPointerT, NumberT>,
+        natural_loop: &Loop,
+        liveness: &Liveness,
+        constants: &Dataflow<BTreeMap<Register, Constant<NumberT>>>,
+    ) -> Vec<(usize, usize)> {
+        let analysis = ConstantPropagation {
+            arithmetic: self.arithmetic,
+        };
+        // registers read before they're written to may be unset when the loop is entered
+        let unset = liveness.live_in(FunctionGraph::<FunctionPointerT, NumberT>::ENTRY);
+        let mut writes: HashMap<Register, usize> = HashMap::new();
+        for register in function
+            .blocks
+            .iter()
+            .flatten()
+            .flat_map(Instruction::writes)
+        {
+            *writes.entry(register).or_default() += 1;
+        }
+        let written_in_loop: HashSet<Register> = natural_loop
+            .blocks
+            .iter()
+            .flat_map(|block| &function.blocks[*block])
+            .flat_map(Instruction::writes)
+            .collect();
+        let states: HashMap<usize, _> = natural_loop
+            .blocks
+            .iter()
+            .map(|block| {
+                (
+                    *block,
+                    constants.per_instruction(&analysis, function, *block),
+                )
+            })
+            .collect();
+
+        let mut hoisted = Vec::new();
+        let mut hoisted_writes = HashSet::new();
+        // an instruction can become invariant once what it reads is hoisted, so keep going until nothing else is
+        loop {
+            let count = hoisted.len();
+            for &block in &natural_loop.blocks {
+                for (index, instruction) in function.blocks[block].iter().enumerate() {
+                    let [out] = instruction.writes()[..] else {
+                        continue;
+                    };
+                    let invariant = instruction.reads().iter().all(|register| {
+                        !unset.contains(register)
+                            && (!written_in_loop.contains(register)
+                                || hoisted_writes.contains(register))
+                    });
+                    if invariant
+                        && writes[&out] == 1
+                        && !unset.contains(&out)
+                        && !hoisted.contains(&(block, index))
+                        && self.cannot_fail(instruction, &states[&block][index])
+                    {
+                        hoisted.push((block, index));
+                        hoisted_writes.insert(out);
+                    }
+                }
+            }
+            if hoisted.len() == count {
+                return hoisted;
+            }
+        }
+    }
+
+    /// Move the `hoisted` instructions of the loop with `header` and `blocks` into a new preheader at the end of the blocks of `function`
+    fn hoist<FunctionPointerT: Eq + Debug + Clone + Hash, NumberT: Clone>(
+        function: &mut FunctionGraph<FunctionPointerT, NumberT>,
+        header: usize,
+        blocks: &BTreeSet<usize>,
+        hoisted: &[(usize, usize)],
+    ) {
+        let preheader = function.blocks.len();
+        let mut instructions = Vec::new();
+        // the preheader takes the arguments of the jumps into the loop in registers of its own, and passes them on
+        let mut arguments = Vec::new();
+        if let Some(Instruction::LoadBlockArgs(loaded)) = function.blocks[header].first() {
+            let next = function
+                .blocks
+                .iter()
+                .flatten()
+                .flat_map(|instruction| instruction.reads().into_iter().chain(instruction.writes()))
+                .map(|register| register.0 + 1)
+                .max()
+                .unwrap_or(0);
+            arguments = (next..next + loaded.len()).map(Register).collect();
+            instructions.push(Instruction::LoadBlockArgs(arguments.clone()));
+        }
+        instructions.extend(
+            hoisted
+                .iter()
+                .map(|(block, index)| function.blocks[*block][*index].clone()),
+        );
+        instructions.push(Instruction::Jump {
+            to: header,
+            arguments,
+        });
+
+        let mut removed: Vec<_> = hoisted.to_vec();
+        removed.sort_unstable();
+        for (block, index) in removed.into_iter().rev() {
+            function.blocks[block].remove(index);
+        }
+        for (block, instructions) in function.blocks.iter_mut().enumerate() {
+            if blocks.contains(&block) {
+                continue;
+            }
+            for instruction in instructions {
+                if instruction
+                    .jump_target()
+                    .is_some_and(|(to, _)| *to == header)
+                {
+                    *instruction =
+                        instruction
+                            .clone()
+                            .map_blocks(|to| if to == header { preheader } else { to });
+                }
+            }
+        }
+        function.blocks.push(instructions);
+    }
+}
+
+impl<FunctionPointerT: Eq + std::fmt::Debug + Clone + Hash, NumberT: Numeric>
+    OptimizationPass<FunctionPointerT, NumberT> for LoopInvariantCodeMotion
+{
+    type Error = NeverErrors;
+
+    fn optimize_program(
+        &mut self,
+        program: &mut Graph<FunctionPointerT, NumberT>,
+    ) -> Result<bool, Self::Error> {
+        let mut changed = false;
+        let functions: Vec<_> = program
+            .functions()
+            .map(|(function, _)| function.clone())
+            .collect();
+
+        for function in functions {
+            // hoist out of one loop at a time, since a preheader changes the loops around it
+            while let (Some(loops), Some(liveness), Some(function_graph)) = (
+                program.loops(&function),
+                program.liveness(&function),
+                program.function(&function),
+            ) {
+                let constants = Dataflow::solve(
+                    &ConstantPropagation {
+                        arithmetic: self.arithmetic,
+                    },
+                    function_graph,
+                );
+                let Some((natural_loop, hoisted)) = loops
+                    .loops()
+                    .iter()
+                    .rev()
+                    .filter(|natural_loop| {
+                        natural_loop.header != FunctionGraph::<FunctionPointerT, NumberT>::ENTRY
+                    })
+                    .find_map(|natural_loop| {
+                        let hoisted =
+                            self.invariants(function_graph, natural_loop, &liveness, &constants);
+                        (!hoisted.is_empty()).then_some((natural_loop, hoisted))
+                    })
+                else {
+                    break;
+                };
+
+                let function_graph = program
+                    .function_mut(&function)
+                    .expect("the function was part of the graph a moment ago");
+                Self::hoist(
+                    function_graph,
+                    natural_loop.header,
+                    &natural_loop.blocks,
+                    &hoisted,
+                );
+                changed = true;
+            }
+        }
+
+        Ok(changed)
+    }
+}
@@ -1,39 +1,108 @@
 use std::collections::HashMap;
 use std::hash::Hash;
 
-use calc_ir::{Instruction, Program};
+use calc_ir::zir::{FunctionEntry, Layout, Module, ZirError};
+use calc_ir::{Instruction, Number, Numeric, Program};
 
-pub struct FlatProgram<FunctionPointerT: Eq + std::fmt::Debug + Clone + Hash> {
+pub struct FlatProgram<FunctionPointerT: Eq + std::fmt::Debug + Clone + Hash, NumberT = Number> {
     function_pointer_map: HashMap<FunctionPointerT, usize>,
-    all_instructions: Vec<Instruction<usize, FunctionPointerT>>,
+    all_instructions: Vec<Instruction<usize, FunctionPointerT, NumberT>>,
 }
 
-impl<FunctionPointerT: Eq + std::fmt::Debug + Clone + Hash> Program
-    for FlatProgram<FunctionPointerT>
+impl<FunctionPointerT: Eq + std::fmt::Debug + Clone + Hash, NumberT: Numeric> Program
+    for FlatProgram<FunctionPointerT, NumberT>
 {
     type BlockPointer = usize;
 
     type FunctionPointer = FunctionPointerT;
 
+    type Number = NumberT;
+
     fn get_function_entry(
         &self,
         function_id: &Self::FunctionPointer,
     ) -> Option<Self::BlockPointer> {
-        self.function_pointer_map.get(function_id).map(Clone::clone)
+        self.function_pointer_map.get(function_id).copied()
     }
 
     fn get_ir(
         &self,
         block_id: &Self::BlockPointer,
-    ) -> &[calc_ir::Instruction<Self::BlockPointer, Self::FunctionPointer>] {
+    ) -> &[calc_ir::Instruction<Self::BlockPointer, Self::FunctionPointer, Self::Number>] {
         &self.all_instructions[*block_id..self.all_instructions.len()]
     }
 
-    fn get_all_functions<'a>(&'a self) -> Vec<(&'a Self::FunctionPointer, &'a Self::BlockPointer)> {
+    fn get_all_functions(&self) -> Vec<(&Self::FunctionPointer, &Self::BlockPointer)> {
         self.function_pointer_map.iter().collect()
     }
 }
 
+impl<NumberT: Numeric> FlatProgram<String, NumberT> {
+    /// This program as a [`Module`] with [`Layout::Flat`], with its functions sorted by name so that the same program is always
+    /// written the same way
+    #[must_use]
+    pub fn to_module(&self) -> Module<NumberT> {
+        let mut functions: Vec<_> = self
+            .function_pointer_map
+            .iter()
+            .map(|(name, entry)| FunctionEntry {
+                name: name.clone(),
+                entry: *entry,
+                arity: None,
+                register_count: None,
+                attributes: calc_ir::FunctionAttributes::default(),
+            })
+            .collect();
+        functions.sort_by(|a, b| a.name.cmp(&b.name));
+        Module {
+            layout: Layout::Flat,
+            functions,
+            blocks: vec![self.all_instructions.clone()],
+        }
+    }
+
+    /// Write this program as a `.zir` file, see [`Self::to_module`]
+    #[must_use]
+    pub fn to_zir(&self) -> Vec<u8> {
+        self.to_module().to_bytes()
+    }
+
+    /// Build a program from a [`Module`] with [`Layout::Flat`], everything about its functions other than their entries is dropped
+    ///
+    /// # Errors
+    /// [`ZirError::WrongLayout`] if the module has [`Layout::Blocks`], or any other [`ZirError`] if it isn't valid
+    pub fn from_module(module: Module<NumberT>) -> Result<Self, ZirError> {
+        if module.layout != Layout::Flat {
+            return Err(ZirError::WrongLayout {
+                expected: Layout::Flat,
+                found: module.layout,
+            });
+        }
+        module.check_blocks()?;
+        let mut function_pointer_map = HashMap::new();
+        for function in module.functions {
+            if function_pointer_map
+                .insert(function.name.clone(), function.entry)
+                .is_some()
+            {
+                return Err(ZirError::DuplicateFunction(function.name));
+            }
+        }
+        Ok(Self {
+            function_pointer_map,
+            all_instructions: module.blocks.into_iter().flatten().collect(),
+        })
+    }
+
+    /// Read a program from a `.zir` file with [`Layout::Flat`]
+    ///
+    /// # Errors
+    /// If the file is malformed, or has a different layout, see [`ZirError`]
+    pub fn from_zir(bytes: &[u8]) -> Result<Self, ZirError> {
+        Self::from_module(Module::from_bytes(bytes)?)
+    }
+}
+
 /*
 
 <
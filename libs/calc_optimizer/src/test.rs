@@ -0,0 +1,1216 @@
+use crate::passes::{ConstantFolding, OptimizationPass};
+use crate::{FunctionGraph, Graph};
+use calc_ir::builder::{
+    instructions::{Arithmetic, BitWise, BlockJump},
+    Program,
+};
+use calc_ir::{ArithmeticMode, FunctionAttributes, Instruction, Register};
+
+/// a function with a loop, where the loop block jumps back to itself
+fn looping_program() -> calc_ir::program::implementations::BasicProgram {
+    let mut builder = Program::new();
+    let mut main_function = builder.make_fn("main".to_string());
+
+    let loop_block_id = main_function.reserve_block();
+
+    let mut entry_block = main_function.build_block();
+    let zero = entry_block.add_immediate(0);
+    entry_block.add_cond_jump_with_args(BlockJump::Unconditional, loop_block_id, vec![zero]);
+    let (entry_block_id, main_function) = entry_block.finalize();
+
+    let mut loop_block = main_function.build_reserved_block(loop_block_id);
+    let counter = loop_block.add_block_params(1)[0];
+    loop_block.add_cond_jump_with_args(BlockJump::NoneZero(counter), loop_block_id, vec![counter]);
+    loop_block.add_fn_call("helper".to_string(), vec![counter]);
+    loop_block.add_ret(counter);
+    let (_, main_function) = loop_block.finalize();
+    let builder = main_function.finalize(entry_block_id);
+
+    let mut helper = builder.make_fn("helper".to_string());
+    helper.set_attributes(FunctionAttributes {
+        noinline: true,
+        ..FunctionAttributes::default()
+    });
+    let mut entry_block = helper.build_block();
+    let one = entry_block.add_immediate(1);
+    entry_block.add_ret(one);
+    let (entry_block_id, helper) = entry_block.finalize();
+    let builder = helper.finalize(entry_block_id);
+
+    builder.finalize()
+}
+
+#[test]
+fn graph_from_program() {
+    let program = looping_program();
+    let graph = Graph::from_program(&program, vec!["main".to_string()]);
+
+    // helper is reachable through a call, and keeps its metadata
+    let helper = graph.function(&"helper".to_string()).unwrap();
+    assert!(helper.attributes.noinline);
+    assert_eq!(helper.arity, Some(0));
+
+    let main = graph.function(&"main".to_string()).unwrap();
+    assert_eq!(main.blocks.len(), 2);
+    assert_eq!(main.successors(FunctionGraph::<String>::ENTRY), vec![1]);
+    assert_eq!(main.successors(1), vec![1]);
+    assert_eq!(main.predecessors(1), vec![0, 1]);
+    assert_eq!(
+        main.blocks[0].last(),
+        Some(&Instruction::Jump {
+            to: 1,
+            arguments: vec![calc_ir::Register(0)]
+        })
+    );
+}
+
+#[test]
+fn graph_skips_unreachable_functions() {
+    let program = looping_program();
+    let graph = Graph::from_program(&program, vec!["helper".to_string()]);
+
+    assert!(graph.function(&"main".to_string()).is_none());
+    assert_eq!(graph.functions().count(), 1);
+}
+
+/// a function that returns `(MAX + 1) << 1`, which overflows differently in every mode
+fn overflowing_program() -> calc_ir::program::implementations::BasicProgram {
+    let mut builder = Program::new();
+    let mut main_function = builder.make_fn("main".to_string());
+
+    let mut entry_block = main_function.build_block();
+    let max = entry_block.add_immediate(isize::MAX);
+    let one = entry_block.add_immediate(1);
+    let sum = entry_block.add_arithmetic(Arithmetic::Add, max, one);
+    let shifted = entry_block.add_bitwise(BitWise::ShiftLeft, sum, one);
+    entry_block.add_ret(shifted);
+    let (entry_block_id, main_function) = entry_block.finalize();
+    let builder = main_function.finalize(entry_block_id);
+
+    builder.finalize()
+}
+
+/// constant folding gives the same result as interpreting the unoptimized program in every mode
+#[test]
+fn constant_folding_matches_interpreter() {
+    for mode in [ArithmeticMode::Wrapping, ArithmeticMode::Saturating] {
+        let program = overflowing_program();
+        let mut graph = Graph::from_program(&program, vec!["main".to_string()]);
+
+        let changed = ConstantFolding { arithmetic: mode }
+            .optimize_program(&mut graph)
+            .unwrap();
+        assert!(changed);
+
+        let interpreted = calc_interpreter::Interpreter::new(&program)
+            .with_arithmetic(mode)
+            .interpret(&"main".to_string(), &[])
+            .unwrap();
+
+        let main = graph.function(&"main".to_string()).unwrap();
+        assert_eq!(
+            main.blocks[0][3],
+            Instruction::LoadImmediate(interpreted, Register(3))
+        );
+    }
+}
+
+/// operations that fail are left for the program to fail on when it's run
+#[test]
+fn constant_folding_leaves_errors() {
+    let program = overflowing_program();
+    let mut graph = Graph::from_program(&program, vec!["main".to_string()]);
+    let before = graph.function(&"main".to_string()).unwrap().clone();
+
+    let changed = ConstantFolding {
+        arithmetic: ArithmeticMode::Checked,
+    }
+    .optimize_program(&mut graph)
+    .unwrap();
+
+    assert!(!changed);
+    assert_eq!(graph.function(&"main".to_string()), Some(&before));
+}
+
+/// flat programs can only be built by reading them, and they're written back the same way
+#[test]
+fn flat_program_zir() {
+    use crate::structs::FlatProgram;
+    use calc_ir::program::implementations::BasicProgram;
+    use calc_ir::zir::{FunctionEntry, Layout, Module, ZirError};
+
+    let function = |name: &str, entry| FunctionEntry {
+        name: name.to_string(),
+        entry,
+        arity: None,
+        register_count: None,
+        attributes: FunctionAttributes::default(),
+    };
+    let module = Module {
+        layout: Layout::Flat,
+        functions: vec![function("double", 0), function("main", 3)],
+        blocks: vec![vec![
+            Instruction::LoadArgs(vec![Register(0)]),
+            Instruction::Add {
+                lhs: Register(0),
+                rhs: Register(0),
+                out: Register(1),
+            },
+            Instruction::Ret(Register(1)),
+            Instruction::LoadImmediate(21, Register(0)),
+            Instruction::Call {
+                function_id: "double".to_string(),
+                arguments: vec![Register(0)],
+                out: Register(1),
+            },
+            Instruction::JNonZero {
+                check: Register(1),
+                to: 7,
+                arguments: Vec::new(),
+            },
+            Instruction::Invalid,
+            Instruction::Ret(Register(1)),
+        ]],
+    };
+
+    let program = FlatProgram::from_zir(&module.to_bytes()).unwrap();
+    assert_eq!(program.to_module(), module);
+    assert_eq!(
+        calc_interpreter::interpret_function(&"main".to_string(), &program, &[]),
+        Ok(42)
+    );
+
+    assert_eq!(
+        BasicProgram::<isize>::from_zir(&program.to_zir()).err(),
+        Some(ZirError::WrongLayout {
+            expected: Layout::Blocks,
+            found: Layout::Flat
+        })
+    );
+    let mut split = module.clone();
+    split.blocks.push(Vec::new());
+    assert_eq!(
+        FlatProgram::from_module(split).err(),
+        Some(ZirError::NotFlat { blocks: 2 })
+    );
+    let mut outside = module;
+    outside.functions.push(function("outside", 8));
+    assert_eq!(
+        FlatProgram::<String, isize>::from_zir(&outside.to_bytes()).err(),
+        Some(ZirError::BlockOutOfRange { block: 8 })
+    );
+}
+
+#[test]
+fn dot_export() {
+    use crate::dot::{call_graph, control_flow, program_call_graph};
+
+    let program = looping_program();
+    let graph = Graph::from_program(&program, vec!["main".to_string()]);
+
+    let cfg = control_flow(&graph);
+    assert!(cfg.starts_with("digraph control_flow {\n"));
+    assert!(cfg.contains("    subgraph cluster_0 {\n        label=\"helper\";\n"));
+    assert!(cfg.contains("        f1_b0 -> f1_b1 [label=\"jump\"];\n"));
+    assert!(cfg.contains("        f1_b1 -> f1_b1 [label=\"taken\"];\n"));
+    // names in instructions are escaped
+    assert!(cfg.contains("Call { function_id: \\\"helper\\\""));
+    assert_eq!(cfg.matches('{').count(), cfg.matches('}').count());
+
+    assert_eq!(
+        call_graph(&graph),
+        "digraph calls {\n    node [shape=ellipse, fontname=monospace];\n    \"helper\";\n    \"main\";\n    \
+         \"main\" -> \"helper\" [label=\"1\"];\n}\n"
+    );
+
+    let mut builder = Program::new();
+    let mut main_function = builder.make_fn("main".to_string());
+    let mut entry_block = main_function.build_block();
+    let one = entry_block.add_immediate(1);
+    let first = entry_block.add_fn_call("native".to_string(), vec![one]);
+    let second = entry_block.add_fn_call("native".to_string(), vec![first]);
+    entry_block.add_ret(second);
+    let (entry_block_id, main_function) = entry_block.finalize();
+    let program = main_function.finalize(entry_block_id).finalize();
+    assert_eq!(
+        program_call_graph(&program),
+        "digraph calls {\n    node [shape=ellipse, fontname=monospace];\n    \"main\";\n    \"native\" [style=dashed];\n    \
+         \"main\" -> \"native\" [label=\"2\"];\n}\n"
+    );
+}
+
+/// a diamond into a block that loops on itself and then returns, along with a block that only jumps to itself and can't be reached
+fn diamond_and_loop() -> FunctionGraph<String> {
+    let jump = |to| Instruction::Jump {
+        to,
+        arguments: Vec::new(),
+    };
+    FunctionGraph {
+        blocks: vec![
+            vec![
+                Instruction::JZero {
+                    check: Register(0),
+                    to: 2,
+                    arguments: Vec::new(),
+                },
+                jump(1),
+            ],
+            vec![jump(3)],
+            vec![jump(3)],
+            vec![
+                Instruction::JNonZero {
+                    check: Register(0),
+                    to: 3,
+                    arguments: Vec::new(),
+                },
+                jump(4),
+            ],
+            vec![Instruction::Ret(Register(0))],
+            vec![jump(5)],
+        ],
+        arity: None,
+        attributes: FunctionAttributes::default(),
+    }
+}
+
+#[test]
+fn dominators() {
+    use crate::analysis::DominatorTree;
+
+    let tree = DominatorTree::dominators(&diamond_and_loop());
+    assert_eq!(tree.root(), 0);
+    assert_eq!(
+        (0..6)
+            .map(|block| tree.immediate(block))
+            .collect::<Vec<_>>(),
+        [None, Some(0), Some(0), Some(0), Some(3), None]
+    );
+    assert_eq!(tree.reverse_postorder()[0], 0);
+    assert_eq!(tree.reverse_postorder().len(), 5);
+    assert!(!tree.contains(5));
+    assert!(tree.dominates(0, 4));
+    assert!(tree.dominates(3, 3));
+    assert!(!tree.dominates(1, 3));
+    assert!(!tree.dominates(0, 5));
+    assert_eq!(tree.children(0), [1, 2, 3]);
+
+    assert_eq!(tree.frontier(0), [] as [usize; 0]);
+    assert_eq!(tree.frontier(1), [3]);
+    assert_eq!(tree.frontier(2), [3]);
+    // the loop back to itself puts a block in its own frontier
+    assert_eq!(tree.frontier(3), [3]);
+    assert_eq!(tree.frontier(4), [] as [usize; 0]);
+}
+
+#[test]
+fn post_dominators() {
+    use crate::analysis::DominatorTree;
+
+    let tree = DominatorTree::post_dominators(&diamond_and_loop());
+    assert_eq!(tree.root(), 6);
+    assert_eq!(
+        (0..7)
+            .map(|block| tree.immediate(block))
+            .collect::<Vec<_>>(),
+        [Some(3), Some(3), Some(3), Some(4), Some(6), None, None]
+    );
+    // the infinite loop never leaves the function
+    assert!(!tree.contains(5));
+    assert!(tree.dominates(3, 0));
+    assert!(!tree.dominates(1, 0));
+
+    // which blocks decide whether a block runs
+    assert_eq!(tree.frontier(1), [0]);
+    assert_eq!(tree.frontier(2), [0]);
+    assert_eq!(tree.frontier(3), [3]);
+    assert_eq!(tree.frontier(0), [] as [usize; 0]);
+}
+
+/// analyses are computed once, and recomputed after a pass changes the graph, which can be shared between threads
+#[test]
+fn cached_dominance() {
+    use std::sync::Arc;
+
+    fn shareable<T: Send + Sync>(_: &T) {}
+
+    let program = overflowing_program();
+    let mut graph = Graph::from_program(&program, vec!["main".to_string()]);
+    let main = "main".to_string();
+
+    let first = graph.dominance(&main).unwrap();
+    assert!(Arc::ptr_eq(&first, &graph.dominance(&main).unwrap()));
+    assert!(graph.dominance(&"missing".to_string()).is_none());
+
+    // a pass that leaves every function alone keeps the analyses
+    let mut renumbering = crate::passes::RegisterRenumbering();
+    assert_eq!(graph.run_pass(&mut renumbering).ok(), Some(false));
+    assert!(Arc::ptr_eq(&first, &graph.dominance(&main).unwrap()));
+
+    // borrowing every function mutably throws them away, even if nothing ends up changing
+    let mut checked = ConstantFolding {
+        arithmetic: ArithmeticMode::Checked,
+    };
+    assert_eq!(graph.run_pass(&mut checked).ok(), Some(false));
+    let second = graph.dominance(&main).unwrap();
+    assert!(!Arc::ptr_eq(&first, &second));
+    assert_eq!(*first, *second);
+
+    let mut wrapping = ConstantFolding {
+        arithmetic: ArithmeticMode::Wrapping,
+    };
+    assert_eq!(graph.run_pass(&mut wrapping).ok(), Some(true));
+    let second = graph.dominance(&main).unwrap();
+    assert_eq!(*first, *second);
+
+    shareable(&graph);
+    std::thread::scope(|scope| {
+        let graph = &graph;
+        let other = scope.spawn(|| graph.dominance(&"main".to_string()).unwrap());
+        assert!(Arc::ptr_eq(&second, &other.join().unwrap()));
+    });
+}
+
+/// changing a function through the graph throws away its analyses, so none of them are ever out of date
+#[test]
+fn mutable_access_invalidates() {
+    use crate::analysis::{Dominance, Liveness, LoopNest};
+
+    let mut graph = Graph::from_program(&overflowing_program(), vec!["main".to_string()]);
+    let main = "main".to_string();
+    assert!(graph.loops(&main).unwrap().loops().is_empty());
+    let liveness = graph.liveness(&main).unwrap();
+
+    let sparse = sparse_registers();
+    *graph.function_mut(&main).unwrap() = sparse.clone();
+    assert_ne!(*graph.liveness(&main).unwrap(), *liveness);
+    assert_eq!(*graph.liveness(&main).unwrap(), Liveness::of(&sparse));
+    assert_eq!(*graph.dominance(&main).unwrap(), Dominance::of(&sparse));
+    assert_eq!(graph.loops(&main).unwrap().loops().len(), 1);
+
+    // without the jump back to its start, block 2 isn't a loop any more
+    for (_, function) in graph.functions_mut() {
+        function.blocks[2].remove(2);
+    }
+    let function = graph.function(&main).unwrap();
+    let dominance = Dominance::of(function);
+    assert_eq!(
+        *graph.loops(&main).unwrap(),
+        LoopNest::of(function, &dominance.dominators)
+    );
+    assert!(graph.loops(&main).unwrap().loops().is_empty());
+    assert_eq!(*graph.liveness(&main).unwrap(), Liveness::of(function));
+}
+
+/// a function that reads a register nothing writes to, writes one that nothing reads, and has a loop
+fn sparse_registers() -> FunctionGraph<String> {
+    FunctionGraph {
+        blocks: vec![
+            vec![
+                Instruction::LoadArgs(vec![Register(0), Register(5)]),
+                Instruction::JZero {
+                    check: Register(0),
+                    to: 1,
+                    arguments: Vec::new(),
+                },
+                Instruction::Jump {
+                    to: 2,
+                    arguments: Vec::new(),
+                },
+            ],
+            vec![Instruction::Ret(Register(1))],
+            vec![
+                Instruction::LoadImmediate(7, Register(9)),
+                Instruction::Add {
+                    lhs: Register(0),
+                    rhs: Register(0),
+                    out: Register(4),
+                },
+                Instruction::JNonZero {
+                    check: Register(4),
+                    to: 2,
+                    arguments: Vec::new(),
+                },
+                Instruction::Ret(Register(4)),
+            ],
+        ],
+        arity: Some(2),
+        attributes: FunctionAttributes::default(),
+    }
+}
+
+#[test]
+fn liveness() {
+    use crate::analysis::Liveness;
+    use std::collections::BTreeSet;
+
+    let set = |registers: &[usize]| -> BTreeSet<Register> {
+        registers.iter().copied().map(Register).collect()
+    };
+    let function = sparse_registers();
+    let liveness = Liveness::of(&function);
+
+    // the register that's never written is live from the start
+    assert_eq!(*liveness.live_in(0), set(&[1]));
+    assert_eq!(*liveness.live_out(0), set(&[0, 1]));
+    assert_eq!(*liveness.live_in(1), set(&[1]));
+    assert_eq!(*liveness.live_out(1), set(&[]));
+    // the loop keeps what it reads alive around the back edge
+    assert_eq!(*liveness.live_in(2), set(&[0]));
+    assert_eq!(*liveness.live_out(2), set(&[0]));
+    assert_eq!(
+        liveness.before_each(&function, 2),
+        [set(&[0]), set(&[0]), set(&[0, 4]), set(&[4])]
+    );
+    assert_eq!(
+        liveness.before_each(&function, 0),
+        [set(&[1]), set(&[0, 1]), set(&[0])]
+    );
+}
+
+#[test]
+fn register_renumbering() {
+    use crate::analysis::Liveness;
+    use crate::passes::RegisterRenumbering;
+
+    let mut graph = Graph::from_program(&overflowing_program(), Vec::new());
+    assert!(!graph.run_pass(&mut RegisterRenumbering()).unwrap());
+
+    let program = overflowing_program();
+    let mut graph = Graph::from_program(&program, vec!["main".to_string()]);
+    let main = "main".to_string();
+    *graph.function_mut(&main).unwrap() = sparse_registers();
+    assert_eq!(
+        *graph.liveness(&main).unwrap(),
+        Liveness::of(&sparse_registers())
+    );
+
+    assert!(graph.run_pass(&mut RegisterRenumbering()).unwrap());
+    let renumbered = graph.function(&main).unwrap();
+    // live registers are numbered in the order they appear, and the dead ones each get their own register after them
+    assert_eq!(
+        renumbered.blocks,
+        [
+            vec![
+                Instruction::LoadArgs(vec![Register(0), Register(3)]),
+                Instruction::JZero {
+                    check: Register(0),
+                    to: 1,
+                    arguments: Vec::new(),
+                },
+                Instruction::Jump {
+                    to: 2,
+                    arguments: Vec::new(),
+                },
+            ],
+            vec![Instruction::Ret(Register(1))],
+            vec![
+                Instruction::LoadImmediate(7, Register(4)),
+                Instruction::Add {
+                    lhs: Register(0),
+                    rhs: Register(0),
+                    out: Register(2),
+                },
+                Instruction::JNonZero {
+                    check: Register(2),
+                    to: 2,
+                    arguments: Vec::new(),
+                },
+                Instruction::Ret(Register(2)),
+            ],
+        ]
+    );
+
+    // renumbering is already as dense as it gets
+    assert!(!graph.run_pass(&mut RegisterRenumbering()).unwrap());
+}
+
+/// once constants are folded, the registers that only fed into them are dead and come after the one that's returned
+#[test]
+fn register_renumbering_after_folding() {
+    use crate::passes::RegisterRenumbering;
+
+    let program = overflowing_program();
+    let mut graph = Graph::from_program(&program, vec!["main".to_string()]);
+    graph
+        .run_pass(&mut ConstantFolding {
+            arithmetic: ArithmeticMode::Wrapping,
+        })
+        .unwrap();
+    assert!(graph.run_pass(&mut RegisterRenumbering()).unwrap());
+
+    let expected = calc_interpreter::Interpreter::new(&program)
+        .with_arithmetic(ArithmeticMode::Wrapping)
+        .interpret(&"main".to_string(), &[])
+        .unwrap();
+    // everything but the returned value is dead once it's folded
+    let main = graph.function(&"main".to_string()).unwrap();
+    assert_eq!(
+        main.blocks[0][3..],
+        [
+            Instruction::LoadImmediate(expected, Register(0)),
+            Instruction::Ret(Register(0))
+        ]
+    );
+    assert_eq!(
+        main.blocks[0][..3]
+            .iter()
+            .map(Instruction::writes)
+            .collect::<Vec<_>>(),
+        [[Register(1)], [Register(2)], [Register(3)]]
+    );
+}
+
+#[test]
+fn constant_propagation() {
+    use crate::dataflow::{Constant, ConstantPropagation, Dataflow};
+
+    let function: FunctionGraph<String> = FunctionGraph {
+        blocks: vec![
+            vec![
+                Instruction::LoadArgs(vec![Register(0)]),
+                Instruction::LoadImmediate(2, Register(1)),
+                Instruction::JZero {
+                    check: Register(0),
+                    to: 2,
+                    arguments: Vec::new(),
+                },
+                Instruction::Jump {
+                    to: 1,
+                    arguments: Vec::new(),
+                },
+            ],
+            vec![
+                Instruction::LoadImmediate(3, Register(2)),
+                Instruction::Jump {
+                    to: 3,
+                    arguments: Vec::new(),
+                },
+            ],
+            vec![
+                Instruction::LoadImmediate(4, Register(2)),
+                Instruction::Jump {
+                    to: 3,
+                    arguments: Vec::new(),
+                },
+            ],
+            vec![
+                Instruction::Add {
+                    lhs: Register(1),
+                    rhs: Register(1),
+                    out: Register(3),
+                },
+                Instruction::Divide {
+                    lhs: Register(3),
+                    rhs: Register(4),
+                    out: Register(5),
+                },
+                Instruction::Ret(Register(3)),
+            ],
+            vec![
+                Instruction::LoadImmediate(5, Register(0)),
+                Instruction::Ret(Register(0)),
+            ],
+        ],
+        arity: Some(1),
+        attributes: FunctionAttributes::default(),
+    };
+    let analysis = ConstantPropagation {
+        arithmetic: ArithmeticMode::Checked,
+    };
+    let constants = Dataflow::solve(&analysis, &function);
+
+    let before = constants.before(3);
+    assert_eq!(before[&Register(0)], Constant::Varying);
+    assert_eq!(before[&Register(1)], Constant::Known(2));
+    // the two paths into the block disagree
+    assert_eq!(before[&Register(2)], Constant::Varying);
+
+    let states = constants.per_instruction(&analysis, &function, 3);
+    assert_eq!(states.len(), 4);
+    assert_eq!(states[1][&Register(3)], Constant::Known(4));
+    // dividing by a register that was never written can't be evaluated
+    assert_eq!(states[2][&Register(5)], Constant::Varying);
+    assert_eq!(states[3], *constants.after(3));
+
+    // the block that can't be reached is never looked at
+    assert!(constants.before(4).is_empty());
+    assert!(constants.after(4).is_empty());
+}
+
+/// the values a register can hold, as an inclusive range, or None if nothing has flowed in
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Range(Option<(isize, isize)>);
+
+impl crate::dataflow::Lattice for Range {
+    fn bottom() -> Self {
+        Range(None)
+    }
+
+    fn join(&mut self, other: &Self) -> bool {
+        let joined = match (self.0, other.0) {
+            (None, range) | (range, None) => Range(range),
+            (Some((low, high)), Some((other_low, other_high))) => {
+                Range(Some((low.min(other_low), high.max(other_high))))
+            }
+        };
+        let changed = joined != *self;
+        *self = joined;
+        changed
+    }
+
+    fn widen(&mut self, other: &Self) -> bool {
+        let (Some((low, high)), Some((other_low, other_high))) = (self.0, other.0) else {
+            return self.join(other);
+        };
+        let low = if other_low < low { isize::MIN } else { low };
+        let high = if other_high > high { isize::MAX } else { high };
+        let changed = Some((low, high)) != self.0;
+        self.0 = Some((low, high));
+        changed
+    }
+}
+
+/// a range analysis, which only knows about immediates and addition
+struct Ranges;
+
+impl crate::dataflow::Analysis<String> for Ranges {
+    type State = std::collections::BTreeMap<Register, Range>;
+    const DIRECTION: crate::dataflow::Direction = crate::dataflow::Direction::Forward;
+
+    fn boundary(&self, _: &FunctionGraph<String>) -> Self::State {
+        Self::State::new()
+    }
+
+    fn transfer(&self, instruction: &Instruction<usize, String>, state: &mut Self::State) {
+        let range = |register| state.get(&register).and_then(|range: &Range| range.0);
+        let value = match instruction {
+            Instruction::LoadImmediate(value, _) => Some((*value, *value)),
+            Instruction::Add { lhs, rhs, .. } => {
+                range(*lhs)
+                    .zip(range(*rhs))
+                    .map(|((low, high), (other_low, other_high))| {
+                        (
+                            low.saturating_add(other_low),
+                            high.saturating_add(other_high),
+                        )
+                    })
+            }
+            _ => None,
+        };
+        for register in instruction.writes() {
+            state.insert(
+                register,
+                Range(Some(value.unwrap_or((isize::MIN, isize::MAX)))),
+            );
+        }
+    }
+
+    fn branch(
+        &self,
+        instruction: &Instruction<usize, String>,
+        taken: bool,
+        state: &mut Self::State,
+    ) {
+        if let (Instruction::JEqual { lhs, rhs, .. }, true) = (instruction, taken) {
+            let (Some(Range(Some((low, high)))), Some(Range(Some((other_low, other_high))))) =
+                (state.get(lhs), state.get(rhs))
+            else {
+                return;
+            };
+            let (low, high) = (*low.max(other_low), *high.min(other_high));
+            let equal = Range((low <= high).then_some((low, high)));
+            state.insert(*lhs, equal.clone());
+            state.insert(*rhs, equal);
+        }
+    }
+}
+
+/// a counting loop only terminates by widening, and the exit branch narrows the range back down
+#[test]
+fn range_analysis() {
+    use crate::dataflow::Dataflow;
+
+    let function: FunctionGraph<String> = FunctionGraph {
+        blocks: vec![
+            vec![
+                Instruction::LoadImmediate(0, Register(0)),
+                Instruction::LoadImmediate(1, Register(1)),
+                Instruction::LoadImmediate(10, Register(2)),
+                Instruction::Jump {
+                    to: 1,
+                    arguments: Vec::new(),
+                },
+            ],
+            vec![
+                Instruction::JEqual {
+                    lhs: Register(0),
+                    rhs: Register(2),
+                    to: 2,
+                    arguments: Vec::new(),
+                },
+                Instruction::Add {
+                    lhs: Register(0),
+                    rhs: Register(1),
+                    out: Register(0),
+                },
+                Instruction::Jump {
+                    to: 1,
+                    arguments: Vec::new(),
+                },
+            ],
+            vec![Instruction::Ret(Register(0))],
+        ],
+        arity: Some(0),
+        attributes: FunctionAttributes::default(),
+    };
+    let ranges = Dataflow::solve(&Ranges, &function);
+
+    assert_eq!(ranges.before(1)[&Register(0)], Range(Some((0, isize::MAX))));
+    assert_eq!(ranges.before(1)[&Register(1)], Range(Some((1, 1))));
+    assert_eq!(ranges.before(2)[&Register(0)], Range(Some((10, 10))));
+    assert_eq!(ranges.after(1)[&Register(0)], Range(Some((1, isize::MAX))));
+}
+
+/// a loop nested in another, where the inner loop loads an immediate on every iteration
+fn nested_loops() -> FunctionGraph<String> {
+    let jump = |to| Instruction::Jump {
+        to,
+        arguments: Vec::new(),
+    };
+    let jump_zero = |to| Instruction::JZero {
+        check: Register(0),
+        to,
+        arguments: Vec::new(),
+    };
+    FunctionGraph {
+        blocks: vec![
+            vec![Instruction::LoadArgs(vec![Register(0)]), jump(1)],
+            vec![jump_zero(4), jump(2)],
+            vec![jump_zero(1), jump(3)],
+            vec![Instruction::LoadImmediate(7, Register(1)), jump(2)],
+            vec![Instruction::Ret(Register(0))],
+        ],
+        arity: Some(1),
+        attributes: FunctionAttributes::default(),
+    }
+}
+
+#[test]
+fn loop_nest() {
+    use crate::analysis::{DominatorTree, Loop, LoopNest};
+    use std::collections::BTreeSet;
+
+    let function = nested_loops();
+    let loops = LoopNest::of(&function, &DominatorTree::dominators(&function));
+    assert_eq!(
+        loops.loops(),
+        [
+            Loop {
+                header: 1,
+                latches: vec![2],
+                blocks: BTreeSet::from([1, 2, 3]),
+                parent: None,
+                children: vec![1],
+                depth: 1,
+            },
+            Loop {
+                header: 2,
+                latches: vec![3],
+                blocks: BTreeSet::from([2, 3]),
+                parent: Some(0),
+                children: Vec::new(),
+                depth: 2,
+            },
+        ]
+    );
+    assert_eq!(
+        (0..5)
+            .map(|block| loops.innermost(block))
+            .collect::<Vec<_>>(),
+        [None, Some(0), Some(1), Some(1), None]
+    );
+    assert_eq!(
+        (0..5).map(|block| loops.depth(block)).collect::<Vec<_>>(),
+        [0, 1, 2, 2, 0]
+    );
+
+    // a block that loops on itself is a loop of its own, and unreachable loops aren't loops
+    let function = diamond_and_loop();
+    let loops = LoopNest::of(&function, &DominatorTree::dominators(&function));
+    assert_eq!(loops.loops().len(), 1);
+    assert_eq!(loops.loops()[0].header, 3);
+    assert_eq!(loops.loops()[0].latches, [3]);
+    assert_eq!(loops.loops()[0].blocks, BTreeSet::from([3]));
+    assert_eq!(loops.innermost(5), None);
+}
+
+/// a hoisted instruction moves out of every loop it's invariant in, one preheader at a time
+#[test]
+fn licm_nested_loops() {
+    use crate::passes::LoopInvariantCodeMotion;
+
+    let main = "main".to_string();
+    let mut graph = Graph::from_program(&overflowing_program(), vec![main.clone()]);
+    *graph.function_mut(&main).unwrap() = nested_loops();
+    let mut licm = LoopInvariantCodeMotion {
+        arithmetic: ArithmeticMode::Wrapping,
+    };
+    assert!(graph.run_pass(&mut licm).unwrap());
+
+    let jump = |to| Instruction::Jump {
+        to,
+        arguments: Vec::new(),
+    };
+    let jump_zero = |to| Instruction::JZero {
+        check: Register(0),
+        to,
+        arguments: Vec::new(),
+    };
+    assert_eq!(
+        graph.function(&main).unwrap().blocks,
+        [
+            vec![Instruction::LoadArgs(vec![Register(0)]), jump(6)],
+            vec![jump_zero(4), jump(5)],
+            vec![jump_zero(1), jump(3)],
+            vec![jump(2)],
+            vec![Instruction::Ret(Register(0))],
+            vec![jump(2)],
+            vec![Instruction::LoadImmediate(7, Register(1)), jump(1)],
+        ]
+    );
+    assert_eq!(graph.loops(&main).unwrap().loops()[1].blocks.len(), 2);
+    assert!(!graph.run_pass(&mut licm).unwrap());
+}
+
+/// sums `n * 3 / 3` `n` times, with the loop taking its counter and sum as block arguments
+fn summing_loop() -> FunctionGraph<String> {
+    FunctionGraph {
+        blocks: vec![
+            vec![
+                Instruction::LoadArgs(vec![Register(0)]),
+                Instruction::LoadImmediate(0, Register(1)),
+                Instruction::Jump {
+                    to: 1,
+                    arguments: vec![Register(1), Register(1)],
+                },
+            ],
+            vec![
+                Instruction::LoadBlockArgs(vec![Register(2), Register(3)]),
+                Instruction::JEqual {
+                    lhs: Register(2),
+                    rhs: Register(0),
+                    to: 3,
+                    arguments: vec![Register(3)],
+                },
+                Instruction::Jump {
+                    to: 2,
+                    arguments: Vec::new(),
+                },
+            ],
+            vec![
+                Instruction::LoadImmediate(1, Register(4)),
+                Instruction::LoadImmediate(3, Register(5)),
+                Instruction::Multiply {
+                    lhs: Register(0),
+                    rhs: Register(5),
+                    out: Register(6),
+                },
+                Instruction::Divide {
+                    lhs: Register(6),
+                    rhs: Register(5),
+                    out: Register(7),
+                },
+                // n is zero when the loop never runs, so this can't be hoisted
+                Instruction::Divide {
+                    lhs: Register(6),
+                    rhs: Register(0),
+                    out: Register(8),
+                },
+                Instruction::Add {
+                    lhs: Register(3),
+                    rhs: Register(7),
+                    out: Register(9),
+                },
+                Instruction::Add {
+                    lhs: Register(2),
+                    rhs: Register(4),
+                    out: Register(10),
+                },
+                Instruction::Jump {
+                    to: 1,
+                    arguments: vec![Register(10), Register(9)],
+                },
+            ],
+            vec![
+                Instruction::LoadBlockArgs(vec![Register(11)]),
+                Instruction::Ret(Register(11)),
+            ],
+        ],
+        arity: Some(1),
+        attributes: FunctionAttributes::default(),
+    }
+}
+
+#[test]
+fn licm_only_hoists_what_cannot_fail() {
+    use crate::passes::LoopInvariantCodeMotion;
+
+    let main = "main".to_string();
+    let mut graph = Graph::from_program(&overflowing_program(), vec![main.clone()]);
+    *graph.function_mut(&main).unwrap() = summing_loop();
+    assert!(graph
+        .run_pass(&mut LoopInvariantCodeMotion {
+            arithmetic: ArithmeticMode::Wrapping,
+        })
+        .unwrap());
+
+    let function = graph.function(&main).unwrap();
+    let original = summing_loop();
+    // the preheader loads the arguments into fresh registers and passes them on to the loop
+    assert_eq!(
+        function.blocks[4],
+        [
+            Instruction::LoadBlockArgs(vec![Register(12), Register(13)]),
+            original.blocks[2][0].clone(),
+            original.blocks[2][1].clone(),
+            original.blocks[2][2].clone(),
+            original.blocks[2][3].clone(),
+            Instruction::Jump {
+                to: 1,
+                arguments: vec![Register(12), Register(13)],
+            },
+        ]
+    );
+    assert_eq!(function.blocks[2], original.blocks[2][4..]);
+    assert_eq!(
+        function.blocks[0][2],
+        Instruction::Jump {
+            to: 4,
+            arguments: vec![Register(1), Register(1)],
+        }
+    );
+    // the jump back to the header from inside the loop still goes straight to it
+    assert_eq!(function.blocks[1], original.blocks[1]);
+    assert_eq!(function.blocks[3], original.blocks[3]);
+
+    // multiplying can overflow when it's checked, so nothing that depends on it can be hoisted either
+    let mut graph = Graph::from_program(&overflowing_program(), vec![main.clone()]);
+    *graph.function_mut(&main).unwrap() = summing_loop();
+    assert!(graph
+        .run_pass(&mut LoopInvariantCodeMotion {
+            arithmetic: ArithmeticMode::Checked,
+        })
+        .unwrap());
+    let function = graph.function(&main).unwrap();
+    assert_eq!(function.blocks[4][1..3], original.blocks[2][..2]);
+    assert_eq!(function.blocks[4].len(), 4);
+    assert_eq!(function.blocks[2], original.blocks[2][2..]);
+}
+
+/// renumbering leaves every register written once, so a dead write in a loop can still be hoisted out of it afterwards
+#[test]
+fn licm_after_renumbering() {
+    use crate::passes::{LoopInvariantCodeMotion, RegisterRenumbering};
+
+    let main = "main".to_string();
+    let mut graph = Graph::from_program(&overflowing_program(), vec![main.clone()]);
+    let mut function = summing_loop();
+    function.blocks[2].insert(0, Instruction::LoadImmediate(5, Register(12)));
+    *graph.function_mut(&main).unwrap() = function;
+    assert!(graph.run_pass(&mut RegisterRenumbering()).unwrap());
+
+    let writes: Vec<Register> = graph
+        .function(&main)
+        .unwrap()
+        .blocks
+        .iter()
+        .flatten()
+        .flat_map(Instruction::writes)
+        .collect();
+    let distinct: std::collections::HashSet<&Register> = writes.iter().collect();
+    assert_eq!(distinct.len(), writes.len());
+
+    assert!(graph
+        .run_pass(&mut LoopInvariantCodeMotion {
+            arithmetic: ArithmeticMode::Wrapping,
+        })
+        .unwrap());
+    let function = graph.function(&main).unwrap();
+    assert_eq!(
+        function.blocks[4][1],
+        Instruction::LoadImmediate(5, Register(11))
+    );
+    assert!(!function.blocks[2].contains(&function.blocks[4][1]));
+}
+
+/// counts its first argument down to zero, adding its second argument times 3, divided by 3 and then by the first argument to the total every time,
+/// where everything but the division by the first argument can be hoisted out of the loop
+fn invariant_loop_program() -> calc_ir::program::implementations::BasicProgram {
+    let mut builder = Program::new();
+    let mut function = builder.make_fn("scaled_sum".to_string());
+    let loop_block_id = function.reserve_block();
+    let exit_block_id = function.reserve_block();
+
+    let mut entry_block = function.build_block();
+    let args = entry_block.add_load_args(2);
+    let total = entry_block.add_immediate(0);
+    entry_block.add_cond_jump_with_args(
+        BlockJump::Unconditional,
+        loop_block_id,
+        vec![args[0], total],
+    );
+    let (entry_block_id, function) = entry_block.finalize();
+
+    let mut loop_block = function.build_reserved_block(loop_block_id);
+    let params = loop_block.add_block_params(2);
+    loop_block.add_cond_jump_with_args(BlockJump::Zero(params[0]), exit_block_id, vec![params[1]]);
+    let one = loop_block.add_immediate(1);
+    let three = loop_block.add_immediate(3);
+    let tripled = loop_block.add_arithmetic(Arithmetic::Multiply, args[1], three);
+    let scaled = loop_block.add_arithmetic(Arithmetic::Divide, tripled, three);
+    let divided = loop_block.add_arithmetic(Arithmetic::Divide, scaled, args[0]);
+    let total = loop_block.add_arithmetic(Arithmetic::Add, params[1], divided);
+    let remaining = loop_block.add_arithmetic(Arithmetic::Subtract, params[0], one);
+    loop_block.add_cond_jump_with_args(
+        BlockJump::Unconditional,
+        loop_block_id,
+        vec![remaining, total],
+    );
+    let (_, function) = loop_block.finalize();
+
+    let mut exit_block = function.build_reserved_block(exit_block_id);
+    let result = exit_block.add_block_params(1)[0];
+    exit_block.add_ret(result);
+    let (_, function) = exit_block.finalize();
+    function.finalize(entry_block_id).finalize()
+}
+
+/// `graph` as a program of its own, with the blocks of every function one after another
+fn graph_program(graph: &Graph<String>) -> calc_ir::program::implementations::BasicProgram {
+    use calc_ir::program::implementations::BasicProgram;
+    use calc_ir::zir::{FunctionEntry, Layout, Module};
+
+    let mut module = Module {
+        layout: Layout::Blocks,
+        functions: Vec::new(),
+        blocks: Vec::new(),
+    };
+    for (name, function) in graph.functions() {
+        let entry = module.blocks.len();
+        module.functions.push(FunctionEntry {
+            name: name.clone(),
+            entry,
+            arity: function.arity,
+            register_count: None,
+            attributes: function.attributes,
+        });
+        module.blocks.extend(function.blocks.iter().map(|block| {
+            block
+                .iter()
+                .map(|instruction| instruction.clone().map_blocks(|block| entry + block))
+                .collect()
+        }));
+    }
+    BasicProgram::from_module(module).unwrap()
+}
+
+/// hoisting out of loops doesn't change what a function returns
+#[test]
+fn licm_matches_interpreter() {
+    use crate::passes::LoopInvariantCodeMotion;
+    use calc_interpreter::interpret_function;
+
+    let program = invariant_loop_program();
+    let function = "scaled_sum".to_string();
+    let mut graph = Graph::from_program(&program, vec![function.clone()]);
+    let changed = graph
+        .run_pass(&mut LoopInvariantCodeMotion {
+            arithmetic: ArithmeticMode::Wrapping,
+        })
+        .unwrap();
+    assert!(changed);
+    // loading the parameters, the exit, the division by the first argument, the add, the count down and the jump back are left in it
+    assert_eq!(graph.function(&function).unwrap().blocks[1].len(), 6);
+
+    let optimized = graph_program(&graph);
+    for arguments in [[0, 4], [5, 4], [3, -7], [2, isize::MAX]] {
+        assert_eq!(
+            interpret_function(&function, &optimized, &arguments),
+            interpret_function(&function, &program, &arguments),
+            "{arguments:?}"
+        );
+    }
+}
+
+/// a repeated call to a pure function is removed, while the same call to a function that isn't pure is kept
+#[test]
+fn pure_call_elimination() {
+    use crate::passes::PureCallElimination;
+    use calc_interpreter::interpret_function;
+
+    let mut builder = Program::new();
+    let mut main_function = builder.make_fn("main".to_string());
+    let mut entry_block = main_function.build_block();
+    let argument = entry_block.add_load_args(1)[0];
+    let first = entry_block.add_fn_call("square".to_string(), vec![argument]);
+    let second = entry_block.add_fn_call("square".to_string(), vec![argument]);
+    let fourth = entry_block.add_fn_call("square".to_string(), vec![second]);
+    let total = entry_block.add_arithmetic(Arithmetic::Add, first, fourth);
+    let impure = entry_block.add_fn_call("impure_square".to_string(), vec![argument]);
+    let impure_again = entry_block.add_fn_call("impure_square".to_string(), vec![argument]);
+    let impure_total = entry_block.add_arithmetic(Arithmetic::Add, impure, impure_again);
+    let total = entry_block.add_arithmetic(Arithmetic::Add, total, impure_total);
+    entry_block.add_ret(total);
+    let (entry_block_id, main_function) = entry_block.finalize();
+    let builder = main_function.finalize(entry_block_id);
+
+    for (name, pure) in [("square", true), ("impure_square", false)] {
+        let mut function = builder.make_fn(name.to_string());
+        function.set_attributes(FunctionAttributes {
+            pure,
+            ..FunctionAttributes::default()
+        });
+        let mut entry_block = function.build_block();
+        let argument = entry_block.add_load_args(1)[0];
+        let square = entry_block.add_arithmetic(Arithmetic::Multiply, argument, argument);
+        entry_block.add_ret(square);
+        let (entry_block_id, function) = entry_block.finalize();
+        function.finalize(entry_block_id);
+    }
+    let program = builder.finalize();
+
+    let main = "main".to_string();
+    let mut graph = Graph::from_program(&program, vec![main.clone()]);
+    assert!(graph.run_pass(&mut PureCallElimination()).unwrap());
+    let calls: Vec<_> = graph.function(&main).unwrap().blocks[0]
+        .iter()
+        .filter_map(|instruction| match instruction {
+            Instruction::Call {
+                function_id,
+                arguments,
+                ..
+            } => Some((function_id.as_str(), arguments.clone())),
+            _ => None,
+        })
+        .collect();
+    // the square of the square reads the first call's result
+    assert_eq!(
+        calls,
+        vec![
+            ("square", vec![argument]),
+            ("square", vec![first]),
+            ("impure_square", vec![argument]),
+            ("impure_square", vec![argument]),
+        ]
+    );
+    assert!(!graph.run_pass(&mut PureCallElimination()).unwrap());
+
+    let optimized = graph_program(&graph);
+    for arguments in [[0], [3], [-5]] {
+        assert_eq!(
+            interpret_function(&main, &optimized, &arguments),
+            interpret_function(&main, &program, &arguments),
+            "{arguments:?}"
+        );
+    }
+}